@@ -3,6 +3,15 @@ pub use sea_orm_migration::prelude::*;
 mod m20240416_000001_create_message_table;
 mod m20240416_000002_create_session_table;
 mod m20240416_000003_create_user_table;
+mod m20240416_000004_create_job_table;
+mod m20240416_000005_create_summary_cache_table;
+mod m20240416_000006_create_knowledge_base_tables;
+mod m20240416_000007_create_memory_table;
+mod m20240416_000008_add_session_history_summary;
+mod m20240416_000009_add_session_settings;
+mod m20240416_000010_create_derived_document_table;
+mod m20240416_000011_add_session_auto_memory;
+mod m20240416_000012_create_team_member_table;
 
 pub struct Migrator;
 
@@ -13,6 +22,15 @@ impl MigratorTrait for Migrator {
             Box::new(m20240416_000001_create_message_table::Migration),
             Box::new(m20240416_000002_create_session_table::Migration),
             Box::new(m20240416_000003_create_user_table::Migration),
+            Box::new(m20240416_000004_create_job_table::Migration),
+            Box::new(m20240416_000005_create_summary_cache_table::Migration),
+            Box::new(m20240416_000006_create_knowledge_base_tables::Migration),
+            Box::new(m20240416_000007_create_memory_table::Migration),
+            Box::new(m20240416_000008_add_session_history_summary::Migration),
+            Box::new(m20240416_000009_add_session_settings::Migration),
+            Box::new(m20240416_000010_create_derived_document_table::Migration),
+            Box::new(m20240416_000011_add_session_auto_memory::Migration),
+            Box::new(m20240416_000012_create_team_member_table::Migration),
         ]
     }
 }
@@ -0,0 +1,66 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(Job::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(Job::JobId)
+                            .string_len(36)
+                            .not_null()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(Job::SessionId).integer().not_null())
+                    .col(ColumnDef::new(Job::Filename).string().not_null())
+                    .col(ColumnDef::new(Job::StorageKey).string().not_null())
+                    .col(
+                        ColumnDef::new(Job::Status)
+                            .enumeration(
+                                Alias::new("job_status"),
+                                vec![
+                                    Alias::new("queued"),
+                                    Alias::new("parsing"),
+                                    Alias::new("chunking"),
+                                    Alias::new("embedding"),
+                                    Alias::new("completed"),
+                                    Alias::new("failed"),
+                                ],
+                            )
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(Job::Progress).integer().not_null())
+                    .col(ColumnDef::new(Job::Error).text())
+                    .col(ColumnDef::new(Job::CreateTime).timestamp().not_null())
+                    .col(ColumnDef::new(Job::UpdateTime).timestamp().not_null())
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(Job::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum Job {
+    Table,
+    JobId,
+    SessionId,
+    Filename,
+    StorageKey,
+    Status,
+    Progress,
+    Error,
+    CreateTime,
+    UpdateTime,
+}
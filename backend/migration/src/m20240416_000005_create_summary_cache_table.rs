@@ -0,0 +1,64 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(SummaryCache::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(SummaryCache::SummaryCacheId)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(SummaryCache::DocumentHash)
+                            .string_len(64)
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(SummaryCache::SettingsHash)
+                            .string_len(64)
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(SummaryCache::Summary).text().not_null())
+                    .col(
+                        ColumnDef::new(SummaryCache::CreateTime)
+                            .timestamp()
+                            .not_null(),
+                    )
+                    .index(
+                        Index::create()
+                            .name("idx_summary_cache_key")
+                            .col(SummaryCache::DocumentHash)
+                            .col(SummaryCache::SettingsHash)
+                            .unique(),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(SummaryCache::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum SummaryCache {
+    Table,
+    SummaryCacheId,
+    DocumentHash,
+    SettingsHash,
+    Summary,
+    CreateTime,
+}
@@ -0,0 +1,171 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(KnowledgeBase::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(KnowledgeBase::KnowledgeBaseId)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(KnowledgeBase::Name).string().not_null())
+                    .col(ColumnDef::new(KnowledgeBase::Description).text().not_null())
+                    .col(
+                        ColumnDef::new(KnowledgeBase::OwnerType)
+                            .enumeration(
+                                Alias::new("owner_type"),
+                                vec![Alias::new("user"), Alias::new("team")],
+                            )
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(KnowledgeBase::OwnerId).integer().not_null())
+                    .col(
+                        ColumnDef::new(KnowledgeBase::CreateTime)
+                            .timestamp()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(KnowledgeBase::LastUpdateTime)
+                            .timestamp()
+                            .not_null(),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_table(
+                Table::create()
+                    .table(KnowledgeBaseDocument::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(KnowledgeBaseDocument::DocumentId)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(KnowledgeBaseDocument::KnowledgeBaseId)
+                            .integer()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(KnowledgeBaseDocument::Filename)
+                            .string()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(KnowledgeBaseDocument::StorageKey)
+                            .string()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(KnowledgeBaseDocument::CreateTime)
+                            .timestamp()
+                            .not_null(),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_table(
+                Table::create()
+                    .table(SessionKnowledgeBase::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(SessionKnowledgeBase::SessionId)
+                            .integer()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(SessionKnowledgeBase::KnowledgeBaseId)
+                            .integer()
+                            .not_null(),
+                    )
+                    .primary_key(
+                        Index::create()
+                            .col(SessionKnowledgeBase::SessionId)
+                            .col(SessionKnowledgeBase::KnowledgeBaseId),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+        //知识库文档也由后台任务处理，此时任务不属于任何会话
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Job::Table)
+                    .modify_column(ColumnDef::new(Job::SessionId).integer().null())
+                    .add_column(ColumnDef::new(Job::KnowledgeBaseId).integer().null())
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Job::Table)
+                    .drop_column(Job::KnowledgeBaseId)
+                    .modify_column(ColumnDef::new(Job::SessionId).integer().not_null())
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .drop_table(Table::drop().table(SessionKnowledgeBase::Table).to_owned())
+            .await?;
+        manager
+            .drop_table(Table::drop().table(KnowledgeBaseDocument::Table).to_owned())
+            .await?;
+        manager
+            .drop_table(Table::drop().table(KnowledgeBase::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum KnowledgeBase {
+    Table,
+    KnowledgeBaseId,
+    Name,
+    Description,
+    OwnerType,
+    OwnerId,
+    CreateTime,
+    LastUpdateTime,
+}
+
+#[derive(DeriveIden)]
+enum KnowledgeBaseDocument {
+    Table,
+    DocumentId,
+    KnowledgeBaseId,
+    Filename,
+    StorageKey,
+    CreateTime,
+}
+
+#[derive(DeriveIden)]
+enum SessionKnowledgeBase {
+    Table,
+    SessionId,
+    KnowledgeBaseId,
+}
+
+#[derive(DeriveIden)]
+enum Job {
+    Table,
+    SessionId,
+    KnowledgeBaseId,
+}
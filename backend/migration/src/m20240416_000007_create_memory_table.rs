@@ -0,0 +1,52 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(Memory::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(Memory::MemoryId)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(Memory::UserId).integer().not_null())
+                    .col(ColumnDef::new(Memory::Content).text().not_null())
+                    .col(ColumnDef::new(Memory::Embedding).json().null())
+                    .col(ColumnDef::new(Memory::SourceSessionId).integer().null())
+                    .col(ColumnDef::new(Memory::CreateTime).timestamp().not_null())
+                    .index(
+                        Index::create()
+                            .name("idx_memory_user_id")
+                            .col(Memory::UserId),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(Memory::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum Memory {
+    Table,
+    MemoryId,
+    UserId,
+    Content,
+    Embedding,
+    SourceSessionId,
+    CreateTime,
+}
@@ -0,0 +1,53 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Session::Table)
+                    .add_column(ColumnDef::new(Session::SystemPrompt).text().null())
+                    .add_column(ColumnDef::new(Session::Model).string_len(64).null())
+                    .add_column(ColumnDef::new(Session::Temperature).float().null())
+                    .add_column(ColumnDef::new(Session::TopP).float().null())
+                    .add_column(ColumnDef::new(Session::PenaltyScore).float().null())
+                    .add_column(ColumnDef::new(Session::MaxOutputTokens).integer().null())
+                    .add_column(ColumnDef::new(Session::EnabledFunctions).json().null())
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Session::Table)
+                    .drop_column(Session::EnabledFunctions)
+                    .drop_column(Session::MaxOutputTokens)
+                    .drop_column(Session::PenaltyScore)
+                    .drop_column(Session::TopP)
+                    .drop_column(Session::Temperature)
+                    .drop_column(Session::Model)
+                    .drop_column(Session::SystemPrompt)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum Session {
+    Table,
+    SystemPrompt,
+    Model,
+    Temperature,
+    TopP,
+    PenaltyScore,
+    MaxOutputTokens,
+    EnabledFunctions,
+}
@@ -0,0 +1,85 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(DerivedDocument::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(DerivedDocument::DerivedDocumentId)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(DerivedDocument::SessionId)
+                            .integer()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(DerivedDocument::Kind)
+                            .string_len(32)
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(DerivedDocument::Filename)
+                            .string_len(255)
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(DerivedDocument::SourceHash)
+                            .string_len(64)
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(DerivedDocument::SettingsHash)
+                            .string_len(64)
+                            .not_null(),
+                    )
+                    //译文可能超过TEXT类型的64KB上限
+                    .col(
+                        ColumnDef::new(DerivedDocument::Content)
+                            .custom(Alias::new("longtext"))
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(DerivedDocument::CreateTime)
+                            .timestamp()
+                            .not_null(),
+                    )
+                    .index(
+                        Index::create()
+                            .name("idx_derived_document_session_id")
+                            .col(DerivedDocument::SessionId),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(DerivedDocument::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum DerivedDocument {
+    Table,
+    DerivedDocumentId,
+    SessionId,
+    Kind,
+    Filename,
+    SourceHash,
+    SettingsHash,
+    Content,
+    CreateTime,
+}
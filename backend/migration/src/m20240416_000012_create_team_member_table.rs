@@ -0,0 +1,44 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(TeamMember::Table)
+                    .if_not_exists()
+                    .col(ColumnDef::new(TeamMember::TeamId).integer().not_null())
+                    .col(ColumnDef::new(TeamMember::UserId).integer().not_null())
+                    .col(
+                        ColumnDef::new(TeamMember::CreateTime)
+                            .timestamp()
+                            .not_null(),
+                    )
+                    .primary_key(
+                        Index::create()
+                            .col(TeamMember::TeamId)
+                            .col(TeamMember::UserId),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(TeamMember::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum TeamMember {
+    Table,
+    TeamId,
+    UserId,
+    CreateTime,
+}
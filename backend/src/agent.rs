@@ -1,11 +1,26 @@
 use crate::entities;
 use anyhow::Result;
-use erniebot_rs::chat::{ChatEndpoint, ChatOpt, Message, Role};
-use sea_orm::{ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, Set};
+use erniebot_rs::{
+    chat::{ChatEndpoint, ChatOpt, Message, Role},
+    embedding::EmbeddingEndpoint,
+};
+use sea_orm::{ActiveModelTrait, ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, Set};
 use serde::{Deserialize, Serialize};
 use std::fs;
+use tracing::{info, warn};
 
+use crate::chunker::estimate_tokens;
+use crate::confirmation::{confirm, ConfirmationResult, FunctionConfirmer};
+use crate::context_window::{
+    fit_to_budget, select_window, summary_messages, truncate_to_tokens, update_summary,
+    ContextConfig, CONTEXT_CONFIG_PATH,
+};
+use crate::data::ConfirmFunctionRequest;
 use crate::functions::{get_function_registry, Context, FunctionRegistry};
+use crate::index::session_text_path;
+use crate::knowledge_base::attached_knowledge_bases;
+use crate::memory::{extract_memories, format_memories, recall, RECALL_TOP_K};
+use crate::session_settings::SessionSettings;
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct FunctionSelectResult {
@@ -14,14 +29,19 @@ pub struct FunctionSelectResult {
     thoughts: String,
 }
 
-fn generate_request(message: &str, function_registry: &FunctionRegistry) -> Result<String> {
+fn generate_request(
+    message: &str,
+    memories: &str,
+    function_registry: &FunctionRegistry,
+) -> Result<String> {
     //从templates/select.template读取
     let template = fs::read_to_string("templates/select.template")?;
     let function_list = function_registry.get_ernie_functions();
     let functions_string = serde_json::to_string(&function_list)?;
     let request = template
         .replace("{{functions}}", &functions_string)
-        .replace("{{message}}", message);
+        .replace("{{message}}", message)
+        .replace("{{memories}}", memories);
     Ok(request)
 }
 
@@ -47,131 +67,197 @@ fn extract_result(response_string: &str) -> Result<FunctionSelectResult> {
     Ok(result)
 }
 
+const RETRY_PROMPT: &str =
+    "辛苦您了，但是您的回复中似乎没有我想要的符合要求的json。请您重新尝试回答刚才的问题";
+
+//调用大模型前检查请求长度，超出模型的输入上限时丢弃最早的历史消息
+struct ChatClient {
+    endpoint: ChatEndpoint,
+    budget: usize,
+    //会话设置中的人设和采样参数，每次调用都会带上
+    options: Vec<ChatOpt>,
+}
+
+//history中只有真实的对话，选择函数、后处理等提示词只在本次调用中附加，不写入history
 async fn try_get_response(
-    chat_endpoint: &ChatEndpoint,
-    messages: &Vec<Message>,
-    options: &Vec<ChatOpt>,
+    chat: &ChatClient,
+    history: &[Message],
+    prompt: &[Message],
 ) -> Result<String> {
-    let response = chat_endpoint.ainvoke(messages, options).await?;
+    let messages = fit_to_budget(history, prompt, chat.budget)?;
+    invoke(chat, &messages).await
+}
+
+async fn invoke(chat: &ChatClient, messages: &Vec<Message>) -> Result<String> {
+    let response = chat.endpoint.ainvoke(messages, &chat.options).await?;
     let result = response.get_chat_result()?;
     Ok(result)
 }
 
+fn user_message(content: String) -> Message {
+    Message {
+        role: Role::User,
+        content,
+        ..Default::default()
+    }
+}
+
 async fn select_function(
     message: &str,
-    chat_history: &mut Vec<Message>,
-    chat_endpoint: &ChatEndpoint,
+    memories: &str,
+    chat_history: &[Message],
+    chat: &ChatClient,
     function_registry: &FunctionRegistry,
     max_retry: usize,
 ) -> Result<FunctionSelectResult> {
-    let request = generate_request(message, function_registry)?;
-    chat_history.push(Message {
-        role: Role::User,
-        content: request,
-        ..Default::default()
-    });
-
-    let options = Vec::new();
+    let request = generate_request(message, memories, function_registry)?;
+    //重试时只附加上一次不合格的回复，提示词不会随重试次数增长
+    let mut prompt = vec![user_message(request.clone())];
     let mut retry = 0;
     loop {
-        let try_result = try_get_response(chat_endpoint, chat_history, &options).await;
-        if try_result.is_err() {
-            println!("{:?}", try_result);
-            retry += 1;
-            if retry >= max_retry {
-                return Err(anyhow::anyhow!("Max retry reached"));
+        //超出上下文长度时重试也不会成功，直接返回；请求失败(如网络波动)和回复中的json无法解析时重试
+        let messages = fit_to_budget(chat_history, &prompt, chat.budget)?;
+        let response = match invoke(chat, &messages).await {
+            Ok(response) => response,
+            Err(e) => {
+                warn!("select function request failed: {:?}", e);
+                retry += 1;
+                if retry >= max_retry {
+                    return Err(anyhow::anyhow!("Max retry reached"));
+                }
+                continue;
             }
-            continue;
-        }
-        let response = try_result.unwrap();
-        chat_history.push(Message {
-            role: Role::Assistant,
-            content: response.clone(),
-            ..Default::default()
-        });
+        };
         match extract_result(&response) {
             Ok(result) => return Ok(result),
             Err(_) => {
                 retry += 1;
                 if retry >= max_retry {
                     return Err(anyhow::anyhow!("Max retry reached"));
-                } else {
-                    chat_history.push(Message {
-                        role: Role::User,
-                        content: "辛苦您了，但是您的回复中似乎没有我想要的符合要求的json。请您重新尝试回答刚才的问题".to_string(),
-                        ..Default::default()
-                    });
                 }
+                prompt = vec![
+                    user_message(request.clone()),
+                    Message {
+                        role: Role::Assistant,
+                        content: response,
+                        ..Default::default()
+                    },
+                    user_message(RETRY_PROMPT.to_string()),
+                ];
             }
         }
     }
 }
 
 async fn postprocess(
+    message: &str,
+    function_name: &str,
     response: &str,
-    chat_history: &mut Vec<Message>,
-    chat_endpoint: &ChatEndpoint,
+    chat_history: &[Message],
+    chat: &ChatClient,
 ) -> Result<String> {
     let template = fs::read_to_string("templates/postprocess.template")?;
-    let request = template.replace("{{response}}", response);
-    let options = Vec::new();
-    chat_history.push(Message {
-        role: Role::User,
-        content: request,
-        ..Default::default()
-    });
-    let response = try_get_response(chat_endpoint, chat_history, &options).await?;
+    //函数结果(如文档摘要)可能很长，截断到单条消息能容纳的长度
+    let response = truncate_to_tokens(
+        response,
+        chat.budget
+            .saturating_sub(estimate_tokens(&template))
+            .saturating_sub(estimate_tokens(message)),
+    );
+    let request = template
+        .replace("{{message}}", message)
+        .replace("{{function}}", function_name)
+        .replace("{{response}}", &response);
+    let prompt = [user_message(request)];
+    let response = try_get_response(chat, chat_history, &prompt).await?;
     Ok(response)
 }
 
-async fn fallback(
-    message: &str,
-    chat_history: &mut Vec<Message>,
-    chat_endpoint: &ChatEndpoint,
-) -> Result<String> {
-    let options = Vec::new();
-    chat_history.push(Message {
-        role: Role::User,
-        content: message.to_string(),
-        ..Default::default()
-    });
-    let response = try_get_response(chat_endpoint, chat_history, &options).await?;
+async fn fallback(message: &str, chat_history: &[Message], chat: &ChatClient) -> Result<String> {
+    let prompt = [user_message(message.to_string())];
+    let response = try_get_response(chat, chat_history, &prompt).await?;
     Ok(response)
 }
 
+enum Execution {
+    Executed(Result<String>),
+    //有副作用的函数没有得到用户确认，没有执行，返回给用户的说明
+    NotConfirmed(String),
+}
+
+//有副作用的函数需要用户确认后才执行，只读的函数直接执行
+async fn execute_function<F: std::future::Future<Output = ConfirmationResult>>(
+    function_registry: &FunctionRegistry,
+    function_name: &str,
+    parameters: serde_json::Value,
+    thoughts: String,
+    session_id: i32,
+    confirm: impl FnOnce(ConfirmFunctionRequest) -> F,
+    execute: impl FnOnce(serde_json::Value) -> Result<String>,
+) -> Execution {
+    if function_registry.requires_confirmation(function_name) {
+        let request = ConfirmFunctionRequest {
+            confirmation_id: uuid::Uuid::new_v4().to_string(),
+            session_id,
+            function: function_name.to_string(),
+            description: function_registry.get_description_by_name(function_name),
+            parameters: parameters.clone(),
+            thoughts,
+        };
+        if let Some(message) = confirm(request).await.rejection_message(function_name) {
+            return Execution::NotConfirmed(message);
+        }
+    }
+    Execution::Executed(execute(parameters))
+}
+
 async fn single_step(
     message: &str,
-    chat_history: &mut Vec<Message>,
-    chat_endpoint: &ChatEndpoint,
+    memories: &str,
+    chat_history: &[Message],
+    chat: &ChatClient,
     function_registry: &FunctionRegistry,
     max_retry: usize,
     context: &Context,
 ) -> Result<String> {
-    let original_history_length = chat_history.len();
     let result = select_function(
         message,
+        memories,
         chat_history,
-        chat_endpoint,
+        chat,
         function_registry,
         max_retry,
     )
     .await?;
     println!("function choice: {:?}", result);
     let function_name = result.function;
-    let parameters = result.parameters;
-    let response = function_registry.execute_function_by_name(&function_name, parameters, context);
+    let execution = execute_function(
+        function_registry,
+        &function_name,
+        result.parameters,
+        result.thoughts,
+        context.session_id,
+        |request| confirm(context.confirmer.as_ref(), request),
+        |parameters| {
+            function_registry.execute_function_by_name(&function_name, parameters, context)
+        },
+    )
+    .await;
+    let response = match execution {
+        Execution::Executed(response) => response,
+        Execution::NotConfirmed(message) => return Ok(message),
+    };
     let response = match response {
         Ok(response) => {
             if function_registry.if_postprocess_by_name(&function_name) {
-                postprocess(&response, chat_history, chat_endpoint).await?
+                postprocess(message, &function_name, &response, chat_history, chat).await?
             } else {
                 response
             }
         }
         Err(_) => {
-            chat_history.truncate(original_history_length);
             println!("fallback");
-            fallback(message, chat_history, chat_endpoint).await?
+            fallback(message, chat_history, chat).await?
         }
     };
     println!("response: {:?}", response);
@@ -191,30 +277,83 @@ pub async fn reply(
     session_id: i32,
     db: &DatabaseConnection,
     chat_endpoint: &ChatEndpoint,
+    embedding_endpoint: &EmbeddingEndpoint,
+    confirmer: Option<FunctionConfirmer>,
 ) -> Result<String> {
-    let context = Context {
-        session_id,
-        chat_endpoint: chat_endpoint.clone(),
-    };
-    let function_registry = get_function_registry();
     let user_message_time = chrono::Utc::now();
     let mut messages = entities::prelude::Message::find()
         .filter(entities::message::Column::SessionId.eq(session_id))
         .all(db)
         .await?;
     messages.sort_by_key(|x| x.create_time);
-    let mut chat_history: Vec<Message> = messages
-        .iter()
-        .map(|x| Message {
-            role: role_transform(&x.role),
-            content: x.content.clone(),
-            ..Default::default()
-        })
-        .collect();
+    let session = entities::prelude::Session::find_by_id(session_id)
+        .one(db)
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("Session not found"))?;
+    //长期记忆属于用户，在该用户的所有会话中共用
+    let user_id = session.user_id;
+    let settings = SessionSettings::from_model(&session);
+    let session_endpoint = settings.chat_endpoint(chat_endpoint).await?;
+    let context = Context {
+        session_id,
+        chat_endpoint: session_endpoint.clone(),
+        embedding_endpoint: embedding_endpoint.clone(),
+        db: db.clone(),
+        confirmer,
+    };
+    let mut function_registry = get_function_registry();
+    if let Some(enabled_functions) = &settings.enabled_functions {
+        function_registry.retain_enabled(enabled_functions);
+    }
+    let has_documents = tokio::fs::try_exists(session_text_path(session_id))
+        .await
+        .unwrap_or(false)
+        || !attached_knowledge_bases(db, session_id).await?.is_empty();
+    function_registry.retain_available(has_documents);
+    let context_config = ContextConfig::load(CONTEXT_CONFIG_PATH)?;
+    //系统人设同样占用输入长度
+    let system_tokens = settings.system_prompt.as_deref().map_or(0, estimate_tokens);
+    let chat = ChatClient {
+        endpoint: session_endpoint,
+        budget: context_config
+            .input_budget(settings.model_name())
+            .saturating_sub(system_tokens),
+        options: settings.chat_options(),
+    };
+    //只原样保留最近几轮对话，滑出窗口的对话合并到会话的摘要中
+    let window = select_window(&messages, session.summarized_message_id, &context_config);
+    let mut history_summary = session.history_summary.clone();
+    if let Some(last) = window.evicted.last() {
+        //摘要请求发给会话使用的模型，分批大小按同一个模型的输入上限计算
+        let summary = update_summary(
+            &chat.endpoint,
+            history_summary.as_deref(),
+            &window.evicted,
+            &context_config,
+            chat.budget,
+        )
+        .await?;
+        let mut session: entities::session::ActiveModel = session.into();
+        session.history_summary = Set(Some(summary.clone()));
+        session.summarized_message_id = Set(Some(last.message_id));
+        session.update(db).await?;
+        history_summary = Some(summary);
+    }
+    let mut chat_history: Vec<Message> = history_summary
+        .as_deref()
+        .map(summary_messages)
+        .unwrap_or_default();
+    chat_history.extend(window.recent.iter().map(|x| Message {
+        role: role_transform(&x.role),
+        content: x.content.clone(),
+        ..Default::default()
+    }));
+    let memories = recall(db, embedding_endpoint, user_id, message, RECALL_TOP_K).await?;
     let response = single_step(
         message,
-        &mut chat_history,
-        chat_endpoint,
+        &format_memories(&memories),
+        &chat_history,
+        &chat,
         &function_registry,
         3,
         &context,
@@ -242,17 +381,136 @@ pub async fn reply(
     entities::prelude::Message::insert(assistant_message)
         .exec(db)
         .await?;
+    //自动提取的记忆没有经过用户确认，只在会话设置中开启了auto_memory时执行
+    if !settings.auto_memory() {
+        return Ok(response);
+    }
+    //提取记忆需要再调用一次大模型，放在后台执行，不影响本轮回复
+    let db = db.clone();
+    let chat_endpoint = chat_endpoint.clone();
+    let embedding_endpoint = embedding_endpoint.clone();
+    let message = message.to_string();
+    tokio::spawn(async move {
+        let result = extract_memories(
+            &db,
+            &chat_endpoint,
+            &embedding_endpoint,
+            session_id,
+            &message,
+        )
+        .await;
+        match result {
+            Ok(memories) if !memories.is_empty() => {
+                info!("extracted memories: {:?}", memories)
+            }
+            Ok(_) => {}
+            Err(e) => warn!("extract memories failed: {:?}", e),
+        }
+    });
     Ok(response)
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::functions::get_function_registry;
+    use super::{execute_function, Execution};
+    use crate::{
+        chunker::estimate_tokens,
+        confirmation::{confirm, ConfirmationResult},
+        context_window::{ContextConfig, CONTEXT_CONFIG_PATH},
+        functions::get_function_registry,
+        CHAT_MODEL,
+    };
+    use std::cell::Cell;
+
     #[test]
     fn test_generate_request() {
         let message = "你好".to_string();
         let function_registry = get_function_registry();
-        let request = super::generate_request(&message, &function_registry).unwrap();
+        let request = super::generate_request(&message, "（无）", &function_registry).unwrap();
         println!("{}", request);
     }
+
+    //函数全部启用、会话中有文档时，选择函数的提示词也要放得进8k模型的输入上限
+    #[test]
+    fn test_select_prompt_fits_budget() {
+        let config = ContextConfig::load(CONTEXT_CONFIG_PATH).unwrap();
+        let request = super::generate_request("你好", "（无）", &get_function_registry()).unwrap();
+        let tokens = estimate_tokens(&request);
+        let budget = config.input_budget(CHAT_MODEL);
+        assert!(tokens < budget, "{} >= {}", tokens, budget);
+    }
+
+    #[tokio::test]
+    async fn test_execute_function_requires_confirmation() {
+        let registry = get_function_registry();
+        let parameters = serde_json::json!({"content": "我喜欢喝绿茶"});
+        //remember有副作用，被拒绝、超时或者没有确认渠道时都不执行
+        for result in [
+            ConfirmationResult::Rejected,
+            ConfirmationResult::TimedOut,
+            ConfirmationResult::Unavailable,
+        ] {
+            let executed = Cell::new(false);
+            let execution = execute_function(
+                &registry,
+                "remember",
+                parameters.clone(),
+                String::new(),
+                1,
+                |request| {
+                    assert_eq!(request.function, "remember");
+                    assert_eq!(request.parameters, parameters);
+                    async move { result }
+                },
+                |_| {
+                    executed.set(true);
+                    Ok(String::new())
+                },
+            )
+            .await;
+            assert!(matches!(execution, Execution::NotConfirmed(_)));
+            assert!(!executed.get());
+        }
+        //通过HTTP接口发送的消息没有确认渠道
+        let executed = Cell::new(false);
+        let execution = execute_function(
+            &registry,
+            "remember",
+            parameters.clone(),
+            String::new(),
+            1,
+            |request| confirm(None, request),
+            |_| {
+                executed.set(true);
+                Ok(String::new())
+            },
+        )
+        .await;
+        assert!(matches!(execution, Execution::NotConfirmed(_)));
+        assert!(!executed.get());
+
+        let execution = execute_function(
+            &registry,
+            "remember",
+            parameters.clone(),
+            String::new(),
+            1,
+            |_| async { ConfirmationResult::Approved },
+            |_| Ok("已记住".to_string()),
+        )
+        .await;
+        assert!(matches!(execution, Execution::Executed(Ok(x)) if x == "已记住"));
+        //只读的函数不需要确认
+        let execution = execute_function(
+            &registry,
+            "calculator",
+            serde_json::json!({"expression": "1+1"}),
+            String::new(),
+            1,
+            |_| async { panic!("calculator does not require confirmation") },
+            |_| Ok("2".to_string()),
+        )
+        .await;
+        assert!(matches!(execution, Execution::Executed(Ok(x)) if x == "2"));
+    }
 }
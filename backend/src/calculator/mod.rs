@@ -0,0 +1,560 @@
+mod number;
+mod parser;
+mod symbolic;
+mod units;
+
+use anyhow::Result;
+use num_bigint::BigInt;
+use num_rational::BigRational;
+use serde::Serialize;
+
+use number::{check_exact_size, format_decimal, format_fraction, Num};
+use parser::{parse, BinaryOp, Expr, Statement};
+use symbolic::{solve, Poly};
+use units::{display_unit, lookup, Dims, DIMENSIONLESS};
+
+pub const DEFAULT_PRECISION: usize = 20;
+pub const MAX_PRECISION: usize = 100;
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct CalculationResult {
+    //规范化后的表达式，便于后处理时向用户解释
+    pub expression: String,
+    pub result: String,
+    //结果经过舍入时给出分数或根式形式
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exact: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub unit: Option<String>,
+    pub approximate: bool,
+}
+
+//求值过程中的值：带量纲的数，或者含未知数的多项式
+#[derive(Debug, Clone)]
+enum Value {
+    Quantity(Num, Dims),
+    Poly(Poly),
+}
+
+fn unit_name(dims: &Dims) -> String {
+    display_unit(dims).unwrap_or("无单位".to_string())
+}
+
+fn literal(expr: &Expr) -> Option<&str> {
+    match expr {
+        Expr::Number(number) => Some(number),
+        _ => None,
+    }
+}
+
+//"20 degC"、"-40 °F" 表示绝对温度，先加上零点偏移再换算成开尔文；单独出现的温度单位视为温差
+fn absolute_temperature(expr: &Expr) -> Result<Option<(Num, Dims)>> {
+    let (negative, expr) = match expr {
+        Expr::Neg(inner) => (true, &**inner),
+        expr => (false, expr),
+    };
+    let Expr::Binary(BinaryOp::Mul, left, right) = expr else {
+        return Ok(None);
+    };
+    let (negative, number) = match &**left {
+        Expr::Neg(inner) => (!negative, literal(inner)),
+        left => (negative, literal(left)),
+    };
+    let (Some(number), Expr::Ident(name)) = (number, &**right) else {
+        return Ok(None);
+    };
+    let Some(unit) = lookup(name) else {
+        return Ok(None);
+    };
+    let Some(offset) = &unit.offset else {
+        return Ok(None);
+    };
+    let mut value = Num::parse(number)?;
+    if negative {
+        value = value.neg();
+    }
+    Ok(Some((value.add(offset).mul(&unit.factor)?, unit.dims)))
+}
+
+fn eval(expr: &Expr) -> Result<Value> {
+    if let Some((value, dims)) = absolute_temperature(expr)? {
+        return Ok(Value::Quantity(value, dims));
+    }
+    match expr {
+        Expr::Number(number) => Ok(Value::Quantity(Num::parse(number)?, DIMENSIONLESS)),
+        Expr::Ident(name) => ident(name),
+        Expr::Neg(inner) => Ok(match eval(inner)? {
+            Value::Quantity(value, dims) => Value::Quantity(value.neg(), dims),
+            Value::Poly(poly) => Value::Poly(poly.neg()),
+        }),
+        Expr::Binary(op, left, right) => binary(*op, eval(left)?, eval(right)?),
+        Expr::Call(name, args) => {
+            let args = args
+                .iter()
+                .map(|x| match eval(x)? {
+                    Value::Quantity(value, dims) => Ok((value, dims)),
+                    Value::Poly(_) => Err(anyhow::anyhow!("函数 {} 的参数不能含未知数", name)),
+                })
+                .collect::<Result<Vec<_>>>()?;
+            call(name, args)
+        }
+        Expr::Factorial(inner) => match eval(inner)? {
+            Value::Quantity(value, DIMENSIONLESS) => {
+                Ok(Value::Quantity(factorial(&value)?, DIMENSIONLESS))
+            }
+            _ => Err(anyhow::anyhow!("阶乘只支持无单位的整数")),
+        },
+    }
+}
+
+//标识符依次按常量、单位、未知数解析，未知数只能是单个字母
+fn ident(name: &str) -> Result<Value> {
+    match name {
+        "pi" | "π" => {
+            return Ok(Value::Quantity(
+                Num::Approx(std::f64::consts::PI),
+                DIMENSIONLESS,
+            ))
+        }
+        "e" => {
+            return Ok(Value::Quantity(
+                Num::Approx(std::f64::consts::E),
+                DIMENSIONLESS,
+            ))
+        }
+        _ => {}
+    }
+    if let Some(unit) = lookup(name) {
+        return Ok(Value::Quantity(unit.factor.clone(), unit.dims));
+    }
+    let mut chars = name.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) if c.is_alphabetic() => Ok(Value::Poly(Poly::variable(name))),
+        _ => Err(anyhow::anyhow!("未知的单位或常量: {}", name)),
+    }
+}
+
+fn to_poly(value: Value, variable: &str) -> Result<Poly> {
+    match value {
+        Value::Poly(poly) => Ok(poly),
+        Value::Quantity(value, DIMENSIONLESS) => Ok(Poly::constant(variable, value)),
+        Value::Quantity(_, dims) => Err(anyhow::anyhow!(
+            "含未知数的表达式不支持单位: {}",
+            unit_name(&dims)
+        )),
+    }
+}
+
+fn combine(a: &Dims, b: &Dims, sign: i32) -> Dims {
+    let mut dims = *a;
+    for (dim, other) in dims.iter_mut().zip(b) {
+        *dim += sign * other;
+    }
+    dims
+}
+
+fn binary(op: BinaryOp, left: Value, right: Value) -> Result<Value> {
+    let (left, right) = match (left, right) {
+        (Value::Quantity(a, da), Value::Quantity(b, db)) => {
+            return quantity_binary(op, a, da, b, db)
+        }
+        (left, right) => (left, right),
+    };
+    let variable = match (&left, &right) {
+        (Value::Poly(poly), _) | (_, Value::Poly(poly)) => poly.variable.clone(),
+        _ => unreachable!(),
+    };
+    if op == BinaryOp::Pow {
+        let exponent = match right {
+            Value::Quantity(value, DIMENSIONLESS) => value.as_integer(),
+            _ => None,
+        };
+        let exponent = exponent.ok_or_else(|| anyhow::anyhow!("含未知数时指数必须是整数"))?;
+        return Ok(Value::Poly(to_poly(left, &variable)?.powi(exponent)?));
+    }
+    let (left, right) = (to_poly(left, &variable)?, to_poly(right, &variable)?);
+    let poly = match op {
+        BinaryOp::Add => left.add(&right)?,
+        BinaryOp::Sub => left.sub(&right)?,
+        BinaryOp::Mul => left.mul(&right)?,
+        BinaryOp::Div => left.div(&right)?,
+        BinaryOp::Pow => unreachable!(),
+    };
+    Ok(Value::Poly(poly))
+}
+
+fn quantity_binary(op: BinaryOp, a: Num, da: Dims, b: Num, db: Dims) -> Result<Value> {
+    match op {
+        BinaryOp::Add | BinaryOp::Sub => {
+            if da != db {
+                return Err(anyhow::anyhow!(
+                    "单位不一致，无法相加减: {} 和 {}",
+                    unit_name(&da),
+                    unit_name(&db)
+                ));
+            }
+            let value = match op {
+                BinaryOp::Add => a.add(&b),
+                _ => a.sub(&b),
+            };
+            Ok(Value::Quantity(value, da))
+        }
+        BinaryOp::Mul => Ok(Value::Quantity(a.mul(&b)?, combine(&da, &db, 1))),
+        BinaryOp::Div => Ok(Value::Quantity(a.div(&b)?, combine(&da, &db, -1))),
+        BinaryOp::Pow => {
+            if db != DIMENSIONLESS {
+                return Err(anyhow::anyhow!("指数不能带单位"));
+            }
+            if let Some(exponent) = b.as_integer() {
+                let dims = da.map(|x| x * exponent as i32);
+                return Ok(Value::Quantity(a.powi(exponent)?, dims));
+            }
+            if b == Num::ratio(1, 2) {
+                return call("sqrt", vec![(a, da)]);
+            }
+            if da != DIMENSIONLESS {
+                return Err(anyhow::anyhow!("带单位的数只能做整数次幂或开平方"));
+            }
+            Ok(Value::Quantity(
+                real(a.to_f64().powf(b.to_f64()))?,
+                DIMENSIONLESS,
+            ))
+        }
+    }
+}
+
+fn real(value: f64) -> Result<Num> {
+    match value.is_finite() {
+        true => Ok(Num::Approx(value)),
+        false => Err(anyhow::anyhow!("结果超出了定义域或不是有限的数")),
+    }
+}
+
+fn factorial(value: &Num) -> Result<Num> {
+    match value.as_integer().filter(|x| (0..=1000).contains(x)) {
+        Some(n) => check_exact_size(BigRational::from_integer(
+            (1..=n).map(BigInt::from).product(),
+        )),
+        None => Err(anyhow::anyhow!("阶乘只支持0到1000的整数")),
+    }
+}
+
+//整数次方根，能整除的有理数保持精确
+fn root(value: &Num, dims: &Dims, n: i32) -> Result<Value> {
+    if dims.iter().any(|x| x % n != 0) {
+        return Err(anyhow::anyhow!("{} 不能开{}次方", unit_name(dims), n));
+    }
+    let dims = dims.map(|x| x / n);
+    if n == 2 {
+        return Ok(Value::Quantity(value.sqrt()?, dims));
+    }
+    if let Num::Exact(exact) = value {
+        let numerator = exact.numer().cbrt();
+        let denominator = exact.denom().cbrt();
+        let candidate = BigRational::new(numerator, denominator);
+        if &(&candidate * &candidate * &candidate) == exact {
+            return Ok(Value::Quantity(Num::Exact(candidate), dims));
+        }
+    }
+    Ok(Value::Quantity(Num::Approx(value.to_f64().cbrt()), dims))
+}
+
+fn round(value: &Num, name: &str) -> Num {
+    match value {
+        Num::Exact(exact) => Num::Exact(match name {
+            "floor" => exact.floor(),
+            "ceil" => exact.ceil(),
+            _ => exact.round(),
+        }),
+        Num::Approx(approx) => Num::Approx(match name {
+            "floor" => approx.floor(),
+            "ceil" => approx.ceil(),
+            _ => approx.round(),
+        }),
+    }
+}
+
+fn call(name: &str, args: Vec<(Num, Dims)>) -> Result<Value> {
+    let expected = match name {
+        "min" | "max" => args.len().max(1),
+        "log" => args.len().clamp(1, 2),
+        _ => 1,
+    };
+    if args.len() != expected {
+        return Err(anyhow::anyhow!("函数 {} 的参数个数不正确", name));
+    }
+    let (value, dims) = &args[0];
+    match name {
+        "sqrt" => return root(value, dims, 2),
+        "cbrt" => return root(value, dims, 3),
+        "abs" => return Ok(Value::Quantity(value.abs(), *dims)),
+        "floor" | "ceil" | "round" => return Ok(Value::Quantity(round(value, name), *dims)),
+        "min" | "max" => {
+            if args.iter().any(|(_, x)| x != dims) {
+                return Err(anyhow::anyhow!("函数 {} 的参数单位不一致", name));
+            }
+            let ordering = match name {
+                "min" => std::cmp::Ordering::Less,
+                _ => std::cmp::Ordering::Greater,
+            };
+            let mut best = value.clone();
+            for (value, _) in &args[1..] {
+                if value.cmp(&best) == ordering {
+                    best = value.clone();
+                }
+            }
+            return Ok(Value::Quantity(best, *dims));
+        }
+        _ => {}
+    }
+    if args.iter().any(|(_, x)| *x != DIMENSIONLESS) {
+        return Err(anyhow::anyhow!("函数 {} 的参数不能带单位", name));
+    }
+    if name == "factorial" {
+        return Ok(Value::Quantity(factorial(value)?, DIMENSIONLESS));
+    }
+    let x = value.to_f64();
+    let result = match name {
+        "exp" => x.exp(),
+        "ln" => x.ln(),
+        "log10" => x.log10(),
+        "log2" => x.log2(),
+        "log" if args.len() == 2 => x.ln() / args[1].0.to_f64().ln(),
+        "log" => x.ln(),
+        "sin" => x.sin(),
+        "cos" => x.cos(),
+        "tan" => x.tan(),
+        "asin" => x.asin(),
+        "acos" => x.acos(),
+        "atan" => x.atan(),
+        "sinh" => x.sinh(),
+        "cosh" => x.cosh(),
+        "tanh" => x.tanh(),
+        _ => return Err(anyhow::anyhow!("不支持的函数: {}", name)),
+    };
+    //sin(pi)之类的结果只有浮点误差，视为0
+    let result = match name {
+        "sin" | "cos" | "tan" if result.abs() < 1e-15 => 0.0,
+        _ => result,
+    };
+    Ok(Value::Quantity(real(result)?, DIMENSIONLESS))
+}
+
+fn unit_names(expr: &Expr, names: &mut Vec<String>) {
+    match expr {
+        Expr::Ident(name) if lookup(name).is_some() => names.push(name.clone()),
+        Expr::Neg(inner) | Expr::Factorial(inner) => unit_names(inner, names),
+        Expr::Binary(_, left, right) => {
+            unit_names(left, names);
+            unit_names(right, names);
+        }
+        Expr::Call(_, args) => args.iter().for_each(|x| unit_names(x, names)),
+        _ => {}
+    }
+}
+
+//表达式中只用了同一个单位且结果量纲与之相同时（如 3 km * 2）结果沿用该单位，否则用国际单位制表示
+fn preferred_unit(expr: &Expr, dims: &Dims) -> Option<String> {
+    let mut names = Vec::new();
+    unit_names(expr, &mut names);
+    let first = names.first()?;
+    let unit = lookup(first)?;
+    let absolute = unit.offset.is_none() || absolute_temperature(expr).ok()??.1 == unit.dims;
+    let dimensioned = *dims != DIMENSIONLESS;
+    (names.iter().all(|x| x == first) && unit.dims == *dims && dimensioned && absolute)
+        .then(|| first.clone())
+}
+
+fn to_unit(value: &Num, name: &str) -> Result<Num> {
+    let unit = lookup(name).ok_or_else(|| anyhow::anyhow!("未知的单位: {}", name))?;
+    let value = value.div(&unit.factor)?;
+    Ok(match &unit.offset {
+        Some(offset) => value.sub(offset),
+        None => value,
+    })
+}
+
+fn quantity_result(
+    expression: String,
+    value: &Num,
+    unit: Option<String>,
+    precision: usize,
+) -> Result<CalculationResult> {
+    let (result, rounded) = format_decimal(value, precision)?;
+    let exact = match value {
+        Num::Exact(value) if rounded => Some(format_fraction(value)),
+        _ => None,
+    };
+    Ok(CalculationResult {
+        expression,
+        result,
+        exact,
+        unit,
+        approximate: rounded,
+    })
+}
+
+//计算表达式、单位换算（"60 km/h to m/s"）、化简（"(x+1)^2"）或解一元一次、二次方程（"x^2 - 2 = 0"）
+pub fn calculate(expression: &str, precision: usize) -> Result<CalculationResult> {
+    let precision = precision.clamp(1, MAX_PRECISION);
+    let statement = parse(expression)?;
+    let normalized = statement.to_string();
+    match &statement {
+        Statement::Expr(expr) => match eval(expr)? {
+            Value::Poly(poly) if poly.degree() > 0 => Ok(CalculationResult {
+                expression: normalized,
+                result: poly.format(precision)?,
+                exact: None,
+                unit: None,
+                approximate: !poly.is_exact(),
+            }),
+            Value::Poly(poly) => {
+                quantity_result(normalized, &poly.coefficients[0], None, precision)
+            }
+            Value::Quantity(value, dims) => match preferred_unit(expr, &dims) {
+                Some(name) => {
+                    let value = to_unit(&value, &name)?;
+                    quantity_result(normalized, &value, Some(name), precision)
+                }
+                None => quantity_result(normalized, &value, display_unit(&dims), precision),
+            },
+        },
+        Statement::Convert(expr, target) => {
+            let Value::Quantity(value, dims) = eval(expr)? else {
+                return Err(anyhow::anyhow!("含未知数的表达式不能换算单位"));
+            };
+            let unit = target.to_string().replace(' ', "");
+            //换算到摄氏度、华氏度时需要处理零点偏移
+            let offset_unit = match target {
+                Expr::Ident(name) => lookup(name).filter(|x| x.offset.is_some()),
+                _ => None,
+            };
+            let (value, target_dims) = match offset_unit {
+                Some(target_unit) => (to_unit(&value, &unit)?, target_unit.dims),
+                None => match eval(target)? {
+                    Value::Quantity(factor, target_dims) => (value.div(&factor)?, target_dims),
+                    Value::Poly(_) => return Err(anyhow::anyhow!("未知的单位: {}", unit)),
+                },
+            };
+            if dims != target_dims {
+                return Err(anyhow::anyhow!(
+                    "无法把 {} 换算为 {}",
+                    unit_name(&dims),
+                    unit
+                ));
+            }
+            quantity_result(normalized, &value, Some(unit), precision)
+        }
+        Statement::Equation(left, right) => {
+            let (left, right) = (eval(left)?, eval(right)?);
+            let variable = match (&left, &right) {
+                (Value::Poly(poly), _) | (_, Value::Poly(poly)) => poly.variable.clone(),
+                (Value::Quantity(a, da), Value::Quantity(b, db)) => {
+                    let holds = da == db && a.equals(b);
+                    return Ok(CalculationResult {
+                        expression: normalized,
+                        result: match holds {
+                            true => "成立".to_string(),
+                            false => "不成立".to_string(),
+                        },
+                        exact: None,
+                        unit: None,
+                        approximate: false,
+                    });
+                }
+            };
+            let poly = to_poly(left, &variable)?.sub(&to_poly(right, &variable)?)?;
+            let solution = solve(&poly, precision)?;
+            Ok(CalculationResult {
+                expression: normalized,
+                result: solution.result,
+                exact: solution.exact,
+                unit: None,
+                approximate: solution.approximate,
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::calculate;
+
+    fn result(expression: &str) -> (String, Option<String>) {
+        let result = calculate(expression, 20).unwrap();
+        (result.result, result.unit)
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        assert_eq!(result("1/3").0, "0.33333333333333333333");
+        assert_eq!(calculate("1/3", 20).unwrap().exact.unwrap(), "1/3");
+        assert_eq!(result("0.1 + 0.2").0, "0.3");
+        assert_eq!(result("2^100").0, "1.2676506002282294015e30");
+        assert_eq!(result("sqrt(16) + 3!").0, "10");
+        assert_eq!(result("sin(30 deg)").0, "0.5");
+        assert_eq!(result("log(8, 2)").0, "3");
+        assert!(calculate("1/0", 20).is_err());
+        let precise = calculate("sqrt(2)", 5).unwrap();
+        assert_eq!(precise.result, "1.4142");
+        assert!(precise.approximate);
+    }
+
+    #[test]
+    fn test_size_limits() {
+        for expression in [
+            "1e99999999",
+            "1e-2000000",
+            "(10^10000)^10000",
+            "(10^20000)*(10^20000)",
+        ] {
+            assert!(calculate(expression, 20).is_err(), "{}", expression);
+        }
+        assert_eq!(result("1e300 * 1e300").0, "1e600");
+        assert!(calculate("1000!", 20).is_ok());
+    }
+
+    #[test]
+    fn test_units() {
+        assert_eq!(
+            result("60 km/h to m/s"),
+            ("16.666666666666666667".to_string(), Some("m/s".to_string()))
+        );
+        assert_eq!(
+            result("3 km * 2"),
+            ("6".to_string(), Some("km".to_string()))
+        );
+        assert_eq!(
+            result("3 km + 500 m"),
+            ("3500".to_string(), Some("m".to_string()))
+        );
+        assert_eq!(
+            result("100 degC to degF"),
+            ("212".to_string(), Some("degF".to_string()))
+        );
+        assert_eq!(result("-40 degC to degF").0, "-40");
+        assert_eq!(
+            result("10 N * 2 m"),
+            ("20".to_string(), Some("J".to_string()))
+        );
+        assert!(calculate("1 m + 1 s", 20).is_err());
+        assert!(calculate("1 m to s", 20).is_err());
+    }
+
+    #[test]
+    fn test_symbolic() {
+        assert_eq!(result("(x+1)^2").0, "x^2 + 2x + 1");
+        assert_eq!(result("(x^2-1)/(x-1)").0, "x + 1");
+        assert_eq!(result("2x + 3 = 7").0, "x = 2");
+        assert_eq!(result("x^2 = 2x + 3").0, "x = -1, x = 3");
+        assert_eq!(result("1 + 1 = 2").0, "成立");
+        //精确值按有理数比较，近似值按相对误差比较
+        assert_eq!(result("0.1 + 0.2 = 0.3").0, "成立");
+        assert_eq!(result("1e-20 = 2e-20").0, "不成立");
+        assert_eq!(result("1e-20 = 1e-20").0, "成立");
+        assert_eq!(result("sqrt(2)^2 * 1e20 = 2e20").0, "成立");
+        assert_eq!(result("sqrt(2) * 1e-20 = 1.4142e-20").0, "不成立");
+        let normalized = calculate("2x+3=7", 20).unwrap().expression;
+        assert_eq!(normalized, "2 * x + 3 = 7");
+    }
+}
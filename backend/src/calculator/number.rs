@@ -0,0 +1,293 @@
+use anyhow::Result;
+use num_bigint::BigInt;
+use num_rational::BigRational;
+use num_traits::{One, Signed, ToPrimitive, Zero};
+
+//字面量中科学计数法的指数上限
+const MAX_LITERAL_EXPONENT: i32 = 1000;
+//精确结果分子分母的总位数上限，约3万位十进制数字，避免超大的数耗尽内存和CPU
+const MAX_EXACT_BITS: u64 = 100_000;
+
+//整数和有限小数的运算用有理数精确计算，开方、三角函数等无法精确表示的结果退化为f64
+#[derive(Debug, Clone, PartialEq)]
+pub enum Num {
+    Exact(BigRational),
+    Approx(f64),
+}
+
+impl Num {
+    pub fn integer(value: i64) -> Self {
+        Num::Exact(BigRational::from_integer(value.into()))
+    }
+
+    pub fn ratio(numerator: i64, denominator: i64) -> Self {
+        Num::Exact(BigRational::new(numerator.into(), denominator.into()))
+    }
+
+    //把 "12.5e-3" 这样的字面量解析为精确的有理数
+    pub fn parse(text: &str) -> Result<Self> {
+        let (mantissa, exponent) = match text.find(['e', 'E']) {
+            Some(index) => (&text[..index], text[index + 1..].parse::<i32>()?),
+            None => (text, 0),
+        };
+        if exponent.abs() > MAX_LITERAL_EXPONENT {
+            return Err(anyhow::anyhow!(
+                "指数过大: {}，科学计数法的指数不能超过{}",
+                text,
+                MAX_LITERAL_EXPONENT
+            ));
+        }
+        let (integer, fraction) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+        let digits: BigInt = format!("{}{}", integer, fraction).parse()?;
+        let scale = exponent - fraction.len() as i32;
+        Ok(Num::Exact(BigRational::from_integer(digits) * pow10(scale)))
+    }
+
+    pub fn to_f64(&self) -> f64 {
+        match self {
+            Num::Exact(value) => value.to_f64().unwrap_or(f64::NAN),
+            Num::Approx(value) => *value,
+        }
+    }
+
+    pub fn is_exact(&self) -> bool {
+        matches!(self, Num::Exact(_))
+    }
+
+    pub fn is_zero(&self) -> bool {
+        match self {
+            Num::Exact(value) => value.is_zero(),
+            Num::Approx(value) => *value == 0.0,
+        }
+    }
+
+    pub fn is_negative(&self) -> bool {
+        match self {
+            Num::Exact(value) => value.is_negative(),
+            Num::Approx(value) => *value < 0.0,
+        }
+    }
+
+    //两边都是精确值时直接比较，有近似值时按相对误差比较，与数值的大小无关
+    pub fn equals(&self, other: &Num) -> bool {
+        match (self, other) {
+            (Num::Exact(a), Num::Exact(b)) => a == b,
+            (a, b) => {
+                let (a, b) = (a.to_f64(), b.to_f64());
+                a == b || (a - b).abs() <= 1e-12 * a.abs().max(b.abs())
+            }
+        }
+    }
+
+    pub fn as_integer(&self) -> Option<i64> {
+        match self {
+            Num::Exact(value) if value.is_integer() => value.to_integer().to_i64(),
+            Num::Approx(value) if value.fract() == 0.0 && value.abs() < 1e15 => Some(*value as i64),
+            _ => None,
+        }
+    }
+
+    fn binary(
+        &self,
+        other: &Num,
+        exact: impl Fn(&BigRational, &BigRational) -> BigRational,
+        approx: impl Fn(f64, f64) -> f64,
+    ) -> Num {
+        match (self, other) {
+            (Num::Exact(a), Num::Exact(b)) => Num::Exact(exact(a, b)),
+            (a, b) => Num::Approx(approx(a.to_f64(), b.to_f64())),
+        }
+    }
+
+    pub fn add(&self, other: &Num) -> Num {
+        self.binary(other, |a, b| a + b, |a, b| a + b)
+    }
+
+    pub fn sub(&self, other: &Num) -> Num {
+        self.binary(other, |a, b| a - b, |a, b| a - b)
+    }
+
+    pub fn mul(&self, other: &Num) -> Result<Num> {
+        if let (Num::Exact(a), Num::Exact(b)) = (self, other) {
+            if bits(a) + bits(b) > MAX_EXACT_BITS {
+                return Err(anyhow::anyhow!("结果过大"));
+            }
+        }
+        Ok(self.binary(other, |a, b| a * b, |a, b| a * b))
+    }
+
+    pub fn div(&self, other: &Num) -> Result<Num> {
+        if other.is_zero() {
+            return Err(anyhow::anyhow!("除数不能为0"));
+        }
+        Ok(self.binary(other, |a, b| a / b, |a, b| a / b))
+    }
+
+    pub fn neg(&self) -> Num {
+        match self {
+            Num::Exact(value) => Num::Exact(-value),
+            Num::Approx(value) => Num::Approx(-value),
+        }
+    }
+
+    pub fn abs(&self) -> Num {
+        match self {
+            Num::Exact(value) => Num::Exact(value.abs()),
+            Num::Approx(value) => Num::Approx(value.abs()),
+        }
+    }
+
+    //整数次幂保持精确，指数过大时拒绝计算
+    pub fn powi(&self, exponent: i64) -> Result<Num> {
+        if exponent.abs() > 10000 {
+            return Err(anyhow::anyhow!("指数过大"));
+        }
+        match self {
+            Num::Exact(value) => {
+                if value.is_zero() && exponent < 0 {
+                    return Err(anyhow::anyhow!("除数不能为0"));
+                }
+                //计算前按位数估算结果的大小
+                if bits(value).saturating_mul(exponent.unsigned_abs()) > MAX_EXACT_BITS {
+                    return Err(anyhow::anyhow!("结果过大"));
+                }
+                Ok(Num::Exact(value.pow(exponent as i32)))
+            }
+            Num::Approx(value) => Ok(Num::Approx(value.powi(exponent as i32))),
+        }
+    }
+
+    //有理数是完全平方数时开方仍然精确
+    pub fn sqrt(&self) -> Result<Num> {
+        if self.is_negative() {
+            return Err(anyhow::anyhow!("不能对负数开平方"));
+        }
+        if let Num::Exact(value) = self {
+            let numerator = value.numer().sqrt();
+            let denominator = value.denom().sqrt();
+            if &(&numerator * &numerator) == value.numer()
+                && &(&denominator * &denominator) == value.denom()
+            {
+                return Ok(Num::Exact(BigRational::new(numerator, denominator)));
+            }
+        }
+        Ok(Num::Approx(self.to_f64().sqrt()))
+    }
+
+    pub fn cmp(&self, other: &Num) -> std::cmp::Ordering {
+        match (self, other) {
+            (Num::Exact(a), Num::Exact(b)) => a.cmp(b),
+            (a, b) => a.to_f64().total_cmp(&b.to_f64()),
+        }
+    }
+}
+
+//有理数的分子分母的有效位数之和
+fn bits(value: &BigRational) -> u64 {
+    value.numer().bits() + value.denom().bits()
+}
+
+//精确结果超过位数上限时报错
+pub fn check_exact_size(value: BigRational) -> Result<Num> {
+    match bits(&value) > MAX_EXACT_BITS {
+        true => Err(anyhow::anyhow!("结果过大")),
+        false => Ok(Num::Exact(value)),
+    }
+}
+
+fn pow10(exponent: i32) -> BigRational {
+    let base = BigRational::from_integer(10.into());
+    if exponent >= 0 {
+        base.pow(exponent)
+    } else {
+        BigRational::one() / base.pow(-exponent)
+    }
+}
+
+//有理数写成分数，整数不带分母
+pub fn format_fraction(value: &BigRational) -> String {
+    if value.is_integer() {
+        value.numer().to_string()
+    } else {
+        format!("{}/{}", value.numer(), value.denom())
+    }
+}
+
+//保留precision位有效数字，返回文本和是否经过了舍入
+pub fn format_decimal(value: &Num, precision: usize) -> Result<(String, bool)> {
+    let (value, precision, mut rounded) = match value {
+        Num::Exact(value) => (value.clone(), precision, false),
+        Num::Approx(value) => {
+            let exact = BigRational::from_float(*value)
+                .ok_or_else(|| anyhow::anyhow!("结果不是有限的数"))?;
+            (exact, precision.min(15), true)
+        }
+    };
+    if value.is_zero() {
+        return Ok(("0".to_string(), rounded));
+    }
+    let sign = if value.is_negative() { "-" } else { "" };
+    let value = value.abs();
+    //value = m × 10^k，1 <= m < 10
+    let mut exponent =
+        value.numer().to_string().len() as i32 - value.denom().to_string().len() as i32;
+    if value < pow10(exponent) {
+        exponent -= 1;
+    }
+    let scaled = &value * pow10(precision as i32 - 1 - exponent);
+    let mut digits = scaled.round().to_integer();
+    rounded |= !scaled.is_integer();
+    if digits.to_string().len() > precision {
+        digits /= 10;
+        exponent += 1;
+    }
+    let digits = digits.to_string();
+    let text = if (-6..precision as i32).contains(&exponent) {
+        let text = if exponent >= 0 {
+            let point = exponent as usize + 1;
+            let padded = format!("{:0<width$}", digits, width = point);
+            format!("{}.{}", &padded[..point], &padded[point..])
+        } else {
+            format!("0.{}{}", "0".repeat((-exponent - 1) as usize), digits)
+        };
+        text.trim_end_matches('0').trim_end_matches('.').to_string()
+    } else {
+        let mantissa = format!("{}.{}", &digits[..1], &digits[1..]);
+        format!(
+            "{}e{}",
+            mantissa.trim_end_matches('0').trim_end_matches('.'),
+            exponent
+        )
+    };
+    Ok((format!("{}{}", sign, text), rounded))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{format_decimal, Num};
+
+    #[test]
+    fn test_format_decimal() {
+        let format = |value: &Num, precision| format_decimal(value, precision).unwrap();
+        assert_eq!(
+            format(&Num::parse("0.1").unwrap(), 20),
+            ("0.1".to_string(), false)
+        );
+        assert_eq!(format(&Num::ratio(1, 3), 5), ("0.33333".to_string(), true));
+        assert_eq!(format(&Num::ratio(-2, 3), 3), ("-0.667".to_string(), true));
+        assert_eq!(format(&Num::integer(1200), 20), ("1200".to_string(), false));
+        assert_eq!(
+            format(&Num::parse("9.9996").unwrap(), 4),
+            ("10".to_string(), true)
+        );
+        assert_eq!(
+            format(&Num::parse("1.5e30").unwrap(), 20),
+            ("1.5e30".to_string(), false)
+        );
+        assert_eq!(
+            format(&Num::parse("0.000012").unwrap(), 20),
+            ("0.000012".to_string(), false)
+        );
+        assert_eq!(format(&Num::Approx(0.5), 20), ("0.5".to_string(), true));
+    }
+}
@@ -0,0 +1,413 @@
+use anyhow::Result;
+use std::fmt;
+
+use super::units::lookup;
+
+//表达式树的最大深度：括号、负号、乘方的嵌套以及连续的运算都计入，
+//递归下降解析、求值和释放都是递归的，过深的输入会耗尽栈空间
+const MAX_DEPTH: usize = 256;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(String),
+    Ident(String),
+    Op(char),
+    //to、in、-> 表示单位换算
+    Convert,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Pow,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Number(String),
+    //单位、常量或未知数，求值时按名称区分
+    Ident(String),
+    Neg(Box<Expr>),
+    Binary(BinaryOp, Box<Expr>, Box<Expr>),
+    Call(String, Vec<Expr>),
+    Factorial(Box<Expr>),
+}
+
+//整个输入可以是表达式、单位换算或方程
+#[derive(Debug, Clone, PartialEq)]
+pub enum Statement {
+    Expr(Expr),
+    Convert(Expr, Expr),
+    Equation(Expr, Expr),
+}
+
+fn tokenize(input: &str) -> Result<Vec<Token>> {
+    let chars: Vec<char> = input.chars().collect();
+    let mut tokens = Vec::new();
+    let mut index = 0;
+    while index < chars.len() {
+        let c = chars[index];
+        if c.is_whitespace() {
+            index += 1;
+        } else if c.is_ascii_digit()
+            || (c == '.' && chars.get(index + 1).is_some_and(|x| x.is_ascii_digit()))
+        {
+            let start = index;
+            while index < chars.len() && (chars[index].is_ascii_digit() || chars[index] == '.') {
+                index += 1;
+            }
+            //科学计数法的e后面必须是数字或带符号的数字，否则e是常数或单位的一部分
+            if index < chars.len() && (chars[index] == 'e' || chars[index] == 'E') {
+                let sign = matches!(chars.get(index + 1), Some('+') | Some('-')) as usize;
+                if chars
+                    .get(index + 1 + sign)
+                    .is_some_and(|x| x.is_ascii_digit())
+                {
+                    index += 1 + sign;
+                    while index < chars.len() && chars[index].is_ascii_digit() {
+                        index += 1;
+                    }
+                }
+            }
+            tokens.push(Token::Number(chars[start..index].iter().collect()));
+        } else if c.is_alphabetic() || c == '_' || c == '°' {
+            let start = index;
+            while index < chars.len()
+                && (chars[index].is_alphanumeric() || chars[index] == '_' || chars[index] == '°')
+            {
+                index += 1;
+            }
+            let ident: String = chars[start..index].iter().collect();
+            match ident.as_str() {
+                "to" | "in" => tokens.push(Token::Convert),
+                _ => tokens.push(Token::Ident(ident)),
+            }
+        } else if c == '-' && chars.get(index + 1) == Some(&'>') {
+            tokens.push(Token::Convert);
+            index += 2;
+        } else if c == '*' && chars.get(index + 1) == Some(&'*') {
+            tokens.push(Token::Op('^'));
+            index += 2;
+        } else {
+            let op = match c {
+                '×' | '·' => '*',
+                '÷' => '/',
+                '（' => '(',
+                '）' => ')',
+                '，' => ',',
+                c => c,
+            };
+            if !"+-*/^(),=!".contains(op) {
+                return Err(anyhow::anyhow!("无法识别的字符: {}", c));
+            }
+            tokens.push(Token::Op(op));
+            index += 1;
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+    depth: usize,
+}
+
+impl Parser {
+    //出错时整个解析失败，只有成功返回时才需要恢复深度
+    fn enter(&mut self) -> Result<()> {
+        self.depth += 1;
+        if self.depth > MAX_DEPTH {
+            return Err(anyhow::anyhow!("表达式嵌套超过{}层", MAX_DEPTH));
+        }
+        Ok(())
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn eat(&mut self, op: char) -> bool {
+        if self.peek() == Some(&Token::Op(op)) {
+            self.position += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, op: char) -> Result<()> {
+        match self.eat(op) {
+            true => Ok(()),
+            false => Err(anyhow::anyhow!("缺少 {}", op)),
+        }
+    }
+
+    fn statement(&mut self) -> Result<Statement> {
+        let left = self.sum()?;
+        let statement = match self.peek() {
+            Some(Token::Convert) => {
+                self.position += 1;
+                Statement::Convert(left, self.sum()?)
+            }
+            Some(Token::Op('=')) => {
+                self.position += 1;
+                Statement::Equation(left, self.sum()?)
+            }
+            _ => Statement::Expr(left),
+        };
+        match self.peek() {
+            None => Ok(statement),
+            Some(token) => Err(anyhow::anyhow!("表达式中有多余的内容: {:?}", token)),
+        }
+    }
+
+    fn sum(&mut self) -> Result<Expr> {
+        let depth = self.depth;
+        let mut left = self.product()?;
+        loop {
+            let op = if self.eat('+') {
+                BinaryOp::Add
+            } else if self.eat('-') {
+                BinaryOp::Sub
+            } else {
+                self.depth = depth;
+                return Ok(left);
+            };
+            self.enter()?;
+            left = Expr::Binary(op, Box::new(left), Box::new(self.product()?));
+        }
+    }
+
+    //数字后面直接跟单位、未知数或括号时视为相乘，如 5 km、2x、3(x+1)
+    fn product(&mut self) -> Result<Expr> {
+        let depth = self.depth;
+        let mut left = self.unary()?;
+        loop {
+            let op = if self.eat('*') {
+                BinaryOp::Mul
+            } else if self.eat('/') {
+                BinaryOp::Div
+            } else if matches!(
+                self.peek(),
+                Some(Token::Number(_)) | Some(Token::Ident(_)) | Some(Token::Op('('))
+            ) {
+                BinaryOp::Mul
+            } else {
+                self.depth = depth;
+                return Ok(left);
+            };
+            self.enter()?;
+            left = Expr::Binary(op, Box::new(left), Box::new(self.unary()?));
+        }
+    }
+
+    fn unary(&mut self) -> Result<Expr> {
+        self.enter()?;
+        let expr = if self.eat('-') {
+            Expr::Neg(Box::new(self.unary()?))
+        } else if self.eat('+') {
+            self.unary()?
+        } else {
+            self.power()?
+        };
+        self.depth -= 1;
+        Ok(expr)
+    }
+
+    //乘方是右结合的，指数可以带负号
+    fn power(&mut self) -> Result<Expr> {
+        let base = self.postfix()?;
+        if self.eat('^') {
+            return Ok(Expr::Binary(
+                BinaryOp::Pow,
+                Box::new(base),
+                Box::new(self.unary()?),
+            ));
+        }
+        Ok(base)
+    }
+
+    fn postfix(&mut self) -> Result<Expr> {
+        let depth = self.depth;
+        let mut expr = self.primary()?;
+        while self.eat('!') {
+            self.enter()?;
+            expr = Expr::Factorial(Box::new(expr));
+        }
+        self.depth = depth;
+        Ok(expr)
+    }
+
+    fn primary(&mut self) -> Result<Expr> {
+        match self.next() {
+            Some(Token::Number(number)) => Ok(Expr::Number(number)),
+            Some(Token::Ident(name)) => {
+                if !self.eat('(') {
+                    return Ok(Expr::Ident(name));
+                }
+                let mut args = Vec::new();
+                if !self.eat(')') {
+                    loop {
+                        args.push(self.sum()?);
+                        if self.eat(')') {
+                            break;
+                        }
+                        self.expect(',')?;
+                    }
+                }
+                Ok(Expr::Call(name, args))
+            }
+            Some(Token::Op('(')) => {
+                let expr = self.sum()?;
+                self.expect(')')?;
+                Ok(expr)
+            }
+            Some(token) => Err(anyhow::anyhow!("表达式不完整，意外的 {:?}", token)),
+            None => Err(anyhow::anyhow!("表达式不完整")),
+        }
+    }
+}
+
+pub fn parse(input: &str) -> Result<Statement> {
+    let tokens = tokenize(input)?;
+    if tokens.is_empty() {
+        return Err(anyhow::anyhow!("表达式为空"));
+    }
+    Parser {
+        tokens,
+        position: 0,
+        depth: 0,
+    }
+    .statement()
+}
+
+fn precedence(expr: &Expr) -> u8 {
+    match expr {
+        Expr::Binary(BinaryOp::Add | BinaryOp::Sub, _, _) => 1,
+        Expr::Binary(BinaryOp::Mul | BinaryOp::Div, _, _) => 2,
+        Expr::Neg(_) => 3,
+        Expr::Binary(BinaryOp::Pow, _, _) => 4,
+        _ => 5,
+    }
+}
+
+struct Wrapped<'a>(&'a Expr, bool);
+
+impl fmt::Display for Wrapped<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.1 {
+            true => write!(f, "({})", self.0),
+            false => write!(f, "{}", self.0),
+        }
+    }
+}
+
+//规范化的表达式：显式写出运算符，只保留必要的括号；数字乘单位写成 5 km
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Expr::Number(number) => write!(f, "{}", number),
+            Expr::Ident(name) => write!(f, "{}", name),
+            Expr::Neg(expr) => write!(f, "-{}", Wrapped(expr, precedence(expr) < 3)),
+            Expr::Factorial(expr) => write!(f, "{}!", Wrapped(expr, precedence(expr) < 5)),
+            Expr::Call(name, args) => {
+                let args: Vec<String> = args.iter().map(|x| x.to_string()).collect();
+                write!(f, "{}({})", name, args.join(", "))
+            }
+            Expr::Binary(BinaryOp::Mul, left, right)
+                if matches!(**left, Expr::Number(_))
+                    && matches!(&**right, Expr::Ident(name) if lookup(name).is_some()) =>
+            {
+                write!(f, "{} {}", left, right)
+            }
+            Expr::Binary(op, left, right) => {
+                let (symbol, level) = match op {
+                    BinaryOp::Add => ("+", 1),
+                    BinaryOp::Sub => ("-", 1),
+                    BinaryOp::Mul => ("*", 2),
+                    BinaryOp::Div => ("/", 2),
+                    BinaryOp::Pow => ("^", 4),
+                };
+                //左结合的运算右侧同级时需要括号，乘方相反
+                let (left_wrap, right_wrap) = match op {
+                    BinaryOp::Pow => (precedence(left) <= level, precedence(right) < level),
+                    _ => (precedence(left) < level, precedence(right) <= level),
+                };
+                let spaced = level == 1 || level == 2;
+                write!(
+                    f,
+                    "{}{}{}{}{}",
+                    Wrapped(left, left_wrap),
+                    if spaced { " " } else { "" },
+                    symbol,
+                    if spaced { " " } else { "" },
+                    Wrapped(right, right_wrap)
+                )
+            }
+        }
+    }
+}
+
+impl fmt::Display for Statement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Statement::Expr(expr) => write!(f, "{}", expr),
+            Statement::Convert(expr, unit) => write!(f, "{} to {}", expr, unit),
+            Statement::Equation(left, right) => write!(f, "{} = {}", left, right),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse, MAX_DEPTH};
+
+    #[test]
+    fn test_parse() {
+        let normalized = |input: &str| parse(input).unwrap().to_string();
+        assert_eq!(normalized("1+2*3"), "1 + 2 * 3");
+        assert_eq!(normalized("(1+2)*3"), "(1 + 2) * 3");
+        assert_eq!(normalized("60km/h -> m/s"), "60 km / h to m / s");
+        assert_eq!(normalized("2x^2-3x=5"), "2 * x^2 - 3 * x = 5");
+        assert_eq!(normalized("-2^2"), "-2^2");
+        assert_eq!(normalized("(-2)^2"), "(-2)^2");
+        assert_eq!(normalized("2^3^2"), "2^3^2");
+        assert_eq!(normalized("1-(2-3)"), "1 - (2 - 3)");
+        assert_eq!(normalized("sqrt(2)×3"), "sqrt(2) * 3");
+        assert_eq!(normalized("1.5e3 m in km"), "1.5e3 m to km");
+        assert!(parse("1 +").is_err());
+        assert!(parse("2 $ 3").is_err());
+    }
+
+    //过深的嵌套返回错误，而不是耗尽栈空间
+    #[test]
+    fn test_parse_depth_limit() {
+        let nested = |depth: usize| format!("{}1{}", "(".repeat(depth), ")".repeat(depth));
+        assert!(parse(&nested(100)).is_ok());
+        for input in [
+            nested(50_000),
+            format!("{}1", "-".repeat(50_000)),
+            format!("{}2", "2^".repeat(50_000)),
+            format!("{}1", "1+".repeat(50_000)),
+            format!("2{}", "!".repeat(50_000)),
+            format!("{}1{}", "sqrt(".repeat(50_000), ")".repeat(50_000)),
+        ] {
+            let error = parse(&input).unwrap_err();
+            assert!(
+                error.to_string().contains(&MAX_DEPTH.to_string()),
+                "{}",
+                error
+            );
+        }
+    }
+}
@@ -0,0 +1,332 @@
+use anyhow::Result;
+use num_traits::Signed;
+
+use super::number::{format_decimal, format_fraction, Num};
+
+//单个未知数的多项式，coefficients[i]是i次项的系数
+#[derive(Debug, Clone, PartialEq)]
+pub struct Poly {
+    pub variable: String,
+    pub coefficients: Vec<Num>,
+}
+
+impl Poly {
+    pub fn variable(name: &str) -> Self {
+        Self {
+            variable: name.to_string(),
+            coefficients: vec![Num::integer(0), Num::integer(1)],
+        }
+    }
+
+    pub fn constant(variable: &str, value: Num) -> Self {
+        Self {
+            variable: variable.to_string(),
+            coefficients: vec![value],
+        }
+    }
+
+    fn new(variable: &str, mut coefficients: Vec<Num>) -> Self {
+        while coefficients.len() > 1 && coefficients.last().is_some_and(|x| x.is_zero()) {
+            coefficients.pop();
+        }
+        Self {
+            variable: variable.to_string(),
+            coefficients,
+        }
+    }
+
+    pub fn degree(&self) -> usize {
+        self.coefficients.len() - 1
+    }
+
+    fn coefficient(&self, degree: usize) -> Num {
+        self.coefficients
+            .get(degree)
+            .cloned()
+            .unwrap_or(Num::integer(0))
+    }
+
+    fn check_variable(&self, other: &Poly) -> Result<()> {
+        match self.variable == other.variable {
+            true => Ok(()),
+            false => Err(anyhow::anyhow!(
+                "只支持含一个未知数的表达式，出现了 {} 和 {}",
+                self.variable,
+                other.variable
+            )),
+        }
+    }
+
+    pub fn add(&self, other: &Poly) -> Result<Poly> {
+        self.check_variable(other)?;
+        let length = self.coefficients.len().max(other.coefficients.len());
+        let coefficients = (0..length)
+            .map(|x| self.coefficient(x).add(&other.coefficient(x)))
+            .collect();
+        Ok(Poly::new(&self.variable, coefficients))
+    }
+
+    pub fn neg(&self) -> Poly {
+        Poly::new(
+            &self.variable,
+            self.coefficients.iter().map(|x| x.neg()).collect(),
+        )
+    }
+
+    pub fn sub(&self, other: &Poly) -> Result<Poly> {
+        self.add(&other.neg())
+    }
+
+    pub fn mul(&self, other: &Poly) -> Result<Poly> {
+        self.check_variable(other)?;
+        let mut coefficients = vec![Num::integer(0); self.degree() + other.degree() + 1];
+        for (i, a) in self.coefficients.iter().enumerate() {
+            for (j, b) in other.coefficients.iter().enumerate() {
+                coefficients[i + j] = coefficients[i + j].add(&a.mul(b)?);
+            }
+        }
+        Ok(Poly::new(&self.variable, coefficients))
+    }
+
+    pub fn powi(&self, exponent: i64) -> Result<Poly> {
+        if !(0..=64).contains(&exponent) {
+            return Err(anyhow::anyhow!("多项式只支持0到64次的整数次幂"));
+        }
+        let mut result = Poly::constant(&self.variable, Num::integer(1));
+        for _ in 0..exponent {
+            result = result.mul(self)?;
+        }
+        Ok(result)
+    }
+
+    //多项式长除法，只有能整除时才化简，例如 (x^2 - 1)/(x - 1) = x + 1
+    pub fn div(&self, other: &Poly) -> Result<Poly> {
+        self.check_variable(other)?;
+        let divisor = other.coefficient(other.degree());
+        if divisor.is_zero() {
+            return Err(anyhow::anyhow!("除数不能为0"));
+        }
+        if self.degree() < other.degree() {
+            return Err(anyhow::anyhow!("无法化简为多项式"));
+        }
+        let mut remainder = self.coefficients.clone();
+        let mut quotient = vec![Num::integer(0); self.degree() - other.degree() + 1];
+        for index in (0..quotient.len()).rev() {
+            let factor = remainder[index + other.degree()].div(&divisor)?;
+            for (offset, coefficient) in other.coefficients.iter().enumerate() {
+                remainder[index + offset] =
+                    remainder[index + offset].sub(&factor.mul(coefficient)?);
+            }
+            quotient[index] = factor;
+        }
+        if remainder.iter().any(|x| !is_negligible(x)) {
+            return Err(anyhow::anyhow!("无法化简为多项式"));
+        }
+        Ok(Poly::new(&self.variable, quotient))
+    }
+
+    pub fn is_exact(&self) -> bool {
+        self.coefficients.iter().all(|x| x.is_exact())
+    }
+
+    //按降幂排列，如 x^2 + 2x - 1/2
+    pub fn format(&self, precision: usize) -> Result<String> {
+        let mut text = String::new();
+        for degree in (0..=self.degree()).rev() {
+            let coefficient = self.coefficient(degree);
+            if coefficient.is_zero() && !(degree == 0 && text.is_empty()) {
+                continue;
+            }
+            let sign = match (text.is_empty(), coefficient.is_negative()) {
+                (true, true) => "-",
+                (true, false) => "",
+                (false, true) => " - ",
+                (false, false) => " + ",
+            };
+            let magnitude = format_coefficient(&coefficient.abs(), precision)?;
+            let term = match degree {
+                0 => String::new(),
+                1 => self.variable.clone(),
+                degree => format!("{}^{}", self.variable, degree),
+            };
+            let coefficient = match (degree, magnitude.as_str()) {
+                (0, _) => magnitude,
+                (_, "1") => String::new(),
+                (_, magnitude) if magnitude.contains('/') => format!("({})", magnitude),
+                (_, magnitude) => magnitude.to_string(),
+            };
+            text.push_str(&format!("{}{}{}", sign, coefficient, term));
+        }
+        Ok(text)
+    }
+}
+
+fn is_negligible(value: &Num) -> bool {
+    match value {
+        Num::Exact(_) => value.is_zero(),
+        Num::Approx(value) => value.abs() < 1e-12,
+    }
+}
+
+fn format_coefficient(value: &Num, precision: usize) -> Result<String> {
+    match value {
+        Num::Exact(value) => Ok(format_fraction(value)),
+        value => Ok(format_decimal(value, precision)?.0),
+    }
+}
+
+//方程的解：result是小数形式，exact是根式或分数形式（与result相同时为None）
+#[derive(Debug, Clone, PartialEq)]
+pub struct Solution {
+    pub result: String,
+    pub exact: Option<String>,
+    pub approximate: bool,
+}
+
+fn format_root(value: &Num, precision: usize) -> Result<(String, Option<String>, bool)> {
+    let (decimal, rounded) = format_decimal(value, precision)?;
+    let exact = match value {
+        Num::Exact(value) if rounded => Some(format_fraction(value)),
+        _ => None,
+    };
+    Ok((decimal, exact, rounded))
+}
+
+fn roots(variable: &str, values: &[Num], precision: usize) -> Result<Solution> {
+    let mut results = Vec::new();
+    let mut exacts = Vec::new();
+    let mut approximate = false;
+    for value in values {
+        let (decimal, exact, rounded) = format_root(value, precision)?;
+        approximate |= rounded;
+        results.push(format!("{} = {}", variable, decimal));
+        exacts.push(format!("{} = {}", variable, exact.unwrap_or(decimal)));
+    }
+    let result = results.join(", ");
+    let exact = exacts.join(", ");
+    Ok(Solution {
+        exact: (exact != result).then_some(exact),
+        result,
+        approximate,
+    })
+}
+
+//求解 poly = 0，支持一次和二次方程；二次方程判别式不是完全平方数时给出根式和近似值
+pub fn solve(poly: &Poly, precision: usize) -> Result<Solution> {
+    let variable = &poly.variable;
+    match poly.degree() {
+        0 => Ok(Solution {
+            result: match is_negligible(&poly.coefficient(0)) {
+                true => format!("恒成立，{} 可以取任意值", variable),
+                false => "无解".to_string(),
+            },
+            exact: None,
+            approximate: false,
+        }),
+        1 => {
+            let root = poly.coefficient(0).neg().div(&poly.coefficient(1))?;
+            roots(variable, &[root], precision)
+        }
+        2 => {
+            let (a, b, c) = (
+                poly.coefficient(2),
+                poly.coefficient(1),
+                poly.coefficient(0),
+            );
+            //x = p ± √q，p = -b/2a，q = (b^2 - 4ac)/4a^2
+            let two_a = a.mul(&Num::integer(2))?;
+            let p = b.neg().div(&two_a)?;
+            let discriminant = b.mul(&b)?.sub(&a.mul(&c)?.mul(&Num::integer(4))?);
+            let q = discriminant.div(&two_a.mul(&two_a)?)?;
+            if is_negligible(&q) {
+                return roots(variable, &[p], precision);
+            }
+            let root = q.abs().sqrt()?;
+            let radical = match &q {
+                Num::Exact(value) => format!("√({})", format_fraction(&value.abs())),
+                _ => format!("√{}", format_decimal(&q.abs(), precision)?.0),
+            };
+            let prefix = match &p {
+                p if p.is_zero() => "±".to_string(),
+                Num::Exact(value) => format!("{} ± ", format_fraction(value)),
+                p => format!("{} ± ", format_decimal(p, precision)?.0),
+            };
+            if q.is_negative() {
+                //复数根
+                let (imaginary, _) = format_decimal(&root, precision)?;
+                let result = match p.is_zero() {
+                    true => format!("{v} = {i}i, {v} = -{i}i", v = variable, i = imaginary),
+                    false => {
+                        let (real, _) = format_decimal(&p, precision)?;
+                        format!(
+                            "{v} = {r} + {i}i, {v} = {r} - {i}i",
+                            v = variable,
+                            r = real,
+                            i = imaginary
+                        )
+                    }
+                };
+                return Ok(Solution {
+                    result,
+                    exact: Some(format!("{} = {}{}i", variable, prefix, radical)),
+                    approximate: true,
+                });
+            }
+            let mut values = vec![p.sub(&root), p.add(&root)];
+            values.sort_by(|a, b| a.cmp(b));
+            let mut solution = roots(variable, &values, precision)?;
+            if !root.is_exact() {
+                solution.exact = Some(format!("{} = {}{}", variable, prefix, radical));
+            }
+            Ok(solution)
+        }
+        _ => Err(anyhow::anyhow!("暂不支持三次及以上的方程")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{solve, Poly};
+    use crate::calculator::number::Num;
+
+    #[test]
+    fn test_poly() {
+        let x = Poly::variable("x");
+        let one = Poly::constant("x", Num::integer(1));
+        let square = x.add(&one).unwrap().powi(2).unwrap();
+        assert_eq!(square.format(20).unwrap(), "x^2 + 2x + 1");
+        let quotient = square.div(&x.add(&one).unwrap()).unwrap();
+        assert_eq!(quotient.format(20).unwrap(), "x + 1");
+        assert!(square.div(&x.sub(&one).unwrap()).is_err());
+        let half = Poly::constant("x", Num::ratio(-1, 2));
+        assert_eq!(x.mul(&half).unwrap().format(20).unwrap(), "-(1/2)x");
+        assert!(x.add(&Poly::variable("y")).is_err());
+    }
+
+    #[test]
+    fn test_solve() {
+        let equation = |coefficients: &[i64]| Poly {
+            variable: "x".to_string(),
+            coefficients: coefficients.iter().map(|x| Num::integer(*x)).collect(),
+        };
+        assert_eq!(
+            solve(&equation(&[-7, 3]), 20).unwrap().result,
+            "x = 2.3333333333333333333"
+        );
+        assert_eq!(
+            solve(&equation(&[-7, 3]), 20).unwrap().exact.unwrap(),
+            "x = 7/3"
+        );
+        assert_eq!(
+            solve(&equation(&[-3, 2, 1]), 20).unwrap().result,
+            "x = -3, x = 1"
+        );
+        let irrational = solve(&equation(&[-1, -1, 1]), 5).unwrap();
+        assert_eq!(irrational.result, "x = -0.61803, x = 1.618");
+        assert_eq!(irrational.exact.unwrap(), "x = 1/2 ± √(5/4)");
+        let complex = solve(&equation(&[1, 0, 1]), 5).unwrap();
+        assert_eq!(complex.result, "x = 1i, x = -1i");
+        assert_eq!(solve(&equation(&[1]), 20).unwrap().result, "无解");
+        assert!(solve(&equation(&[1, 0, 0, 1]), 20).is_err());
+    }
+}
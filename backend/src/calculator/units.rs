@@ -0,0 +1,234 @@
+use lazy_static::lazy_static;
+use std::collections::HashMap;
+
+use super::number::Num;
+
+//量纲依次为长度(m)、质量(kg)、时间(s)、电流(A)、温度(K)、物质的量(mol)、发光强度(cd)
+pub type Dims = [i32; 7];
+
+pub const DIMENSIONLESS: Dims = [0; 7];
+const BASE_UNITS: [&str; 7] = ["m", "kg", "s", "A", "K", "mol", "cd"];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Unit {
+    //换算成国际单位制基本单位的系数
+    pub factor: Num,
+    pub dims: Dims,
+    //摄氏度、华氏度等有零点偏移的温度单位：开尔文 = (数值 + offset) × factor
+    pub offset: Option<Num>,
+}
+
+const L: Dims = [1, 0, 0, 0, 0, 0, 0];
+const M: Dims = [0, 1, 0, 0, 0, 0, 0];
+const T: Dims = [0, 0, 1, 0, 0, 0, 0];
+const AREA: Dims = [2, 0, 0, 0, 0, 0, 0];
+const VOLUME: Dims = [3, 0, 0, 0, 0, 0, 0];
+const SPEED: Dims = [1, 0, -1, 0, 0, 0, 0];
+const FORCE: Dims = [1, 1, -2, 0, 0, 0, 0];
+const ENERGY: Dims = [2, 1, -2, 0, 0, 0, 0];
+const POWER: Dims = [2, 1, -3, 0, 0, 0, 0];
+const PRESSURE: Dims = [-1, 1, -2, 0, 0, 0, 0];
+const FREQUENCY: Dims = [0, 0, -1, 0, 0, 0, 0];
+const CURRENT: Dims = [0, 0, 0, 1, 0, 0, 0];
+const CHARGE: Dims = [0, 0, 1, 1, 0, 0, 0];
+const VOLTAGE: Dims = [2, 1, -3, -1, 0, 0, 0];
+const RESISTANCE: Dims = [2, 1, -3, -2, 0, 0, 0];
+const TEMPERATURE: Dims = [0, 0, 0, 0, 1, 0, 0];
+const AMOUNT: Dims = [0, 0, 0, 0, 0, 1, 0];
+const LUMINOUS: Dims = [0, 0, 0, 0, 0, 0, 1];
+
+fn exact(names: &[&'static str], factor: &str, dims: Dims) -> Vec<(&'static str, Unit)> {
+    let factor = Num::parse(factor).unwrap();
+    names
+        .iter()
+        .map(|name| {
+            (
+                *name,
+                Unit {
+                    factor: factor.clone(),
+                    dims,
+                    offset: None,
+                },
+            )
+        })
+        .collect()
+}
+
+lazy_static! {
+    static ref UNITS: HashMap<&'static str, Unit> = {
+        let mut units = Vec::new();
+        //长度
+        units.extend(exact(&["m", "meter", "米"], "1", L));
+        units.extend(exact(&["km", "kilometer", "公里", "千米"], "1000", L));
+        units.extend(exact(&["cm", "厘米"], "0.01", L));
+        units.extend(exact(&["mm", "毫米"], "0.001", L));
+        units.extend(exact(&["um", "μm", "微米"], "1e-6", L));
+        units.extend(exact(&["nm", "纳米"], "1e-9", L));
+        units.extend(exact(&["inch", "英寸"], "0.0254", L));
+        units.extend(exact(&["ft", "foot", "feet", "英尺"], "0.3048", L));
+        units.extend(exact(&["yd", "yard"], "0.9144", L));
+        units.extend(exact(&["mi", "mile", "英里"], "1609.344", L));
+        units.extend(exact(&["nmi", "海里"], "1852", L));
+        units.extend(exact(&["里"], "500", L));
+        //质量
+        units.extend(exact(&["kg", "千克", "公斤"], "1", M));
+        units.extend(exact(&["g", "gram", "克"], "0.001", M));
+        units.extend(exact(&["mg", "毫克"], "1e-6", M));
+        units.extend(exact(&["tonne", "吨"], "1000", M));
+        units.extend(exact(&["lb", "pound", "磅"], "0.45359237", M));
+        units.extend(exact(&["oz", "ounce", "盎司"], "0.028349523125", M));
+        units.extend(exact(&["斤"], "0.5", M));
+        units.extend(exact(&["两"], "0.05", M));
+        //时间
+        units.extend(exact(&["s", "sec", "second", "秒"], "1", T));
+        units.extend(exact(&["ms"], "0.001", T));
+        units.extend(exact(&["us", "μs"], "1e-6", T));
+        units.extend(exact(&["ns"], "1e-9", T));
+        units.extend(exact(&["min", "minute", "分钟"], "60", T));
+        units.extend(exact(&["h", "hr", "hour", "小时"], "3600", T));
+        units.extend(exact(&["day", "天"], "86400", T));
+        units.extend(exact(&["week", "周"], "604800", T));
+        units.extend(exact(&["year", "yr", "年"], "31557600", T));
+        //面积、体积
+        units.extend(exact(&["ha", "公顷"], "10000", AREA));
+        units.extend(exact(&["acre"], "4046.8564224", AREA));
+        units.extend(exact(&["L", "liter", "升"], "0.001", VOLUME));
+        units.extend(exact(&["mL", "ml", "毫升"], "1e-6", VOLUME));
+        units.extend(exact(&["gal", "gallon"], "0.003785411784", VOLUME));
+        //速度
+        units.extend(exact(&["mph"], "0.44704", SPEED));
+        units.extend(exact(&["knot", "节"], "1852", SPEED).into_iter().map(|(name, mut unit)| {
+            unit.factor = unit.factor.div(&Num::integer(3600)).unwrap();
+            (name, unit)
+        }));
+        //力、能量、功率、压强、频率
+        units.extend(exact(&["N", "newton", "牛"], "1", FORCE));
+        units.extend(exact(&["kN"], "1000", FORCE));
+        units.extend(exact(&["lbf"], "4.4482216152605", FORCE));
+        units.extend(exact(&["J", "joule", "焦耳"], "1", ENERGY));
+        units.extend(exact(&["kJ"], "1000", ENERGY));
+        units.extend(exact(&["cal", "卡"], "4.184", ENERGY));
+        units.extend(exact(&["kcal", "千卡"], "4184", ENERGY));
+        units.extend(exact(&["Wh"], "3600", ENERGY));
+        units.extend(exact(&["kWh"], "3600000", ENERGY));
+        units.extend(exact(&["eV"], "1.602176634e-19", ENERGY));
+        units.extend(exact(&["W", "watt", "瓦"], "1", POWER));
+        units.extend(exact(&["kW", "千瓦"], "1000", POWER));
+        units.extend(exact(&["MW"], "1e6", POWER));
+        units.extend(exact(&["hp"], "745.69987158227022", POWER));
+        units.extend(exact(&["Pa", "pascal", "帕"], "1", PRESSURE));
+        units.extend(exact(&["kPa"], "1000", PRESSURE));
+        units.extend(exact(&["MPa"], "1e6", PRESSURE));
+        units.extend(exact(&["bar"], "100000", PRESSURE));
+        units.extend(exact(&["atm"], "101325", PRESSURE));
+        units.extend(exact(&["psi"], "6894.757293168361", PRESSURE));
+        units.extend(exact(&["mmHg"], "133.322387415", PRESSURE));
+        units.extend(exact(&["Hz", "hertz", "赫兹"], "1", FREQUENCY));
+        units.extend(exact(&["kHz"], "1000", FREQUENCY));
+        units.extend(exact(&["MHz"], "1e6", FREQUENCY));
+        units.extend(exact(&["GHz"], "1e9", FREQUENCY));
+        //电学
+        units.extend(exact(&["A", "ampere", "安培"], "1", CURRENT));
+        units.extend(exact(&["mA"], "0.001", CURRENT));
+        units.extend(exact(&["C", "coulomb", "库仑"], "1", CHARGE));
+        units.extend(exact(&["mAh"], "3.6", CHARGE));
+        units.extend(exact(&["V", "volt", "伏"], "1", VOLTAGE));
+        units.extend(exact(&["ohm", "Ω", "欧姆"], "1", RESISTANCE));
+        //温度、物质的量、发光强度
+        units.extend(exact(&["K", "kelvin", "开尔文"], "1", TEMPERATURE));
+        units.extend(exact(&["mol"], "1", AMOUNT));
+        units.extend(exact(&["cd"], "1", LUMINOUS));
+        for name in ["degC", "°C", "celsius", "摄氏度"] {
+            units.push((
+                name,
+                Unit {
+                    factor: Num::integer(1),
+                    dims: TEMPERATURE,
+                    offset: Some(Num::parse("273.15").unwrap()),
+                },
+            ));
+        }
+        for name in ["degF", "°F", "fahrenheit", "华氏度"] {
+            units.push((
+                name,
+                Unit {
+                    factor: Num::ratio(5, 9),
+                    dims: TEMPERATURE,
+                    offset: Some(Num::parse("459.67").unwrap()),
+                },
+            ));
+        }
+        //角度是无量纲的，度换算成弧度
+        units.extend(exact(&["rad", "弧度"], "1", DIMENSIONLESS));
+        for name in ["deg", "°"] {
+            units.push((
+                name,
+                Unit {
+                    factor: Num::Approx(std::f64::consts::PI / 180.0),
+                    dims: DIMENSIONLESS,
+                    offset: None,
+                },
+            ));
+        }
+        units.into_iter().collect()
+    };
+}
+
+pub fn lookup(name: &str) -> Option<&'static Unit> {
+    UNITS.get(name)
+}
+
+//没有指定换算目标时，结果用国际单位制表示，常见的导出单位用其名称
+pub fn display_unit(dims: &Dims) -> Option<String> {
+    if *dims == DIMENSIONLESS {
+        return None;
+    }
+    let named = [
+        (FORCE, "N"),
+        (ENERGY, "J"),
+        (POWER, "W"),
+        (PRESSURE, "Pa"),
+        (FREQUENCY, "Hz"),
+        (CHARGE, "C"),
+        (VOLTAGE, "V"),
+        (RESISTANCE, "ohm"),
+    ];
+    if let Some((_, name)) = named.iter().find(|(x, _)| x == dims) {
+        return Some(name.to_string());
+    }
+    let part = |index: usize, power: i32| match power {
+        1 => BASE_UNITS[index].to_string(),
+        power => format!("{}^{}", BASE_UNITS[index], power),
+    };
+    let numerator: Vec<String> = (0..7)
+        .filter(|&x| dims[x] > 0)
+        .map(|x| part(x, dims[x]))
+        .collect();
+    let denominator: Vec<String> = (0..7)
+        .filter(|&x| dims[x] < 0)
+        .map(|x| part(x, -dims[x]))
+        .collect();
+    let numerator = match numerator.is_empty() {
+        true => "1".to_string(),
+        false => numerator.join("*"),
+    };
+    Some(match denominator.len() {
+        0 => numerator,
+        1 => format!("{}/{}", numerator, denominator[0]),
+        _ => format!("{}/({})", numerator, denominator.join("*")),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{display_unit, lookup, FORCE, SPEED};
+
+    #[test]
+    fn test_units() {
+        assert_eq!(lookup("km").unwrap().factor.to_f64(), 1000.0);
+        assert!(lookup("x").is_none());
+        assert_eq!(display_unit(&FORCE).unwrap(), "N");
+        assert_eq!(display_unit(&SPEED).unwrap(), "m/s");
+        assert_eq!(display_unit(&[0, 0, -2, 0, 0, 0, 0]).unwrap(), "1/s^2");
+    }
+}
@@ -0,0 +1,350 @@
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+lazy_static! {
+    static ref CHINESE_HEADING: Regex =
+        Regex::new(r"^第[一二三四五六七八九十百千零〇0-9]+([章节部分篇])").unwrap();
+    //多级编号(如 "2.1 背景")，或者单个数字后跟 "." 或 "、"(如 "3. 方法"、"3、方法")；
+    //数字后只有空格的行(如 "2024 年度报告"、表格行 "3 台 5000")不是标题
+    static ref NUMBERED_HEADING: Regex =
+        Regex::new(r"^(?:(\d+(?:\.\d+)+)\.?\s+\S|(\d+)[.、]\s*[^\d\s.])").unwrap();
+}
+
+//编号标题(如 "2.1 背景")最长的字符数，超过则视为正文
+const MAX_NUMBERED_HEADING_LENGTH: usize = 40;
+
+//按照千帆文档的估算方式：token数 ≈ 汉字数 + 英文单词数 * 1.3，其它非空白符号各算一个token
+pub fn estimate_tokens(text: &str) -> usize {
+    let mut cjk = 0usize;
+    let mut words = 0usize;
+    let mut symbols = 0usize;
+    let mut in_word = false;
+    for c in text.chars() {
+        if c.is_ascii_alphanumeric() {
+            if !in_word {
+                words += 1;
+                in_word = true;
+            }
+            continue;
+        }
+        in_word = false;
+        if is_cjk(c) {
+            cjk += 1;
+        } else if !c.is_whitespace() {
+            symbols += 1;
+        }
+    }
+    cjk + (words * 13).div_ceil(10) + symbols
+}
+
+fn is_cjk(c: char) -> bool {
+    matches!(c as u32,
+        0x4E00..=0x9FFF | 0x3400..=0x4DBF | 0x20000..=0x2A6DF | 0xF900..=0xFAFF
+        | 0x3040..=0x30FF | 0xAC00..=0xD7AF)
+}
+
+//判断一行是否为标题，返回标题层级(1为最高级)
+pub fn heading_level(line: &str) -> Option<usize> {
+    let line = line.trim();
+    if line.is_empty() {
+        return None;
+    }
+    if line.starts_with('#') {
+        let level = line.chars().take_while(|x| *x == '#').count();
+        let rest = &line[level..];
+        if level <= 6 && rest.starts_with(' ') && !rest.trim().is_empty() {
+            return Some(level);
+        }
+        return None;
+    }
+    if let Some(captures) = CHINESE_HEADING.captures(line) {
+        if line.chars().count() <= MAX_NUMBERED_HEADING_LENGTH {
+            return match &captures[1] {
+                "节" => Some(2),
+                _ => Some(1),
+            };
+        }
+    }
+    if let Some(captures) = NUMBERED_HEADING.captures(line) {
+        let ends_like_sentence = line.ends_with(['。', '.', '；', ';', '，', ',', '：', ':']);
+        if line.chars().count() <= MAX_NUMBERED_HEADING_LENGTH && !ends_like_sentence {
+            let number = captures.get(1).or(captures.get(2)).unwrap().as_str();
+            return Some(number.split('.').count());
+        }
+    }
+    None
+}
+
+//去掉markdown标题前的 '#'
+pub fn heading_title(line: &str) -> String {
+    line.trim().trim_start_matches('#').trim().to_string()
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub struct ChunkerConfig {
+    pub max_tokens: usize,
+    //相邻两个片段之间重叠的token数，只在同一个章节内重叠
+    pub overlap_tokens: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Chunk {
+    pub text: String,
+    //在原文中的字节偏移 [start, end)
+    pub start: usize,
+    pub end: usize,
+    pub tokens: usize,
+    //片段所在的最近一级标题
+    pub heading: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Unit {
+    start: usize,
+    end: usize,
+    tokens: usize,
+    is_heading: bool,
+}
+
+//先按标题和段落切分，段落过长再按句子切分，句子仍然过长才按字符切分，最后把这些单元尽量装满每个片段
+pub fn split(text: &str, config: &ChunkerConfig) -> Vec<Chunk> {
+    let max_tokens = config.max_tokens.max(1);
+    let units = split_units(text, max_tokens);
+    let mut chunks = Vec::new();
+    let mut current: Vec<Unit> = Vec::new();
+    let mut current_tokens = 0;
+    let mut heading: Option<String> = None;
+    let mut current_heading: Option<String> = None;
+    for unit in units {
+        if unit.is_heading {
+            if !current.is_empty() {
+                chunks.push(make_chunk(text, &current, current_heading.clone()));
+                current.clear();
+                current_tokens = 0;
+            }
+            heading = Some(heading_title(&text[unit.start..unit.end]));
+        }
+        if !current.is_empty() && current_tokens + unit.tokens > max_tokens {
+            chunks.push(make_chunk(text, &current, current_heading.clone()));
+            current = overlap_units(
+                &current,
+                config.overlap_tokens,
+                max_tokens.saturating_sub(unit.tokens),
+            );
+            current_tokens = current.iter().map(|x| x.tokens).sum();
+        }
+        if current.is_empty() {
+            current_heading = heading.clone();
+        }
+        current_tokens += unit.tokens;
+        current.push(unit);
+    }
+    if !current.is_empty() {
+        chunks.push(make_chunk(text, &current, current_heading));
+    }
+    chunks
+}
+
+//取上一个片段末尾的若干单元作为下一个片段的开头
+fn overlap_units(units: &[Unit], overlap_tokens: usize, budget: usize) -> Vec<Unit> {
+    let limit = overlap_tokens.min(budget);
+    let mut tokens = 0;
+    let mut start = units.len();
+    while start > 1 {
+        let unit = units[start - 1];
+        if unit.is_heading || tokens + unit.tokens > limit {
+            break;
+        }
+        tokens += unit.tokens;
+        start -= 1;
+    }
+    units[start..].to_vec()
+}
+
+fn make_chunk(text: &str, units: &[Unit], heading: Option<String>) -> Chunk {
+    let start = units[0].start;
+    let end = units[units.len() - 1].end;
+    let chunk_text = text[start..end].to_string();
+    Chunk {
+        tokens: estimate_tokens(&chunk_text),
+        text: chunk_text,
+        start,
+        end,
+        heading,
+    }
+}
+
+fn split_units(text: &str, max_tokens: usize) -> Vec<Unit> {
+    let mut units = Vec::new();
+    let mut paragraph: Option<(usize, usize)> = None;
+    let mut offset = 0;
+    for line in text.split_inclusive('\n') {
+        let line_start = offset;
+        offset += line.len();
+        let content = line.trim_end_matches(['\n', '\r']);
+        let line_end = line_start + content.len();
+        if content.trim().is_empty() || heading_level(content).is_some() {
+            if let Some((start, end)) = paragraph.take() {
+                push_paragraph(text, start, end, max_tokens, &mut units);
+            }
+            if !content.trim().is_empty() {
+                units.push(Unit {
+                    start: line_start,
+                    end: line_end,
+                    tokens: estimate_tokens(content),
+                    is_heading: true,
+                });
+            }
+            continue;
+        }
+        paragraph = match paragraph {
+            Some((start, _)) => Some((start, line_end)),
+            None => Some((line_start, line_end)),
+        };
+    }
+    if let Some((start, end)) = paragraph {
+        push_paragraph(text, start, end, max_tokens, &mut units);
+    }
+    units
+}
+
+fn push_paragraph(text: &str, start: usize, end: usize, max_tokens: usize, units: &mut Vec<Unit>) {
+    let tokens = estimate_tokens(&text[start..end]);
+    if tokens <= max_tokens {
+        units.push(Unit {
+            start,
+            end,
+            tokens,
+            is_heading: false,
+        });
+        return;
+    }
+    for (sentence_start, sentence_end) in sentence_ranges(text, start, end) {
+        let tokens = estimate_tokens(&text[sentence_start..sentence_end]);
+        if tokens <= max_tokens {
+            units.push(Unit {
+                start: sentence_start,
+                end: sentence_end,
+                tokens,
+                is_heading: false,
+            });
+        } else {
+            hard_split(text, sentence_start, sentence_end, max_tokens, units);
+        }
+    }
+}
+
+//句子在句末标点之后结束，英文句点只有后面跟着空白时才算句末
+fn sentence_ranges(text: &str, start: usize, end: usize) -> Vec<(usize, usize)> {
+    let slice = &text[start..end];
+    let mut ranges = Vec::new();
+    let mut sentence_start = 0;
+    let mut chars = slice.char_indices().peekable();
+    while let Some((index, c)) = chars.next() {
+        let next = chars.peek().map(|x| x.1);
+        let is_end = match c {
+            '。' | '！' | '？' | '；' | '!' | '?' | ';' | '\n' => true,
+            '.' => next.is_none_or(|x| x.is_whitespace()),
+            _ => false,
+        };
+        if is_end {
+            let sentence_end = index + c.len_utf8();
+            if !slice[sentence_start..sentence_end].trim().is_empty() {
+                ranges.push((start + sentence_start, start + sentence_end));
+            }
+            sentence_start = sentence_end;
+        }
+    }
+    if !slice[sentence_start..].trim().is_empty() {
+        ranges.push((start + sentence_start, end));
+    }
+    ranges
+}
+
+//超长的句子按字符切分，尽量在空白处断开以免切断英文单词
+fn hard_split(text: &str, start: usize, end: usize, max_tokens: usize, units: &mut Vec<Unit>) {
+    let mut piece_start = start;
+    let mut last_space: Option<usize> = None;
+    let mut piece_end = start;
+    for (index, c) in text[start..end].char_indices() {
+        let position = start + index;
+        if estimate_tokens(&text[piece_start..position + c.len_utf8()]) > max_tokens
+            && position > piece_start
+        {
+            let split_at = match last_space {
+                Some(space) if space > piece_start => space,
+                _ => position,
+            };
+            units.push(Unit {
+                start: piece_start,
+                end: split_at,
+                tokens: estimate_tokens(&text[piece_start..split_at]),
+                is_heading: false,
+            });
+            piece_start = split_at;
+            last_space = None;
+        }
+        if c.is_whitespace() {
+            last_space = Some(position);
+        }
+        piece_end = position + c.len_utf8();
+    }
+    if piece_end > piece_start {
+        units.push(Unit {
+            start: piece_start,
+            end: piece_end,
+            tokens: estimate_tokens(&text[piece_start..piece_end]),
+            is_heading: false,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{estimate_tokens, heading_level, split, ChunkerConfig};
+
+    #[test]
+    fn test_estimate_tokens() {
+        assert_eq!(estimate_tokens("你好世界"), 4);
+        assert_eq!(estimate_tokens("hello world"), 3);
+        assert_eq!(estimate_tokens("你好, world!"), 2 + 2 + 2);
+    }
+
+    #[test]
+    fn test_heading_level() {
+        assert_eq!(heading_level("## Slide 3: 总结"), Some(2));
+        assert_eq!(heading_level("第三章 实验结果"), Some(1));
+        assert_eq!(heading_level("2.1 研究背景"), Some(2));
+        assert_eq!(heading_level("1. 这是一个很普通的列表项。"), None);
+        assert_eq!(heading_level("#hashtag"), None);
+        assert_eq!(heading_level("3. 实验方法"), Some(1));
+        assert_eq!(heading_level("4、结论"), Some(1));
+        assert_eq!(heading_level("2024 年度报告"), None);
+        assert_eq!(heading_level("3 台 5000"), None);
+        assert_eq!(heading_level("1.5kg 样品"), None);
+    }
+
+    #[test]
+    fn test_split_respects_headings_and_limits() {
+        let text = "# 第一章\n第一句话。第二句话。第三句话。\n\n# 第二章\nThe quick brown fox jumps over the lazy dog. It was fun.";
+        let config = ChunkerConfig {
+            max_tokens: 12,
+            overlap_tokens: 5,
+        };
+        let chunks = split(text, &config);
+        for chunk in chunks.iter() {
+            assert!(chunk.tokens <= config.max_tokens, "{:?}", chunk);
+            assert_eq!(&text[chunk.start..chunk.end], chunk.text);
+        }
+        assert!(chunks[0].text.starts_with("# 第一章"));
+        assert_eq!(chunks[0].heading.as_deref(), Some("第一章"));
+        let second_chapter: Vec<_> = chunks
+            .iter()
+            .filter(|x| x.heading.as_deref() == Some("第二章"))
+            .collect();
+        assert!(second_chapter.len() >= 2);
+        assert!(second_chapter.iter().all(|x| !x.text.contains("第三句话")));
+        assert!(chunks.iter().all(|x| !x.text.contains("qu\n")));
+    }
+}
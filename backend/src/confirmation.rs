@@ -0,0 +1,125 @@
+use std::time::Duration;
+
+use socketioxide::extract::SocketRef;
+use tracing::info;
+
+use crate::data::{ConfirmFunctionReply, ConfirmFunctionRequest};
+
+//等待用户确认的最长时间，超时视为拒绝
+const CONFIRMATION_TIMEOUT: Duration = Duration::from_secs(120);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfirmationResult {
+    Approved,
+    Rejected,
+    TimedOut,
+    //没有可以发起确认的连接，如通过HTTP接口发送的消息
+    Unavailable,
+}
+
+impl ConfirmationResult {
+    //未确认时返回给用户的说明
+    pub fn rejection_message(&self, function_name: &str) -> Option<String> {
+        match self {
+            ConfirmationResult::Approved => None,
+            ConfirmationResult::Rejected => {
+                Some(format!("您取消了{}的执行，没有做任何修改。", function_name))
+            }
+            ConfirmationResult::TimedOut => Some(format!(
+                "等待确认超时，没有执行{}，没有做任何修改。",
+                function_name
+            )),
+            ConfirmationResult::Unavailable => Some(format!(
+                "{}需要您确认后才能执行，请通过实时对话发送该请求。",
+                function_name
+            )),
+        }
+    }
+}
+
+//通过Socket.IO向发起对话的客户端发送confirm_function请求，并等待客户端的ack
+#[derive(Clone)]
+pub struct FunctionConfirmer {
+    socket: SocketRef,
+}
+
+impl FunctionConfirmer {
+    pub fn new(socket: SocketRef) -> Self {
+        Self { socket }
+    }
+
+    pub async fn confirm(&self, request: ConfirmFunctionRequest) -> ConfirmationResult {
+        info!("confirm function: {:?}", request);
+        let ack = self
+            .socket
+            .timeout(CONFIRMATION_TIMEOUT)
+            .emit_with_ack::<_, ConfirmFunctionReply>("confirm_function", request);
+        let ack = match ack {
+            Ok(ack) => ack,
+            Err(e) => {
+                info!("emit confirm_function failed: {:?}", e);
+                return ConfirmationResult::Unavailable;
+            }
+        };
+        match ack.await {
+            Ok(response) if response.data.approved => ConfirmationResult::Approved,
+            Ok(_) => ConfirmationResult::Rejected,
+            Err(socketioxide::AckError::Timeout) => ConfirmationResult::TimedOut,
+            Err(e) => {
+                info!("confirm_function ack failed: {:?}", e);
+                ConfirmationResult::Unavailable
+            }
+        }
+    }
+}
+
+pub async fn confirm(
+    confirmer: Option<&FunctionConfirmer>,
+    request: ConfirmFunctionRequest,
+) -> ConfirmationResult {
+    match confirmer {
+        Some(confirmer) => confirmer.confirm(request).await,
+        None => ConfirmationResult::Unavailable,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{confirm, ConfirmationResult};
+    use crate::data::ConfirmFunctionRequest;
+
+    #[tokio::test]
+    async fn test_confirm_without_confirmer() {
+        let request = ConfirmFunctionRequest {
+            confirmation_id: "1".to_string(),
+            session_id: 1,
+            function: "remember".to_string(),
+            description: String::new(),
+            parameters: serde_json::json!({}),
+            thoughts: String::new(),
+        };
+        assert_eq!(
+            confirm(None, request).await,
+            ConfirmationResult::Unavailable
+        );
+    }
+
+    #[test]
+    fn test_rejection_message() {
+        assert_eq!(
+            ConfirmationResult::Approved.rejection_message("forget"),
+            None
+        );
+        for (result, expected) in [
+            (ConfirmationResult::Rejected, "您取消了forget的执行"),
+            (ConfirmationResult::TimedOut, "等待确认超时，没有执行forget"),
+            (
+                ConfirmationResult::Unavailable,
+                "forget需要您确认后才能执行",
+            ),
+        ] {
+            let message = result.rejection_message("forget").unwrap();
+            assert!(message.starts_with(expected), "{}", message);
+        }
+    }
+}
@@ -0,0 +1,351 @@
+use std::collections::HashMap;
+
+use anyhow::Result;
+use erniebot_rs::chat::{ChatEndpoint, Message, Role};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    chunker::estimate_tokens,
+    entities::{message, sea_orm_active_enums},
+};
+
+pub const CONTEXT_CONFIG_PATH: &str = "configs/context_config.json";
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ContextConfig {
+    //模型单次请求的输入token上限，未在model_max_input_tokens中列出的模型使用默认值
+    pub default_max_input_tokens: usize,
+    #[serde(default)]
+    pub model_max_input_tokens: HashMap<String, usize>,
+    //token数是估算值，预留一部分余量
+    #[serde(default)]
+    pub safety_margin_tokens: usize,
+    //原样保留的最近对话轮数和token数，更早的对话合并到摘要中
+    pub max_recent_turns: usize,
+    pub max_history_tokens: usize,
+    pub max_summary_tokens: usize,
+}
+
+impl ContextConfig {
+    pub fn load(path: &str) -> Result<Self> {
+        let config_string = std::fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&config_string)?)
+    }
+
+    pub fn input_budget(&self, model: &str) -> usize {
+        let limit = self
+            .model_max_input_tokens
+            .get(model)
+            .copied()
+            .unwrap_or(self.default_max_input_tokens);
+        limit.saturating_sub(self.safety_margin_tokens)
+    }
+}
+
+pub fn messages_tokens(messages: &[Message]) -> usize {
+    messages.iter().map(|x| estimate_tokens(&x.content)).sum()
+}
+
+//历史消息中需要原样发送的最近几轮，以及滑出窗口、尚未合并到摘要中的较早几轮
+#[derive(Debug, Clone, PartialEq)]
+pub struct HistoryWindow {
+    pub recent: Vec<message::Model>,
+    pub evicted: Vec<message::Model>,
+}
+
+//一轮对话从用户消息开始，包括其后的所有回复
+fn group_turns(messages: &[message::Model]) -> Vec<&[message::Model]> {
+    let mut turns = Vec::new();
+    let mut start = 0;
+    for (index, message) in messages.iter().enumerate() {
+        if index > start && message.role == sea_orm_active_enums::Role::User {
+            turns.push(&messages[start..index]);
+            start = index;
+        }
+    }
+    if start < messages.len() {
+        turns.push(&messages[start..]);
+    }
+    turns
+}
+
+//messages需要按时间排序，summarized_message_id及之前的消息已经包含在摘要中
+pub fn select_window(
+    messages: &[message::Model],
+    summarized_message_id: Option<i32>,
+    config: &ContextConfig,
+) -> HistoryWindow {
+    let pending: Vec<message::Model> = messages
+        .iter()
+        .filter(|x| summarized_message_id.is_none_or(|id| x.message_id > id))
+        .cloned()
+        .collect();
+    let turns = group_turns(&pending);
+    let mut kept_turns = 0;
+    let mut kept_tokens = 0;
+    for turn in turns.iter().rev() {
+        let tokens: usize = turn.iter().map(|x| estimate_tokens(&x.content)).sum();
+        if kept_turns >= config.max_recent_turns || kept_tokens + tokens > config.max_history_tokens
+        {
+            break;
+        }
+        kept_turns += 1;
+        kept_tokens += tokens;
+    }
+    let split = turns.len() - kept_turns;
+    HistoryWindow {
+        evicted: turns[..split].concat(),
+        recent: turns[split..].concat(),
+    }
+}
+
+//摘要作为一问一答放在历史的最前面，保持用户和助手消息交替出现
+pub fn summary_messages(summary: &str) -> Vec<Message> {
+    vec![
+        Message {
+            role: Role::User,
+            content: format!("以下是我们之前对话的摘要：\n{}", summary),
+            ..Default::default()
+        },
+        Message {
+            role: Role::Assistant,
+            content: "好的，我会参考之前对话的内容。".to_string(),
+            ..Default::default()
+        },
+    ]
+}
+
+//history是用户可见的对话记录，prompt是本次调用临时附加的提示词；超出上限时从最早的历史开始成对丢弃，
+//提示词本身就超出上限时返回错误
+pub fn fit_to_budget(
+    history: &[Message],
+    prompt: &[Message],
+    budget: usize,
+) -> Result<Vec<Message>> {
+    let prompt_tokens = messages_tokens(prompt);
+    if prompt_tokens > budget {
+        return Err(anyhow::anyhow!(
+            "请求约{}个token，超出了模型的上下文长度限制({}个token)",
+            prompt_tokens,
+            budget
+        ));
+    }
+    let mut start = 0;
+    while start < history.len() && messages_tokens(&history[start..]) + prompt_tokens > budget {
+        start += 2;
+    }
+    let mut messages = history[start.min(history.len())..].to_vec();
+    messages.extend_from_slice(prompt);
+    Ok(messages)
+}
+
+//截断文本使其不超过max_tokens个token，按字符二分查找最长的前缀
+pub fn truncate_to_tokens(text: &str, max_tokens: usize) -> String {
+    if estimate_tokens(text) <= max_tokens {
+        return text.to_string();
+    }
+    let boundaries: Vec<usize> = text.char_indices().map(|x| x.0).collect();
+    let prefix = |chars: usize| &text[..boundaries.get(chars).copied().unwrap_or(text.len())];
+    let (mut low, mut high) = (0, boundaries.len());
+    while low < high {
+        let middle = (low + high).div_ceil(2);
+        if estimate_tokens(prefix(middle)) <= max_tokens {
+            low = middle;
+        } else {
+            high = middle - 1;
+        }
+    }
+    prefix(low).to_string()
+}
+
+pub fn format_transcript(messages: &[message::Model]) -> String {
+    messages
+        .iter()
+        .map(|x| match x.role {
+            sea_orm_active_enums::Role::User => format!("用户：{}", x.content),
+            sea_orm_active_enums::Role::Assistant => format!("助手：{}", x.content),
+            sea_orm_active_enums::Role::Function => format!("函数结果：{}", x.content),
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+//把滑出窗口的对话逐批合并到已有的摘要中，每批连同模板和摘要都不超过模型的输入上限
+pub async fn update_summary(
+    chat_endpoint: &ChatEndpoint,
+    summary: Option<&str>,
+    evicted: &[message::Model],
+    config: &ContextConfig,
+    budget: usize,
+) -> Result<String> {
+    let template = std::fs::read_to_string("templates/history_summary.template")?;
+    let mut summary = summary.unwrap_or("（无）").to_string();
+    for transcript in summary_transcripts(evicted, &template, config, budget) {
+        let request = template
+            .replace("{{summary}}", &summary)
+            .replace("{{messages}}", &transcript)
+            .replace("{{max_tokens}}", &config.max_summary_tokens.to_string());
+        let messages = vec![Message {
+            role: Role::User,
+            content: request,
+            ..Default::default()
+        }];
+        let response = chat_endpoint.ainvoke(&messages, &Vec::new()).await?;
+        summary = truncate_to_tokens(
+            response.get_chat_result()?.trim(),
+            config.max_summary_tokens,
+        );
+    }
+    Ok(summary)
+}
+
+//budget是实际调用的模型的输入上限，每批对话记录连同模板和已有的摘要都不超过它
+fn summary_transcripts(
+    evicted: &[message::Model],
+    template: &str,
+    config: &ContextConfig,
+    budget: usize,
+) -> Vec<String> {
+    let batch_budget = budget
+        .saturating_sub(estimate_tokens(template))
+        .saturating_sub(config.max_summary_tokens)
+        .max(1);
+    batch_turns(evicted, batch_budget)
+        .iter()
+        .map(|batch| truncate_to_tokens(&format_transcript(batch), batch_budget))
+        .collect()
+}
+
+//按轮次分批，单独一轮超出预算时自成一批，由调用方截断
+fn batch_turns(messages: &[message::Model], budget: usize) -> Vec<Vec<message::Model>> {
+    let mut batches: Vec<Vec<message::Model>> = Vec::new();
+    let mut current: Vec<message::Model> = Vec::new();
+    let mut current_tokens = 0;
+    for turn in group_turns(messages) {
+        let tokens = estimate_tokens(&format_transcript(turn));
+        if !current.is_empty() && current_tokens + tokens > budget {
+            batches.push(std::mem::take(&mut current));
+            current_tokens = 0;
+        }
+        current.extend_from_slice(turn);
+        current_tokens += tokens;
+    }
+    if !current.is_empty() {
+        batches.push(current);
+    }
+    batches
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        fit_to_budget, select_window, summary_transcripts, truncate_to_tokens, ContextConfig,
+        CONTEXT_CONFIG_PATH,
+    };
+    use crate::{
+        chunker::estimate_tokens,
+        entities::{message, sea_orm_active_enums},
+    };
+    use erniebot_rs::chat::{Message, Role};
+
+    fn make_message(
+        message_id: i32,
+        role: sea_orm_active_enums::Role,
+        content: &str,
+    ) -> message::Model {
+        message::Model {
+            message_id,
+            session_id: 1,
+            role,
+            content: content.to_string(),
+            message_type: sea_orm_active_enums::MessageType::Text,
+            create_time: chrono::Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_select_window() {
+        let config = ContextConfig {
+            default_max_input_tokens: 5120,
+            model_max_input_tokens: Default::default(),
+            safety_margin_tokens: 0,
+            max_recent_turns: 2,
+            max_history_tokens: 100,
+            max_summary_tokens: 50,
+        };
+        let messages: Vec<message::Model> = (1..=8)
+            .map(|id| {
+                let role = if id % 2 == 1 {
+                    sea_orm_active_enums::Role::User
+                } else {
+                    sea_orm_active_enums::Role::Assistant
+                };
+                make_message(id, role, "你好")
+            })
+            .collect();
+        let window = select_window(&messages, Some(2), &config);
+        let evicted: Vec<i32> = window.evicted.iter().map(|x| x.message_id).collect();
+        let recent: Vec<i32> = window.recent.iter().map(|x| x.message_id).collect();
+        assert_eq!(evicted, vec![3, 4]);
+        assert_eq!(recent, vec![5, 6, 7, 8]);
+    }
+
+    #[test]
+    fn test_fit_to_budget() {
+        let message = |role, content: &str| Message {
+            role,
+            content: content.to_string(),
+            ..Default::default()
+        };
+        let history = vec![
+            message(Role::User, "很早以前的问题"),
+            message(Role::Assistant, "很早以前的回答"),
+        ];
+        let prompt = vec![message(Role::User, "现在的问题")];
+        assert_eq!(fit_to_budget(&history, &prompt, 100).unwrap().len(), 3);
+        let fitted = fit_to_budget(&history, &prompt, 5).unwrap();
+        assert_eq!(fitted.len(), 1);
+        assert_eq!(fitted[0].content, "现在的问题");
+        assert!(fit_to_budget(&history, &prompt, 2).is_err());
+    }
+
+    #[test]
+    fn test_truncate_to_tokens() {
+        let text = "第一句话。The quick brown fox.";
+        assert_eq!(truncate_to_tokens(text, 100), text);
+        let truncated = truncate_to_tokens(text, 6);
+        assert!(estimate_tokens(&truncated) <= 6);
+        assert!(text.starts_with(&truncated));
+        assert_eq!(truncated, "第一句话。");
+    }
+
+    //会话使用长上下文模型时，摘要请求按该模型的上限分批，每批都放得进同一个模型
+    #[test]
+    fn test_summary_transcripts_large_context_model() {
+        let config = ContextConfig::load(CONTEXT_CONFIG_PATH).unwrap();
+        let template = std::fs::read_to_string("templates/history_summary.template").unwrap();
+        let evicted: Vec<message::Model> = (1..=400)
+            .map(|id| {
+                let role = if id % 2 == 1 {
+                    sea_orm_active_enums::Role::User
+                } else {
+                    sea_orm_active_enums::Role::Assistant
+                };
+                make_message(id, role, &"这是一段比较长的对话内容。".repeat(20))
+            })
+            .collect();
+        let large_budget = config.input_budget("ernie-speed-128k");
+        let small_budget = config.input_budget("ernie-4.0-8k");
+        let large = summary_transcripts(&evicted, &template, &config, large_budget);
+        let small = summary_transcripts(&evicted, &template, &config, small_budget);
+        assert!(large.len() < small.len());
+        for (transcripts, budget) in [(large, large_budget), (small, small_budget)] {
+            for transcript in transcripts {
+                let request_tokens = estimate_tokens(&template)
+                    + config.max_summary_tokens
+                    + estimate_tokens(&transcript);
+                assert!(request_tokens <= budget, "{} > {}", request_tokens, budget);
+            }
+        }
+    }
+}
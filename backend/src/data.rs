@@ -1,6 +1,9 @@
+use axum::Json;
 use serde::{Deserialize, Serialize};
 use serde_json::value::Value;
 
+use crate::session_settings::SessionSettings;
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct TextJsonResponse {
     pub code: u32,
@@ -33,4 +36,84 @@ pub struct ChatRequest {
 #[derive(Serialize, Deserialize, Debug)]
 pub struct CreateSessionRequest {
     pub user_id: i32,
+    //创建会话时可以同时指定会话设置
+    #[serde(flatten)]
+    pub settings: SessionSettings,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SubscribeRequest {
+    pub session_id: Option<i32>,
+    pub knowledge_base_id: Option<i32>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct CreateKnowledgeBaseRequest {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    //user 或 team
+    pub owner_type: String,
+    pub owner_id: i32,
+    //创建者，所有者为用户时必须是该用户本人，为团队时必须是该团队的成员
+    pub user_id: i32,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct UpdateKnowledgeBaseRequest {
+    pub name: Option<String>,
+    pub description: Option<String>,
+}
+
+//调用方以哪个用户或团队的身份访问知识库，查询和修改都只限于该所有者的知识库
+#[derive(Serialize, Deserialize, Debug)]
+pub struct KnowledgeBaseOwnerQuery {
+    //user 或 team
+    pub owner_type: String,
+    pub owner_id: i32,
+    //调用方的用户，只能以自己或者自己所在团队的身份访问
+    pub user_id: i32,
+}
+
+//修改团队成员的用户，必须已经是该团队的成员
+#[derive(Serialize, Deserialize, Debug)]
+pub struct TeamMemberQuery {
+    pub user_id: i32,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct AttachKnowledgeBaseRequest {
+    pub knowledge_base_id: i32,
+    //关联团队的知识库时需要指定团队，会话的用户必须是该团队的成员
+    pub team_id: Option<i32>,
+}
+
+pub fn error_response(error: &str) -> Json<JsonDataResponse> {
+    Json(JsonDataResponse {
+        code: 500,
+        data: serde_json::json!({
+            "error": error
+        }),
+    })
+}
+
+//服务端请求用户确认执行有副作用的函数，客户端通过ack回复ConfirmFunctionReply
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ConfirmFunctionRequest {
+    pub confirmation_id: String,
+    pub session_id: i32,
+    pub function: String,
+    pub description: String,
+    pub parameters: Value,
+    pub thoughts: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ConfirmFunctionReply {
+    pub approved: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SetFunctionEnabledRequest {
+    pub enabled: bool,
 }
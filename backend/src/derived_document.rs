@@ -0,0 +1,123 @@
+use axum::{
+    extract::{Json, Path},
+    http::header,
+    response::{IntoResponse, Response},
+    Extension,
+};
+use sea_orm::*;
+
+use crate::{
+    data::{error_response, JsonDataResponse},
+    entities::{derived_document, prelude::DerivedDocument},
+};
+
+//由函数根据会话文档生成的文档（如译文），保存在derived_document表中，可以通过接口下载
+pub fn download_url(session_id: i32, derived_document_id: i32) -> String {
+    format!(
+        "/sessions/{}/derived_documents/{}",
+        session_id, derived_document_id
+    )
+}
+
+fn derived_document_to_json(document: &derived_document::Model) -> serde_json::Value {
+    serde_json::json!({
+        "derived_document_id": document.derived_document_id,
+        "session_id": document.session_id,
+        "kind": document.kind,
+        "filename": document.filename,
+        "size": document.content.len(),
+        "download_url": download_url(document.session_id, document.derived_document_id),
+        "create_time": document.create_time,
+    })
+}
+
+fn not_found() -> Json<JsonDataResponse> {
+    Json(JsonDataResponse {
+        code: 404,
+        data: serde_json::json!({
+            "error": "Document not found"
+        }),
+    })
+}
+
+//文件名可能包含中文，按RFC 6266使用filename*参数
+fn content_disposition(filename: &str) -> String {
+    let ascii: String = filename
+        .chars()
+        .map(|x| match x.is_ascii_alphanumeric() || ".-_".contains(x) {
+            true => x,
+            false => '_',
+        })
+        .collect();
+    let encoded: String = url::form_urlencoded::byte_serialize(filename.as_bytes())
+        .collect::<String>()
+        .replace('+', "%20");
+    format!(
+        "attachment; filename=\"{}\"; filename*=UTF-8''{}",
+        ascii, encoded
+    )
+}
+
+#[axum_macros::debug_handler]
+pub async fn list_derived_documents(
+    Extension(db): Extension<DatabaseConnection>,
+    Path(session_id): Path<i32>,
+) -> Json<JsonDataResponse> {
+    let documents = DerivedDocument::find()
+        .filter(derived_document::Column::SessionId.eq(session_id))
+        .order_by_desc(derived_document::Column::CreateTime)
+        .all(&db)
+        .await;
+    match documents {
+        Ok(documents) => Json(JsonDataResponse {
+            code: 200,
+            data: serde_json::json!(documents
+                .iter()
+                .map(derived_document_to_json)
+                .collect::<Vec<_>>()),
+        }),
+        Err(e) => error_response(&e.to_string()),
+    }
+}
+
+#[axum_macros::debug_handler]
+pub async fn download_derived_document(
+    Extension(db): Extension<DatabaseConnection>,
+    Path((session_id, derived_document_id)): Path<(i32, i32)>,
+) -> Response {
+    let document = DerivedDocument::find_by_id(derived_document_id)
+        .filter(derived_document::Column::SessionId.eq(session_id))
+        .one(&db)
+        .await;
+    match document {
+        Ok(Some(document)) => (
+            [
+                (
+                    header::CONTENT_TYPE,
+                    "text/markdown; charset=utf-8".to_string(),
+                ),
+                (
+                    header::CONTENT_DISPOSITION,
+                    content_disposition(&document.filename),
+                ),
+            ],
+            document.content,
+        )
+            .into_response(),
+        Ok(None) => not_found().into_response(),
+        Err(e) => error_response(&e.to_string()).into_response(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::content_disposition;
+
+    #[test]
+    fn test_content_disposition() {
+        assert_eq!(
+            content_disposition("报告 v2.en.md"),
+            "attachment; filename=\"___v2.en.md\"; filename*=UTF-8''%E6%8A%A5%E5%91%8A%20v2.en.md"
+        );
+    }
+}
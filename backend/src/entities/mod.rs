@@ -2,7 +2,15 @@
 
 pub mod prelude;
 
+pub mod derived_document;
+pub mod job;
+pub mod knowledge_base;
+pub mod knowledge_base_document;
+pub mod memory;
 pub mod message;
 pub mod sea_orm_active_enums;
 pub mod session;
+pub mod session_knowledge_base;
+pub mod summary_cache;
+pub mod team_member;
 pub mod user;
@@ -1,5 +1,13 @@
 //! `SeaORM` Entity. Generated by sea-orm-codegen 0.12.15
 
+pub use super::derived_document::Entity as DerivedDocument;
+pub use super::job::Entity as Job;
+pub use super::knowledge_base::Entity as KnowledgeBase;
+pub use super::knowledge_base_document::Entity as KnowledgeBaseDocument;
+pub use super::memory::Entity as Memory;
 pub use super::message::Entity as Message;
 pub use super::session::Entity as Session;
+pub use super::session_knowledge_base::Entity as SessionKnowledgeBase;
+pub use super::summary_cache::Entity as SummaryCache;
+pub use super::team_member::Entity as TeamMember;
 //pub use super::user::Entity as User;
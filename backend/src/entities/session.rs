@@ -2,7 +2,7 @@
 
 use sea_orm::entity::prelude::*;
 
-#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq)]
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel)]
 #[sea_orm(table_name = "session")]
 pub struct Model {
     #[sea_orm(primary_key)]
@@ -10,6 +10,21 @@ pub struct Model {
     pub user_id: i32,
     pub create_time: DateTimeUtc,
     pub last_update_time: DateTimeUtc,
+    #[sea_orm(column_type = "Text", nullable)]
+    pub history_summary: Option<String>,
+    pub summarized_message_id: Option<i32>,
+    #[sea_orm(column_type = "Text", nullable)]
+    pub system_prompt: Option<String>,
+    pub model: Option<String>,
+    #[sea_orm(column_type = "Float", nullable)]
+    pub temperature: Option<f32>,
+    #[sea_orm(column_type = "Float", nullable)]
+    pub top_p: Option<f32>,
+    #[sea_orm(column_type = "Float", nullable)]
+    pub penalty_score: Option<f32>,
+    pub max_output_tokens: Option<i32>,
+    pub enabled_functions: Option<Json>,
+    pub auto_memory: Option<bool>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
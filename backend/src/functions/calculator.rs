@@ -1,21 +1,24 @@
 use super::function::{Context, Function};
+use crate::calculator::{calculate, DEFAULT_PRECISION};
 use anyhow::Result;
-use evalexpr::eval;
 use schemars::{schema::RootSchema, schema_for, JsonSchema};
 use serde::{Deserialize, Serialize};
 
 #[derive(JsonSchema, Debug, Clone, Serialize, Deserialize, PartialEq)]
 struct CalculatorParameters {
+    /// 数学表达式、单位换算或方程，例如 "1/3 + 2^0.5"、"60 km/h to m/s"、"x^2 - 2x - 3 = 0"
     expression: String,
+    /// 结果保留的有效数字位数，默认20，最大100
+    precision: Option<usize>,
 }
 
 pub struct CalculatorFunction {}
 impl Function for CalculatorFunction {
     fn execute(&self, parameters: serde_json::Value, _: &Context) -> Result<String> {
         let parameters: CalculatorParameters = serde_json::from_value(parameters)?;
-        let expression = parameters.expression;
-        let result = eval(&expression)?;
-        Ok(result.to_string())
+        let precision = parameters.precision.unwrap_or(DEFAULT_PRECISION);
+        let result = calculate(&parameters.expression, precision)?;
+        Ok(serde_json::to_string(&result)?)
     }
 
     fn if_postprocess(&self) -> bool {
@@ -27,7 +30,7 @@ impl Function for CalculatorFunction {
     }
 
     fn get_description(&self) -> String {
-        "涉及到简单数学问题时，可以将问题表述为一个非代数表达式，使用该函数来计算该表达式的值。请注意该函数的限制，如果不能表达为一个数学表达式，请不要使用该函数".to_string()
+        "涉及到数学计算时使用该函数。整数和小数按任意精度精确计算（1/3不会变成0），支持sqrt、cbrt、abs、exp、ln、log10、log2、log(x, 底数)、sin、cos、tan（可写 30 deg）、asin、acos、atan、sinh、cosh、tanh、floor、ceil、round、min、max、阶乘!，常量pi和e。数字可以带物理单位（如 5 km、3 kg * 9.8 m/s^2），用 to 换算单位（如 100 degC to degF）。含一个单字母未知数时可以化简多项式（如 (x+1)^2），或求解一元一次、二次方程（如 x^2 = 2x + 3）。返回规范化的表达式、结果、单位，以及结果是否为近似值。".to_string()
     }
 
     fn get_parameter_schema(&self) -> RootSchema {
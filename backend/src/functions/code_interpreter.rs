@@ -0,0 +1,252 @@
+use super::function::{block_on, Capability, Context, Function};
+use crate::knowledge_base::read_document;
+use anyhow::Result;
+use rhai::{
+    module_resolvers::DummyModuleResolver, Array, Dynamic, Engine, EvalAltResult, FLOAT, INT,
+};
+use schemars::{schema::RootSchema, schema_for, JsonSchema};
+use serde::{Deserialize, Serialize};
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+//脚本的资源限制
+const MAX_OPERATIONS: u64 = 5_000_000;
+const TIMEOUT: Duration = Duration::from_secs(5);
+const MAX_CALL_LEVELS: usize = 32;
+const MAX_STRING_SIZE: usize = 1 << 20;
+const MAX_ARRAY_SIZE: usize = 100_000;
+const MAX_OUTPUT_CHARS: usize = 8000;
+
+#[derive(JsonSchema, Debug, Clone, Serialize, Deserialize, PartialEq)]
+struct CodeInterpreterParameters {
+    /// Rhai脚本，最后一个表达式的值作为结果返回
+    code: String,
+    /// document()读取的关联知识库文档名，不填则为会话上传的文档
+    document: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct InterpreterOutput {
+    pub stdout: String,
+    pub result: serde_json::Value,
+}
+
+fn to_float(value: &Dynamic) -> Result<FLOAT, Box<EvalAltResult>> {
+    value
+        .as_float()
+        .or_else(|_| value.as_int().map(|x| x as FLOAT))
+        .map_err(|_| format!("{} 不是数字", value.type_name()).into())
+}
+
+fn numbers(array: &Array) -> Result<Vec<FLOAT>, Box<EvalAltResult>> {
+    if array.is_empty() {
+        return Err("数组为空".into());
+    }
+    array.iter().map(to_float).collect()
+}
+
+fn mean(array: Array) -> Result<FLOAT, Box<EvalAltResult>> {
+    let values = numbers(&array)?;
+    Ok(values.iter().sum::<FLOAT>() / values.len() as FLOAT)
+}
+
+fn median(array: Array) -> Result<FLOAT, Box<EvalAltResult>> {
+    let mut values = numbers(&array)?;
+    values.sort_by(|a, b| a.total_cmp(b));
+    let middle = values.len() / 2;
+    Ok(match values.len() % 2 {
+        0 => (values[middle - 1] + values[middle]) / 2.0,
+        _ => values[middle],
+    })
+}
+
+//样本方差，只有一个数时为0
+fn variance(array: Array) -> Result<FLOAT, Box<EvalAltResult>> {
+    let values = numbers(&array)?;
+    if values.len() < 2 {
+        return Ok(0.0);
+    }
+    let mean = values.iter().sum::<FLOAT>() / values.len() as FLOAT;
+    let squares: FLOAT = values.iter().map(|x| (x - mean).powi(2)).sum();
+    Ok(squares / (values.len() - 1) as FLOAT)
+}
+
+fn cell_value(cell: &str) -> Dynamic {
+    if let Ok(value) = cell.parse::<INT>() {
+        return Dynamic::from(value);
+    }
+    match cell.parse::<FLOAT>() {
+        Ok(value) => Dynamic::from(value),
+        Err(_) => Dynamic::from(cell.to_string()),
+    }
+}
+
+//把csv、制表符分隔或markdown表格解析为二维数组，markdown表格的分隔行会被跳过
+fn parse_table(text: &str) -> Array {
+    text.lines()
+        .map(str::trim)
+        .filter(|x| !x.is_empty())
+        .filter(|x| !(x.contains('-') && x.chars().all(|c| "|-: ".contains(c))))
+        .map(|line| {
+            let cells: Vec<&str> = if line.contains('|') {
+                line.trim_matches('|').split('|').collect()
+            } else if line.contains('\t') {
+                line.split('\t').collect()
+            } else {
+                line.split(',').collect()
+            };
+            let row: Array = cells.into_iter().map(|x| cell_value(x.trim())).collect();
+            Dynamic::from(row)
+        })
+        .collect()
+}
+
+//print和debug的输出，超出上限的部分丢弃
+fn append_output(stdout: &Mutex<String>, text: &str) {
+    let mut stdout = stdout.lock().unwrap();
+    if stdout.chars().count() < MAX_OUTPUT_CHARS {
+        stdout.push_str(text);
+        stdout.push('\n');
+    }
+}
+
+fn map_error(error: EvalAltResult) -> anyhow::Error {
+    match error {
+        EvalAltResult::ErrorTooManyOperations(_) => anyhow::anyhow!("脚本超出了运算次数上限"),
+        EvalAltResult::ErrorTerminated(_, _) => {
+            anyhow::anyhow!("脚本执行超过了{}秒", TIMEOUT.as_secs())
+        }
+        error => anyhow::anyhow!("脚本执行出错: {}", error),
+    }
+}
+
+//脚本只能通过document()读取会话文档的文本，没有文件、网络等其它宿主访问
+pub fn run(code: &str, document: Option<String>) -> Result<InterpreterOutput> {
+    let mut engine = Engine::new();
+    engine.set_max_operations(MAX_OPERATIONS);
+    engine.set_max_call_levels(MAX_CALL_LEVELS);
+    engine.set_max_string_size(MAX_STRING_SIZE);
+    engine.set_max_array_size(MAX_ARRAY_SIZE);
+    engine.set_max_map_size(MAX_ARRAY_SIZE);
+    engine.disable_symbol("eval");
+    //默认的模块解析器会通过import读取宿主上的文件
+    engine.set_module_resolver(DummyModuleResolver::new());
+    let start = Instant::now();
+    engine.on_progress(move |_| (start.elapsed() > TIMEOUT).then(Dynamic::default));
+    let stdout = Arc::new(Mutex::new(String::new()));
+    let print_output = stdout.clone();
+    engine.on_print(move |text| append_output(&print_output, text));
+    let debug_output = stdout.clone();
+    engine.on_debug(move |text, _, _| append_output(&debug_output, text));
+    engine.register_fn("document", move || -> Result<String, Box<EvalAltResult>> {
+        document
+            .clone()
+            .ok_or_else(|| "当前会话没有上传文档".into())
+    });
+    engine.register_fn("parse_table", |text: &str| parse_table(text));
+    engine.register_fn("sum", |array: Array| -> Result<FLOAT, Box<EvalAltResult>> {
+        Ok(numbers(&array)?.iter().sum())
+    });
+    engine.register_fn("mean", mean);
+    engine.register_fn("median", median);
+    engine.register_fn("variance", variance);
+    engine.register_fn("stdev", |array: Array| variance(array).map(|x| x.sqrt()));
+    let result = engine.eval::<Dynamic>(code).map_err(|e| map_error(*e))?;
+    let result =
+        rhai::serde::from_dynamic::<serde_json::Value>(&result).map_err(|e| map_error(*e))?;
+    let stdout = stdout
+        .lock()
+        .unwrap()
+        .chars()
+        .take(MAX_OUTPUT_CHARS)
+        .collect();
+    Ok(InterpreterOutput { stdout, result })
+}
+
+pub struct CodeInterpreterFunction {}
+
+impl Function for CodeInterpreterFunction {
+    fn execute(&self, parameters: serde_json::Value, context: &Context) -> Result<String> {
+        let parameters: CodeInterpreterParameters = serde_json::from_value(parameters)?;
+        //指定的知识库文档不存在时直接报错，会话中没有上传文档时只在脚本调用document()时报错
+        let document = match &parameters.document {
+            Some(name) => Some(block_on(read_document(
+                &context.db,
+                context.session_id,
+                Some(name),
+            ))?),
+            None => block_on(read_document(&context.db, context.session_id, None)).ok(),
+        };
+        let output = tokio::task::block_in_place(|| run(&parameters.code, document))?;
+        Ok(serde_json::to_string(&output)?)
+    }
+
+    fn if_postprocess(&self) -> bool {
+        true
+    }
+
+    fn get_name(&self) -> String {
+        "code_interpreter".to_string()
+    }
+
+    fn get_description(&self) -> String {
+        "在沙箱中运行Rhai脚本，适合多步计算、循环、对上传文档中的表格做统计等单个表达式无法完成的问题。脚本最后一个表达式的值作为结果返回，print的内容也会返回。可以使用document()读取当前会话上传文档的文本，parse_table(text)把csv或markdown表格解析为二维数组（数字会自动转换），sum、mean、median、variance、stdev对数字数组做统计。脚本不能访问文件和网络，运算次数和时间有上限。".to_string()
+    }
+
+    fn get_parameter_schema(&self) -> RootSchema {
+        schema_for!(CodeInterpreterParameters)
+    }
+
+    fn get_capabilities(&self) -> Vec<Capability> {
+        vec![Capability::Documents]
+    }
+
+    //没有文档时也可以用来计算
+    fn requires_documents(&self) -> bool {
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::run;
+
+    #[test]
+    fn test_run() {
+        let document =
+            "| 城市 | 人口 |\n| --- | --- |\n| 北京 | 2185.8 |\n| 上海 | 2487 |\n".to_string();
+        let code = r#"
+            let rows = parse_table(document());
+            let values = [];
+            for row in rows { if type_of(row[1]) != "string" { values.push(row[1]); } }
+            print(`共${values.len()}个城市`);
+            #{ total: sum(values), median: median(values), half: 1 / 2 }
+        "#;
+        let output = run(code, Some(document)).unwrap();
+        assert_eq!(output.stdout, "共2个城市\n");
+        assert_eq!(output.result["total"], 4672.8);
+        assert_eq!(output.result["median"], 2336.4);
+        assert_eq!(output.result["half"], 0);
+        assert!(run("document()", None).is_err());
+    }
+
+    #[test]
+    fn test_limits() {
+        let error = run("loop { }", None).unwrap_err();
+        assert!(error.to_string().contains("运算次数"));
+        assert!(run(r#"eval("1 + 1")"#, None).is_err());
+    }
+
+    #[test]
+    fn test_import_disabled() {
+        let path = std::env::temp_dir().join(format!("module-{}.rhai", uuid::Uuid::new_v4()));
+        std::fs::write(&path, "export const SECRET = 42;").unwrap();
+        let module = path.with_extension("");
+        let code = format!(r#"import "{}" as m; m::SECRET"#, module.display());
+        let result = run(&code, None);
+        std::fs::remove_file(&path).unwrap();
+        assert!(result.is_err());
+    }
+}
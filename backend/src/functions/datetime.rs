@@ -0,0 +1,749 @@
+use super::function::{Context, Function};
+use anyhow::Result;
+use chrono::{
+    DateTime, Datelike, Duration, FixedOffset, Months, NaiveDate, NaiveDateTime, NaiveTime, Utc,
+    Weekday,
+};
+use lazy_static::lazy_static;
+use regex::Regex;
+use schemars::{schema::RootSchema, schema_for, JsonSchema};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    fmt::Write,
+};
+
+pub const DATETIME_CONFIG_PATH: &str = "configs/datetime_config.json";
+
+//按工作日加减时最多遍历的天数
+const MAX_DAYS: i64 = 100_000;
+
+lazy_static! {
+    static ref TIME: Regex = Regex::new(
+        r"(?:上午|下午|晚上|中午)?\s*\d{1,2}(?:[:：]\d{2}(?:[:：]\d{2})?|点(?:半|\d{1,2}分?)?)\s*$"
+    )
+    .unwrap();
+    static ref TIME_PARTS: Regex = Regex::new(
+        r"^(上午|下午|晚上|中午)?\s*(\d{1,2})(?:[:：](\d{2})(?:[:：](\d{2}))?|点(?:(半)|(\d{1,2})分?)?)$"
+    )
+    .unwrap();
+    static ref ISO_DATE: Regex = Regex::new(r"^(\d{4})[-/.](\d{1,2})[-/.](\d{1,2})$").unwrap();
+    static ref COMPACT_DATE: Regex = Regex::new(r"^(\d{4})(\d{2})(\d{2})$").unwrap();
+    static ref CHINESE_DATE: Regex =
+        Regex::new(r"^(?:(\d{4})年)?\s*(\d{1,2})月\s*(\d{1,2})[日号]?$").unwrap();
+    static ref ENGLISH_DATE: Regex =
+        Regex::new(r"^([A-Za-z]+)\.?\s+(\d{1,2})(?:st|nd|rd|th)?,?(?:\s+(\d{4}))?$").unwrap();
+    static ref ENGLISH_DATE_DAY_FIRST: Regex =
+        Regex::new(r"^(\d{1,2})(?:st|nd|rd|th)?\s+([A-Za-z]+)\.?,?(?:\s+(\d{4}))?$").unwrap();
+    static ref WEEKDAY: Regex =
+        Regex::new(r"^(上|下|本|这)?(?:周|星期|礼拜)([一二三四五六日天1-7])$").unwrap();
+    static ref RELATIVE: Regex =
+        Regex::new(r"^(\d+)\s*(天|周|个?星期|个?月|年)(后|前|以后|之后|以前|之前)$").unwrap();
+    static ref RELATIVE_ENGLISH: Regex =
+        Regex::new(r"^(?:in\s+(\d+)\s+(day|week|month|year)s?|(\d+)\s+(day|week|month|year)s?\s+ago)$")
+            .unwrap();
+    static ref UTC_OFFSET: Regex =
+        Regex::new(r"^(?:UTC|GMT)?\s*([+-])(\d{1,2})(?::?(\d{2}))?$").unwrap();
+}
+
+const WEEKDAY_NAMES: [&str; 7] = [
+    "星期一",
+    "星期二",
+    "星期三",
+    "星期四",
+    "星期五",
+    "星期六",
+    "星期日",
+];
+
+//节假日日历：holidays是放假的日期及节日名称，workdays是因调休需要上班的周末，日期格式为YYYY-MM-DD
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct HolidayCalendar {
+    pub description: String,
+    pub holidays: BTreeMap<String, String>,
+    pub workdays: BTreeSet<String>,
+}
+
+impl HolidayCalendar {
+    fn key(date: &NaiveDate) -> String {
+        date.format("%Y-%m-%d").to_string()
+    }
+
+    pub fn holiday(&self, date: &NaiveDate) -> Option<&str> {
+        self.holidays.get(&Self::key(date)).map(|x| x.as_str())
+    }
+
+    pub fn is_workday(&self, date: &NaiveDate) -> bool {
+        if self.workdays.contains(&Self::key(date)) {
+            return true;
+        }
+        self.holiday(date).is_none() && !matches!(date.weekday(), Weekday::Sat | Weekday::Sun)
+    }
+
+    //日历中出现过节假日的年份视为已覆盖，其余年份只按周末计算
+    pub fn covers(&self, year: i32) -> bool {
+        let prefix = format!("{}-", year);
+        self.holidays.keys().any(|x| x.starts_with(&prefix))
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct DatetimeConfig {
+    pub default_timezone: String,
+    pub default_calendar: String,
+    pub calendars: HashMap<String, HolidayCalendar>,
+}
+
+impl DatetimeConfig {
+    pub fn load(path: &str) -> Result<Self> {
+        let config_string = std::fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&config_string)?)
+    }
+}
+
+#[derive(JsonSchema, Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+enum Operation {
+    Now,
+    Parse,
+    Diff,
+    Add,
+    Format,
+}
+
+#[derive(JsonSchema, Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+enum DurationUnit {
+    Days,
+    Weeks,
+    Months,
+    Years,
+    Hours,
+    Minutes,
+    BusinessDays,
+}
+
+#[derive(JsonSchema, Debug, Clone, Serialize, Deserialize, PartialEq)]
+struct DatetimeParameters {
+    operation: Operation,
+    /// 日期或时间，如 2025-12-25、12月25日、明天、下周一，默认今天
+    date: Option<String>,
+    /// diff的结束日期，没写年份时取date之后最近的一天
+    end: Option<String>,
+    /// add的数量，可为负数
+    amount: Option<i64>,
+    /// add的单位，默认days
+    unit: Option<DurationUnit>,
+    /// strftime格式
+    format: Option<String>,
+    /// 时区，如 +08:00，默认北京时间
+    timezone: Option<String>,
+    /// 节假日日历，默认中国大陆
+    calendar: Option<String>,
+}
+
+//不依赖时区数据库，只支持固定偏移和常见的不使用夏令时的时区名称
+fn parse_timezone(text: &str) -> Result<FixedOffset> {
+    let text = text.trim();
+    let hours = match text {
+        "UTC" | "GMT" | "Z" | "Etc/UTC" => Some(0),
+        "北京时间" | "Asia/Shanghai" | "Asia/Chongqing" | "Asia/Hong_Kong" | "Asia/Macau"
+        | "Asia/Taipei" | "Asia/Singapore" | "Asia/Kuala_Lumpur" | "Asia/Manila" => Some(8 * 60),
+        "Asia/Tokyo" | "Asia/Seoul" => Some(9 * 60),
+        "Asia/Bangkok" | "Asia/Jakarta" | "Asia/Ho_Chi_Minh" => Some(7 * 60),
+        "Asia/Kolkata" | "Asia/Calcutta" => Some(5 * 60 + 30),
+        "Asia/Dubai" => Some(4 * 60),
+        "Europe/Moscow" => Some(3 * 60),
+        _ => None,
+    };
+    let minutes = match (hours, UTC_OFFSET.captures(text)) {
+        (Some(minutes), _) => minutes,
+        (None, Some(captures)) => {
+            let hours: i32 = captures[2].parse()?;
+            let minutes: i32 = captures.get(3).map_or(Ok(0), |x| x.as_str().parse())?;
+            let sign = if &captures[1] == "-" { -1 } else { 1 };
+            sign * (hours * 60 + minutes)
+        }
+        (None, None) => {
+            return Err(anyhow::anyhow!(
+                "不支持的时区 {}，请使用UTC偏移，例如 +08:00",
+                text
+            ))
+        }
+    };
+    FixedOffset::east_opt(minutes * 60).ok_or_else(|| anyhow::anyhow!("时区偏移超出范围: {}", text))
+}
+
+//解析出的时刻，没有给出时间时只有日期
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Moment {
+    date: NaiveDate,
+    time: Option<NaiveTime>,
+}
+
+impl Moment {
+    fn datetime(&self) -> NaiveDateTime {
+        self.date.and_time(self.time.unwrap_or_default())
+    }
+}
+
+fn month_number(name: &str) -> Option<u32> {
+    let months = [
+        "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+    ];
+    let name = name.to_lowercase();
+    if name.len() < 3 {
+        return None;
+    }
+    months
+        .iter()
+        .position(|x| name.starts_with(x))
+        .map(|x| x as u32 + 1)
+}
+
+fn ymd(year: i32, month: u32, day: u32) -> Result<NaiveDate> {
+    NaiveDate::from_ymd_opt(year, month, day)
+        .ok_or_else(|| anyhow::anyhow!("日期不存在: {}-{}-{}", year, month, day))
+}
+
+fn add_months(date: NaiveDate, months: i64) -> Result<NaiveDate> {
+    let result = match months >= 0 {
+        true => date.checked_add_months(Months::new(months.try_into()?)),
+        false => date.checked_sub_months(Months::new((-months).try_into()?)),
+    };
+    result.ok_or_else(|| anyhow::anyhow!("日期超出范围"))
+}
+
+fn add_days(date: NaiveDate, days: i64) -> Result<NaiveDate> {
+    date.checked_add_signed(
+        Duration::try_days(days).ok_or_else(|| anyhow::anyhow!("天数超出范围"))?,
+    )
+    .ok_or_else(|| anyhow::anyhow!("日期超出范围"))
+}
+
+fn parse_time(text: &str) -> Result<NaiveTime> {
+    let captures = TIME_PARTS
+        .captures(text.trim())
+        .ok_or_else(|| anyhow::anyhow!("无法识别的时间: {}", text))?;
+    let mut hour: u32 = captures[2].parse()?;
+    let minute: u32 = match (captures.get(3), captures.get(5), captures.get(6)) {
+        (Some(minute), _, _) | (_, _, Some(minute)) => minute.as_str().parse()?,
+        (_, Some(_), _) => 30,
+        _ => 0,
+    };
+    let second: u32 = captures.get(4).map_or(Ok(0), |x| x.as_str().parse())?;
+    if matches!(captures.get(1).map(|x| x.as_str()), Some("下午" | "晚上")) && hour < 12 {
+        hour += 12;
+    }
+    NaiveTime::from_hms_opt(hour, minute, second)
+        .ok_or_else(|| anyhow::anyhow!("时间不存在: {}", text))
+}
+
+//没有写年份的日期按今年计算；给出after时取不早于after的最近一次，例如diff的结束日期
+fn month_day(
+    month: u32,
+    day: u32,
+    today: NaiveDate,
+    after: Option<NaiveDate>,
+) -> Result<NaiveDate> {
+    let Some(after) = after else {
+        return ymd(today.year(), month, day);
+    };
+    let date = ymd(after.year(), month, day)?;
+    match date < after {
+        true => ymd(after.year() + 1, month, day),
+        false => Ok(date),
+    }
+}
+
+fn parse_date(text: &str, today: NaiveDate, after: Option<NaiveDate>) -> Result<NaiveDate> {
+    let text = text.trim();
+    let lower = text.to_lowercase();
+    let offset = match lower.as_str() {
+        "" | "今天" | "今日" | "today" | "now" | "现在" => Some(0),
+        "明天" | "tomorrow" => Some(1),
+        "后天" => Some(2),
+        "大后天" => Some(3),
+        "昨天" | "yesterday" => Some(-1),
+        "前天" => Some(-2),
+        _ => None,
+    };
+    if let Some(offset) = offset {
+        return add_days(today, offset);
+    }
+    if let Some(captures) = ISO_DATE
+        .captures(text)
+        .or_else(|| COMPACT_DATE.captures(text))
+    {
+        return ymd(
+            captures[1].parse()?,
+            captures[2].parse()?,
+            captures[3].parse()?,
+        );
+    }
+    if let Some(captures) = CHINESE_DATE.captures(text) {
+        let (month, day) = (captures[2].parse()?, captures[3].parse()?);
+        return match captures.get(1) {
+            Some(year) => ymd(year.as_str().parse()?, month, day),
+            None => month_day(month, day, today, after),
+        };
+    }
+    let english = ENGLISH_DATE
+        .captures(text)
+        .map(|x| (x.get(1), x.get(2), x.get(3)))
+        .or_else(|| {
+            ENGLISH_DATE_DAY_FIRST
+                .captures(text)
+                .map(|x| (x.get(2), x.get(1), x.get(3)))
+        });
+    if let Some((Some(month), Some(day), year)) = english {
+        if let Some(month) = month_number(month.as_str()) {
+            let day = day.as_str().parse()?;
+            return match year {
+                Some(year) => ymd(year.as_str().parse()?, month, day),
+                None => month_day(month, day, today, after),
+            };
+        }
+    }
+    //周一为一周的第一天
+    if let Some(captures) = WEEKDAY.captures(text) {
+        let index = "一二三四五六日".find(&captures[2]).map(|x| x / 3);
+        let index = match index {
+            Some(index) => index as i64,
+            None if &captures[2] == "天" => 6,
+            None => captures[2].parse::<i64>()? - 1,
+        };
+        let week = match captures.get(1).map(|x| x.as_str()) {
+            Some("上") => -1,
+            Some("下") => 1,
+            _ => 0,
+        };
+        let monday = today.weekday().num_days_from_monday() as i64;
+        return add_days(today, week * 7 + index - monday);
+    }
+    let relative = RELATIVE.captures(text).map(|x| {
+        let sign = if x[3].ends_with('前') { -1 } else { 1 };
+        (
+            x[1].to_string(),
+            x[2].trim_start_matches('个').to_string(),
+            sign,
+        )
+    });
+    let relative = relative.or_else(|| {
+        RELATIVE_ENGLISH.captures(&lower).map(|x| match x.get(1) {
+            Some(amount) => (amount.as_str().to_string(), x[2].to_string(), 1),
+            None => (x[3].to_string(), x[4].to_string(), -1),
+        })
+    });
+    if let Some((amount, unit, sign)) = relative {
+        let amount = sign * amount.parse::<i64>()?;
+        return match unit.as_str() {
+            "天" | "day" => add_days(today, amount),
+            "周" | "星期" | "week" => add_days(today, amount * 7),
+            "月" | "month" => add_months(today, amount),
+            _ => add_months(today, amount * 12),
+        };
+    }
+    Err(anyhow::anyhow!("无法识别的日期: {}", text))
+}
+
+fn parse_moment(
+    text: &str,
+    now: &DateTime<FixedOffset>,
+    after: Option<NaiveDate>,
+) -> Result<Moment> {
+    let text = text.trim();
+    if let Ok(datetime) = DateTime::parse_from_rfc3339(text) {
+        let datetime = datetime.with_timezone(now.offset());
+        return Ok(Moment {
+            date: datetime.date_naive(),
+            time: Some(datetime.time()),
+        });
+    }
+    let (date, time) = match TIME.find(text) {
+        Some(found) => {
+            let date = text[..found.start()].trim_end_matches('T');
+            (date, Some(parse_time(found.as_str())?))
+        }
+        None => (text, None),
+    };
+    let date = parse_date(date, now.date_naive(), after)?;
+    let time = match text.to_lowercase().as_str() {
+        "now" | "现在" => Some(now.time()),
+        _ => time,
+    };
+    Ok(Moment { date, time })
+}
+
+struct Calculator<'a> {
+    now: DateTime<FixedOffset>,
+    calendar: &'a HolidayCalendar,
+    calendar_name: String,
+    //涉及到的日历未覆盖的年份
+    uncovered_years: BTreeSet<i32>,
+}
+
+impl Calculator<'_> {
+    fn check_year(&mut self, date: &NaiveDate) {
+        if !self.calendar.covers(date.year()) {
+            self.uncovered_years.insert(date.year());
+        }
+    }
+
+    fn describe(&mut self, moment: &Moment) -> serde_json::Value {
+        self.check_year(&moment.date);
+        let date = &moment.date;
+        let iso_week = date.iso_week();
+        let mut value = serde_json::json!({
+            "date": date.format("%Y-%m-%d").to_string(),
+            "weekday": WEEKDAY_NAMES[date.weekday().num_days_from_monday() as usize],
+            "iso_week": format!("{}-W{:02}", iso_week.year(), iso_week.week()),
+            "day_of_year": date.ordinal(),
+            "is_workday": self.calendar.is_workday(date),
+        });
+        if let Some(holiday) = self.calendar.holiday(date) {
+            value["holiday"] = holiday.into();
+        }
+        if moment.time.is_some() {
+            let datetime = moment
+                .datetime()
+                .and_local_timezone(*self.now.offset())
+                .unwrap();
+            value["datetime"] = datetime.to_rfc3339().into();
+        }
+        value
+    }
+
+    //start之后到end（含）之间的工作日数，end早于start时为负数
+    fn business_days_between(&mut self, start: NaiveDate, end: NaiveDate) -> Result<i64> {
+        let (from, to, sign) = match start <= end {
+            true => (start, end, 1),
+            false => (end, start, -1),
+        };
+        if (to - from).num_days() > MAX_DAYS {
+            return Err(anyhow::anyhow!("日期间隔过大"));
+        }
+        let mut count = 0;
+        let mut date = from;
+        while date < to {
+            date = date
+                .succ_opt()
+                .ok_or_else(|| anyhow::anyhow!("日期超出范围"))?;
+            self.check_year(&date);
+            if self.calendar.is_workday(&date) {
+                count += 1;
+            }
+        }
+        Ok(sign * count)
+    }
+
+    fn add_business_days(&mut self, start: NaiveDate, amount: i64) -> Result<NaiveDate> {
+        let step = if amount < 0 { -1 } else { 1 };
+        let mut date = start;
+        let mut remaining = amount.abs();
+        let mut days = 0;
+        while remaining > 0 {
+            date = add_days(date, step)?;
+            self.check_year(&date);
+            if self.calendar.is_workday(&date) {
+                remaining -= 1;
+            }
+            days += 1;
+            if days > MAX_DAYS {
+                return Err(anyhow::anyhow!("工作日数过大"));
+            }
+        }
+        Ok(date)
+    }
+
+    fn moment(&self, text: &Option<String>) -> Result<Moment> {
+        parse_moment(text.as_deref().unwrap_or("今天"), &self.now, None)
+    }
+
+    fn run(&mut self, parameters: &DatetimeParameters) -> Result<serde_json::Value> {
+        let mut value = match parameters.operation {
+            Operation::Now => {
+                let now = Moment {
+                    date: self.now.date_naive(),
+                    time: Some(self.now.time()),
+                };
+                self.describe(&now)
+            }
+            Operation::Parse => {
+                let moment = self.moment(&parameters.date)?;
+                self.describe(&moment)
+            }
+            Operation::Diff => {
+                let start = self.moment(&parameters.date)?;
+                let end = parameters
+                    .end
+                    .as_ref()
+                    .ok_or_else(|| anyhow::anyhow!("diff需要提供end"))?;
+                //"到3月3日还有几天"指的是下一个3月3日
+                let end = parse_moment(end, &self.now, Some(start.date))?;
+                let days = (end.date - start.date).num_days();
+                //整月数：从start开始加上若干个月不超过end
+                let mut months = (end.date.year() - start.date.year()) as i64 * 12
+                    + end.date.month() as i64
+                    - start.date.month() as i64;
+                while months > 0 && add_months(start.date, months)? > end.date {
+                    months -= 1;
+                }
+                while months < 0 && add_months(start.date, months)? < end.date {
+                    months += 1;
+                }
+                let remaining_days = (end.date - add_months(start.date, months)?).num_days();
+                let mut value = serde_json::json!({
+                    "start": self.describe(&start),
+                    "end": self.describe(&end),
+                    "days": days,
+                    "weeks": days / 7,
+                    "remaining_days_after_weeks": days % 7,
+                    "months": months,
+                    "remaining_days_after_months": remaining_days,
+                    "business_days": self.business_days_between(start.date, end.date)?,
+                });
+                if start.time.is_some() || end.time.is_some() {
+                    let duration = end.datetime() - start.datetime();
+                    value["hours"] = (duration.num_minutes() as f64 / 60.0).into();
+                    value["minutes"] = duration.num_minutes().into();
+                }
+                value
+            }
+            Operation::Add => {
+                let start = self.moment(&parameters.date)?;
+                let amount = parameters
+                    .amount
+                    .ok_or_else(|| anyhow::anyhow!("add需要提供amount"))?;
+                let unit = parameters.unit.unwrap_or(DurationUnit::Days);
+                let result = match unit {
+                    DurationUnit::Days => Moment {
+                        date: add_days(start.date, amount)?,
+                        ..start
+                    },
+                    DurationUnit::Weeks => Moment {
+                        date: add_days(start.date, amount.saturating_mul(7))?,
+                        ..start
+                    },
+                    DurationUnit::Months => Moment {
+                        date: add_months(start.date, amount)?,
+                        ..start
+                    },
+                    DurationUnit::Years => Moment {
+                        date: add_months(start.date, amount.saturating_mul(12))?,
+                        ..start
+                    },
+                    DurationUnit::BusinessDays => Moment {
+                        date: self.add_business_days(start.date, amount)?,
+                        ..start
+                    },
+                    DurationUnit::Hours | DurationUnit::Minutes => {
+                        let minutes = match unit {
+                            DurationUnit::Hours => amount.saturating_mul(60),
+                            _ => amount,
+                        };
+                        let duration = Duration::try_minutes(minutes)
+                            .ok_or_else(|| anyhow::anyhow!("时长超出范围"))?;
+                        let datetime = start
+                            .datetime()
+                            .checked_add_signed(duration)
+                            .ok_or_else(|| anyhow::anyhow!("日期超出范围"))?;
+                        Moment {
+                            date: datetime.date(),
+                            time: Some(datetime.time()),
+                        }
+                    }
+                };
+                serde_json::json!({
+                    "start": self.describe(&start),
+                    "result": self.describe(&result),
+                })
+            }
+            Operation::Format => {
+                let moment = self.moment(&parameters.date)?;
+                let format = parameters.format.as_deref().unwrap_or("%Y-%m-%d");
+                let datetime = moment
+                    .datetime()
+                    .and_local_timezone(*self.now.offset())
+                    .unwrap();
+                let mut formatted = String::new();
+                write!(formatted, "{}", datetime.format(format))
+                    .map_err(|_| anyhow::anyhow!("无效的格式: {}", format))?;
+                serde_json::json!({
+                    "date": self.describe(&moment),
+                    "formatted": formatted,
+                })
+            }
+        };
+        value["timezone"] = self.now.offset().to_string().into();
+        if !self.uncovered_years.is_empty() {
+            let years: Vec<String> = self.uncovered_years.iter().map(|x| x.to_string()).collect();
+            value["note"] = format!(
+                "节假日日历{}没有{}年的数据，这些年份只按周末判断工作日",
+                self.calendar_name,
+                years.join("、")
+            )
+            .into();
+        }
+        Ok(value)
+    }
+}
+
+fn run(
+    parameters: &DatetimeParameters,
+    config: &DatetimeConfig,
+    now: DateTime<Utc>,
+) -> Result<serde_json::Value> {
+    let timezone = parameters
+        .timezone
+        .as_deref()
+        .unwrap_or(&config.default_timezone);
+    let calendar_name = parameters
+        .calendar
+        .clone()
+        .unwrap_or(config.default_calendar.clone());
+    let calendar = config.calendars.get(&calendar_name).ok_or_else(|| {
+        let names: Vec<&String> = config.calendars.keys().collect();
+        anyhow::anyhow!(
+            "没有名为 {} 的节假日日历，可用的日历: {:?}",
+            calendar_name,
+            names
+        )
+    })?;
+    let mut calculator = Calculator {
+        now: now.with_timezone(&parse_timezone(timezone)?),
+        calendar,
+        calendar_name,
+        uncovered_years: BTreeSet::new(),
+    };
+    calculator.run(parameters)
+}
+
+pub struct DatetimeFunction {}
+
+impl Function for DatetimeFunction {
+    fn execute(&self, parameters: serde_json::Value, _context: &Context) -> Result<String> {
+        let parameters: DatetimeParameters = serde_json::from_value(parameters)?;
+        let config = DatetimeConfig::load(DATETIME_CONFIG_PATH)?;
+        let result = run(&parameters, &config, Utc::now())?;
+        Ok(result.to_string())
+    }
+
+    fn if_postprocess(&self) -> bool {
+        true
+    }
+
+    fn get_name(&self) -> String {
+        "datetime".to_string()
+    }
+
+    fn get_description(&self) -> String {
+        "日期、时间和日历问题使用该函数，不要自己推算：now当前时间；parse日期的星期、是否工作日、节日；diff两个日期相差的天数、月数和工作日数；add加减时长或工作日；format格式化日期。工作日按中国法定节假日和调休计算。".to_string()
+    }
+
+    fn get_parameter_schema(&self) -> RootSchema {
+        schema_for!(DatetimeParameters)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        parse_moment, parse_timezone, run, DatetimeConfig, DatetimeParameters, Operation,
+        DATETIME_CONFIG_PATH,
+    };
+    use chrono::{DateTime, NaiveDate, TimeZone, Utc};
+
+    fn now() -> DateTime<Utc> {
+        //北京时间 2025-03-05 10:00，星期三
+        Utc.with_ymd_and_hms(2025, 3, 5, 2, 0, 0).unwrap()
+    }
+
+    fn parameters(operation: Operation, date: &str) -> DatetimeParameters {
+        DatetimeParameters {
+            operation,
+            date: Some(date.to_string()),
+            end: None,
+            amount: None,
+            unit: None,
+            format: None,
+            timezone: None,
+            calendar: None,
+        }
+    }
+
+    #[test]
+    fn test_parse_moment() {
+        let now = now().with_timezone(&parse_timezone("Asia/Shanghai").unwrap());
+        let date = |text: &str| parse_moment(text, &now, None).unwrap().date;
+        let ymd = |y, m, d| NaiveDate::from_ymd_opt(y, m, d).unwrap();
+        assert_eq!(date("2025-12-25"), ymd(2025, 12, 25));
+        assert_eq!(date("2025年12月25日"), ymd(2025, 12, 25));
+        assert_eq!(date("3月3号"), ymd(2025, 3, 3));
+        assert_eq!(date("March 3rd, 2026"), ymd(2026, 3, 3));
+        assert_eq!(date("明天"), ymd(2025, 3, 6));
+        assert_eq!(date("下周一"), ymd(2025, 3, 10));
+        assert_eq!(date("周日"), ymd(2025, 3, 9));
+        assert_eq!(date("10天后"), ymd(2025, 3, 15));
+        assert_eq!(date("1 month ago"), ymd(2025, 2, 5));
+        let moment = parse_moment("2025-12-25 下午3点半", &now, None).unwrap();
+        assert_eq!(moment.time.unwrap().to_string(), "15:30:00");
+        let moment = parse_moment("2025-01-01T00:00:00Z", &now, None).unwrap();
+        assert_eq!(moment.time.unwrap().to_string(), "08:00:00");
+        assert!(parse_moment("2025-02-30", &now, None).is_err());
+        assert!(parse_timezone("America/New_York").is_err());
+        assert_eq!(
+            parse_timezone("UTC-5").unwrap().local_minus_utc(),
+            -5 * 3600
+        );
+    }
+
+    #[test]
+    fn test_run() {
+        let config = DatetimeConfig::load(DATETIME_CONFIG_PATH).unwrap();
+        let result = run(&parameters(Operation::Parse, "2025-10-01"), &config, now()).unwrap();
+        assert_eq!(result["weekday"], "星期三");
+        assert_eq!(result["is_workday"], false);
+        assert_eq!(result["holiday"], "国庆节、中秋节");
+        //调休上班的周日
+        let result = run(&parameters(Operation::Parse, "2025-09-28"), &config, now()).unwrap();
+        assert_eq!(result["is_workday"], true);
+        //9月28日之后到10月11日，上班的有9月29、30日，10月9、10日和调休的11日
+        let mut diff = parameters(Operation::Diff, "2025-09-28");
+        diff.end = Some("2025-10-11".to_string());
+        let result = run(&diff, &config, now()).unwrap();
+        assert_eq!(result["days"], 13);
+        assert_eq!(result["business_days"], 5);
+        let mut add = parameters(Operation::Add, "2025-09-30");
+        add.amount = Some(1);
+        add.unit = Some(super::DurationUnit::BusinessDays);
+        let result = run(&add, &config, now()).unwrap();
+        assert_eq!(result["result"]["date"], "2025-10-09");
+        let result = run(&parameters(Operation::Parse, "2030-01-01"), &config, now()).unwrap();
+        assert!(result["note"].as_str().unwrap().contains("2030"));
+        let mut format = parameters(Operation::Format, "2025-12-25");
+        format.format = Some("%Y年%m月%d日".to_string());
+        let result = run(&format, &config, now()).unwrap();
+        assert_eq!(result["formatted"], "2025年12月25日");
+    }
+
+    #[test]
+    fn test_diff_without_year() {
+        let config = DatetimeConfig::load(DATETIME_CONFIG_PATH).unwrap();
+        //北京时间 2026-10-19
+        let now = Utc.with_ymd_and_hms(2026, 10, 19, 2, 0, 0).unwrap();
+        let mut diff = parameters(Operation::Diff, "今天");
+        diff.end = Some("March 3".to_string());
+        let result = run(&diff, &config, now).unwrap();
+        assert_eq!(result["end"]["date"], "2027-03-03");
+        diff.end = Some("12月25日".to_string());
+        let result = run(&diff, &config, now).unwrap();
+        assert_eq!(result["end"]["date"], "2026-12-25");
+        let mut diff = parameters(Operation::Diff, "12月25日");
+        diff.end = Some("1月5日".to_string());
+        let result = run(&diff, &config, now).unwrap();
+        assert_eq!(result["days"], 11);
+        let result = run(&parameters(Operation::Parse, "March 3"), &config, now).unwrap();
+        assert_eq!(result["date"], "2026-03-03");
+    }
+}
@@ -0,0 +1,93 @@
+use super::function::{block_on, Capability, Context, Function};
+use crate::{
+    knowledge_base::read_document,
+    outline::{build_outline, OutlineNode},
+};
+use anyhow::Result;
+use schemars::{schema::RootSchema, schema_for, JsonSchema};
+use serde::{Deserialize, Serialize};
+
+#[derive(JsonSchema, Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
+#[serde(default)]
+struct DocumentOutlineParameters {
+    /// 只返回不深于该层级的标题，1为文档中最高一级的标题；不填则返回全部层级
+    max_level: Option<usize>,
+    /// 关联知识库中的文档名，不填则为会话上传的文档
+    document: Option<String>,
+}
+
+//层级相对于文档中最浅的标题计算，例如pptx的每页幻灯片是二级标题，max_level为1时返回所有幻灯片
+fn limit_depth(outline: &mut Vec<OutlineNode>, max_level: usize) {
+    let Some(top) = outline.iter().map(|x| x.level).min() else {
+        return;
+    };
+    let deepest = top + max_level.max(1) - 1;
+    outline.retain(|x| x.level <= deepest);
+    let mut stack: Vec<_> = outline.iter_mut().collect();
+    while let Some(node) = stack.pop() {
+        node.children.retain(|x| x.level <= deepest);
+        stack.extend(node.children.iter_mut());
+    }
+}
+
+pub struct DocumentOutlineFunction {}
+
+impl Function for DocumentOutlineFunction {
+    fn execute(&self, parameters: serde_json::Value, context: &Context) -> Result<String> {
+        let parameters: DocumentOutlineParameters = serde_json::from_value(parameters)?;
+        let documents = block_on(read_document(
+            &context.db,
+            context.session_id,
+            parameters.document.as_deref(),
+        ))?;
+        let mut outline = build_outline(&documents);
+        if let Some(max_level) = parameters.max_level {
+            limit_depth(&mut outline, max_level);
+        }
+        if outline.is_empty() {
+            return Ok("文档中没有识别到章节标题".to_string());
+        }
+        Ok(serde_json::to_string(&outline)?)
+    }
+
+    fn if_postprocess(&self) -> bool {
+        true
+    }
+
+    fn get_name(&self) -> String {
+        "document_outline".to_string()
+    }
+
+    fn get_description(&self) -> String {
+        "获取上传文档的章节目录，返回标题树，包含每个标题的层级和所在页码。当用户想了解文档结构，或需要先确定章节再对某个章节进行总结时，可以调用该函数。".to_string()
+    }
+
+    fn get_parameter_schema(&self) -> RootSchema {
+        schema_for!(DocumentOutlineParameters)
+    }
+
+    fn get_capabilities(&self) -> Vec<Capability> {
+        vec![Capability::Documents]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::limit_depth;
+    use crate::outline::build_outline;
+
+    #[test]
+    fn test_limit_depth_pptx() {
+        let text = "## Slide 1\n### 议程\n内容\n## Slide 2\n### 市场分析\n#### 竞品\n内容\n";
+        let mut outline = build_outline(text);
+        limit_depth(&mut outline, 1);
+        assert_eq!(outline.len(), 2);
+        assert!(outline.iter().all(|x| x.children.is_empty()));
+        assert_eq!(outline[1].page, 2);
+
+        let mut outline = build_outline(text);
+        limit_depth(&mut outline, 2);
+        assert_eq!(outline[1].children[0].title, "市场分析");
+        assert!(outline[1].children[0].children.is_empty());
+    }
+}
@@ -0,0 +1,106 @@
+use super::function::{block_on, Capability, Context, Function};
+use crate::{
+    index::{index_path, knowledge_base_index_path, HybridIndex, SearchHit},
+    knowledge_base::attached_knowledge_bases,
+};
+use anyhow::Result;
+use schemars::{schema::RootSchema, schema_for, JsonSchema};
+use serde::{Deserialize, Serialize};
+
+fn default_top_k() -> usize {
+    5
+}
+
+#[derive(JsonSchema, Debug, Clone, Serialize, Deserialize, PartialEq)]
+struct DocumentSearchParameters {
+    /// 检索的内容，可以是问题、关键词、产品型号或人名
+    query: String,
+    /// 返回的片段数量
+    #[serde(default = "default_top_k")]
+    top_k: usize,
+}
+
+pub struct DocumentSearchFunction {}
+
+impl Function for DocumentSearchFunction {
+    fn execute(&self, parameters: serde_json::Value, context: &Context) -> Result<String> {
+        let parameters: DocumentSearchParameters = serde_json::from_value(parameters)?;
+        //会话中上传的文档和会话关联的所有知识库一起检索
+        let mut sources = Vec::new();
+        if let Ok(index) = HybridIndex::load(&index_path(context.session_id)) {
+            sources.push(("当前会话文档".to_string(), index));
+        }
+        for knowledge_base in block_on(attached_knowledge_bases(&context.db, context.session_id))? {
+            if let Ok(index) =
+                HybridIndex::load(&knowledge_base_index_path(knowledge_base.knowledge_base_id))
+            {
+                sources.push((format!("知识库: {}", knowledge_base.name), index));
+            }
+        }
+        if sources.is_empty() {
+            return Err(anyhow::anyhow!("当前会话没有上传文档，也没有关联知识库"));
+        }
+        //向量化失败时退化为只用关键词检索
+        let query_embedding = context
+            .embedding_endpoint
+            .invoke(&vec![parameters.query.clone()], None)
+            .and_then(|x| x.get_embedding_results())
+            .ok()
+            .and_then(|mut x| x.pop());
+        let top_k = parameters.top_k.clamp(1, 20);
+        //各索引的RRF分数尺度一致，可以直接按分数合并
+        let mut hits: Vec<(&str, SearchHit)> = sources
+            .iter()
+            .flat_map(|(source, index)| {
+                index
+                    .search(&parameters.query, query_embedding.as_deref(), top_k)
+                    .into_iter()
+                    .map(move |hit| (source.as_str(), hit))
+            })
+            .collect();
+        hits.sort_by(|a, b| b.1.score.total_cmp(&a.1.score));
+        hits.truncate(top_k);
+        if hits.is_empty() {
+            return Ok("没有在文档中找到相关内容".to_string());
+        }
+        //来源依次为会话文档或知识库、知识库中的文档名、片段所在的标题
+        let result = hits
+            .iter()
+            .enumerate()
+            .map(|(rank, (source, hit))| {
+                let location = [
+                    Some(*source),
+                    hit.chunk.document.as_deref(),
+                    hit.chunk.heading.as_deref(),
+                ]
+                .into_iter()
+                .flatten()
+                .collect::<Vec<_>>()
+                .join(" / ");
+                format!("[{}] ({})\n{}", rank + 1, location, hit.chunk.text)
+            })
+            .collect::<Vec<String>>()
+            .join("\n\n");
+        Ok(result)
+    }
+
+    fn if_postprocess(&self) -> bool {
+        true
+    }
+
+    fn get_name(&self) -> String {
+        "document_search".to_string()
+    }
+
+    fn get_description(&self) -> String {
+        "在用户上传的文档和会话关联的知识库中检索与问题相关的片段，同时使用关键词匹配和语义匹配，适合回答文档中的具体问题，或查找产品型号、人名等关键词。".to_string()
+    }
+
+    fn get_parameter_schema(&self) -> RootSchema {
+        schema_for!(DocumentSearchParameters)
+    }
+
+    fn get_capabilities(&self) -> Vec<Capability> {
+        vec![Capability::Documents]
+    }
+}
@@ -1,76 +1,414 @@
-use super::function::{Context, Function};
+use super::function::{block_on, Capability, Context, Function};
+use crate::{
+    chunker::{self, ChunkerConfig},
+    entities::{prelude::SummaryCache, summary_cache},
+    knowledge_base::read_document,
+    outline::{build_outline, find_section, page_ranges},
+    storage::sha256_hex,
+};
 use anyhow::Result;
 use erniebot_rs::chat::{ChatEndpoint, Message, Role};
 use schemars::{schema::RootSchema, schema_for, JsonSchema};
+use sea_orm::{ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, Set};
 use serde::{Deserialize, Serialize};
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Mutex,
+};
+use tracing::{debug, info};
 
-#[derive(JsonSchema, Debug, Clone, Serialize, Deserialize, PartialEq)]
-struct DocumentSummaryFunctionParameters {}
+#[derive(JsonSchema, Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+enum SummaryStyle {
+    BulletPoints,
+    Abstract,
+    ExecutiveSummary,
+}
+
+#[derive(JsonSchema, Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
+#[serde(default)]
+struct DocumentSummaryFunctionParameters {
+    /// 只总结某个章节，填写document_outline返回的章节标题或标题中的关键词；不填则总结全文
+    section: Option<String>,
+    /// 只总结从该页开始的内容，页码从1开始，pdf按页、pptx按幻灯片计算
+    start_page: Option<usize>,
+    /// 只总结到该页为止的内容(包含该页)
+    end_page: Option<usize>,
+    /// 建议的摘要字数
+    length: Option<usize>,
+    /// 摘要使用的语言，如"中文"、"English"；不填则与文档一致
+    language: Option<String>,
+    /// 摘要的形式：bullet_points为要点列表，abstract为一段式摘要，executive_summary为面向决策者的执行摘要
+    style: Option<SummaryStyle>,
+    /// 关联知识库中的文档名，不填则为会话上传的文档
+    document: Option<String>,
+}
+
+//填入模板的摘要要求
+struct SummaryRequest {
+    suggest_summary_length: String,
+    requirements: String,
+}
+
+impl SummaryRequest {
+    fn new(
+        parameters: &DocumentSummaryFunctionParameters,
+        summary_config: &DocumentSummaryConfig,
+    ) -> Self {
+        let suggest_summary_length = parameters
+            .length
+            .map_or(summary_config.suggest_summary_length.to_string(), |x| {
+                x.to_string()
+            });
+        let mut requirements = String::new();
+        if let Some(language) = &parameters.language {
+            requirements.push_str(&format!("请使用{}输出总结。", language));
+        }
+        match parameters.style {
+            Some(SummaryStyle::BulletPoints) => {
+                requirements.push_str("请以要点列表的形式输出总结，每个要点占一行。")
+            }
+            Some(SummaryStyle::Abstract) => {
+                requirements.push_str("请以一段连贯的摘要形式输出总结，不要分点。")
+            }
+            Some(SummaryStyle::ExecutiveSummary) => requirements.push_str(
+                "请以面向决策者的执行摘要形式输出总结，先给出核心结论，再列出关键依据和建议。",
+            ),
+            None => {}
+        }
+        Self {
+            suggest_summary_length,
+            requirements,
+        }
+    }
+
+    fn fill(&self, template: &str) -> String {
+        template
+            .replace("{{suggest_summary_length}}", &self.suggest_summary_length)
+            .replace("{{requirements}}", &self.requirements)
+    }
+}
+
+const SUMMARY_TEMPLATES: [&str; 3] = [
+    "templates/summary.template",
+    "templates/summary_map.template",
+    "templates/summary_reduce.template",
+];
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+enum SummaryStrategy {
+    //逐段生成摘要，每段都参考前文的摘要
+    #[default]
+    Refine,
+    //并发生成各段摘要，再逐层合并
+    MapReduce,
+}
+
+fn default_max_concurrency() -> usize {
+    4
+}
+
+fn default_merge_group_size() -> usize {
+    4
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 struct DocumentSummaryConfig {
-    max_chunk_length: i32,
+    #[serde(default)]
+    strategy: SummaryStrategy,
+    //每个片段最多的token数，按chunker::estimate_tokens估算
+    #[serde(alias = "max_chunk_length")]
+    max_chunk_tokens: usize,
+    #[serde(default)]
+    chunk_overlap_tokens: usize,
     suggest_summary_length: i32,
+    #[serde(default = "default_max_concurrency")]
+    max_concurrency: usize,
+    #[serde(default = "default_merge_group_size")]
+    merge_group_size: usize,
 }
 
 pub struct DocumentSummaryFunction {}
 
 impl DocumentSummaryFunction {
-    fn get_documents(&self, session_id: i32) -> Result<String> {
-        let filepath = format!("./files/{}.txt", session_id);
-        let res = std::fs::read_to_string(filepath)?;
+    fn get_documents(&self, context: &Context, document: Option<&str>) -> Result<String> {
+        let res = block_on(read_document(&context.db, context.session_id, document))?;
         Ok(res)
     }
-    //逐步生成摘要
-    fn get_summary(&self, chat_endpoint: &ChatEndpoint, documents: &str) -> Result<String> {
-        let summary_template = std::fs::read_to_string("templates/summary.template")?;
-        println!("summary_template: {:?}", summary_template);
-        let summary_config_string = std::fs::read_to_string("configs/summary_config.json")?;
-        println!("summary_config_string: {:?}", summary_config_string);
-        let summary_config: DocumentSummaryConfig = serde_json::from_str(&summary_config_string)?;
 
-        println!("summary_config: {:?}", summary_config);
-        let chars_vec: Vec<char> = documents.chars().collect();
-        let chars_len = chars_vec.len();
-        println!("Document length: {}", chars_len);
-        let mut previous_summary = String::new();
-        let mut start = 0;
-        while start < chars_len {
-            let mut segment_end = start + summary_config.max_chunk_length as usize;
-            if segment_end > chars_len {
-                segment_end = chars_len;
+    fn chat(&self, chat_endpoint: &ChatEndpoint, request_string: String) -> Result<String> {
+        let message = Message {
+            role: Role::User,
+            content: request_string,
+            ..Default::default()
+        };
+        let options = Vec::new();
+        debug!("Request: {:?}", message.content);
+        let response = chat_endpoint.invoke(&vec![message], &options)?;
+        Ok(response.get_chat_result()?.to_string())
+    }
+
+    //根据章节或页码范围选出需要总结的文本
+    fn select_text<'a>(
+        &self,
+        documents: &'a str,
+        parameters: &DocumentSummaryFunctionParameters,
+    ) -> Result<&'a str> {
+        if let Some(section) = &parameters.section {
+            let outline = build_outline(documents);
+            return match find_section(&outline, section) {
+                Some(node) => Ok(&documents[node.start..node.end]),
+                None => Err(anyhow::anyhow!("Section not found: {}", section)),
+            };
+        }
+        if parameters.start_page.is_none() && parameters.end_page.is_none() {
+            return Ok(documents);
+        }
+        let pages = page_ranges(documents);
+        let start_page = parameters.start_page.unwrap_or(1).max(1);
+        let end_page = parameters.end_page.unwrap_or(pages.len()).min(pages.len());
+        if start_page > end_page {
+            return Err(anyhow::anyhow!(
+                "Invalid page range, the document has {} pages",
+                pages.len()
+            ));
+        }
+        Ok(&documents[pages[start_page - 1].0..pages[end_page - 1].1])
+    }
+
+    //配置、所有模板内容和调用参数的哈希，任何一个改变都会使已缓存的摘要失效
+    fn get_settings_hash(
+        &self,
+        summary_config_string: &str,
+        parameters: &DocumentSummaryFunctionParameters,
+    ) -> Result<String> {
+        let mut settings = summary_config_string.to_string();
+        settings.push('\n');
+        settings.push_str(&serde_json::to_string(parameters)?);
+        for template in SUMMARY_TEMPLATES.iter() {
+            settings.push('\n');
+            settings.push_str(&std::fs::read_to_string(template)?);
+        }
+        Ok(sha256_hex(settings.as_bytes()))
+    }
+
+    fn get_cached_summary(
+        &self,
+        db: &DatabaseConnection,
+        document_hash: &str,
+        settings_hash: &str,
+    ) -> Result<Option<String>> {
+        let cached = block_on(
+            SummaryCache::find()
+                .filter(summary_cache::Column::DocumentHash.eq(document_hash))
+                .filter(summary_cache::Column::SettingsHash.eq(settings_hash))
+                .one(db),
+        )?;
+        Ok(cached.map(|x| x.summary))
+    }
+
+    fn save_cached_summary(
+        &self,
+        db: &DatabaseConnection,
+        document_hash: &str,
+        settings_hash: &str,
+        summary: &str,
+    ) -> Result<()> {
+        let cache = summary_cache::ActiveModel {
+            document_hash: Set(document_hash.to_string()),
+            settings_hash: Set(settings_hash.to_string()),
+            summary: Set(summary.to_string()),
+            create_time: Set(chrono::Utc::now()),
+            ..Default::default()
+        };
+        block_on(SummaryCache::insert(cache).exec(db))?;
+        Ok(())
+    }
+
+    fn get_summary(
+        &self,
+        chat_endpoint: &ChatEndpoint,
+        summary_config_string: &str,
+        documents: &str,
+        parameters: &DocumentSummaryFunctionParameters,
+    ) -> Result<String> {
+        debug!("summary_config_string: {:?}", summary_config_string);
+        let summary_config: DocumentSummaryConfig = serde_json::from_str(summary_config_string)?;
+        debug!("summary_config: {:?}", summary_config);
+        let request = SummaryRequest::new(parameters, &summary_config);
+
+        info!("Document tokens: {}", chunker::estimate_tokens(documents));
+        let chunker_config = ChunkerConfig {
+            max_tokens: summary_config.max_chunk_tokens,
+            overlap_tokens: summary_config.chunk_overlap_tokens,
+        };
+        let segments: Vec<String> = chunker::split(documents, &chunker_config)
+            .into_iter()
+            .map(|x| x.text)
+            .collect();
+        match summary_config.strategy {
+            SummaryStrategy::Refine => self.refine_summary(chat_endpoint, &request, &segments),
+            SummaryStrategy::MapReduce => {
+                self.map_reduce_summary(chat_endpoint, &summary_config, &request, &segments)
             }
-            let segment = chars_vec[start..segment_end].iter().collect::<String>();
+        }
+    }
+
+    //逐步生成摘要
+    fn refine_summary(
+        &self,
+        chat_endpoint: &ChatEndpoint,
+        request: &SummaryRequest,
+        segments: &[String],
+    ) -> Result<String> {
+        let summary_template =
+            request.fill(&std::fs::read_to_string("templates/summary.template")?);
+        let mut previous_summary = String::new();
+        for segment in segments.iter() {
             let request_string = summary_template
-                .replace(
-                    "{{suggest_summary_length}}",
-                    &summary_config.suggest_summary_length.to_string(),
-                )
                 .replace("{{previous_summary}}", &previous_summary)
-                .replace("{{current_text}}", &segment);
-
-            let message = Message {
-                role: Role::User,
-                content: request_string,
-                ..Default::default()
-            };
-            let options = Vec::new();
-            println!("Request: {:?}", message.content);
-            let response = chat_endpoint.invoke(&vec![message], &options)?;
-            previous_summary = response.get_chat_result()?.to_string();
-            start += summary_config.max_chunk_length as usize;
+                .replace("{{current_text}}", segment);
+            previous_summary = self.chat(chat_endpoint, request_string)?;
         }
         Ok(previous_summary)
     }
+
+    //先并发地对每个片段生成摘要，再每merge_group_size个摘要合并为一个，直到只剩一个
+    fn map_reduce_summary(
+        &self,
+        chat_endpoint: &ChatEndpoint,
+        summary_config: &DocumentSummaryConfig,
+        request: &SummaryRequest,
+        segments: &[String],
+    ) -> Result<String> {
+        let map_template =
+            request.fill(&std::fs::read_to_string("templates/summary_map.template")?);
+        let reduce_template = request.fill(&std::fs::read_to_string(
+            "templates/summary_reduce.template",
+        )?);
+        let mut summaries =
+            run_concurrently(segments, summary_config.max_concurrency, |segment| {
+                let request_string = map_template.replace("{{current_text}}", segment);
+                self.chat(chat_endpoint, request_string)
+            })?;
+        let merge_group_size = summary_config.merge_group_size.max(2);
+        while summaries.len() > 1 {
+            info!("Merging {} partial summaries", summaries.len());
+            let groups: Vec<&[String]> = summaries.chunks(merge_group_size).collect();
+            summaries = run_concurrently(&groups, summary_config.max_concurrency, |group| {
+                if group.len() == 1 {
+                    return Ok(group[0].clone());
+                }
+                let numbered = group
+                    .iter()
+                    .enumerate()
+                    .map(|(index, summary)| format!("第{}部分：{}", index + 1, summary))
+                    .collect::<Vec<String>>()
+                    .join("\n");
+                let request_string = reduce_template.replace("{{summaries}}", &numbered);
+                self.chat(chat_endpoint, request_string)
+            })?;
+        }
+        Ok(summaries.pop().unwrap_or_default())
+    }
+
+    fn summarize(
+        &self,
+        context: &Context,
+        documents: &str,
+        parameters: &DocumentSummaryFunctionParameters,
+    ) -> Result<String> {
+        let summary_config_string = std::fs::read_to_string("configs/summary_config.json")?;
+        let document_hash = sha256_hex(documents.as_bytes());
+        let settings_hash = self.get_settings_hash(&summary_config_string, parameters)?;
+        if let Some(summary) =
+            self.get_cached_summary(&context.db, &document_hash, &settings_hash)?
+        {
+            info!("使用缓存的文档摘要");
+            return Ok(summary);
+        }
+        let text = self.select_text(documents, parameters)?;
+        let summary = self.get_summary(
+            &context.chat_endpoint,
+            &summary_config_string,
+            text,
+            parameters,
+        )?;
+        //同一文档可能被并发地摘要，缓存写入失败不影响本次结果
+        if let Err(e) =
+            self.save_cached_summary(&context.db, &document_hash, &settings_hash, &summary)
+        {
+            info!("save summary cache failed: {:?}", e);
+        }
+        Ok(summary)
+    }
+}
+
+//对会话文档以外的文本（如网页正文）生成摘要，与文档摘要共用配置、模板和缓存
+pub fn summarize_text(
+    context: &Context,
+    text: &str,
+    length: Option<usize>,
+    language: Option<String>,
+) -> Result<String> {
+    let parameters = DocumentSummaryFunctionParameters {
+        length,
+        language,
+        ..Default::default()
+    };
+    DocumentSummaryFunction {}.summarize(context, text, &parameters)
+}
+
+//最多使用max_concurrency个线程处理inputs，结果与inputs一一对应；任意一个失败则返回错误
+pub fn run_concurrently<T, F>(inputs: &[T], max_concurrency: usize, f: F) -> Result<Vec<String>>
+where
+    T: Sync,
+    F: Fn(&T) -> Result<String> + Sync,
+{
+    let next_index = AtomicUsize::new(0);
+    let results: Vec<Mutex<Option<Result<String>>>> =
+        inputs.iter().map(|_| Mutex::new(None)).collect();
+    let workers = max_concurrency.clamp(1, inputs.len().max(1));
+    std::thread::scope(|scope| {
+        for _ in 0..workers {
+            scope.spawn(|| loop {
+                let index = next_index.fetch_add(1, Ordering::SeqCst);
+                if index >= inputs.len() {
+                    break;
+                }
+                let result = f(&inputs[index]);
+                let failed = result.is_err();
+                *results[index].lock().unwrap() = Some(result);
+                if failed {
+                    //让其它线程尽快停止领取新的输入
+                    next_index.store(inputs.len(), Ordering::SeqCst);
+                    break;
+                }
+            });
+        }
+    });
+    let mut outputs = Vec::with_capacity(inputs.len());
+    let mut cancelled = false;
+    for result in results {
+        match result.into_inner().unwrap() {
+            Some(Ok(output)) => outputs.push(output),
+            Some(Err(e)) => return Err(e),
+            None => cancelled = true,
+        }
+    }
+    if cancelled {
+        return Err(anyhow::anyhow!("Task was cancelled"));
+    }
+    Ok(outputs)
 }
 
 impl Function for DocumentSummaryFunction {
-    fn execute(&self, _: serde_json::Value, context: &Context) -> Result<String> {
-        println!("开始执行文档摘要函数");
-        let session_id = context.session_id;
-        let documents = self.get_documents(session_id)?;
-        let summary = self.get_summary(&context.chat_endpoint, &documents)?;
-        Ok(summary.to_string())
+    fn execute(&self, parameters: serde_json::Value, context: &Context) -> Result<String> {
+        info!("开始执行文档摘要函数");
+        let parameters: DocumentSummaryFunctionParameters = serde_json::from_value(parameters)?;
+        let documents = self.get_documents(context, parameters.document.as_deref())?;
+        self.summarize(context, &documents, &parameters)
     }
 
     fn if_postprocess(&self) -> bool {
@@ -82,11 +420,39 @@ impl Function for DocumentSummaryFunction {
     }
 
     fn get_description(&self) -> String {
-        "当用户的问题想要生成文档摘要或者你判断问题可以从文档摘要中获得启发，可以调用该函数。"
+        "当用户的问题想要生成文档摘要或者你判断问题可以从文档摘要中获得启发，可以调用该函数。可以只总结某个章节或某几页，也可以指定摘要的字数、语言和形式；需要知道文档有哪些章节时，请先调用document_outline。"
             .to_string()
     }
 
     fn get_parameter_schema(&self) -> RootSchema {
         schema_for!(DocumentSummaryFunctionParameters)
     }
+
+    fn get_capabilities(&self) -> Vec<Capability> {
+        vec![Capability::Documents, Capability::Model]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::run_concurrently;
+
+    #[test]
+    fn test_run_concurrently_keeps_order() {
+        let inputs: Vec<i32> = (0..20).collect();
+        let outputs = run_concurrently(&inputs, 3, |x| Ok(x.to_string())).unwrap();
+        assert_eq!(
+            outputs,
+            inputs.iter().map(|x| x.to_string()).collect::<Vec<_>>()
+        );
+
+        let result = run_concurrently(&inputs, 3, |x| {
+            if *x == 5 {
+                Err(anyhow::anyhow!("failed"))
+            } else {
+                Ok(x.to_string())
+            }
+        });
+        assert!(result.is_err());
+    }
 }
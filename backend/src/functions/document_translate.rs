@@ -0,0 +1,497 @@
+use super::{
+    document_summary::run_concurrently,
+    function::{block_on, Capability, Context, Function},
+};
+use crate::{
+    chunker::{self, heading_level, ChunkerConfig},
+    derived_document::download_url,
+    entities::{
+        derived_document, job,
+        prelude::{DerivedDocument, Job},
+        sea_orm_active_enums::JobStatus,
+    },
+    knowledge_base::read_document,
+    outline::{build_outline, find_section},
+    storage::sha256_hex,
+};
+use anyhow::Result;
+use erniebot_rs::chat::{ChatEndpoint, Message, Role};
+use lazy_static::lazy_static;
+use regex::Regex;
+use schemars::{schema::RootSchema, schema_for, JsonSchema};
+use sea_orm::{ColumnTrait, EntityTrait, QueryFilter, QueryOrder, Set};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use tracing::{debug, info};
+
+pub const TRANSLATE_CONFIG_PATH: &str = "configs/translate_config.json";
+const TRANSLATE_TEMPLATE_PATH: &str = "templates/translate.template";
+const DERIVED_DOCUMENT_KIND: &str = "translation";
+//返回给模型的译文开头的字数
+const PREVIEW_CHARS: usize = 300;
+//译文改变了文档结构时重新翻译的提示
+const STRUCTURE_RETRY_NOTE: &str =
+    "\n注意：上一次的译文改变了原文的结构，请严格保持标题、列表项和表格行与原文一一对应。";
+
+lazy_static! {
+    static ref LIST_ITEM: Regex = Regex::new(r"^\s*([-*+•]|\d+[.)、])\s+\S").unwrap();
+}
+
+fn default_max_concurrency() -> usize {
+    4
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+struct TranslateConfig {
+    //每个片段最多的token数，按chunker::estimate_tokens估算
+    max_chunk_tokens: usize,
+    #[serde(default = "default_max_concurrency")]
+    max_concurrency: usize,
+    //领域术语表，原文中出现的术语（任意一侧）会连同译法一起放入提示词
+    #[serde(default)]
+    glossary: BTreeMap<String, String>,
+}
+
+#[derive(JsonSchema, Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
+#[serde(default)]
+struct DocumentTranslateFunctionParameters {
+    /// 目标语言，如"中文"、"English"
+    target_language: String,
+    /// 原文的语言；不填则由模型判断
+    source_language: Option<String>,
+    /// 只翻译某个章节，填写document_outline返回的章节标题或标题中的关键词；不填则翻译全文
+    section: Option<String>,
+    /// 本次翻译额外使用的术语表，key为原文术语，value为译法，会覆盖配置中的同名术语
+    glossary: BTreeMap<String, String>,
+    /// 关联知识库中的文档名，不填则为会话上传的文档
+    document: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum LineKind {
+    Heading(usize),
+    ListItem,
+    TableRow,
+}
+
+fn line_kind(line: &str) -> Option<LineKind> {
+    let trimmed = line.trim();
+    if trimmed.starts_with('#') {
+        return heading_level(trimmed).map(LineKind::Heading);
+    }
+    if trimmed.starts_with('|') {
+        return Some(LineKind::TableRow);
+    }
+    if LIST_ITEM.is_match(line) {
+        return Some(LineKind::ListItem);
+    }
+    None
+}
+
+//文本中的markdown标题、列表项和表格行，译文应与原文一致
+fn structure(text: &str) -> Vec<LineKind> {
+    text.lines().filter_map(line_kind).collect()
+}
+
+//待翻译的片段，separator是译文拼接时片段之前的分隔符
+#[derive(Debug, Clone, PartialEq)]
+struct Segment {
+    separator: &'static str,
+    text: String,
+}
+
+//以空行分隔的块为单位装满每个片段，表格、列表等块不会被拆到两个片段中；
+//块本身过长时按行拆分，单行仍然过长才按句子拆分
+fn split_segments(text: &str, max_tokens: usize) -> Vec<Segment> {
+    let max_tokens = max_tokens.max(1);
+    let mut pieces: Vec<Segment> = Vec::new();
+    for block in text.split("\n\n").filter(|x| !x.trim().is_empty()) {
+        let block = block.trim_matches('\n');
+        if chunker::estimate_tokens(block) <= max_tokens {
+            pieces.push(Segment {
+                separator: "\n\n",
+                text: block.to_string(),
+            });
+            continue;
+        }
+        let mut separator = "\n\n";
+        for line in block.lines() {
+            if chunker::estimate_tokens(line) <= max_tokens {
+                pieces.push(Segment {
+                    separator,
+                    text: line.to_string(),
+                });
+            } else {
+                let config = ChunkerConfig {
+                    max_tokens,
+                    overlap_tokens: 0,
+                };
+                for (index, chunk) in chunker::split(line, &config).into_iter().enumerate() {
+                    pieces.push(Segment {
+                        separator: if index == 0 { separator } else { " " },
+                        text: chunk.text,
+                    });
+                }
+            }
+            separator = "\n";
+        }
+    }
+    let mut segments: Vec<Segment> = Vec::new();
+    let mut current_tokens = 0;
+    for piece in pieces {
+        let tokens = chunker::estimate_tokens(&piece.text);
+        match segments.last_mut() {
+            Some(segment) if current_tokens + tokens <= max_tokens => {
+                segment.text.push_str(piece.separator);
+                segment.text.push_str(&piece.text);
+                current_tokens += tokens;
+            }
+            _ => {
+                segments.push(Segment {
+                    separator: if segments.is_empty() {
+                        ""
+                    } else {
+                        piece.separator
+                    },
+                    text: piece.text,
+                });
+                current_tokens = tokens;
+            }
+        }
+    }
+    segments
+}
+
+//只保留片段中出现的术语；原文是术语表中译法一侧的语言时反过来使用
+fn glossary_for(glossary: &BTreeMap<String, String>, text: &str) -> Vec<(String, String)> {
+    let text = text.to_lowercase();
+    glossary
+        .iter()
+        .filter_map(|(term, translation)| {
+            if text.contains(&term.to_lowercase()) {
+                Some((term.clone(), translation.clone()))
+            } else if text.contains(&translation.to_lowercase()) {
+                Some((translation.clone(), term.clone()))
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+//模型有时会把译文放在代码块中
+fn strip_code_fence(translation: &str, source: &str) -> String {
+    let trimmed = translation.trim();
+    if !trimmed.starts_with("```") || source.trim_start().starts_with("```") {
+        return trimmed.to_string();
+    }
+    let body = trimmed.split_once('\n').map_or("", |x| x.1);
+    body.trim_end()
+        .strip_suffix("```")
+        .unwrap_or(body)
+        .trim()
+        .to_string()
+}
+
+fn file_stem(filename: &str) -> String {
+    match filename.rsplit_once('.') {
+        Some((stem, _)) if !stem.is_empty() => stem.to_string(),
+        _ => filename.to_string(),
+    }
+}
+
+pub struct DocumentTranslateFunction {}
+
+impl DocumentTranslateFunction {
+    fn get_documents(&self, context: &Context, document: Option<&str>) -> Result<String> {
+        let res = block_on(read_document(&context.db, context.session_id, document))?;
+        //译文保存为markdown，不保留pdf的换页符
+        Ok(res.replace('\x0c', "\n"))
+    }
+
+    //知识库文档的文件名或会话中最近一次处理完成的上传文件名，用于生成译文的文件名
+    fn get_source_filename(&self, context: &Context, document: Option<&str>) -> Result<String> {
+        if let Some(document) = document {
+            return Ok(file_stem(document));
+        }
+        let job = block_on(
+            Job::find()
+                .filter(job::Column::SessionId.eq(context.session_id))
+                .filter(job::Column::Status.eq(JobStatus::Completed))
+                .order_by_desc(job::Column::UpdateTime)
+                .one(&context.db),
+        )?;
+        Ok(file_stem(
+            &job.map_or("document".to_string(), |x| x.filename),
+        ))
+    }
+
+    fn chat(&self, chat_endpoint: &ChatEndpoint, request_string: String) -> Result<String> {
+        let message = Message {
+            role: Role::User,
+            content: request_string,
+            ..Default::default()
+        };
+        let options = Vec::new();
+        debug!("Request: {:?}", message.content);
+        let response = chat_endpoint.invoke(&vec![message], &options)?;
+        Ok(response.get_chat_result()?.to_string())
+    }
+
+    fn translate_segment(
+        &self,
+        chat_endpoint: &ChatEndpoint,
+        template: &str,
+        glossary: &BTreeMap<String, String>,
+        segment: &str,
+    ) -> Result<String> {
+        let terms = glossary_for(glossary, segment);
+        let glossary_string = match terms.is_empty() {
+            true => String::new(),
+            false => format!(
+                "4. 以下术语必须使用给定的译法：\n{}",
+                terms
+                    .iter()
+                    .map(|(term, translation)| format!("{} => {}", term, translation))
+                    .collect::<Vec<String>>()
+                    .join("\n")
+            ),
+        };
+        let request_string = template
+            .replace("{{glossary}}", &glossary_string)
+            .replace("{{text}}", segment);
+        let expected = structure(segment);
+        let translation =
+            strip_code_fence(&self.chat(chat_endpoint, request_string.clone())?, segment);
+        if structure(&translation) == expected {
+            return Ok(translation);
+        }
+        info!("translation changed the document structure, retrying");
+        let retry = strip_code_fence(
+            &self.chat(chat_endpoint, request_string + STRUCTURE_RETRY_NOTE)?,
+            segment,
+        );
+        //重试后仍然不一致时使用与原文结构差异较小的译文
+        let difference = |text: &str| structure(text).len().abs_diff(expected.len());
+        match structure(&retry) == expected || difference(&retry) < difference(&translation) {
+            true => Ok(retry),
+            false => Ok(translation),
+        }
+    }
+
+    fn translate(
+        &self,
+        context: &Context,
+        config: &TranslateConfig,
+        text: &str,
+        parameters: &DocumentTranslateFunctionParameters,
+    ) -> Result<String> {
+        let source_language = parameters
+            .source_language
+            .as_ref()
+            .map_or(String::new(), |x| format!("从{}", x));
+        let template = std::fs::read_to_string(TRANSLATE_TEMPLATE_PATH)?
+            .replace("{{source_language}}", &source_language)
+            .replace("{{target_language}}", &parameters.target_language);
+        let mut glossary = config.glossary.clone();
+        glossary.extend(parameters.glossary.clone());
+        let segments = split_segments(text, config.max_chunk_tokens);
+        info!("Translating {} segments", segments.len());
+        let translations = run_concurrently(&segments, config.max_concurrency, |segment| {
+            self.translate_segment(&context.chat_endpoint, &template, &glossary, &segment.text)
+        })?;
+        let mut result = String::new();
+        for (segment, translation) in segments.iter().zip(translations) {
+            result.push_str(segment.separator);
+            result.push_str(&translation);
+        }
+        result.push('\n');
+        Ok(result)
+    }
+
+    fn find_cached(
+        &self,
+        context: &Context,
+        source_hash: &str,
+        settings_hash: &str,
+    ) -> Result<Option<derived_document::Model>> {
+        let cached = block_on(
+            DerivedDocument::find()
+                .filter(derived_document::Column::SessionId.eq(context.session_id))
+                .filter(derived_document::Column::Kind.eq(DERIVED_DOCUMENT_KIND))
+                .filter(derived_document::Column::SourceHash.eq(source_hash))
+                .filter(derived_document::Column::SettingsHash.eq(settings_hash))
+                .one(&context.db),
+        )?;
+        Ok(cached)
+    }
+
+    fn save(
+        &self,
+        context: &Context,
+        filename: &str,
+        source_hash: &str,
+        settings_hash: &str,
+        content: &str,
+    ) -> Result<derived_document::Model> {
+        let document = derived_document::ActiveModel {
+            session_id: Set(context.session_id),
+            kind: Set(DERIVED_DOCUMENT_KIND.to_string()),
+            filename: Set(filename.to_string()),
+            source_hash: Set(source_hash.to_string()),
+            settings_hash: Set(settings_hash.to_string()),
+            content: Set(content.to_string()),
+            create_time: Set(chrono::Utc::now()),
+            ..Default::default()
+        };
+        let result = block_on(DerivedDocument::insert(document).exec(&context.db))?;
+        block_on(DerivedDocument::find_by_id(result.last_insert_id).one(&context.db))?
+            .ok_or_else(|| anyhow::anyhow!("Derived document not found"))
+    }
+}
+
+impl Function for DocumentTranslateFunction {
+    fn execute(&self, parameters: serde_json::Value, context: &Context) -> Result<String> {
+        info!("开始执行文档翻译函数");
+        let parameters: DocumentTranslateFunctionParameters = serde_json::from_value(parameters)?;
+        if parameters.target_language.trim().is_empty() {
+            return Err(anyhow::anyhow!("target_language is required"));
+        }
+        let config_string = std::fs::read_to_string(TRANSLATE_CONFIG_PATH)?;
+        let config: TranslateConfig = serde_json::from_str(&config_string)?;
+        let documents = self.get_documents(context, parameters.document.as_deref())?;
+        let text = match &parameters.section {
+            Some(section) => {
+                let outline = build_outline(&documents);
+                match find_section(&outline, section) {
+                    Some(node) => &documents[node.start..node.end],
+                    None => return Err(anyhow::anyhow!("Section not found: {}", section)),
+                }
+            }
+            None => documents.as_str(),
+        };
+
+        //同一文档在相同配置、模板和参数下的译文直接复用
+        let source_hash = sha256_hex(text.as_bytes());
+        let settings = format!(
+            "{}\n{}\n{}",
+            config_string,
+            serde_json::to_string(&parameters)?,
+            std::fs::read_to_string(TRANSLATE_TEMPLATE_PATH)?
+        );
+        let settings_hash = sha256_hex(settings.as_bytes());
+        let (document, cached) = match self.find_cached(context, &source_hash, &settings_hash)? {
+            Some(document) => (document, true),
+            None => {
+                let translation = self.translate(context, &config, text, &parameters)?;
+                let filename = format!(
+                    "{}.{}.md",
+                    self.get_source_filename(context, parameters.document.as_deref())?,
+                    parameters.target_language.trim()
+                );
+                let document = self.save(
+                    context,
+                    &filename,
+                    &source_hash,
+                    &settings_hash,
+                    &translation,
+                )?;
+                (document, false)
+            }
+        };
+        Ok(serde_json::json!({
+            "derived_document_id": document.derived_document_id,
+            "filename": document.filename,
+            "download_url": download_url(document.session_id, document.derived_document_id),
+            "cached": cached,
+            "preview": document.content.chars().take(PREVIEW_CHARS).collect::<String>(),
+        })
+        .to_string())
+    }
+
+    fn if_postprocess(&self) -> bool {
+        true
+    }
+
+    fn get_name(&self) -> String {
+        "document_translate".to_string()
+    }
+
+    fn get_description(&self) -> String {
+        "当用户要求把上传的文档（或其中某个章节）翻译为另一种语言时调用该函数。译文会保留标题、列表和表格的格式，并使用配置的术语表，保存为会话中的新文档；函数返回译文的开头和下载地址，请把下载地址告诉用户，不要复述全文。".to_string()
+    }
+
+    fn get_parameter_schema(&self) -> RootSchema {
+        schema_for!(DocumentTranslateFunctionParameters)
+    }
+
+    fn get_capabilities(&self) -> Vec<Capability> {
+        vec![Capability::Documents, Capability::Model]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{glossary_for, split_segments, strip_code_fence, structure, LineKind};
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn test_split_segments() {
+        let text = "# Title\n\nFirst paragraph of the document.\n\n| a | b |\n|---|---|\n| 1 | 2 |\n| 3 | 4 |\n\n- item one\n- item two\n";
+        let segments = split_segments(text, 30);
+        //表格作为一个整体不会被拆开
+        assert!(segments
+            .iter()
+            .any(|x| x.text == "| a | b |\n|---|---|\n| 1 | 2 |\n| 3 | 4 |"));
+        let joined: String = segments
+            .iter()
+            .map(|x| format!("{}{}", x.separator, x.text))
+            .collect();
+        assert_eq!(joined, text.trim_end());
+        assert_eq!(
+            structure(text),
+            vec![
+                LineKind::Heading(1),
+                LineKind::TableRow,
+                LineKind::TableRow,
+                LineKind::TableRow,
+                LineKind::TableRow,
+                LineKind::ListItem,
+                LineKind::ListItem,
+            ]
+        );
+
+        //过长的表格按行拆分，拼接时行之间仍然只有一个换行
+        let table = (0..10)
+            .map(|x| format!("| row {} | value {} |", x, x))
+            .collect::<Vec<_>>()
+            .join("\n");
+        let segments = split_segments(&table, 20);
+        assert!(segments.len() > 1);
+        assert!(segments[1..].iter().all(|x| x.separator == "\n"));
+    }
+
+    #[test]
+    fn test_glossary_and_fence() {
+        let glossary = BTreeMap::from([
+            ("Embedding".to_string(), "向量表示".to_string()),
+            ("prompt".to_string(), "提示词".to_string()),
+        ]);
+        assert_eq!(
+            glossary_for(&glossary, "The embedding model"),
+            vec![("Embedding".to_string(), "向量表示".to_string())]
+        );
+        assert_eq!(
+            glossary_for(&glossary, "编写提示词"),
+            vec![("提示词".to_string(), "prompt".to_string())]
+        );
+        assert_eq!(
+            strip_code_fence("```markdown\n# 标题\n```", "# Title"),
+            "# 标题"
+        );
+        assert_eq!(
+            strip_code_fence("```rust\nfn main() {}\n```", "```rust\nfn main() {}\n```"),
+            "```rust\nfn main() {}\n```"
+        );
+    }
+}
@@ -0,0 +1,453 @@
+use super::{
+    document_summary::run_concurrently,
+    function::{block_on, Capability, Context, Function},
+};
+use crate::{
+    chunker::{self, Chunk, ChunkerConfig},
+    json_schema,
+    knowledge_base::read_document,
+    outline::{page_of, page_ranges},
+};
+use anyhow::Result;
+use erniebot_rs::chat::{ChatEndpoint, Message, Role};
+use schemars::{
+    schema::{RootSchema, SchemaObject},
+    schema_for, JsonSchema,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+use tracing::{debug, info};
+
+pub const EXTRACT_CONFIG_PATH: &str = "configs/extract_config.json";
+const EXTRACT_TEMPLATE_PATH: &str = "templates/extract.template";
+
+fn default_max_concurrency() -> usize {
+    4
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+struct ExtractConfig {
+    max_chunk_tokens: usize,
+    #[serde(default)]
+    chunk_overlap_tokens: usize,
+    #[serde(default = "default_max_concurrency")]
+    max_concurrency: usize,
+    //预先定义的schema，调用时通过schema_name使用
+    #[serde(default)]
+    schemas: BTreeMap<String, Value>,
+}
+
+#[derive(JsonSchema, Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
+#[serde(default)]
+struct ExtractFieldsParameters {
+    /// 描述需要提取的字段的JSON Schema，根节点为带properties的object；与schema_name二选一
+    schema: Option<Value>,
+    /// 预先定义的schema名称，如"contract"、"invoice"
+    schema_name: Option<String>,
+    /// 对提取的补充要求，如"金额以元为单位"
+    instructions: Option<String>,
+    /// 关联知识库中的文档名，不填则为会话上传的文档
+    document: Option<String>,
+}
+
+//字段依据的原文位置，start、end为在解析后文本中的字节偏移，在原文中找不到摘录时为空
+#[derive(Debug, Clone, Serialize, PartialEq)]
+struct SourceLocation {
+    quote: String,
+    page: Option<usize>,
+    start: Option<usize>,
+    end: Option<usize>,
+    heading: Option<String>,
+}
+
+//与采用的值不同的提取结果，以及它依据的原文位置
+#[derive(Debug, Clone, Serialize, PartialEq)]
+struct Conflict {
+    value: Value,
+    sources: Vec<SourceLocation>,
+}
+
+//合并各片段的提取结果：数组字段合并去重，其它字段取第一个非空值，与之不同的值记为冲突；
+//sources中只有支持fields中的值的摘录，冲突值的摘录放在对应的冲突中
+#[derive(Debug, Default)]
+struct Extraction {
+    fields: Map<String, Value>,
+    sources: BTreeMap<String, Vec<SourceLocation>>,
+    conflicts: BTreeMap<String, Vec<Conflict>>,
+}
+
+//比较时忽略字符串的大小写和空白
+fn normalize(value: &Value) -> Value {
+    match value {
+        Value::String(text) => Value::String(
+            text.split_whitespace()
+                .collect::<Vec<_>>()
+                .join(" ")
+                .to_lowercase(),
+        ),
+        Value::Array(items) => Value::Array(items.iter().map(normalize).collect()),
+        Value::Object(map) => Value::Object(
+            map.iter()
+                .filter(|(_, x)| !is_empty(x))
+                .map(|(key, x)| (key.clone(), normalize(x)))
+                .collect(),
+        ),
+        value => value.clone(),
+    }
+}
+
+fn is_empty(value: &Value) -> bool {
+    match value {
+        Value::Null => true,
+        Value::String(text) => text.trim().is_empty(),
+        Value::Array(items) => items.is_empty(),
+        Value::Object(map) => map.values().all(is_empty),
+        _ => false,
+    }
+}
+
+fn push_locations(locations: &mut Vec<SourceLocation>, new_locations: Vec<SourceLocation>) {
+    for location in new_locations {
+        if !locations.contains(&location) {
+            locations.push(location);
+        }
+    }
+}
+
+fn push_unique(values: &mut Vec<Value>, value: Value) {
+    let normalized = normalize(&value);
+    if !values.iter().any(|x| normalize(x) == normalized) {
+        values.push(value);
+    }
+}
+
+//模型的回答中可能有多余的文字或代码块标记
+fn parse_response(response: &str) -> Option<(Map<String, Value>, Map<String, Value>)> {
+    let start = response.find('{')?;
+    let end = response.rfind('}')?;
+    if start >= end {
+        return None;
+    }
+    let mut value: Map<String, Value> = serde_json::from_str(&response[start..=end]).ok()?;
+    let fields = match value.remove("fields") {
+        Some(Value::Object(fields)) => fields,
+        _ => return None,
+    };
+    let sources = match value.remove("sources") {
+        Some(Value::Object(sources)) => sources,
+        _ => Map::new(),
+    };
+    Some((fields, sources))
+}
+
+impl Extraction {
+    fn merge(
+        &mut self,
+        properties: &[String],
+        documents: &str,
+        chunk: &Chunk,
+        fields: Map<String, Value>,
+        sources: &Map<String, Value>,
+    ) {
+        for (name, value) in fields {
+            if !properties.contains(&name) || is_empty(&value) {
+                continue;
+            }
+            let quotes: Vec<&str> = match sources.get(&name) {
+                Some(Value::String(quote)) => vec![quote.as_str()],
+                Some(Value::Array(quotes)) => quotes.iter().filter_map(|x| x.as_str()).collect(),
+                _ => Vec::new(),
+            };
+            let locations: Vec<SourceLocation> = quotes
+                .into_iter()
+                .map(str::trim)
+                .filter(|x| !x.is_empty())
+                .map(|x| locate(documents, chunk, x))
+                .collect();
+            match (self.fields.get_mut(&name), value) {
+                (Some(Value::Array(existing)), Value::Array(items)) => {
+                    for item in items.into_iter().filter(|x| !is_empty(x)) {
+                        push_unique(existing, item);
+                    }
+                }
+                (Some(existing), value) => {
+                    if normalize(existing) != normalize(&value) {
+                        self.add_conflict(name, value, locations);
+                        continue;
+                    }
+                }
+                (None, Value::Array(items)) => {
+                    let mut unique = Vec::new();
+                    for item in items.into_iter().filter(|x| !is_empty(x)) {
+                        push_unique(&mut unique, item);
+                    }
+                    self.fields.insert(name.clone(), Value::Array(unique));
+                }
+                (None, value) => {
+                    self.fields.insert(name.clone(), value);
+                }
+            }
+            push_locations(self.sources.entry(name).or_default(), locations);
+        }
+    }
+
+    //相同的冲突值只记录一次，合并它们的摘录
+    fn add_conflict(&mut self, name: String, value: Value, locations: Vec<SourceLocation>) {
+        let conflicts = self.conflicts.entry(name).or_default();
+        let normalized = normalize(&value);
+        match conflicts
+            .iter_mut()
+            .find(|x| normalize(&x.value) == normalized)
+        {
+            Some(conflict) => push_locations(&mut conflict.sources, locations),
+            None => conflicts.push(Conflict {
+                value,
+                sources: locations,
+            }),
+        }
+    }
+}
+
+//在片段中查找摘录，换算为全文的偏移和页码
+fn locate(documents: &str, chunk: &Chunk, quote: &str) -> SourceLocation {
+    let start = chunk.text.find(quote).map(|x| chunk.start + x);
+    SourceLocation {
+        quote: quote.to_string(),
+        page: start.map(|x| page_of(&page_ranges(documents), x)),
+        start,
+        end: start.map(|x| x + quote.len()),
+        heading: start.and(chunk.heading.clone()),
+    }
+}
+
+fn property_names(schema: &SchemaObject) -> Vec<String> {
+    schema
+        .object
+        .as_ref()
+        .map(|x| x.properties.keys().cloned().collect())
+        .unwrap_or_default()
+}
+
+pub struct ExtractFieldsFunction {}
+
+impl ExtractFieldsFunction {
+    fn get_documents(&self, context: &Context, document: Option<&str>) -> Result<String> {
+        let res = block_on(read_document(&context.db, context.session_id, document))?;
+        Ok(res)
+    }
+
+    fn chat(&self, chat_endpoint: &ChatEndpoint, request_string: String) -> Result<String> {
+        let message = Message {
+            role: Role::User,
+            content: request_string,
+            ..Default::default()
+        };
+        let options = Vec::new();
+        debug!("Request: {:?}", message.content);
+        let response = chat_endpoint.invoke(&vec![message], &options)?;
+        Ok(response.get_chat_result()?.to_string())
+    }
+
+    //返回schema的名称和内容，直接传入的schema名称为custom
+    fn get_schema(
+        &self,
+        config: &ExtractConfig,
+        parameters: &ExtractFieldsParameters,
+    ) -> Result<(String, Value)> {
+        match (&parameters.schema, &parameters.schema_name) {
+            (Some(schema), _) => Ok(("custom".to_string(), schema.clone())),
+            (None, Some(name)) => match config.schemas.get(name) {
+                Some(schema) => Ok((name.clone(), schema.clone())),
+                None => Err(anyhow::anyhow!(
+                    "Schema not found: {}, available schemas: {}",
+                    name,
+                    config
+                        .schemas
+                        .keys()
+                        .cloned()
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                )),
+            },
+            (None, None) => Err(anyhow::anyhow!("Either schema or schema_name is required")),
+        }
+    }
+}
+
+impl Function for ExtractFieldsFunction {
+    fn execute(&self, parameters: serde_json::Value, context: &Context) -> Result<String> {
+        info!("开始执行字段提取函数");
+        let parameters: ExtractFieldsParameters = serde_json::from_value(parameters)?;
+        let config: ExtractConfig =
+            serde_json::from_str(&std::fs::read_to_string(EXTRACT_CONFIG_PATH)?)?;
+        let (schema_name, schema_value) = self.get_schema(&config, &parameters)?;
+        let schema: RootSchema = serde_json::from_value(schema_value.clone())
+            .map_err(|e| anyhow::anyhow!("Invalid JSON Schema: {}", e))?;
+        let properties = property_names(&schema.schema);
+        if properties.is_empty() {
+            return Err(anyhow::anyhow!(
+                "The root of the schema must be an object with properties"
+            ));
+        }
+
+        let documents = self.get_documents(context, parameters.document.as_deref())?;
+        let chunker_config = ChunkerConfig {
+            max_tokens: config.max_chunk_tokens,
+            overlap_tokens: config.chunk_overlap_tokens,
+        };
+        let chunks = chunker::split(&documents, &chunker_config);
+        let instructions = parameters
+            .instructions
+            .as_ref()
+            .map_or(String::new(), |x| format!("补充要求：{}", x));
+        let template = std::fs::read_to_string(EXTRACT_TEMPLATE_PATH)?
+            .replace("{{schema}}", &serde_json::to_string_pretty(&schema_value)?)
+            .replace("{{instructions}}", &instructions);
+        info!("Extracting fields from {} chunks", chunks.len());
+        let responses = run_concurrently(&chunks, config.max_concurrency, |chunk| {
+            self.chat(
+                &context.chat_endpoint,
+                template.replace("{{text}}", &chunk.text),
+            )
+        })?;
+
+        let mut extraction = Extraction::default();
+        let mut failed_chunks = 0;
+        for (chunk, response) in chunks.iter().zip(responses) {
+            match parse_response(&response) {
+                Some((fields, sources)) => {
+                    extraction.merge(&properties, &documents, chunk, fields, &sources)
+                }
+                None => {
+                    info!("failed to parse extraction response: {:?}", response);
+                    failed_chunks += 1;
+                }
+            }
+        }
+        let data = Value::Object(extraction.fields);
+        let errors = json_schema::validate(&schema, &data);
+        Ok(serde_json::json!({
+            "schema": schema_name,
+            "data": data,
+            "valid": errors.is_empty(),
+            "errors": errors,
+            "sources": extraction.sources,
+            "conflicts": extraction.conflicts,
+            "chunks": chunks.len(),
+            "failed_chunks": failed_chunks,
+        })
+        .to_string())
+    }
+
+    fn if_postprocess(&self) -> bool {
+        true
+    }
+
+    fn get_name(&self) -> String {
+        "extract_fields".to_string()
+    }
+
+    fn get_description(&self) -> String {
+        "当用户要求从上传的合同、发票等文档中提取结构化信息（如当事人、日期、金额）时调用该函数。可以传入描述字段的JSON Schema，或使用预先定义的schema名称（contract、invoice）。返回提取结果、校验错误、每个字段依据的原文位置，以及不同片段中相互矛盾的值；回答时请指出校验未通过的字段和存在冲突的值。".to_string()
+    }
+
+    fn get_parameter_schema(&self) -> RootSchema {
+        schema_for!(ExtractFieldsParameters)
+    }
+
+    fn get_capabilities(&self) -> Vec<Capability> {
+        vec![Capability::Documents, Capability::Model]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_response, Extraction};
+    use crate::chunker::Chunk;
+
+    fn chunk(documents: &str, start: usize, end: usize) -> Chunk {
+        Chunk {
+            text: documents[start..end].to_string(),
+            start,
+            end,
+            tokens: 0,
+            heading: Some("第一条".to_string()),
+        }
+    }
+
+    #[test]
+    fn test_merge_extractions() {
+        let documents = "甲方：北京某某科技有限公司\n乙方：上海某某有限公司\n\x0c合同金额为人民币10000元。\n甲方：北京某某科技有限公司";
+        let properties = vec!["parties".to_string(), "total_amount".to_string()];
+        let split = documents.find('\x0c').unwrap();
+        let mut extraction = Extraction::default();
+
+        let response = r#"```json
+{"fields": {"parties": [{"name": "北京某某科技有限公司", "role": "甲方"}, {"name": "上海某某有限公司", "role": "乙方"}], "unknown": 1, "total_amount": null},
+ "sources": {"parties": ["甲方：北京某某科技有限公司", "乙方：上海某某有限公司"]}}
+```"#;
+        let (fields, sources) = parse_response(response).unwrap();
+        extraction.merge(
+            &properties,
+            documents,
+            &chunk(documents, 0, split),
+            fields,
+            &sources,
+        );
+
+        let response = r#"{"fields": {"parties": [{"name": "北京某某科技有限公司 ", "role": "甲方"}], "total_amount": 10000},
+            "sources": {"total_amount": "合同金额为人民币10000元", "parties": "甲方：北京某某科技有限公司"}}"#;
+        let (fields, sources) = parse_response(response).unwrap();
+        extraction.merge(
+            &properties,
+            documents,
+            &chunk(documents, split, documents.len()),
+            fields,
+            &sources,
+        );
+
+        let (fields, sources) = parse_response(
+            r#"{"fields": {"total_amount": 12000}, "sources": {"total_amount": "不存在的摘录"}}"#,
+        )
+        .unwrap();
+        extraction.merge(
+            &properties,
+            documents,
+            &chunk(documents, split, documents.len()),
+            fields,
+            &sources,
+        );
+
+        assert_eq!(
+            serde_json::Value::Object(extraction.fields),
+            serde_json::json!({
+                "parties": [{"name": "北京某某科技有限公司", "role": "甲方"}, {"name": "上海某某有限公司", "role": "乙方"}],
+                "total_amount": 10000
+            })
+        );
+        //冲突值的摘录跟随冲突值，不混入sources
+        let conflicts = &extraction.conflicts["total_amount"];
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!(conflicts[0].value, serde_json::json!(12000));
+        assert_eq!(conflicts[0].sources.len(), 1);
+        assert_eq!(conflicts[0].sources[0].quote, "不存在的摘录");
+        assert_eq!(
+            (
+                conflicts[0].sources[0].start,
+                conflicts[0].sources[0].heading.clone()
+            ),
+            (None, None)
+        );
+        let amount = &extraction.sources["total_amount"];
+        assert_eq!(amount.len(), 1);
+        assert_eq!(amount[0].quote, "合同金额为人民币10000元");
+        assert_eq!(amount[0].page, Some(2));
+        //第二页再次出现的甲方与已合并的当事人相同，它的摘录同样支持fields中的值
+        let parties = &extraction.sources["parties"];
+        assert_eq!(parties.len(), 3);
+        assert_eq!((parties[0].page, parties[0].start), (Some(1), Some(0)));
+        assert_eq!(parties[2].page, Some(2));
+        assert!(parse_response("无法提取").is_none());
+    }
+}
@@ -0,0 +1,78 @@
+use super::function::{block_on, Capability, Context, Function, RiskLevel};
+use crate::memory::{forget, format_memories, list_memories, session_user_id};
+use anyhow::Result;
+use schemars::{schema::RootSchema, schema_for, JsonSchema};
+use serde::{Deserialize, Serialize};
+
+#[derive(JsonSchema, Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
+#[serde(default)]
+struct ForgetParameters {
+    /// 要删除的记忆编号，即记忆前方括号中的数字
+    memory_ids: Vec<i32>,
+    /// 要删除的记忆中包含的文字，不知道编号时使用
+    keyword: Option<String>,
+    /// 为true时删除关于用户的全部记忆
+    all: bool,
+}
+
+pub struct ForgetFunction {}
+
+impl Function for ForgetFunction {
+    fn execute(&self, parameters: serde_json::Value, context: &Context) -> Result<String> {
+        let parameters: ForgetParameters = serde_json::from_value(parameters)?;
+        block_on(async {
+            let user_id = session_user_id(&context.db, context.session_id).await?;
+            let memories = list_memories(&context.db, user_id).await?;
+            let keyword = parameters
+                .keyword
+                .as_deref()
+                .map(|x| x.trim().to_lowercase())
+                .filter(|x| !x.is_empty());
+            let memory_ids: Vec<i32> = memories
+                .iter()
+                .filter(|x| {
+                    parameters.all
+                        || parameters.memory_ids.contains(&x.memory_id)
+                        || keyword
+                            .as_ref()
+                            .is_some_and(|keyword| x.content.to_lowercase().contains(keyword))
+                })
+                .map(|x| x.memory_id)
+                .collect();
+            //没有匹配时列出现有的记忆，便于用户指定编号
+            if memory_ids.is_empty() {
+                return Ok(format!(
+                    "没有找到要删除的记忆，目前记住的内容有：\n{}",
+                    format_memories(&memories)
+                ));
+            }
+            let deleted = forget(&context.db, user_id, &memory_ids).await?;
+            Ok(format!("已删除{}条记忆", deleted))
+        })
+    }
+
+    fn if_postprocess(&self) -> bool {
+        false
+    }
+
+    fn get_name(&self) -> String {
+        "forget".to_string()
+    }
+
+    fn get_description(&self) -> String {
+        "当用户要求忘记之前记住的某些内容时调用，可以按记忆编号、包含的文字删除，或删除全部记忆。"
+            .to_string()
+    }
+
+    fn get_parameter_schema(&self) -> RootSchema {
+        schema_for!(ForgetParameters)
+    }
+
+    fn get_risk_level(&self) -> RiskLevel {
+        RiskLevel::SideEffect
+    }
+
+    fn get_capabilities(&self) -> Vec<Capability> {
+        vec![Capability::Memory]
+    }
+}
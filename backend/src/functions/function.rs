@@ -2,15 +2,71 @@ use schemars::schema::RootSchema;
 use std::collections::HashMap;
 type ErnieBotFunction = erniebot_rs::chat::Function;
 use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use tracing::info;
+
+use crate::confirmation::FunctionConfirmer;
 
 use super::{
-    calculator::CalculatorFunction, direct_reply::DirectReplyFunction,
+    calculator::CalculatorFunction,
+    code_interpreter::CodeInterpreterFunction,
+    datetime::DatetimeFunction,
+    direct_reply::DirectReplyFunction,
+    document_outline::DocumentOutlineFunction,
+    document_search::DocumentSearchFunction,
     document_summary::DocumentSummaryFunction,
+    document_translate::DocumentTranslateFunction,
+    extract_fields::ExtractFieldsFunction,
+    forget::ForgetFunction,
+    http_tool::{http_tools, HttpToolFunction},
+    mcp_tool::mcp_tools,
+    remember::RememberFunction,
+    schema,
+    wasm_plugin::wasm_plugins,
+    web_read::WebReadFunction,
+    web_search::WebSearchFunction,
 };
 
 pub struct Context {
     pub session_id: i32,
     pub chat_endpoint: erniebot_rs::chat::ChatEndpoint,
+    pub embedding_endpoint: erniebot_rs::embedding::EmbeddingEndpoint,
+    pub db: sea_orm::DatabaseConnection,
+    //通过Socket.IO请求用户确认，HTTP接口没有确认渠道
+    pub confirmer: Option<FunctionConfirmer>,
+}
+
+//只读的函数直接执行，有副作用的函数需要用户确认后才执行。副作用指修改已有数据(如长期记忆)
+//或外部系统的状态(如非GET的HTTP请求)；只读取公开网页、调用模型、在会话中生成新文档不算副作用
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RiskLevel {
+    ReadOnly,
+    SideEffect,
+}
+
+//函数需要访问的资源
+#[derive(Debug, Clone, Copy, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Capability {
+    Documents,
+    Memory,
+    Model,
+    Network,
+}
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct FunctionInfo {
+    pub name: String,
+    pub description: String,
+    pub risk_level: RiskLevel,
+    pub capabilities: Vec<Capability>,
+}
+
+//Function::execute是同步的，需要访问数据库等异步接口时使用，要求运行在多线程的tokio运行时中
+pub fn block_on<F: std::future::Future>(future: F) -> F::Output {
+    tokio::task::block_in_place(|| tokio::runtime::Handle::current().block_on(future))
 }
 
 pub trait Function {
@@ -19,6 +75,16 @@ pub trait Function {
     fn get_name(&self) -> String;
     fn get_description(&self) -> String;
     fn get_parameter_schema(&self) -> RootSchema;
+    fn get_risk_level(&self) -> RiskLevel {
+        RiskLevel::ReadOnly
+    }
+    fn get_capabilities(&self) -> Vec<Capability> {
+        Vec::new()
+    }
+    //只能处理文档的函数，会话中没有文档时不提供给模型
+    fn requires_documents(&self) -> bool {
+        self.get_capabilities().contains(&Capability::Documents)
+    }
 }
 
 pub struct FunctionRegistry {
@@ -53,13 +119,60 @@ impl FunctionRegistry {
         }
     }
 
+    pub fn get_description_by_name(&self, function_name: &str) -> String {
+        self.functions
+            .get(function_name)
+            .map(|x| x.get_description())
+            .unwrap_or_default()
+    }
+
+    pub fn requires_confirmation(&self, function_name: &str) -> bool {
+        self.functions
+            .get(function_name)
+            .is_some_and(|x| x.get_risk_level() == RiskLevel::SideEffect)
+    }
+
+    pub fn describe(&self) -> Vec<FunctionInfo> {
+        let mut functions: Vec<FunctionInfo> = self
+            .functions
+            .iter()
+            .map(|(name, function)| FunctionInfo {
+                name: name.clone(),
+                description: function.get_description(),
+                risk_level: function.get_risk_level(),
+                capabilities: function.get_capabilities(),
+            })
+            .collect();
+        functions.sort_by(|a, b| a.name.cmp(&b.name));
+        functions
+    }
+
+    pub fn function_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.functions.keys().cloned().collect();
+        names.sort();
+        names
+    }
+
+    //只保留会话启用的函数，direct_reply始终保留，保证模型总有可选的函数
+    pub fn retain_enabled(&mut self, enabled_functions: &[String]) {
+        self.functions
+            .retain(|name, _| name == "direct_reply" || enabled_functions.contains(name));
+    }
+
+    //会话没有上传文档也没有关联知识库时去掉文档函数，缩短选择函数的提示词
+    pub fn retain_available(&mut self, has_documents: bool) {
+        if !has_documents {
+            self.functions.retain(|_, x| !x.requires_documents());
+        }
+    }
+
     pub fn get_ernie_functions(&self) -> Vec<ErnieBotFunction> {
         self.functions
             .iter()
             .map(|(name, function)| ErnieBotFunction {
                 name: name.clone(),
                 description: function.get_description(),
-                parameters: function.get_parameter_schema(),
+                parameters: schema::compact(&function.get_parameter_schema()),
                 ..Default::default()
             })
             .collect()
@@ -74,9 +187,78 @@ pub fn get_function_registry() -> FunctionRegistry {
     registry
         .functions
         .insert("calculator".to_string(), Box::new(CalculatorFunction {}));
+    registry.functions.insert(
+        "code_interpreter".to_string(),
+        Box::new(CodeInterpreterFunction {}),
+    );
+    registry
+        .functions
+        .insert("datetime".to_string(), Box::new(DatetimeFunction {}));
     registry.functions.insert(
         "document_summary".to_string(),
         Box::new(DocumentSummaryFunction {}),
     );
+    registry.functions.insert(
+        "document_translate".to_string(),
+        Box::new(DocumentTranslateFunction {}),
+    );
+    registry.functions.insert(
+        "extract_fields".to_string(),
+        Box::new(ExtractFieldsFunction {}),
+    );
+    registry.functions.insert(
+        "document_outline".to_string(),
+        Box::new(DocumentOutlineFunction {}),
+    );
+    registry.functions.insert(
+        "document_search".to_string(),
+        Box::new(DocumentSearchFunction {}),
+    );
+    registry
+        .functions
+        .insert("remember".to_string(), Box::new(RememberFunction {}));
+    registry
+        .functions
+        .insert("forget".to_string(), Box::new(ForgetFunction {}));
+    registry
+        .functions
+        .insert("web_read".to_string(), Box::new(WebReadFunction {}));
+    registry
+        .functions
+        .insert("web_search".to_string(), Box::new(WebSearchFunction {}));
+    //声明式的HTTP工具不能覆盖内置函数
+    for manifest in http_tools() {
+        if registry.functions.contains_key(&manifest.name) {
+            info!("skip http tool {}: name already registered", manifest.name);
+            continue;
+        }
+        registry.functions.insert(
+            manifest.name.clone(),
+            Box::new(HttpToolFunction {
+                manifest: manifest.clone(),
+            }),
+        );
+    }
+    for plugin in wasm_plugins() {
+        if registry.functions.contains_key(&plugin.manifest.name) {
+            info!(
+                "skip wasm plugin {}: name already registered",
+                plugin.manifest.name
+            );
+            continue;
+        }
+        registry
+            .functions
+            .insert(plugin.manifest.name.clone(), Box::new(plugin.clone()));
+    }
+    for function in mcp_tools() {
+        if registry.functions.contains_key(&function.name) {
+            info!("skip mcp tool {}: name already registered", function.name);
+            continue;
+        }
+        registry
+            .functions
+            .insert(function.name.clone(), Box::new(function.clone()));
+    }
     registry
 }
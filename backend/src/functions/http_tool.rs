@@ -0,0 +1,624 @@
+use super::function::{block_on, Capability, Context, Function, RiskLevel};
+use anyhow::Result;
+use lazy_static::lazy_static;
+use regex::Regex;
+use schemars::schema::RootSchema;
+use serde::Deserialize;
+use std::{collections::HashMap, sync::OnceLock, time::Duration};
+use tracing::info;
+
+lazy_static! {
+    static ref PLACEHOLDER: Regex = Regex::new(r"\{\{\s*([A-Za-z0-9_.]+)\s*\}\}").unwrap();
+    //http工具和wasm插件的函数名都需要满足的格式
+    pub(crate) static ref TOOL_NAME: Regex = Regex::new(r"^[A-Za-z][A-Za-z0-9_]*$").unwrap();
+}
+
+//启动时从目录中加载，之后每次创建FunctionRegistry时使用
+static HTTP_TOOLS: OnceLock<Vec<HttpToolManifest>> = OnceLock::new();
+
+fn default_method() -> String {
+    "GET".to_string()
+}
+
+fn default_timeout_secs() -> u64 {
+    10
+}
+
+fn default_max_bytes() -> usize {
+    1024 * 1024
+}
+
+fn default_postprocess() -> bool {
+    true
+}
+
+//声明式的HTTP工具，url、headers、body中的 {{参数名}} 替换为函数参数，{{env.变量名}} 替换为环境变量
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+pub struct HttpToolManifest {
+    pub name: String,
+    pub description: String,
+    //函数参数的JSON Schema
+    pub parameters: RootSchema,
+    #[serde(default = "default_method")]
+    pub method: String,
+    pub url: String,
+    #[serde(default)]
+    pub headers: HashMap<String, String>,
+    //请求体模板，不设置时POST、PUT、PATCH请求以全部参数作为json请求体，
+    //GET、HEAD、DELETE请求把url中没有用到的参数附加为查询参数
+    #[serde(default)]
+    pub body: Option<serde_json::Value>,
+    //从json响应中取出结果的JSONPath，如 $.data.items[*].title；不设置时返回整个响应
+    #[serde(default)]
+    pub response_path: Option<String>,
+    #[serde(default = "default_timeout_secs")]
+    pub timeout_secs: u64,
+    //响应体的字节数上限，超过时函数执行失败
+    #[serde(default = "default_max_bytes")]
+    pub max_bytes: usize,
+    //不设置时GET和HEAD请求为read_only，其它方法为side_effect
+    #[serde(default)]
+    pub risk_level: Option<RiskLevel>,
+    #[serde(default = "default_postprocess")]
+    pub postprocess: bool,
+}
+
+impl HttpToolManifest {
+    pub fn risk_level(&self) -> RiskLevel {
+        self.risk_level
+            .unwrap_or(match self.method.to_uppercase().as_str() {
+                "GET" | "HEAD" => RiskLevel::ReadOnly,
+                _ => RiskLevel::SideEffect,
+            })
+    }
+
+    fn validate(&self) -> Result<()> {
+        if !TOOL_NAME.is_match(&self.name) {
+            return Err(anyhow::anyhow!("Invalid tool name: {}", self.name));
+        }
+        reqwest::Method::from_bytes(self.method.to_uppercase().as_bytes())?;
+        if let Some(path) = &self.response_path {
+            parse_json_path(path)?;
+        }
+        Ok(())
+    }
+}
+
+//读取目录下所有的 .json 文件，目录不存在时视为没有工具
+pub fn load_http_tools(dir: &str) -> Result<Vec<HttpToolManifest>> {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+    let mut paths: Vec<_> = entries
+        .filter_map(|x| x.ok().map(|x| x.path()))
+        .filter(|x| x.extension().is_some_and(|x| x == "json"))
+        .collect();
+    paths.sort();
+    let mut manifests = Vec::with_capacity(paths.len());
+    for path in paths {
+        let manifest_string = std::fs::read_to_string(&path)?;
+        let manifest: HttpToolManifest = serde_json::from_str(&manifest_string)
+            .map_err(|e| anyhow::anyhow!("{}: {}", path.display(), e))?;
+        manifest
+            .validate()
+            .map_err(|e| anyhow::anyhow!("{}: {}", path.display(), e))?;
+        info!("load http tool {} from {}", manifest.name, path.display());
+        manifests.push(manifest);
+    }
+    Ok(manifests)
+}
+
+pub fn init_http_tools(dir: &str) -> Result<()> {
+    let manifests = load_http_tools(dir)?;
+    let _ = HTTP_TOOLS.set(manifests);
+    Ok(())
+}
+
+pub fn http_tools() -> &'static [HttpToolManifest] {
+    HTTP_TOOLS.get().map(|x| x.as_slice()).unwrap_or_default()
+}
+
+pub struct HttpToolFunction {
+    pub manifest: HttpToolManifest,
+}
+
+impl HttpToolFunction {
+    pub async fn call(&self, parameters: &serde_json::Value) -> Result<String> {
+        let manifest = &self.manifest;
+        let method = reqwest::Method::from_bytes(manifest.method.to_uppercase().as_bytes())?;
+        let url = render_url(&manifest.url, parameters)?;
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(manifest.timeout_secs))
+            .build()?;
+        let mut request = client.request(method.clone(), &url);
+        for (name, value) in manifest.headers.iter() {
+            request = request.header(name, render(value, parameters)?);
+        }
+        match &manifest.body {
+            Some(body) => request = request.json(&render_value(body, parameters)?),
+            None if [
+                reqwest::Method::POST,
+                reqwest::Method::PUT,
+                reqwest::Method::PATCH,
+            ]
+            .contains(&method) =>
+            {
+                request = request.json(parameters)
+            }
+            None if [
+                reqwest::Method::GET,
+                reqwest::Method::HEAD,
+                reqwest::Method::DELETE,
+            ]
+            .contains(&method) =>
+            {
+                request = request.query(&query_parameters(&manifest.url, parameters))
+            }
+            None => {}
+        }
+        let mut response = request.send().await.map_err(|e| {
+            if e.is_timeout() {
+                anyhow::anyhow!(
+                    "{} timed out after {}s",
+                    manifest.name,
+                    manifest.timeout_secs
+                )
+            } else {
+                anyhow::anyhow!("{} request failed: {}", manifest.name, e)
+            }
+        })?;
+        let status = response.status();
+        //按块读取，不会把超大的响应整个读入内存
+        let mut body = Vec::new();
+        let mut truncated = false;
+        while let Some(chunk) = response.chunk().await? {
+            body.extend_from_slice(&chunk);
+            if body.len() > manifest.max_bytes {
+                body.truncate(manifest.max_bytes);
+                truncated = true;
+                break;
+            }
+        }
+        let text = String::from_utf8_lossy(&body).into_owned();
+        if !status.is_success() {
+            let snippet: String = text.chars().take(200).collect();
+            return Err(anyhow::anyhow!(
+                "{} returned {}: {}",
+                manifest.name,
+                status,
+                snippet
+            ));
+        }
+        if truncated {
+            return Err(anyhow::anyhow!(
+                "{} response exceeds {} bytes",
+                manifest.name,
+                manifest.max_bytes
+            ));
+        }
+        let value: serde_json::Value = match serde_json::from_str(&text) {
+            Ok(value) => value,
+            //非json响应直接返回文本
+            Err(_) => return Ok(text),
+        };
+        let value = match &manifest.response_path {
+            Some(path) => {
+                let mut selected = select_json_path(&value, path)?;
+                match selected.len() {
+                    0 => return Err(anyhow::anyhow!("{} returned no result", manifest.name)),
+                    1 => selected.remove(0),
+                    _ => serde_json::Value::Array(selected),
+                }
+            }
+            None => value,
+        };
+        Ok(match value {
+            serde_json::Value::String(text) => text,
+            value => value.to_string(),
+        })
+    }
+}
+
+impl Function for HttpToolFunction {
+    fn execute(&self, parameters: serde_json::Value, _context: &Context) -> Result<String> {
+        block_on(self.call(&parameters))
+    }
+
+    fn if_postprocess(&self) -> bool {
+        self.manifest.postprocess
+    }
+
+    fn get_name(&self) -> String {
+        self.manifest.name.clone()
+    }
+
+    fn get_description(&self) -> String {
+        self.manifest.description.clone()
+    }
+
+    fn get_parameter_schema(&self) -> RootSchema {
+        self.manifest.parameters.clone()
+    }
+
+    fn get_risk_level(&self) -> RiskLevel {
+        self.manifest.risk_level()
+    }
+
+    fn get_capabilities(&self) -> Vec<Capability> {
+        vec![Capability::Network]
+    }
+}
+
+fn placeholder_value(name: &str, parameters: &serde_json::Value) -> Result<serde_json::Value> {
+    if let Some(variable) = name.strip_prefix("env.") {
+        let value = std::env::var(variable)
+            .map_err(|_| anyhow::anyhow!("Environment variable {} is not set", variable))?;
+        return Ok(serde_json::Value::String(value));
+    }
+    Ok(parameters
+        .get(name)
+        .cloned()
+        .unwrap_or(serde_json::Value::Null))
+}
+
+fn value_to_string(value: serde_json::Value) -> String {
+    match value {
+        serde_json::Value::String(text) => text,
+        serde_json::Value::Null => String::new(),
+        value => value.to_string(),
+    }
+}
+
+fn render_with(
+    template: &str,
+    parameters: &serde_json::Value,
+    encode: fn(&str) -> String,
+) -> Result<String> {
+    let mut result = String::with_capacity(template.len());
+    let mut last = 0;
+    for captures in PLACEHOLDER.captures_iter(template) {
+        let whole = captures.get(0).unwrap();
+        result.push_str(&template[last..whole.start()]);
+        let value = value_to_string(placeholder_value(&captures[1], parameters)?);
+        //环境变量是工具作者配置的，可以是完整的地址，原样替换
+        if captures[1].starts_with("env.") {
+            result.push_str(&value);
+        } else {
+            result.push_str(&encode(&value));
+        }
+        last = whole.end();
+    }
+    result.push_str(&template[last..]);
+    Ok(result)
+}
+
+//headers和请求体中的字符串模板，参数值原样替换
+pub fn render(template: &str, parameters: &serde_json::Value) -> Result<String> {
+    render_with(template, parameters, str::to_string)
+}
+
+//url模板，?之前的参数值按路径段转义，之后的按查询参数转义
+pub fn render_url(template: &str, parameters: &serde_json::Value) -> Result<String> {
+    let (path, query) = match template.find('?') {
+        Some(index) => template.split_at(index),
+        None => (template, ""),
+    };
+    let mut url = render_with(path, parameters, encode_path_segment)?;
+    url.push_str(&render_with(query, parameters, |value| {
+        url::form_urlencoded::byte_serialize(value.as_bytes()).collect()
+    })?);
+    Ok(url)
+}
+
+//除RFC 3986中的非保留字符外全部转义，参数值中的/、?、#不会改变url的结构
+fn encode_path_segment(value: &str) -> String {
+    let mut result = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        if byte.is_ascii_alphanumeric() || b"-._~".contains(&byte) {
+            result.push(byte as char);
+        } else {
+            result.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    result
+}
+
+//url模板中没有用到的参数，按参数名排序
+fn query_parameters(template: &str, parameters: &serde_json::Value) -> Vec<(String, String)> {
+    let used: Vec<&str> = PLACEHOLDER
+        .captures_iter(template)
+        .map(|x| x.get(1).unwrap().as_str())
+        .collect();
+    let mut query: Vec<(String, String)> = parameters
+        .as_object()
+        .into_iter()
+        .flatten()
+        .filter(|(name, value)| !used.contains(&name.as_str()) && !value.is_null())
+        .map(|(name, value)| (name.clone(), value_to_string(value.clone())))
+        .collect();
+    query.sort();
+    query
+}
+
+//json模板，整个字符串只有一个占位符时替换为参数原本的json值(数字、数组等)
+pub fn render_value(
+    template: &serde_json::Value,
+    parameters: &serde_json::Value,
+) -> Result<serde_json::Value> {
+    Ok(match template {
+        serde_json::Value::String(text) => match PLACEHOLDER.captures(text) {
+            Some(captures) if captures.get(0).unwrap().as_str() == text.as_str() => {
+                placeholder_value(&captures[1], parameters)?
+            }
+            _ => serde_json::Value::String(render(text, parameters)?),
+        },
+        serde_json::Value::Array(items) => serde_json::Value::Array(
+            items
+                .iter()
+                .map(|x| render_value(x, parameters))
+                .collect::<Result<_>>()?,
+        ),
+        serde_json::Value::Object(map) => serde_json::Value::Object(
+            map.iter()
+                .map(|(key, value)| Ok((key.clone(), render_value(value, parameters)?)))
+                .collect::<Result<_>>()?,
+        ),
+        value => value.clone(),
+    })
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum PathSegment {
+    Key(String),
+    Index(usize),
+    Wildcard,
+}
+
+//支持JSONPath的常用子集：$、.key、['key']、[index]、[*]、.*
+fn parse_json_path(path: &str) -> Result<Vec<PathSegment>> {
+    let invalid = || anyhow::anyhow!("Invalid JSONPath: {}", path);
+    let rest = path.trim().strip_prefix('$').ok_or_else(invalid)?;
+    let mut segments = Vec::new();
+    let mut chars = rest.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '.' => {
+                let mut key = String::new();
+                while let Some(&next) = chars.peek() {
+                    if next == '.' || next == '[' {
+                        break;
+                    }
+                    key.push(next);
+                    chars.next();
+                }
+                match key.as_str() {
+                    "" => return Err(invalid()),
+                    "*" => segments.push(PathSegment::Wildcard),
+                    _ => segments.push(PathSegment::Key(key)),
+                }
+            }
+            '[' => {
+                let mut inner = String::new();
+                for next in chars.by_ref() {
+                    if next == ']' {
+                        break;
+                    }
+                    inner.push(next);
+                }
+                let inner = inner.trim();
+                if inner == "*" {
+                    segments.push(PathSegment::Wildcard);
+                } else if let Some(key) = inner
+                    .strip_prefix('\'')
+                    .and_then(|x| x.strip_suffix('\''))
+                    .or_else(|| inner.strip_prefix('"').and_then(|x| x.strip_suffix('"')))
+                {
+                    segments.push(PathSegment::Key(key.to_string()));
+                } else {
+                    segments.push(PathSegment::Index(inner.parse().map_err(|_| invalid())?));
+                }
+            }
+            _ => return Err(invalid()),
+        }
+    }
+    Ok(segments)
+}
+
+pub fn select_json_path(value: &serde_json::Value, path: &str) -> Result<Vec<serde_json::Value>> {
+    let mut current = vec![value];
+    for segment in parse_json_path(path)? {
+        let mut next = Vec::new();
+        for value in current {
+            match (&segment, value) {
+                (PathSegment::Key(key), serde_json::Value::Object(map)) => {
+                    next.extend(map.get(key))
+                }
+                (PathSegment::Index(index), serde_json::Value::Array(items)) => {
+                    next.extend(items.get(*index))
+                }
+                (PathSegment::Wildcard, serde_json::Value::Array(items)) => next.extend(items),
+                (PathSegment::Wildcard, serde_json::Value::Object(map)) => {
+                    next.extend(map.values())
+                }
+                _ => {}
+            }
+        }
+        current = next;
+    }
+    Ok(current.into_iter().cloned().collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        load_http_tools, render_url, select_json_path, HttpToolFunction, HttpToolManifest,
+    };
+    use crate::functions::function::RiskLevel;
+    use axum::{
+        extract::{Path, Query},
+        routing::get,
+        Json, Router,
+    };
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_select_json_path() {
+        let value = serde_json::json!({
+            "data": {"items": [{"title": "a"}, {"title": "b"}], "total": 2}
+        });
+        let titles = select_json_path(&value, "$.data.items[*].title").unwrap();
+        assert_eq!(titles, vec![serde_json::json!("a"), serde_json::json!("b")]);
+        let total = select_json_path(&value, "$['data']['total']").unwrap();
+        assert_eq!(total, vec![serde_json::json!(2)]);
+        assert!(select_json_path(&value, "data.total").is_err());
+    }
+
+    #[test]
+    fn test_render_url() {
+        let parameters = serde_json::json!({"city": "北京 海淀", "days": 3});
+        let url = render_url(
+            "http://localhost/weather?city={{city}}&days={{ days }}",
+            &parameters,
+        )
+        .unwrap();
+        assert_eq!(
+            url,
+            "http://localhost/weather?city=%E5%8C%97%E4%BA%AC+%E6%B5%B7%E6%B7%80&days=3"
+        );
+        //环境变量中的地址原样替换，路径中的参数值按路径段转义
+        std::env::set_var(
+            "HTTP_TOOL_TEST_RENDER_URL",
+            "https://api.example.com:8443/v1",
+        );
+        let parameters = serde_json::json!({"city": "北京 海淀/西区", "q": "a&b"});
+        let url = render_url(
+            "{{env.HTTP_TOOL_TEST_RENDER_URL}}/cities/{{city}}/weather?q={{q}}",
+            &parameters,
+        )
+        .unwrap();
+        assert_eq!(
+            url,
+            "https://api.example.com:8443/v1/cities/%E5%8C%97%E4%BA%AC%20%E6%B5%B7%E6%B7%80%2F%E8%A5%BF%E5%8C%BA/weather?q=a%26b"
+        );
+    }
+
+    #[test]
+    fn test_load_http_tools() {
+        let dir = std::env::temp_dir().join(format!("http-tools-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("weather.json"),
+            r#"{"name": "weather", "description": "查询天气", "url": "http://localhost/weather",
+                "parameters": {"type": "object", "properties": {"city": {"type": "string"}}}}"#,
+        )
+        .unwrap();
+        std::fs::write(dir.join("notes.txt"), "not a manifest").unwrap();
+        let tools = load_http_tools(dir.to_str().unwrap()).unwrap();
+        assert_eq!(tools.len(), 1);
+        assert_eq!(tools[0].method, "GET");
+        assert_eq!(tools[0].risk_level(), RiskLevel::ReadOnly);
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(load_http_tools(dir.to_str().unwrap()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_default_risk_level() {
+        let manifest = |extra: &str| -> HttpToolManifest {
+            serde_json::from_str(&format!(
+                r#"{{"name": "tool", "description": "", "url": "http://localhost/",
+                    "parameters": {{"type": "object"}}{}}}"#,
+                extra
+            ))
+            .unwrap()
+        };
+        assert_eq!(manifest("").risk_level(), RiskLevel::ReadOnly);
+        assert_eq!(
+            manifest(r#", "method": "head""#).risk_level(),
+            RiskLevel::ReadOnly
+        );
+        assert_eq!(
+            manifest(r#", "method": "POST""#).risk_level(),
+            RiskLevel::SideEffect
+        );
+        assert_eq!(
+            manifest(r#", "method": "DELETE", "risk_level": "read_only""#).risk_level(),
+            RiskLevel::ReadOnly
+        );
+    }
+
+    #[tokio::test]
+    async fn test_call_stub_server() {
+        async fn weather(Query(query): Query<HashMap<String, String>>) -> Json<serde_json::Value> {
+            Json(serde_json::json!({
+                "data": {"city": query.get("city"), "forecast": "晴"}
+            }))
+        }
+        async fn forecast(
+            Path(city): Path<String>,
+            Query(query): Query<HashMap<String, String>>,
+            body: String,
+        ) -> Json<serde_json::Value> {
+            Json(serde_json::json!({"city": city, "query": query, "body": body}))
+        }
+        let app = Router::new()
+            .route("/weather", get(weather))
+            .route("/forecast/:city", get(forecast).delete(forecast))
+            .route("/large", get(|| async { "晴".repeat(1000) }));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let manifest: HttpToolManifest = serde_json::from_value(serde_json::json!({
+            "name": "weather",
+            "description": "查询天气",
+            "url": format!("http://{}/weather?city={{{{city}}}}", address),
+            "parameters": {"type": "object", "properties": {"city": {"type": "string"}}},
+            "response_path": "$.data"
+        }))
+        .unwrap();
+        let function = HttpToolFunction { manifest };
+        let result = function
+            .call(&serde_json::json!({"city": "北京"}))
+            .await
+            .unwrap();
+        let result: serde_json::Value = serde_json::from_str(&result).unwrap();
+        assert_eq!(result["city"], "北京");
+        assert_eq!(result["forecast"], "晴");
+
+        //服务地址来自环境变量；GET和DELETE请求把url中没有用到的参数作为查询参数，不发送请求体
+        std::env::set_var("HTTP_TOOL_TEST_BASE_URL", format!("http://{}", address));
+        for method in ["GET", "DELETE"] {
+            let manifest: HttpToolManifest = serde_json::from_value(serde_json::json!({
+                "name": "forecast",
+                "description": "查询天气预报",
+                "method": method,
+                "url": "{{env.HTTP_TOOL_TEST_BASE_URL}}/forecast/{{city}}",
+                "parameters": {"type": "object", "properties": {"city": {"type": "string"}}}
+            }))
+            .unwrap();
+            let function = HttpToolFunction { manifest };
+            let result = function
+                .call(&serde_json::json!({"city": "北京 海淀", "days": 3}))
+                .await
+                .unwrap();
+            let result: serde_json::Value = serde_json::from_str(&result).unwrap();
+            assert_eq!(result["city"], "北京 海淀");
+            assert_eq!(result["query"], serde_json::json!({"days": "3"}));
+            assert_eq!(result["body"], "");
+        }
+
+        let manifest: HttpToolManifest = serde_json::from_value(serde_json::json!({
+            "name": "large",
+            "description": "返回很长的响应",
+            "url": format!("http://{}/large", address),
+            "parameters": {"type": "object"},
+            "max_bytes": 100
+        }))
+        .unwrap();
+        let function = HttpToolFunction { manifest };
+        let error = function.call(&serde_json::json!({})).await.unwrap_err();
+        assert!(error.to_string().contains("exceeds 100 bytes"));
+    }
+}
@@ -0,0 +1,191 @@
+use super::function::{block_on, Capability, Context, Function, RiskLevel};
+use crate::mcp::{McpClient, McpTransportConfig, Tool};
+use anyhow::Result;
+use schemars::schema::RootSchema;
+use serde::Deserialize;
+use std::{
+    sync::{Arc, OnceLock},
+    time::Duration,
+};
+use tracing::info;
+
+//启动时连接配置的MCP服务并列出工具，之后每次创建FunctionRegistry时使用
+static MCP_TOOLS: OnceLock<Vec<McpToolFunction>> = OnceLock::new();
+
+fn default_timeout_secs() -> u64 {
+    30
+}
+
+fn default_risk_level() -> RiskLevel {
+    RiskLevel::SideEffect
+}
+
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+pub struct McpServerConfig {
+    //工具注册为 {name}_{工具名} 的函数
+    pub name: String,
+    #[serde(flatten)]
+    pub transport: McpTransportConfig,
+    #[serde(default = "default_timeout_secs")]
+    pub timeout_secs: u64,
+    //工具的风险等级，工具声明readOnlyHint为false时总是需要确认
+    #[serde(default = "default_risk_level")]
+    pub risk_level: RiskLevel,
+    //readOnlyHint由服务端声明，只有信任该服务时才按声明为true的工具降为只读
+    #[serde(default)]
+    pub trust_read_only_hint: bool,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, PartialEq)]
+pub struct McpConfig {
+    #[serde(default)]
+    pub servers: Vec<McpServerConfig>,
+}
+
+impl McpConfig {
+    //配置文件不存在时视为没有MCP服务
+    pub fn load(path: &str) -> Result<Self> {
+        match std::fs::read_to_string(path) {
+            Ok(config_string) => Ok(serde_json::from_str(&config_string)?),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+//某个服务连接失败只跳过它的工具，不影响启动
+pub async fn init_mcp_tools(path: &str) -> Result<()> {
+    let config = McpConfig::load(path)?;
+    let mut functions = Vec::new();
+    for server in config.servers.iter() {
+        match connect_server(server).await {
+            Ok(tools) => functions.extend(tools),
+            Err(e) => info!("skip mcp server {}: {:?}", server.name, e),
+        }
+    }
+    let _ = MCP_TOOLS.set(functions);
+    Ok(())
+}
+
+pub fn mcp_tools() -> &'static [McpToolFunction] {
+    MCP_TOOLS.get().map(|x| x.as_slice()).unwrap_or_default()
+}
+
+async fn connect_server(server: &McpServerConfig) -> Result<Vec<McpToolFunction>> {
+    let timeout = Duration::from_secs(server.timeout_secs);
+    let client = Arc::new(McpClient::connect(&server.name, &server.transport, timeout).await?);
+    let tools = client.list_tools().await?;
+    info!("load {} tools from mcp server {}", tools.len(), server.name);
+    Ok(tools
+        .into_iter()
+        .map(|tool| McpToolFunction::new(client.clone(), tool, server))
+        .collect())
+}
+
+//函数名只能包含字母、数字和下划线
+fn function_name(server: &str, tool: &str) -> String {
+    format!("{}_{}", server, tool)
+        .chars()
+        .map(|x| if x.is_ascii_alphanumeric() { x } else { '_' })
+        .collect()
+}
+
+//服务端的声明不可信，只能提高风险等级，除非运维人员配置了信任该服务
+fn tool_risk_level(read_only_hint: Option<bool>, server: &McpServerConfig) -> RiskLevel {
+    match read_only_hint {
+        Some(false) => RiskLevel::SideEffect,
+        Some(true) if server.trust_read_only_hint => RiskLevel::ReadOnly,
+        _ => server.risk_level,
+    }
+}
+
+#[derive(Clone)]
+pub struct McpToolFunction {
+    pub name: String,
+    client: Arc<McpClient>,
+    tool: Tool,
+    risk_level: RiskLevel,
+}
+
+impl McpToolFunction {
+    fn new(client: Arc<McpClient>, tool: Tool, server: &McpServerConfig) -> Self {
+        let read_only_hint = tool.annotations.as_ref().and_then(|x| x.read_only_hint);
+        Self {
+            name: function_name(&client.name, &tool.name),
+            risk_level: tool_risk_level(read_only_hint, server),
+            client,
+            tool,
+        }
+    }
+}
+
+impl Function for McpToolFunction {
+    fn execute(&self, parameters: serde_json::Value, _context: &Context) -> Result<String> {
+        block_on(self.client.call_tool(&self.tool.name, parameters))
+    }
+
+    fn if_postprocess(&self) -> bool {
+        true
+    }
+
+    fn get_name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn get_description(&self) -> String {
+        self.tool
+            .description
+            .clone()
+            .unwrap_or_else(|| format!("{}提供的工具{}", self.client.name, self.tool.name))
+    }
+
+    //MCP工具的输入是标准的JSON Schema，无法解析时退化为任意对象
+    fn get_parameter_schema(&self) -> RootSchema {
+        serde_json::from_value(self.tool.input_schema.clone()).unwrap_or_default()
+    }
+
+    fn get_risk_level(&self) -> RiskLevel {
+        self.risk_level
+    }
+
+    fn get_capabilities(&self) -> Vec<Capability> {
+        vec![Capability::Network]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{function_name, tool_risk_level, McpConfig, RiskLevel};
+    use crate::mcp::McpTransportConfig;
+
+    #[test]
+    fn test_mcp_config() {
+        let config: McpConfig = serde_json::from_str(
+            r#"{"servers": [
+                {"name": "files", "transport": "stdio", "command": "mcp-files", "args": ["--root", "/data"]},
+                {"name": "wiki", "transport": "http", "url": "http://wiki.intranet/mcp", "risk_level": "read_only"}
+            ]}"#,
+        )
+        .unwrap();
+        assert_eq!(config.servers.len(), 2);
+        assert!(matches!(
+            &config.servers[0].transport,
+            McpTransportConfig::Stdio { args, .. } if args.len() == 2
+        ));
+        assert_eq!(config.servers[0].timeout_secs, 30);
+        assert_eq!(config.servers[1].risk_level, RiskLevel::ReadOnly);
+        assert_eq!(function_name("wiki", "search-pages"), "wiki_search_pages");
+
+        //未信任的服务声明只读不会降低风险等级，声明非只读会提高风险等级
+        let files = &config.servers[0];
+        let wiki = &config.servers[1];
+        assert_eq!(tool_risk_level(Some(true), files), RiskLevel::SideEffect);
+        assert_eq!(tool_risk_level(Some(false), wiki), RiskLevel::SideEffect);
+        assert_eq!(tool_risk_level(None, wiki), RiskLevel::ReadOnly);
+        let trusted = super::McpServerConfig {
+            trust_read_only_hint: true,
+            ..files.clone()
+        };
+        assert_eq!(tool_risk_level(Some(true), &trusted), RiskLevel::ReadOnly);
+    }
+}
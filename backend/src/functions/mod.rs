@@ -1,6 +1,23 @@
 mod calculator;
+mod code_interpreter;
+mod datetime;
 mod direct_reply;
+mod document_outline;
+mod document_search;
 mod document_summary;
+mod document_translate;
+mod extract_fields;
+mod forget;
 mod function;
+mod http_tool;
+mod mcp_tool;
+mod remember;
+mod schema;
+mod wasm_plugin;
+mod web_read;
+mod web_search;
 
 pub use function::{get_function_registry, Context, FunctionRegistry};
+pub use http_tool::init_http_tools;
+pub use mcp_tool::init_mcp_tools;
+pub use wasm_plugin::init_wasm_plugins;
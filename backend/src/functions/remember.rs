@@ -0,0 +1,58 @@
+use super::function::{block_on, Capability, Context, Function, RiskLevel};
+use crate::memory::{remember, session_user_id};
+use anyhow::Result;
+use schemars::{schema::RootSchema, schema_for, JsonSchema};
+use serde::{Deserialize, Serialize};
+
+#[derive(JsonSchema, Debug, Clone, Serialize, Deserialize, PartialEq)]
+struct RememberParameters {
+    /// 需要长期记住的关于用户的事实或偏好，用一句简短的陈述句表达，例如"用户希望用英文回答"
+    content: String,
+}
+
+pub struct RememberFunction {}
+
+impl Function for RememberFunction {
+    fn execute(&self, parameters: serde_json::Value, context: &Context) -> Result<String> {
+        let parameters: RememberParameters = serde_json::from_value(parameters)?;
+        let memory = block_on(async {
+            let user_id = session_user_id(&context.db, context.session_id).await?;
+            remember(
+                &context.db,
+                &context.embedding_endpoint,
+                user_id,
+                Some(context.session_id),
+                &parameters.content,
+            )
+            .await
+        })?;
+        Ok(format!(
+            "已记住（编号{}）：{}",
+            memory.memory_id, memory.content
+        ))
+    }
+
+    fn if_postprocess(&self) -> bool {
+        false
+    }
+
+    fn get_name(&self) -> String {
+        "remember".to_string()
+    }
+
+    fn get_description(&self) -> String {
+        "当用户明确要求记住某件事，例如自己的偏好、身份或正在做的项目时，调用该函数保存为长期记忆，以后的对话(包括其它会话)中都会参考这些记忆。".to_string()
+    }
+
+    fn get_parameter_schema(&self) -> RootSchema {
+        schema_for!(RememberParameters)
+    }
+
+    fn get_risk_level(&self) -> RiskLevel {
+        RiskLevel::SideEffect
+    }
+
+    fn get_capabilities(&self) -> Vec<Capability> {
+        vec![Capability::Memory]
+    }
+}
@@ -0,0 +1,132 @@
+use schemars::schema::{InstanceType, RootSchema, Schema, SchemaObject, SingleOrVec};
+use serde_json::Value;
+
+//去掉只对校验有意义、模型不需要的部分，缩短放入提示词的schema：标题、null默认值、
+//schemars为整数生成的format和最小值，以及可选字段的null类型
+pub fn compact(root: &RootSchema) -> RootSchema {
+    let mut root = root.clone();
+    root.meta_schema = None;
+    compact_object(&mut root.schema);
+    for schema in root.definitions.values_mut() {
+        compact_subschema(schema);
+    }
+    root
+}
+
+fn compact_subschema(schema: &mut Schema) {
+    if let Schema::Object(object) = schema {
+        compact_object(object);
+    }
+}
+
+fn is_null_schema(schema: &Schema) -> bool {
+    matches!(
+        schema,
+        Schema::Object(SchemaObject {
+            instance_type: Some(SingleOrVec::Single(x)),
+            ..
+        }) if **x == InstanceType::Null
+    )
+}
+
+fn compact_object(schema: &mut SchemaObject) {
+    if let Some(metadata) = &mut schema.metadata {
+        metadata.title = None;
+        if metadata.default == Some(Value::Null) {
+            metadata.default = None;
+        }
+    }
+    if let Some(format) = schema.format.clone() {
+        if format.starts_with("uint") {
+            if let Some(number) = &mut schema.number {
+                if number.minimum == Some(0.0) {
+                    number.minimum = None;
+                }
+            }
+        }
+        if format.starts_with("uint") || format.starts_with("int") || format == "double" {
+            schema.format = None;
+        }
+    }
+    if let Some(SingleOrVec::Vec(types)) = &schema.instance_type {
+        let types: Vec<InstanceType> = types
+            .iter()
+            .filter(|x| **x != InstanceType::Null)
+            .cloned()
+            .collect();
+        if types.len() == 1 {
+            schema.instance_type = Some(SingleOrVec::Single(Box::new(types[0])));
+        }
+    }
+    //Option<枚举>生成的 anyOf: [{"$ref": ...}, {"type": "null"}]
+    if let Some(subschemas) = &mut schema.subschemas {
+        if let Some(any_of) = &subschemas.any_of {
+            if let [Schema::Object(inner), null] | [null, Schema::Object(inner)] = any_of.as_slice()
+            {
+                if is_null_schema(null) && inner.reference.is_some() {
+                    schema.reference = inner.reference.clone();
+                    subschemas.any_of = None;
+                }
+            }
+        }
+        for list in [
+            &mut subschemas.all_of,
+            &mut subschemas.any_of,
+            &mut subschemas.one_of,
+        ] {
+            list.iter_mut().flatten().for_each(compact_subschema);
+        }
+    }
+    if let Some(object) = &mut schema.object {
+        object.properties.values_mut().for_each(compact_subschema);
+        if let Some(additional) = &mut object.additional_properties {
+            compact_subschema(additional);
+        }
+    }
+    if let Some(array) = &mut schema.array {
+        match &mut array.items {
+            Some(SingleOrVec::Single(item)) => compact_subschema(item),
+            Some(SingleOrVec::Vec(items)) => items.iter_mut().for_each(compact_subschema),
+            None => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::compact;
+    use schemars::{schema_for, JsonSchema};
+    use serde::Deserialize;
+
+    #[derive(JsonSchema, Deserialize)]
+    #[allow(dead_code)]
+    enum Style {
+        Short,
+        Long,
+    }
+
+    #[derive(JsonSchema, Deserialize)]
+    #[allow(dead_code)]
+    struct Options {
+        /// 字数
+        length: Option<usize>,
+        style: Option<Style>,
+    }
+
+    #[test]
+    fn test_compact() {
+        let schema = compact(&schema_for!(Options));
+        let value = serde_json::to_value(&schema).unwrap();
+        assert_eq!(
+            value,
+            serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "length": {"description": "字数", "type": "integer"},
+                    "style": {"$ref": "#/definitions/Style"}
+                },
+                "definitions": {"Style": {"type": "string", "enum": ["Short", "Long"]}}
+            })
+        );
+    }
+}
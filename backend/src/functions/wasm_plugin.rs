@@ -0,0 +1,342 @@
+use super::{
+    function::{Context, Function},
+    http_tool::TOOL_NAME,
+};
+use anyhow::Result;
+use schemars::schema::RootSchema;
+use serde::{Deserialize, Serialize};
+use std::{sync::OnceLock, time::Duration};
+use tracing::info;
+use wasmtime::{
+    Config, Engine, Instance, Linker, Module, Store, StoreLimits, StoreLimitsBuilder, Trap,
+    TypedFunc,
+};
+use wasmtime_wasi::{preview1::WasiP1Ctx, WasiCtxBuilder};
+
+pub const PLUGIN_CONFIG_PATH: &str = "configs/plugin_config.json";
+
+//epoch每隔这么久加一，执行时间上限按这个粒度检查
+const EPOCH_TICK: Duration = Duration::from_millis(10);
+
+static ENGINE: OnceLock<Engine> = OnceLock::new();
+static WASM_PLUGINS: OnceLock<Vec<WasmPlugin>> = OnceLock::new();
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub struct PluginConfig {
+    //每次调用可以消耗的fuel(大致相当于执行的指令数)
+    pub max_fuel: u64,
+    pub max_memory_bytes: usize,
+    pub timeout_ms: u64,
+}
+
+impl PluginConfig {
+    pub fn load(path: &str) -> Result<Self> {
+        let config_string = std::fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&config_string)?)
+    }
+}
+
+//插件通过导出的manifest函数提供名称、说明和参数的JSON Schema
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+pub struct PluginManifest {
+    pub name: String,
+    pub description: String,
+    pub parameters: RootSchema,
+    #[serde(default = "default_postprocess")]
+    pub postprocess: bool,
+}
+
+fn default_postprocess() -> bool {
+    true
+}
+
+//execute返回的json，result和error二选一
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+struct PluginOutput {
+    #[serde(default)]
+    result: Option<serde_json::Value>,
+    #[serde(default)]
+    error: Option<String>,
+}
+
+struct PluginState {
+    wasi: WasiP1Ctx,
+    limits: StoreLimits,
+}
+
+fn engine() -> &'static Engine {
+    ENGINE.get_or_init(|| {
+        let mut config = Config::new();
+        config.consume_fuel(true);
+        config.epoch_interruption(true);
+        let engine = Engine::new(&config).expect("create wasm engine failed");
+        let ticker = engine.clone();
+        std::thread::spawn(move || loop {
+            std::thread::sleep(EPOCH_TICK);
+            ticker.increment_epoch();
+        });
+        engine
+    })
+}
+
+//读取目录下所有的 .wasm 文件，目录不存在时视为没有插件；无法加载的插件跳过
+pub fn load_wasm_plugins(dir: &str, config: &PluginConfig) -> Result<Vec<WasmPlugin>> {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+    let mut paths: Vec<_> = entries
+        .filter_map(|x| x.ok().map(|x| x.path()))
+        .filter(|x| x.extension().is_some_and(|x| x == "wasm"))
+        .collect();
+    paths.sort();
+    let mut plugins = Vec::with_capacity(paths.len());
+    for path in paths {
+        match Module::from_file(engine(), &path).and_then(|x| WasmPlugin::new(x, *config)) {
+            Ok(plugin) => {
+                info!(
+                    "load wasm plugin {} from {}",
+                    plugin.manifest.name,
+                    path.display()
+                );
+                plugins.push(plugin);
+            }
+            Err(e) => info!("skip wasm plugin {}: {:?}", path.display(), e),
+        }
+    }
+    Ok(plugins)
+}
+
+//编译wasm比较耗时，放在阻塞线程中执行
+pub async fn init_wasm_plugins(dir: &str) -> Result<()> {
+    let config = PluginConfig::load(PLUGIN_CONFIG_PATH)?;
+    let dir = dir.to_string();
+    let plugins = tokio::task::spawn_blocking(move || load_wasm_plugins(&dir, &config)).await??;
+    let _ = WASM_PLUGINS.set(plugins);
+    Ok(())
+}
+
+pub fn wasm_plugins() -> &'static [WasmPlugin] {
+    WASM_PLUGINS.get().map(|x| x.as_slice()).unwrap_or_default()
+}
+
+//插件需要导出memory、alloc(len) -> ptr、manifest() -> i64、execute(ptr, len) -> i64，
+//返回的i64高32位是字符串的地址，低32位是长度
+#[derive(Clone)]
+pub struct WasmPlugin {
+    pub manifest: PluginManifest,
+    module: Module,
+    config: PluginConfig,
+}
+
+impl WasmPlugin {
+    pub fn new(module: Module, config: PluginConfig) -> Result<Self> {
+        let mut plugin = Self {
+            manifest: PluginManifest {
+                name: String::new(),
+                description: String::new(),
+                parameters: RootSchema::default(),
+                postprocess: true,
+            },
+            module,
+            config,
+        };
+        let (mut store, instance) = plugin.instantiate()?;
+        let manifest = instance.get_typed_func::<(), i64>(&mut store, "manifest")?;
+        let packed = manifest
+            .call(&mut store, ())
+            .map_err(|e| plugin.map_trap(e))?;
+        plugin.manifest = serde_json::from_slice(&read_packed(&instance, &mut store, packed)?)?;
+        if !TOOL_NAME.is_match(&plugin.manifest.name) {
+            return Err(anyhow::anyhow!(
+                "Invalid plugin name: {}",
+                plugin.manifest.name
+            ));
+        }
+        Ok(plugin)
+    }
+
+    //每次调用都使用新的实例，插件之间、调用之间不共享状态；WASI中没有预打开的目录和环境变量
+    fn instantiate(&self) -> Result<(Store<PluginState>, Instance)> {
+        let state = PluginState {
+            wasi: WasiCtxBuilder::new().build_p1(),
+            limits: StoreLimitsBuilder::new()
+                .memory_size(self.config.max_memory_bytes)
+                .instances(1)
+                .build(),
+        };
+        let mut store = Store::new(engine(), state);
+        store.limiter(|x| &mut x.limits);
+        store.set_fuel(self.config.max_fuel)?;
+        store.set_epoch_deadline((self.config.timeout_ms / EPOCH_TICK.as_millis() as u64).max(1));
+        store.epoch_deadline_trap();
+        let mut linker: Linker<PluginState> = Linker::new(engine());
+        wasmtime_wasi::preview1::add_to_linker_sync(&mut linker, |x| &mut x.wasi)?;
+        let instance = linker
+            .instantiate(&mut store, &self.module)
+            .map_err(|e| self.map_trap(e))?;
+        Ok((store, instance))
+    }
+
+    pub fn call(&self, parameters: &serde_json::Value) -> Result<String> {
+        let (mut store, instance) = self.instantiate()?;
+        let input = serde_json::to_vec(parameters)?;
+        let alloc: TypedFunc<i32, i32> = instance.get_typed_func(&mut store, "alloc")?;
+        let execute: TypedFunc<(i32, i32), i64> = instance.get_typed_func(&mut store, "execute")?;
+        let pointer = alloc
+            .call(&mut store, input.len() as i32)
+            .map_err(|e| self.map_trap(e))?;
+        memory(&instance, &mut store)?.write(&mut store, pointer as usize, &input)?;
+        let packed = execute
+            .call(&mut store, (pointer, input.len() as i32))
+            .map_err(|e| self.map_trap(e))?;
+        let output: PluginOutput =
+            serde_json::from_slice(&read_packed(&instance, &mut store, packed)?)?;
+        match (output.result, output.error) {
+            (_, Some(error)) => Err(anyhow::anyhow!("{} failed: {}", self.manifest.name, error)),
+            (Some(serde_json::Value::String(text)), None) => Ok(text),
+            (Some(value), None) => Ok(value.to_string()),
+            (None, None) => Err(anyhow::anyhow!("{} returned no result", self.manifest.name)),
+        }
+    }
+
+    fn map_trap(&self, error: anyhow::Error) -> anyhow::Error {
+        let name = match self.manifest.name.as_str() {
+            "" => "plugin",
+            name => name,
+        };
+        match error.downcast_ref::<Trap>() {
+            Some(Trap::OutOfFuel) => anyhow::anyhow!("{} exceeded the fuel limit", name),
+            Some(Trap::Interrupt) => {
+                anyhow::anyhow!("{} timed out after {}ms", name, self.config.timeout_ms)
+            }
+            _ => anyhow::anyhow!("{} trapped: {}", name, error),
+        }
+    }
+}
+
+fn memory(instance: &Instance, store: &mut Store<PluginState>) -> Result<wasmtime::Memory> {
+    instance
+        .get_memory(&mut *store, "memory")
+        .ok_or_else(|| anyhow::anyhow!("plugin does not export memory"))
+}
+
+fn read_packed(
+    instance: &Instance,
+    store: &mut Store<PluginState>,
+    packed: i64,
+) -> Result<Vec<u8>> {
+    let pointer = (packed as u64 >> 32) as usize;
+    let length = (packed as u64 & 0xffff_ffff) as usize;
+    let memory = memory(instance, store)?;
+    let data = memory
+        .data(&*store)
+        .get(pointer..pointer + length)
+        .ok_or_else(|| anyhow::anyhow!("plugin returned an out of bounds string"))?;
+    Ok(data.to_vec())
+}
+
+impl Function for WasmPlugin {
+    //执行是纯计算，在当前线程同步运行
+    fn execute(&self, parameters: serde_json::Value, _context: &Context) -> Result<String> {
+        tokio::task::block_in_place(|| self.call(&parameters))
+    }
+
+    fn if_postprocess(&self) -> bool {
+        self.manifest.postprocess
+    }
+
+    fn get_name(&self) -> String {
+        self.manifest.name.clone()
+    }
+
+    fn get_description(&self) -> String {
+        self.manifest.description.clone()
+    }
+
+    fn get_parameter_schema(&self) -> RootSchema {
+        self.manifest.parameters.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{engine, PluginConfig, WasmPlugin};
+    use wasmtime::Module;
+
+    const CONFIG: PluginConfig = PluginConfig {
+        max_fuel: 10_000_000,
+        max_memory_bytes: 2 * 65536,
+        timeout_ms: 1000,
+    };
+
+    //最小的插件：manifest返回固定的json，execute把输入原样放进result，输入为空对象时进入死循环
+    const ECHO_PLUGIN: &str = r#"
+        (module
+            (memory (export "memory") 1)
+            (global $next (mut i32) (i32.const 1024))
+            (data (i32.const 0) "{\"name\":\"echo\",\"description\":\"原样返回参数\",\"parameters\":{\"type\":\"object\"}}")
+            (data (i32.const 512) "{\"result\":")
+            (func (export "alloc") (param $len i32) (result i32)
+                (local $pointer i32)
+                (local.set $pointer (global.get $next))
+                (global.set $next (i32.add (global.get $next) (local.get $len)))
+                (local.get $pointer))
+            (func (export "manifest") (result i64)
+                (i64.const 81))
+            (func (export "execute") (param $pointer i32) (param $len i32) (result i64)
+                (if (i32.eq (local.get $len) (i32.const 2))
+                    (then (loop $forever (br $forever))))
+                (memory.copy (i32.const 2048) (i32.const 512) (i32.const 10))
+                (memory.copy (i32.const 2058) (local.get $pointer) (local.get $len))
+                (i32.store8 (i32.add (i32.const 2058) (local.get $len)) (i32.const 125))
+                (i64.or
+                    (i64.shl (i64.const 2048) (i64.const 32))
+                    (i64.extend_i32_u (i32.add (local.get $len) (i32.const 11))))))
+    "#;
+
+    #[test]
+    fn test_wasm_plugin() {
+        let module = Module::new(engine(), ECHO_PLUGIN).unwrap();
+        let plugin = WasmPlugin::new(module, CONFIG).unwrap();
+        assert_eq!(plugin.manifest.name, "echo");
+        let result = plugin.call(&serde_json::json!({"x": 1})).unwrap();
+        assert_eq!(result, r#"{"x":1}"#);
+        let error = plugin.call(&serde_json::json!({})).unwrap_err();
+        assert!(error.to_string().contains("fuel"));
+    }
+
+    #[test]
+    fn test_timeout() {
+        //fuel足够时由epoch中断结束死循环
+        let config = PluginConfig {
+            max_fuel: u64::MAX,
+            timeout_ms: 50,
+            ..CONFIG
+        };
+        let module = Module::new(engine(), ECHO_PLUGIN).unwrap();
+        let plugin = WasmPlugin::new(module, config).unwrap();
+        let error = plugin.call(&serde_json::json!({})).unwrap_err();
+        assert!(error.to_string().contains("timed out after 50ms"));
+    }
+
+    #[test]
+    fn test_invalid_name() {
+        let plugin = ECHO_PLUGIN.replace(r#"\"name\":\"echo\""#, r#"\"name\":\"1cho\""#);
+        assert_ne!(plugin, ECHO_PLUGIN);
+        let module = Module::new(engine(), plugin).unwrap();
+        let error = WasmPlugin::new(module, CONFIG).err().unwrap();
+        assert!(error.to_string().contains("Invalid plugin name"));
+    }
+
+    #[test]
+    fn test_memory_limit() {
+        let module = Module::new(
+            engine(),
+            r#"(module (memory (export "memory") 4) (func (export "manifest") (result i64) (i64.const 0)))"#,
+        )
+        .unwrap();
+        assert!(WasmPlugin::new(module, CONFIG).is_err());
+    }
+}
@@ -0,0 +1,393 @@
+use super::{
+    document_summary::summarize_text,
+    function::{block_on, Capability, Context, Function},
+};
+use crate::readability::{extract, meta_charset};
+use anyhow::Result;
+use schemars::{schema::RootSchema, schema_for, JsonSchema};
+use serde::{Deserialize, Serialize};
+use std::{
+    net::{IpAddr, SocketAddr},
+    time::Duration,
+};
+use tracing::info;
+use url::{Host, Url};
+
+pub const WEB_CONFIG_PATH: &str = "configs/web_config.json";
+
+fn default_true() -> bool {
+    true
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct WebConfig {
+    //允许访问的域名，包括其子域名；为空时允许所有域名
+    #[serde(default)]
+    pub allow_domains: Vec<String>,
+    //禁止访问的域名，优先于allow_domains
+    #[serde(default)]
+    pub deny_domains: Vec<String>,
+    //禁止访问回环、内网等地址，防止通过函数探测内部服务
+    #[serde(default = "default_true")]
+    pub block_private_addresses: bool,
+    //响应体最多读取的字节数，超出部分丢弃
+    pub max_bytes: usize,
+    pub timeout_secs: u64,
+    pub max_redirects: usize,
+    //不生成摘要时返回的正文最多字数
+    pub max_text_chars: usize,
+    pub user_agent: String,
+}
+
+impl WebConfig {
+    pub fn load(path: &str) -> Result<Self> {
+        let config_string = std::fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&config_string)?)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct WebPage {
+    //跟随重定向后的地址
+    pub url: String,
+    pub title: Option<String>,
+    pub text: String,
+    //响应体超过max_bytes被截断
+    pub truncated: bool,
+}
+
+fn domain_matches(host: &str, domain: &str) -> bool {
+    let domain = domain.trim_start_matches('.').to_lowercase();
+    host == domain || host.ends_with(&format!(".{}", domain))
+}
+
+fn is_private(ip: &IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, ..] = ip.octets();
+            ip.is_private()
+                || ip.is_loopback()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+                || ip.is_documentation()
+                || a == 0
+                //运营商级NAT 100.64.0.0/10
+                || (a == 100 && (64..128).contains(&b))
+        }
+        IpAddr::V6(ip) => {
+            let first = ip.segments()[0];
+            match ip.to_ipv4_mapped() {
+                Some(ip) => is_private(&IpAddr::V4(ip)),
+                None => {
+                    ip.is_loopback()
+                        || ip.is_unspecified()
+                        || (first & 0xfe00) == 0xfc00
+                        || (first & 0xffc0) == 0xfe80
+                }
+            }
+        }
+    }
+}
+
+//检查协议、域名名单，并解析域名确认不指向内网地址；每次重定向都要重新检查。
+//返回检查过的域名地址，请求时只连接这些地址，防止再次解析时被DNS重绑定到内网地址
+pub async fn check_url(url: &Url, config: &WebConfig) -> Result<Vec<SocketAddr>> {
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err(anyhow::anyhow!("只支持http和https网址: {}", url));
+    }
+    let host = url
+        .host_str()
+        .ok_or_else(|| anyhow::anyhow!("网址缺少域名: {}", url))?
+        .trim_matches(['[', ']'])
+        .to_lowercase();
+    if config.deny_domains.iter().any(|x| domain_matches(&host, x)) {
+        return Err(anyhow::anyhow!("禁止访问的网址: {}", url));
+    }
+    if !config.allow_domains.is_empty()
+        && !config
+            .allow_domains
+            .iter()
+            .any(|x| domain_matches(&host, x))
+    {
+        return Err(anyhow::anyhow!("网址不在允许访问的范围内: {}", url));
+    }
+    if !config.block_private_addresses {
+        return Ok(Vec::new());
+    }
+    let (ips, addresses): (Vec<IpAddr>, Vec<SocketAddr>) = match url.host() {
+        Some(Host::Ipv4(ip)) => (vec![IpAddr::V4(ip)], Vec::new()),
+        Some(Host::Ipv6(ip)) => (vec![IpAddr::V6(ip)], Vec::new()),
+        _ => {
+            let port = url.port_or_known_default().unwrap_or(80);
+            let addresses: Vec<SocketAddr> = tokio::net::lookup_host((host.as_str(), port))
+                .await
+                .map_err(|e| anyhow::anyhow!("无法解析域名 {}: {}", host, e))?
+                .collect();
+            (addresses.iter().map(|x| x.ip()).collect(), addresses)
+        }
+    };
+    if ips.iter().any(is_private) {
+        return Err(anyhow::anyhow!("禁止访问内网地址: {}", url));
+    }
+    Ok(addresses)
+}
+
+//addresses不为空时，url中的域名只解析为这些地址
+fn build_client(
+    url: &Url,
+    addresses: &[SocketAddr],
+    config: &WebConfig,
+) -> Result<reqwest::Client> {
+    let mut builder = reqwest::Client::builder()
+        .timeout(Duration::from_secs(config.timeout_secs))
+        .redirect(reqwest::redirect::Policy::none())
+        .user_agent(&config.user_agent);
+    if let (Some(Host::Domain(domain)), false) = (url.host(), addresses.is_empty()) {
+        builder = builder.resolve_to_addrs(domain, addresses);
+    }
+    Ok(builder.build()?)
+}
+
+fn decode(body: &[u8], content_type: &str) -> String {
+    let charset = content_type
+        .split(';')
+        .filter_map(|x| x.trim().strip_prefix("charset="))
+        .next()
+        .map(|x| x.trim_matches('"').to_lowercase())
+        .or_else(|| meta_charset(body));
+    let encoding = charset
+        .and_then(|x| encoding_rs::Encoding::for_label(x.as_bytes()))
+        .unwrap_or(encoding_rs::UTF_8);
+    encoding.decode(body).0.to_string()
+}
+
+//手动跟随重定向，响应体按块读取到max_bytes为止；只接受html和纯文本
+pub async fn fetch_page(url: &str, config: &WebConfig) -> Result<WebPage> {
+    let mut url = Url::parse(url.trim())?;
+    let mut redirects = 0;
+    let mut response = loop {
+        let addresses = check_url(&url, config).await?;
+        let client = build_client(&url, &addresses, config)?;
+        let response = client.get(url.clone()).send().await.map_err(|e| {
+            if e.is_timeout() {
+                anyhow::anyhow!("访问 {} 超过了{}秒", url, config.timeout_secs)
+            } else {
+                anyhow::anyhow!("访问 {} 失败: {}", url, e)
+            }
+        })?;
+        if !response.status().is_redirection() {
+            break response;
+        }
+        redirects += 1;
+        if redirects > config.max_redirects {
+            return Err(anyhow::anyhow!("重定向次数过多: {}", url));
+        }
+        let location = response
+            .headers()
+            .get(reqwest::header::LOCATION)
+            .and_then(|x| x.to_str().ok())
+            .ok_or_else(|| anyhow::anyhow!("重定向缺少Location: {}", url))?;
+        url = url.join(location)?;
+    };
+    if !response.status().is_success() {
+        return Err(anyhow::anyhow!("访问 {} 返回 {}", url, response.status()));
+    }
+    let content_type = response
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|x| x.to_str().ok())
+        .unwrap_or("text/html")
+        .to_lowercase();
+    let is_html = content_type.starts_with("text/html") || content_type.contains("xhtml");
+    if !is_html && !content_type.starts_with("text/plain") {
+        return Err(anyhow::anyhow!("不支持的网页类型: {}", content_type));
+    }
+    let mut body = Vec::new();
+    let mut truncated = false;
+    while let Some(chunk) = response.chunk().await? {
+        body.extend_from_slice(&chunk);
+        if body.len() > config.max_bytes {
+            body.truncate(config.max_bytes);
+            truncated = true;
+            break;
+        }
+    }
+    let text = decode(&body, &content_type);
+    let (title, text) = match is_html {
+        true => {
+            let article = extract(&text);
+            (article.title, article.text)
+        }
+        false => (None, text),
+    };
+    Ok(WebPage {
+        url: url.to_string(),
+        title,
+        text,
+        truncated,
+    })
+}
+
+#[derive(JsonSchema, Debug, Clone, Serialize, Deserialize, PartialEq)]
+struct WebReadParameters {
+    /// 网页地址，只支持http和https
+    url: String,
+    /// 是否对正文生成摘要，默认为true；为false时返回提取出的正文
+    summarize: Option<bool>,
+    /// 建议的摘要字数
+    length: Option<usize>,
+    /// 摘要使用的语言，如"中文"、"English"；不填则与网页一致
+    language: Option<String>,
+}
+
+pub struct WebReadFunction {}
+
+impl Function for WebReadFunction {
+    fn execute(&self, parameters: serde_json::Value, context: &Context) -> Result<String> {
+        let parameters: WebReadParameters = serde_json::from_value(parameters)?;
+        let config = WebConfig::load(WEB_CONFIG_PATH)?;
+        let page = block_on(fetch_page(&parameters.url, &config))?;
+        info!("fetched {} ({} chars)", page.url, page.text.chars().count());
+        if page.text.trim().is_empty() {
+            return Err(anyhow::anyhow!("没有从 {} 提取到正文", page.url));
+        }
+        let mut output = serde_json::json!({
+            "url": page.url,
+            "title": page.title,
+            "truncated": page.truncated,
+        });
+        if parameters.summarize.unwrap_or(true) {
+            output["summary"] =
+                summarize_text(context, &page.text, parameters.length, parameters.language)?.into();
+        } else {
+            let chars = page.text.chars().count();
+            output["text"] = page
+                .text
+                .chars()
+                .take(config.max_text_chars)
+                .collect::<String>()
+                .into();
+            output["truncated"] = (page.truncated || chars > config.max_text_chars).into();
+        }
+        Ok(output.to_string())
+    }
+
+    fn if_postprocess(&self) -> bool {
+        true
+    }
+
+    fn get_name(&self) -> String {
+        "web_read".to_string()
+    }
+
+    fn get_description(&self) -> String {
+        "当用户给出网址并要求总结、阅读或回答网页内容相关的问题时使用该函数。函数会抓取网页，去掉导航、广告等内容后提取正文，默认返回正文的摘要；需要原文细节时可以设置summarize为false以获取正文。".to_string()
+    }
+
+    fn get_parameter_schema(&self) -> RootSchema {
+        schema_for!(WebReadParameters)
+    }
+
+    fn get_capabilities(&self) -> Vec<Capability> {
+        vec![Capability::Network, Capability::Model]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{build_client, check_url, fetch_page, WebConfig};
+    use axum::{http::header, response::Redirect, routing::get, Router};
+    use url::Url;
+
+    fn config() -> WebConfig {
+        WebConfig {
+            allow_domains: Vec::new(),
+            deny_domains: Vec::new(),
+            block_private_addresses: false,
+            max_bytes: 1024,
+            timeout_secs: 5,
+            max_redirects: 2,
+            max_text_chars: 8000,
+            user_agent: "test".to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_check_url() {
+        let mut config = config();
+        config.block_private_addresses = true;
+        let check = |url: &str| Url::parse(url).unwrap();
+        assert!(check_url(&check("http://127.0.0.1/"), &config)
+            .await
+            .is_err());
+        assert!(check_url(&check("http://[::1]/"), &config).await.is_err());
+        assert!(check_url(&check("http://10.1.2.3/"), &config)
+            .await
+            .is_err());
+        assert!(check_url(&check("file:///etc/passwd"), &config)
+            .await
+            .is_err());
+        config.block_private_addresses = false;
+        config.deny_domains = vec!["example.com".to_string()];
+        assert!(check_url(&check("https://news.example.com/"), &config)
+            .await
+            .is_err());
+        config.deny_domains.clear();
+        config.allow_domains = vec!["example.org".to_string()];
+        assert!(check_url(&check("https://example.org/a"), &config)
+            .await
+            .is_ok());
+        assert!(check_url(&check("https://example.net/a"), &config)
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_fetch_stub_server() {
+        let app = Router::new()
+            .route(
+                "/article",
+                get(|| async {
+                    (
+                        [(header::CONTENT_TYPE, "text/html; charset=utf-8")],
+                        "<title>测试</title><nav>菜单</nav><article><p>正文内容</p></article>",
+                    )
+                }),
+            )
+            .route("/moved", get(|| async { Redirect::temporary("/article") }))
+            .route("/loop", get(|| async { Redirect::temporary("/loop") }))
+            .route("/large", get(|| async { "很长的文本".repeat(1000) }))
+            .route(
+                "/binary",
+                get(|| async { ([(header::CONTENT_TYPE, "application/pdf")], "%PDF") }),
+            );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        let url = |path: &str| format!("http://{}{}", address, path);
+
+        let page = fetch_page(&url("/moved"), &config()).await.unwrap();
+        assert_eq!(page.url, url("/article"));
+        assert_eq!(page.title.as_deref(), Some("测试"));
+        assert_eq!(page.text, "正文内容");
+        assert!(!page.truncated);
+        let page = fetch_page(&url("/large"), &config()).await.unwrap();
+        assert!(page.truncated);
+        assert!(fetch_page(&url("/loop"), &config()).await.is_err());
+        assert!(fetch_page(&url("/binary"), &config()).await.is_err());
+        //默认配置禁止访问本机
+        let mut config = config();
+        config.block_private_addresses = true;
+        assert!(fetch_page(&url("/article"), &config).await.is_err());
+
+        //请求只连接检查过的地址，不会重新解析域名
+        let pinned =
+            Url::parse(&format!("http://pinned.invalid:{}/article", address.port())).unwrap();
+        let client = build_client(&pinned, &[address], &config).unwrap();
+        let response = client.get(pinned.clone()).send().await.unwrap();
+        assert!(response.status().is_success());
+        let client = build_client(&pinned, &[], &config).unwrap();
+        assert!(client.get(pinned).send().await.is_err());
+    }
+}
@@ -0,0 +1,486 @@
+use super::{
+    function::{block_on, Capability, Context, Function},
+    http_tool::{render, render_url, render_value, select_json_path},
+};
+use crate::{index::Bm25Index, readability::decode_entities};
+use anyhow::Result;
+use async_trait::async_trait;
+use lazy_static::lazy_static;
+use regex::Regex;
+use schemars::{schema::RootSchema, schema_for, JsonSchema};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, time::Duration};
+
+pub const SEARCH_CONFIG_PATH: &str = "configs/search_config.json";
+
+//摘要片段最多保留的字数
+const MAX_SNIPPET_CHARS: usize = 300;
+
+lazy_static! {
+    static ref HTML_TAG: Regex = Regex::new(r"<[^>]*>").unwrap();
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct SearchResult {
+    pub title: String,
+    pub url: String,
+    #[serde(default)]
+    pub snippet: String,
+}
+
+//搜索后端，返回按相关度排列的结果
+#[async_trait]
+pub trait SearchProvider: Send + Sync {
+    async fn search(&self, query: &str, limit: usize) -> Result<Vec<SearchResult>>;
+}
+
+fn default_method() -> String {
+    "GET".to_string()
+}
+
+fn default_title_field() -> String {
+    "title".to_string()
+}
+
+fn default_url_field() -> String {
+    "url".to_string()
+}
+
+fn default_snippet_field() -> String {
+    "snippet".to_string()
+}
+
+//通用的json搜索接口，url、headers、body中的 {{query}}、{{limit}}、{{env.变量名}} 会被替换
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct JsonApiConfig {
+    pub url: String,
+    #[serde(default = "default_method")]
+    pub method: String,
+    #[serde(default)]
+    pub headers: HashMap<String, String>,
+    #[serde(default)]
+    pub body: Option<serde_json::Value>,
+    //结果列表的JSONPath，如 $.data.items[*]
+    pub results_path: String,
+    //以下字段相对于每个结果，可以是 a.b 形式的嵌套字段
+    #[serde(default = "default_title_field")]
+    pub title_field: String,
+    #[serde(default = "default_url_field")]
+    pub url_field: String,
+    #[serde(default = "default_snippet_field")]
+    pub snippet_field: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "provider", rename_all = "snake_case")]
+pub enum SearchProviderConfig {
+    Searxng {
+        base_url: String,
+        #[serde(default)]
+        language: Option<String>,
+    },
+    JsonApi(JsonApiConfig),
+    //从本地json文件中按BM25检索，用于离线测试和演示
+    Fixture {
+        path: String,
+    },
+}
+
+fn default_max_results() -> usize {
+    5
+}
+
+fn default_timeout_secs() -> u64 {
+    10
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct SearchConfig {
+    #[serde(flatten)]
+    pub provider: SearchProviderConfig,
+    #[serde(default = "default_max_results")]
+    pub max_results: usize,
+    #[serde(default = "default_timeout_secs")]
+    pub timeout_secs: u64,
+}
+
+impl SearchConfig {
+    pub fn load(path: &str) -> Result<Self> {
+        let config_string = std::fs::read_to_string(path)
+            .map_err(|e| anyhow::anyhow!("未配置搜索服务({}): {}", path, e))?;
+        Ok(serde_json::from_str(&config_string)?)
+    }
+
+    pub fn build_provider(&self) -> Result<Box<dyn SearchProvider>> {
+        let timeout = Duration::from_secs(self.timeout_secs);
+        let client = reqwest::Client::builder().timeout(timeout).build()?;
+        Ok(match &self.provider {
+            SearchProviderConfig::Searxng { base_url, language } => Box::new(SearxngProvider {
+                client,
+                base_url: base_url.clone(),
+                language: language.clone(),
+            }),
+            SearchProviderConfig::JsonApi(config) => Box::new(JsonApiProvider {
+                client,
+                config: config.clone(),
+            }),
+            SearchProviderConfig::Fixture { path } => Box::new(FixtureProvider::load(path)?),
+        })
+    }
+}
+
+fn request_error(name: &str, error: reqwest::Error) -> anyhow::Error {
+    if error.is_timeout() {
+        anyhow::anyhow!("{} search timed out", name)
+    } else {
+        anyhow::anyhow!("{} search failed: {}", name, error)
+    }
+}
+
+pub struct SearxngProvider {
+    client: reqwest::Client,
+    base_url: String,
+    language: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SearxngResponse {
+    #[serde(default)]
+    results: Vec<SearxngResult>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SearxngResult {
+    #[serde(default)]
+    title: String,
+    url: String,
+    #[serde(default)]
+    content: String,
+}
+
+//需要在SearXNG的settings.yml中开启json输出格式
+#[async_trait]
+impl SearchProvider for SearxngProvider {
+    async fn search(&self, query: &str, limit: usize) -> Result<Vec<SearchResult>> {
+        let url = format!("{}/search", self.base_url.trim_end_matches('/'));
+        let mut request = self
+            .client
+            .get(url)
+            .query(&[("q", query), ("format", "json")]);
+        if let Some(language) = &self.language {
+            request = request.query(&[("language", language)]);
+        }
+        let response = request
+            .send()
+            .await
+            .map_err(|e| request_error("searxng", e))?
+            .error_for_status()?;
+        let response: SearxngResponse = response.json().await?;
+        Ok(response
+            .results
+            .into_iter()
+            .take(limit)
+            .map(|x| SearchResult {
+                title: x.title,
+                url: x.url,
+                snippet: x.content,
+            })
+            .collect())
+    }
+}
+
+pub struct JsonApiProvider {
+    client: reqwest::Client,
+    config: JsonApiConfig,
+}
+
+fn field_text(item: &serde_json::Value, field: &str) -> Result<String> {
+    let value = select_json_path(item, &format!("$.{}", field))?;
+    Ok(match value.into_iter().next() {
+        Some(serde_json::Value::String(text)) => text,
+        Some(serde_json::Value::Null) | None => String::new(),
+        Some(value) => value.to_string(),
+    })
+}
+
+#[async_trait]
+impl SearchProvider for JsonApiProvider {
+    async fn search(&self, query: &str, limit: usize) -> Result<Vec<SearchResult>> {
+        let config = &self.config;
+        let parameters = serde_json::json!({"query": query, "limit": limit});
+        let method = reqwest::Method::from_bytes(config.method.to_uppercase().as_bytes())?;
+        let mut request = self
+            .client
+            .request(method, render_url(&config.url, &parameters)?);
+        for (name, value) in config.headers.iter() {
+            request = request.header(name, render(value, &parameters)?);
+        }
+        if let Some(body) = &config.body {
+            request = request.json(&render_value(body, &parameters)?);
+        }
+        let response = request
+            .send()
+            .await
+            .map_err(|e| request_error("json api", e))?
+            .error_for_status()?;
+        let value: serde_json::Value = response.json().await?;
+        select_json_path(&value, &config.results_path)?
+            .iter()
+            .take(limit)
+            .map(|item| {
+                Ok(SearchResult {
+                    title: field_text(item, &config.title_field)?,
+                    url: field_text(item, &config.url_field)?,
+                    snippet: field_text(item, &config.snippet_field)?,
+                })
+            })
+            .collect()
+    }
+}
+
+//fixture文件是SearchResult的json数组，按标题和摘要建立BM25索引
+pub struct FixtureProvider {
+    results: Vec<SearchResult>,
+    index: Bm25Index,
+}
+
+impl FixtureProvider {
+    pub fn new(results: Vec<SearchResult>) -> Self {
+        let documents: Vec<String> = results
+            .iter()
+            .map(|x| format!("{}\n{}", x.title, x.snippet))
+            .collect();
+        let index = Bm25Index::build(documents.iter().map(|x| x.as_str()));
+        Self { results, index }
+    }
+
+    pub fn load(path: &str) -> Result<Self> {
+        let fixture_string = std::fs::read_to_string(path)?;
+        Ok(Self::new(serde_json::from_str(&fixture_string)?))
+    }
+}
+
+#[async_trait]
+impl SearchProvider for FixtureProvider {
+    async fn search(&self, query: &str, limit: usize) -> Result<Vec<SearchResult>> {
+        Ok(self
+            .index
+            .search(query, limit)
+            .into_iter()
+            .map(|x| self.results[x].clone())
+            .collect())
+    }
+}
+
+//去掉摘要中的高亮标签和多余空白，丢弃没有网址和重复网址的结果
+fn clean_results(results: Vec<SearchResult>) -> Vec<SearchResult> {
+    let mut seen = Vec::new();
+    let clean = |text: &str| {
+        let text = decode_entities(&HTML_TAG.replace_all(text, ""));
+        text.split_whitespace().collect::<Vec<_>>().join(" ")
+    };
+    results
+        .into_iter()
+        .filter(|x| !x.url.is_empty())
+        .filter(|x| {
+            let duplicated = seen.contains(&x.url);
+            seen.push(x.url.clone());
+            !duplicated
+        })
+        .map(|x| SearchResult {
+            title: clean(&x.title),
+            snippet: clean(&x.snippet).chars().take(MAX_SNIPPET_CHARS).collect(),
+            url: x.url,
+        })
+        .collect()
+}
+
+//清理会丢掉部分结果，向搜索服务多请求一些，清理后再截取需要的数量
+async fn search_cleaned(
+    provider: &dyn SearchProvider,
+    query: &str,
+    limit: usize,
+) -> Result<Vec<SearchResult>> {
+    let mut results = clean_results(provider.search(query, limit * 2).await?);
+    results.truncate(limit);
+    Ok(results)
+}
+
+#[derive(JsonSchema, Debug, Clone, Serialize, Deserialize, PartialEq)]
+struct WebSearchParameters {
+    /// 搜索关键词
+    query: String,
+    /// 返回的结果数，默认为配置中的最大值
+    limit: Option<usize>,
+}
+
+pub struct WebSearchFunction {}
+
+impl Function for WebSearchFunction {
+    fn execute(&self, parameters: serde_json::Value, _context: &Context) -> Result<String> {
+        let parameters: WebSearchParameters = serde_json::from_value(parameters)?;
+        let config = SearchConfig::load(SEARCH_CONFIG_PATH)?;
+        let limit = parameters
+            .limit
+            .unwrap_or(config.max_results)
+            .clamp(1, config.max_results.max(1));
+        let provider = config.build_provider()?;
+        let results = block_on(search_cleaned(provider.as_ref(), &parameters.query, limit))?;
+        let results: Vec<serde_json::Value> = results
+            .into_iter()
+            .enumerate()
+            .map(|(index, x)| {
+                serde_json::json!({
+                    "index": index + 1,
+                    "title": x.title,
+                    "url": x.url,
+                    "snippet": x.snippet,
+                })
+            })
+            .collect();
+        Ok(serde_json::json!({"query": parameters.query, "results": results}).to_string())
+    }
+
+    fn if_postprocess(&self) -> bool {
+        true
+    }
+
+    fn get_name(&self) -> String {
+        "web_search".to_string()
+    }
+
+    fn get_description(&self) -> String {
+        "需要查询最新信息、事实或者用户要求上网搜索时使用该函数。返回若干条带标题、网址和摘要片段的搜索结果，回答时请用 [序号] 标注引用的结果并给出网址；需要网页的完整内容时，可以再用web_read读取结果中的网址。".to_string()
+    }
+
+    fn get_parameter_schema(&self) -> RootSchema {
+        schema_for!(WebSearchParameters)
+    }
+
+    fn get_capabilities(&self) -> Vec<Capability> {
+        vec![Capability::Network]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        clean_results, search_cleaned, FixtureProvider, SearchConfig, SearchProvider,
+        SearchProviderConfig, SearchResult,
+    };
+    use axum::{extract::Query, routing::get, Json, Router};
+    use std::collections::HashMap;
+
+    fn result(title: &str, url: &str, snippet: &str) -> SearchResult {
+        SearchResult {
+            title: title.to_string(),
+            url: url.to_string(),
+            snippet: snippet.to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_fixture_provider() {
+        let provider = FixtureProvider::new(vec![
+            result(
+                "文心一言发布",
+                "https://a.example/1",
+                "百度发布了大语言模型文心一言",
+            ),
+            result("天气预报", "https://a.example/2", "北京明天晴"),
+        ]);
+        let results = provider.search("文心一言", 5).await.unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].url, "https://a.example/1");
+        assert!(provider.search("火星", 5).await.unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_clean_results() {
+        let results = clean_results(vec![
+            result(
+                "<b>Rust</b> &amp; WASM",
+                "https://a.example",
+                "a  <em>b</em>\n c",
+            ),
+            result("重复", "https://a.example", ""),
+            result("没有网址", "", ""),
+        ]);
+        assert_eq!(
+            results,
+            vec![result("Rust & WASM", "https://a.example", "a b c")]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_search_cleaned() {
+        //重复和没有网址的结果被丢弃后仍然返回limit条
+        let provider = FixtureProvider::new(vec![
+            result("Rust 1", "https://a.example/1", "rust"),
+            result("Rust 1 转载", "https://a.example/1", "rust rust"),
+            result("Rust 2", "", "rust"),
+            result("Rust 3", "https://a.example/3", "rust"),
+            result("Rust 4", "https://a.example/4", "rust"),
+        ]);
+        let results = search_cleaned(&provider, "rust", 2).await.unwrap();
+        assert_eq!(results.len(), 2);
+        assert!(results.iter().all(|x| !x.url.is_empty()));
+        assert_ne!(results[0].url, results[1].url);
+    }
+
+    #[tokio::test]
+    async fn test_http_providers() {
+        async fn searxng(Query(query): Query<HashMap<String, String>>) -> Json<serde_json::Value> {
+            assert_eq!(query.get("format").map(|x| x.as_str()), Some("json"));
+            Json(serde_json::json!({"results": [
+                {"title": query["q"], "url": "https://s.example/1", "content": "片段一"},
+                {"title": "第二条", "url": "https://s.example/2", "content": "片段二"}
+            ]}))
+        }
+        async fn api(Query(query): Query<HashMap<String, String>>) -> Json<serde_json::Value> {
+            Json(serde_json::json!({"data": {"items": [
+                {"name": query["keyword"], "link": {"href": "https://j.example"}, "summary": null}
+            ]}}))
+        }
+        let app = Router::new()
+            .route("/search", get(searxng))
+            .route("/api", get(api));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let config: SearchConfig = serde_json::from_value(serde_json::json!({
+            "provider": "searxng", "base_url": format!("http://{}/", address)
+        }))
+        .unwrap();
+        assert_eq!(config.max_results, 5);
+        let results = config
+            .build_provider()
+            .unwrap()
+            .search("rust", 1)
+            .await
+            .unwrap();
+        assert_eq!(
+            results,
+            vec![result("rust", "https://s.example/1", "片段一")]
+        );
+
+        let config: SearchConfig = serde_json::from_value(serde_json::json!({
+            "provider": "json_api",
+            "url": format!("http://{}/api?keyword={{{{query}}}}", address),
+            "results_path": "$.data.items[*]",
+            "title_field": "name",
+            "url_field": "link.href",
+            "snippet_field": "summary"
+        }))
+        .unwrap();
+        assert!(matches!(config.provider, SearchProviderConfig::JsonApi(_)));
+        let results = config
+            .build_provider()
+            .unwrap()
+            .search("北京 天气", 5)
+            .await
+            .unwrap();
+        assert_eq!(results, vec![result("北京 天气", "https://j.example", "")]);
+    }
+}
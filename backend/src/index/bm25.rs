@@ -0,0 +1,85 @@
+use std::collections::HashMap;
+
+use jieba_rs::Jieba;
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+
+lazy_static! {
+    static ref JIEBA: Jieba = Jieba::new();
+}
+
+const K1: f64 = 1.2;
+const B: f64 = 0.75;
+
+//用jieba的搜索引擎模式分词，长词会再切出其中的短词，英文和数字统一转为小写
+pub fn tokenize(text: &str) -> Vec<String> {
+    JIEBA
+        .cut_for_search(text, true)
+        .into_iter()
+        .map(|x| x.trim().to_lowercase())
+        .filter(|x| x.chars().any(|c| c.is_alphanumeric()))
+        .collect()
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Bm25Index {
+    //词 -> [(文档序号, 词频)]
+    postings: HashMap<String, Vec<(usize, u32)>>,
+    doc_lengths: Vec<u32>,
+    average_length: f64,
+}
+
+impl Bm25Index {
+    pub fn build<'a>(documents: impl Iterator<Item = &'a str>) -> Self {
+        let mut postings: HashMap<String, Vec<(usize, u32)>> = HashMap::new();
+        let mut doc_lengths = Vec::new();
+        for (doc, text) in documents.enumerate() {
+            let tokens = tokenize(text);
+            doc_lengths.push(tokens.len() as u32);
+            let mut frequencies: HashMap<String, u32> = HashMap::new();
+            for token in tokens {
+                *frequencies.entry(token).or_insert(0) += 1;
+            }
+            for (token, frequency) in frequencies {
+                postings.entry(token).or_default().push((doc, frequency));
+            }
+        }
+        let total: u64 = doc_lengths.iter().map(|x| *x as u64).sum();
+        let average_length = if doc_lengths.is_empty() {
+            0.0
+        } else {
+            total as f64 / doc_lengths.len() as f64
+        };
+        Self {
+            postings,
+            doc_lengths,
+            average_length,
+        }
+    }
+
+    //返回按相关度从高到低排列的文档序号，不包含与查询没有共同词的文档
+    pub fn search(&self, query: &str, limit: usize) -> Vec<usize> {
+        let doc_count = self.doc_lengths.len() as f64;
+        let mut scores: HashMap<usize, f64> = HashMap::new();
+        let mut query_tokens = tokenize(query);
+        query_tokens.sort();
+        query_tokens.dedup();
+        for token in query_tokens {
+            let postings = match self.postings.get(&token) {
+                Some(postings) => postings,
+                None => continue,
+            };
+            let df = postings.len() as f64;
+            let idf = ((doc_count - df + 0.5) / (df + 0.5) + 1.0).ln();
+            for (doc, frequency) in postings {
+                let tf = *frequency as f64;
+                let length = self.doc_lengths[*doc] as f64;
+                let norm = K1 * (1.0 - B + B * length / self.average_length.max(1.0));
+                *scores.entry(*doc).or_insert(0.0) += idf * tf * (K1 + 1.0) / (tf + norm);
+            }
+        }
+        let mut ranked: Vec<(usize, f64)> = scores.into_iter().collect();
+        ranked.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+        ranked.into_iter().take(limit).map(|x| x.0).collect()
+    }
+}
@@ -0,0 +1,246 @@
+mod bm25;
+mod vector;
+
+use anyhow::Result;
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+
+pub use bm25::Bm25Index;
+pub use vector::{cosine_similarity, VectorIndex};
+
+use crate::ingest::DocumentChunk;
+
+//RRF中的平滑常数，取论文中的推荐值
+const RRF_K: f64 = 60.0;
+//每一路检索参与融合的候选数量
+const CANDIDATES_PER_RANKING: usize = 50;
+
+lazy_static! {
+    //会话文档的文本和各种索引的保存目录
+    static ref FILES_DIR: String = default_files_dir();
+}
+
+//第一次使用时读取环境变量FILES_DIR，未设置时为./files
+#[cfg(not(test))]
+fn default_files_dir() -> String {
+    std::env::var("FILES_DIR").unwrap_or_else(|_| "./files".to_string())
+}
+
+//测试总是使用单独的临时目录，不依赖测试的执行顺序，也不会写入实际的数据目录
+#[cfg(test)]
+fn default_files_dir() -> String {
+    std::env::temp_dir()
+        .join(format!("files-test-{}", uuid::Uuid::new_v4()))
+        .to_string_lossy()
+        .into_owned()
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct IndexedChunk {
+    pub text: String,
+    pub start: usize,
+    pub end: usize,
+    pub heading: Option<String>,
+    //片段来自知识库中的哪个文档，只有合并后的知识库索引才有
+    #[serde(default)]
+    pub document: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct SearchHit {
+    pub chunk: IndexedChunk,
+    pub score: f64,
+}
+
+//关键词检索(BM25)和向量检索的混合索引，两路结果用倒数排名融合(RRF)合并
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct HybridIndex {
+    chunks: Vec<IndexedChunk>,
+    bm25: Bm25Index,
+    vectors: VectorIndex,
+}
+
+pub fn files_dir() -> &'static str {
+    &FILES_DIR
+}
+
+//会话中上传的文档解析后的文本
+pub fn session_text_path(session_id: i32) -> String {
+    format!("{}/{}.txt", files_dir(), session_id)
+}
+
+pub fn index_path(session_id: i32) -> String {
+    format!("{}/{}.index.json", files_dir(), session_id)
+}
+
+//知识库中文档的索引以内容哈希命名，不同知识库中的相同文档共用一份
+pub fn document_index_path(storage_key: &str) -> String {
+    let filename = storage_key.rsplit('/').next().unwrap_or(storage_key);
+    let hash = filename.split('.').next().unwrap_or(filename);
+    format!("{}/documents/{}.index.json", files_dir(), hash)
+}
+
+//知识库中文档解析后的文本，和索引一样按内容哈希命名
+pub fn document_text_path(storage_key: &str) -> String {
+    document_index_path(storage_key).replace(".index.json", ".txt")
+}
+
+pub fn knowledge_base_index_path(knowledge_base_id: i32) -> String {
+    format!(
+        "{}/knowledge_bases/{}.index.json",
+        files_dir(),
+        knowledge_base_id
+    )
+}
+
+impl HybridIndex {
+    pub fn build(chunks: Vec<DocumentChunk>) -> Self {
+        let bm25 = Bm25Index::build(chunks.iter().map(|x| x.text.as_str()));
+        let vectors = VectorIndex::new(chunks.iter().map(|x| x.embedding.clone()).collect());
+        let chunks = chunks
+            .into_iter()
+            .map(|x| IndexedChunk {
+                text: x.text,
+                start: x.start,
+                end: x.end,
+                heading: x.heading,
+                document: None,
+            })
+            .collect();
+        Self {
+            chunks,
+            bm25,
+            vectors,
+        }
+    }
+
+    //合并多个文档的索引，每个片段记录来源的文档名，关键词索引需要根据合并后的文档集合重新计算
+    pub fn merge(indexes: Vec<(String, HybridIndex)>) -> Self {
+        let mut chunks = Vec::new();
+        let mut vectors = VectorIndex::new(Vec::new());
+        for (document, index) in indexes {
+            chunks.extend(index.chunks.into_iter().map(|chunk| IndexedChunk {
+                document: Some(document.clone()),
+                ..chunk
+            }));
+            vectors.extend(index.vectors);
+        }
+        let bm25 = Bm25Index::build(chunks.iter().map(|x| x.text.as_str()));
+        Self {
+            chunks,
+            bm25,
+            vectors,
+        }
+    }
+
+    pub fn load(path: &str) -> Result<Self> {
+        let index_string = std::fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&index_string)?)
+    }
+
+    pub fn save(&self, path: &str) -> Result<()> {
+        if let Some(parent) = std::path::Path::new(path).parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        //先写入临时文件再rename，并发读取的检索不会读到写了一半的索引
+        let partial_path = format!("{}.{}.partial", path, uuid::Uuid::new_v4());
+        std::fs::write(&partial_path, serde_json::to_string(self)?)?;
+        if let Err(e) = std::fs::rename(&partial_path, path) {
+            let _ = std::fs::remove_file(&partial_path);
+            return Err(e.into());
+        }
+        Ok(())
+    }
+
+    //没有查询向量时(如向量化接口失败)只使用关键词检索
+    pub fn search(
+        &self,
+        query: &str,
+        query_embedding: Option<&[f64]>,
+        top_k: usize,
+    ) -> Vec<SearchHit> {
+        let mut rankings = vec![self.bm25.search(query, CANDIDATES_PER_RANKING)];
+        if let Some(query_embedding) = query_embedding {
+            rankings.push(self.vectors.search(query_embedding, CANDIDATES_PER_RANKING));
+        }
+        reciprocal_rank_fusion(&rankings)
+            .into_iter()
+            .take(top_k)
+            .map(|(index, score)| SearchHit {
+                chunk: self.chunks[index].clone(),
+                score,
+            })
+            .collect()
+    }
+}
+
+//每一路排名中第r名(从1开始)贡献 1/(k+r) 分，按总分从高到低返回
+pub fn reciprocal_rank_fusion(rankings: &[Vec<usize>]) -> Vec<(usize, f64)> {
+    let mut scores: Vec<(usize, f64)> = Vec::new();
+    for ranking in rankings {
+        for (rank, index) in ranking.iter().enumerate() {
+            let score = 1.0 / (RRF_K + rank as f64 + 1.0);
+            match scores.iter_mut().find(|x| x.0 == *index) {
+                Some(entry) => entry.1 += score,
+                None => scores.push((*index, score)),
+            }
+        }
+    }
+    scores.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+    scores
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{reciprocal_rank_fusion, HybridIndex};
+    use crate::ingest::DocumentChunk;
+
+    fn chunk(text: &str, embedding: Vec<f64>) -> DocumentChunk {
+        DocumentChunk {
+            index: 0,
+            text: text.to_string(),
+            start: 0,
+            end: text.len(),
+            heading: None,
+            embedding,
+        }
+    }
+
+    #[test]
+    fn test_reciprocal_rank_fusion() {
+        let fused = reciprocal_rank_fusion(&[vec![1, 2, 3], vec![3, 1]]);
+        assert_eq!(fused.iter().map(|x| x.0).collect::<Vec<_>>(), vec![1, 3, 2]);
+    }
+
+    #[test]
+    fn test_hybrid_search() {
+        let index = HybridIndex::build(vec![
+            chunk("产品型号ABC-1234的保修期为两年", vec![1.0, 0.0]),
+            chunk("公司年会将在十二月举行", vec![0.0, 1.0]),
+            chunk("售后服务电话请咨询客服", vec![0.9, 0.1]),
+        ]);
+        let hits = index.search("ABC-1234保修", None, 2);
+        assert!(hits[0].chunk.text.contains("ABC-1234"));
+        let hits = index.search("年会", Some(&[0.0, 1.0]), 3);
+        assert!(hits[0].chunk.text.contains("年会"));
+    }
+
+    #[test]
+    fn test_merge_keeps_document() {
+        let index = HybridIndex::merge(vec![
+            (
+                "保修政策.pdf".to_string(),
+                HybridIndex::build(vec![chunk(
+                    "产品型号ABC-1234的保修期为两年",
+                    vec![1.0, 0.0],
+                )]),
+            ),
+            (
+                "年会通知.docx".to_string(),
+                HybridIndex::build(vec![chunk("公司年会将在十二月举行", vec![0.0, 1.0])]),
+            ),
+        ]);
+        let hits = index.search("年会", Some(&[0.0, 1.0]), 1);
+        assert_eq!(hits[0].chunk.document.as_deref(), Some("年会通知.docx"));
+    }
+}
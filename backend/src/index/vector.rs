@@ -0,0 +1,38 @@
+use serde::{Deserialize, Serialize};
+
+//暴力计算余弦相似度，单个会话的文档片段数量不大，无需近似索引
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct VectorIndex {
+    vectors: Vec<Vec<f64>>,
+}
+
+impl VectorIndex {
+    pub fn new(vectors: Vec<Vec<f64>>) -> Self {
+        Self { vectors }
+    }
+
+    pub fn extend(&mut self, other: VectorIndex) {
+        self.vectors.extend(other.vectors);
+    }
+
+    pub fn search(&self, query: &[f64], limit: usize) -> Vec<usize> {
+        let mut ranked: Vec<(usize, f64)> = self
+            .vectors
+            .iter()
+            .enumerate()
+            .map(|(index, vector)| (index, cosine_similarity(query, vector)))
+            .collect();
+        ranked.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+        ranked.into_iter().take(limit).map(|x| x.0).collect()
+    }
+}
+
+pub fn cosine_similarity(a: &[f64], b: &[f64]) -> f64 {
+    let dot: f64 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm_a = a.iter().map(|x| x * x).sum::<f64>().sqrt();
+    let norm_b = b.iter().map(|x| x * x).sum::<f64>().sqrt();
+    if norm_a == 0.0 || norm_b == 0.0 {
+        return 0.0;
+    }
+    dot / (norm_a * norm_b)
+}
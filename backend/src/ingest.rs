@@ -0,0 +1,474 @@
+use std::{path::PathBuf, sync::Arc};
+
+use anyhow::Result;
+use async_trait::async_trait;
+use erniebot_rs::embedding::EmbeddingEndpoint;
+use sea_orm::{
+    ActiveEnum, ActiveModelTrait, ActiveValue::NotSet, ColumnTrait, DatabaseConnection,
+    EntityTrait, QueryFilter, Set,
+};
+use serde::{Deserialize, Serialize};
+use socketioxide::SocketIo;
+use tokio::sync::{mpsc, Mutex};
+use tracing::info;
+
+use crate::{
+    chunker::{self, ChunkerConfig},
+    entities::{job, prelude::Job, sea_orm_active_enums::JobStatus},
+    index::{
+        document_index_path, document_text_path, files_dir, index_path, session_text_path,
+        HybridIndex,
+    },
+    knowledge_base::rebuild_knowledge_base_index,
+    parser::parse_file,
+    storage::Storage,
+};
+
+//Embedding-V1单次请求最多16条文本，每条不超过384个token
+const EMBEDDING_BATCH_SIZE: usize = 16;
+const EMBEDDING_CHUNKER_CONFIG: ChunkerConfig = ChunkerConfig {
+    max_tokens: 300,
+    overlap_tokens: 30,
+};
+
+//后台任务使用的向量化接口，测试中可以替换为不访问网络的实现
+#[async_trait]
+pub trait Embedder: Send + Sync {
+    async fn embed(&self, input: &[String]) -> Result<Vec<Vec<f64>>>;
+}
+
+#[async_trait]
+impl Embedder for EmbeddingEndpoint {
+    async fn embed(&self, input: &[String]) -> Result<Vec<Vec<f64>>> {
+        let response = self.ainvoke(&input.to_vec(), None).await?;
+        Ok(response.get_embedding_results()?)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct DocumentChunk {
+    pub index: usize,
+    pub text: String,
+    //在解析后文本中的字节偏移
+    pub start: usize,
+    pub end: usize,
+    pub heading: Option<String>,
+    pub embedding: Vec<f64>,
+}
+
+//后台任务处理的文档属于某个会话或某个知识库
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum IngestTarget {
+    Session(i32),
+    KnowledgeBase(i32),
+}
+
+pub fn session_room(session_id: i32) -> String {
+    format!("session-{}", session_id)
+}
+
+pub fn knowledge_base_room(knowledge_base_id: i32) -> String {
+    format!("knowledge-base-{}", knowledge_base_id)
+}
+
+pub fn job_to_json(job: &job::Model) -> serde_json::Value {
+    serde_json::json!({
+        "job_id": job.job_id,
+        "session_id": job.session_id,
+        "knowledge_base_id": job.knowledge_base_id,
+        "filename": job.filename,
+        "status": job.status.to_value(),
+        "progress": job.progress,
+        "error": job.error,
+        "create_time": job.create_time,
+        "update_time": job.update_time,
+    })
+}
+
+//上传后的文件解析、切分、向量化在后台执行，任务状态保存在数据库中，服务重启后未完成的任务会重新执行
+#[derive(Clone)]
+pub struct IngestQueue {
+    sender: mpsc::UnboundedSender<String>,
+    db: DatabaseConnection,
+}
+
+struct IngestWorker {
+    db: DatabaseConnection,
+    storage: Arc<dyn Storage>,
+    embedder: Arc<dyn Embedder>,
+    io: SocketIo,
+    staging_dir: String,
+}
+
+impl IngestQueue {
+    pub fn start(
+        workers: usize,
+        db: DatabaseConnection,
+        storage: Arc<dyn Storage>,
+        embedder: Arc<dyn Embedder>,
+        io: SocketIo,
+        staging_dir: &str,
+    ) -> Self {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let receiver = Arc::new(Mutex::new(receiver));
+        let worker = Arc::new(IngestWorker {
+            db: db.clone(),
+            storage,
+            embedder,
+            io,
+            staging_dir: staging_dir.to_string(),
+        });
+        for _ in 0..workers {
+            tokio::spawn(worker.clone().run(receiver.clone()));
+        }
+        Self { sender, db }
+    }
+
+    pub async fn submit(
+        &self,
+        target: IngestTarget,
+        filename: &str,
+        storage_key: &str,
+    ) -> Result<String> {
+        let (session_id, knowledge_base_id) = match target {
+            IngestTarget::Session(session_id) => (Some(session_id), None),
+            IngestTarget::KnowledgeBase(knowledge_base_id) => (None, Some(knowledge_base_id)),
+        };
+        let job_id = uuid::Uuid::new_v4().to_string();
+        let job = job::ActiveModel {
+            job_id: Set(job_id.clone()),
+            session_id: Set(session_id),
+            knowledge_base_id: Set(knowledge_base_id),
+            filename: Set(filename.to_string()),
+            storage_key: Set(storage_key.to_string()),
+            status: Set(JobStatus::Queued),
+            progress: Set(0),
+            error: Set(None),
+            create_time: Set(chrono::Utc::now()),
+            update_time: Set(chrono::Utc::now()),
+        };
+        Job::insert(job).exec(&self.db).await?;
+        self.sender.send(job_id.clone())?;
+        Ok(job_id)
+    }
+
+    //重新排队上次运行时没有完成的任务，每个任务都从头开始执行
+    pub async fn resume(&self) -> Result<()> {
+        let jobs = Job::find()
+            .filter(job::Column::Status.is_not_in([JobStatus::Completed, JobStatus::Failed]))
+            .all(&self.db)
+            .await?;
+        for job in jobs {
+            info!("resume ingest job: {}", job.job_id);
+            let job_id = job.job_id.clone();
+            let mut job: job::ActiveModel = job.into();
+            job.status = Set(JobStatus::Queued);
+            job.progress = Set(0);
+            job.update_time = Set(chrono::Utc::now());
+            job.update(&self.db).await?;
+            self.sender.send(job_id)?;
+        }
+        Ok(())
+    }
+
+    pub async fn get_job(&self, job_id: &str) -> Result<Option<job::Model>> {
+        let job = Job::find_by_id(job_id.to_string()).one(&self.db).await?;
+        Ok(job)
+    }
+}
+
+impl IngestWorker {
+    async fn run(self: Arc<Self>, receiver: Arc<Mutex<mpsc::UnboundedReceiver<String>>>) {
+        loop {
+            let job_id = receiver.lock().await.recv().await;
+            let job_id = match job_id {
+                Some(job_id) => job_id,
+                None => break,
+            };
+            info!("start ingest job: {}", job_id);
+            if let Err(e) = self.process(&job_id).await {
+                info!("ingest job {} failed: {:?}", job_id, e);
+                let _ = self
+                    .update(&job_id, JobStatus::Failed, None, Some(e.to_string()))
+                    .await;
+            }
+        }
+    }
+
+    async fn process(&self, job_id: &str) -> Result<()> {
+        let job = Job::find_by_id(job_id.to_string())
+            .one(&self.db)
+            .await?
+            .ok_or_else(|| anyhow::anyhow!("Job not found"))?;
+
+        self.update(job_id, JobStatus::Parsing, Some(0), None)
+            .await?;
+        let documents = self.parse(&job).await?;
+        if let Some(session_id) = job.session_id {
+            tokio::fs::create_dir_all(files_dir()).await?;
+            tokio::fs::write(session_text_path(session_id), &documents).await?;
+        }
+
+        self.update(job_id, JobStatus::Chunking, Some(30), None)
+            .await?;
+        let chunks = chunker::split(&documents, &EMBEDDING_CHUNKER_CONFIG);
+
+        self.update(job_id, JobStatus::Embedding, Some(40), None)
+            .await?;
+        let mut results = Vec::with_capacity(chunks.len());
+        for batch in chunks.chunks(EMBEDDING_BATCH_SIZE) {
+            let input: Vec<String> = batch.iter().map(|x| x.text.clone()).collect();
+            let embeddings = self.embedder.embed(&input).await?;
+            for (chunk, embedding) in batch.iter().zip(embeddings) {
+                results.push(DocumentChunk {
+                    index: results.len(),
+                    text: chunk.text.clone(),
+                    start: chunk.start,
+                    end: chunk.end,
+                    heading: chunk.heading.clone(),
+                    embedding,
+                });
+            }
+            let progress = 40 + 60 * results.len() / chunks.len();
+            self.update(job_id, JobStatus::Embedding, Some(progress as i32), None)
+                .await?;
+        }
+        let index = HybridIndex::build(results);
+        match (job.session_id, job.knowledge_base_id) {
+            (Some(session_id), _) => index.save(&index_path(session_id))?,
+            (None, Some(knowledge_base_id)) => {
+                //文档的文本和索引按内容保存，知识库的索引由其中所有文档的索引合并而成
+                tokio::fs::create_dir_all(format!("{}/documents", files_dir())).await?;
+                tokio::fs::write(document_text_path(&job.storage_key), &documents).await?;
+                index.save(&document_index_path(&job.storage_key))?;
+                rebuild_knowledge_base_index(&self.db, knowledge_base_id).await?;
+            }
+            (None, None) => return Err(anyhow::anyhow!("Job has no target")),
+        }
+
+        self.update(job_id, JobStatus::Completed, Some(100), None)
+            .await?;
+        Ok(())
+    }
+
+    //从存储后端取回文件到暂存目录后解析，解析器依赖扩展名判断格式
+    async fn parse(&self, job: &job::Model) -> Result<String> {
+        let extension = job.storage_key.rsplit('.').next().unwrap_or("txt");
+        tokio::fs::create_dir_all(&self.staging_dir).await?;
+        let local_path =
+            PathBuf::from(&self.staging_dir).join(format!("{}.{}", job.job_id, extension));
+        let data = self.storage.get(&job.storage_key).await?;
+        tokio::fs::write(&local_path, data).await?;
+        let path_string = local_path.to_str().unwrap().to_string();
+        let documents = tokio::task::spawn_blocking(move || parse_file(&path_string)).await?;
+        let _ = tokio::fs::remove_file(&local_path).await;
+        documents
+    }
+
+    async fn update(
+        &self,
+        job_id: &str,
+        status: JobStatus,
+        progress: Option<i32>,
+        error: Option<String>,
+    ) -> Result<()> {
+        let job = job::ActiveModel {
+            job_id: Set(job_id.to_string()),
+            status: Set(status),
+            progress: progress.map_or(NotSet, Set),
+            error: Set(error),
+            update_time: Set(chrono::Utc::now()),
+            ..Default::default()
+        };
+        let job = job.update(&self.db).await?;
+        let room = match (job.session_id, job.knowledge_base_id) {
+            (Some(session_id), _) => session_room(session_id),
+            (None, Some(knowledge_base_id)) => knowledge_base_room(knowledge_base_id),
+            (None, None) => return Ok(()),
+        };
+        if let Some(ns) = self.io.of("/ws") {
+            let _ = ns.to(room).emit("job_progress", job_to_json(&job));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Embedder, IngestQueue, IngestTarget};
+    use crate::{
+        entities::{job, prelude::Job, sea_orm_active_enums::JobStatus},
+        index::{index_path, session_text_path},
+        storage::LocalStorage,
+    };
+    use anyhow::Result;
+    use async_trait::async_trait;
+    use sea_orm::{
+        ConnectOptions, ConnectionTrait, Database, DatabaseConnection, EntityTrait, Schema, Set,
+    };
+    use std::{sync::Arc, time::Duration};
+    use tokio::sync::Mutex;
+
+    //记录每次向量化时任务的状态和进度
+    struct StubEmbedder {
+        db: DatabaseConnection,
+        progress: Mutex<Vec<(JobStatus, i32)>>,
+    }
+
+    #[async_trait]
+    impl Embedder for StubEmbedder {
+        async fn embed(&self, input: &[String]) -> Result<Vec<Vec<f64>>> {
+            for job in Job::find().all(&self.db).await? {
+                if job.status == JobStatus::Embedding {
+                    self.progress.lock().await.push((job.status, job.progress));
+                }
+            }
+            Ok(input.iter().map(|x| vec![x.len() as f64, 1.0]).collect())
+        }
+    }
+
+    async fn memory_db() -> DatabaseConnection {
+        let mut options = ConnectOptions::new("sqlite::memory:");
+        //每个sqlite内存数据库连接都是独立的数据库
+        options.max_connections(1);
+        let db = Database::connect(options).await.unwrap();
+        let schema = Schema::new(db.get_database_backend());
+        let statement = schema.create_table_from_entity(Job);
+        db.execute(db.get_database_backend().build(&statement))
+            .await
+            .unwrap();
+        db
+    }
+
+    async fn wait_finished(queue: &IngestQueue, job_id: &str) -> job::Model {
+        for _ in 0..200 {
+            let job = queue.get_job(job_id).await.unwrap().unwrap();
+            if matches!(job.status, JobStatus::Completed | JobStatus::Failed) {
+                return job;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+        panic!("job {} did not finish", job_id);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_ingest_job_lifecycle() {
+        let root = std::env::temp_dir().join(format!("ingest-test-{}", uuid::Uuid::new_v4()));
+        tokio::fs::create_dir_all(&root).await.unwrap();
+        let key = "ab/abcdef.txt";
+        tokio::fs::create_dir_all(root.join("ab")).await.unwrap();
+        let text = (0..400)
+            .map(|x| format!("第{}段：文档解析、切分和向量化在后台任务中执行。", x))
+            .collect::<Vec<_>>()
+            .join("\n\n");
+        tokio::fs::write(root.join(key), &text).await.unwrap();
+
+        let db = memory_db().await;
+        let embedder = Arc::new(StubEmbedder {
+            db: db.clone(),
+            progress: Mutex::new(Vec::new()),
+        });
+        let (_, io) = socketioxide::SocketIo::new_layer();
+        let storage = Arc::new(LocalStorage::new(root.to_str().unwrap()));
+        let staging_dir = root.join("staging");
+        let queue = IngestQueue::start(
+            2,
+            db.clone(),
+            storage,
+            embedder.clone(),
+            io,
+            staging_dir.to_str().unwrap(),
+        );
+
+        let session_id = 1_000_000 + (uuid::Uuid::new_v4().as_u128() % 1_000_000) as i32;
+        let job_id = queue
+            .submit(IngestTarget::Session(session_id), "a.txt", key)
+            .await
+            .unwrap();
+        let missing_id = queue
+            .submit(
+                IngestTarget::Session(session_id + 1),
+                "b.txt",
+                "cd/missing.txt",
+            )
+            .await
+            .unwrap();
+
+        let job = wait_finished(&queue, &job_id).await;
+        assert_eq!(
+            (job.status, job.progress, job.error),
+            (JobStatus::Completed, 100, None)
+        );
+        let documents = tokio::fs::read_to_string(session_text_path(session_id))
+            .await
+            .unwrap();
+        assert_eq!(documents.trim(), text);
+        assert!(tokio::fs::try_exists(index_path(session_id)).await.unwrap());
+        //分批向量化时进度从40开始递增
+        let progress: Vec<i32> = embedder.progress.lock().await.iter().map(|x| x.1).collect();
+        assert!(progress.len() > 1);
+        assert!(progress[0] >= 40 && progress.windows(2).all(|x| x[0] < x[1]));
+
+        let failed = wait_finished(&queue, &missing_id).await;
+        assert_eq!(failed.status, JobStatus::Failed);
+        assert!(failed.error.is_some());
+
+        let _ = tokio::fs::remove_file(session_text_path(session_id)).await;
+        let _ = tokio::fs::remove_file(index_path(session_id)).await;
+        tokio::fs::remove_dir_all(&root).await.unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_resume_requeues_unfinished_jobs() {
+        let db = memory_db().await;
+        for (job_id, status, progress) in [
+            ("embedding", JobStatus::Embedding, 70),
+            ("completed", JobStatus::Completed, 100),
+            ("failed", JobStatus::Failed, 30),
+        ] {
+            let job = job::ActiveModel {
+                job_id: Set(job_id.to_string()),
+                session_id: Set(Some(1)),
+                knowledge_base_id: Set(None),
+                filename: Set("a.txt".to_string()),
+                storage_key: Set("ab/abcdef.txt".to_string()),
+                status: Set(status),
+                progress: Set(progress),
+                error: Set(None),
+                create_time: Set(chrono::Utc::now()),
+                update_time: Set(chrono::Utc::now()),
+            };
+            Job::insert(job).exec(&db).await.unwrap();
+        }
+        let embedder = Arc::new(StubEmbedder {
+            db: db.clone(),
+            progress: Mutex::new(Vec::new()),
+        });
+        let (_, io) = socketioxide::SocketIo::new_layer();
+        let root = std::env::temp_dir().join(format!("ingest-test-{}", uuid::Uuid::new_v4()));
+        let storage = Arc::new(LocalStorage::new(root.to_str().unwrap()));
+        let staging_dir = root.join("staging");
+        let queue = IngestQueue::start(
+            1,
+            db.clone(),
+            storage,
+            embedder,
+            io,
+            staging_dir.to_str().unwrap(),
+        );
+        queue.resume().await.unwrap();
+
+        //未完成的任务被重新执行，存储中没有文件所以执行失败；已结束的任务保持不变
+        let job = wait_finished(&queue, "embedding").await;
+        assert_eq!(job.status, JobStatus::Failed);
+        assert!(job.error.is_some());
+        let job = queue.get_job("completed").await.unwrap().unwrap();
+        assert_eq!((job.status, job.progress), (JobStatus::Completed, 100));
+        let job = queue.get_job("failed").await.unwrap().unwrap();
+        assert_eq!(
+            (job.status, job.progress, job.error),
+            (JobStatus::Failed, 30, None)
+        );
+        let _ = tokio::fs::remove_dir_all(&root).await;
+    }
+}
@@ -0,0 +1,465 @@
+use regex::Regex;
+use schemars::schema::{InstanceType, RootSchema, Schema, SchemaObject, SingleOrVec};
+use serde_json::Value;
+
+//$ref嵌套的最大层数，防止循环引用
+const MAX_DEPTH: usize = 32;
+
+//用schemars的schema类型校验json值，支持类型、枚举、常量、字符串和数值约束、数组、对象、
+//allOf/anyOf/oneOf/not/if、definitions中的引用，以及date、date-time格式；返回所有错误，为空表示通过
+pub fn validate(root: &RootSchema, value: &Value) -> Vec<String> {
+    let mut errors = Vec::new();
+    Validator { root }.check_object(&root.schema, value, "$", &mut errors, 0);
+    errors
+}
+
+//解析 "#"、"#/definitions/名称" 和 "#/$defs/名称" 形式的引用
+pub fn resolve<'a>(root: &'a RootSchema, reference: &str) -> Option<&'a Schema> {
+    if reference == "#" {
+        return None;
+    }
+    let name = reference
+        .strip_prefix("#/definitions/")
+        .or_else(|| reference.strip_prefix("#/$defs/"))?;
+    root.definitions.get(name)
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+fn type_matches(instance_type: &InstanceType, value: &Value) -> bool {
+    match instance_type {
+        InstanceType::Null => value.is_null(),
+        InstanceType::Boolean => value.is_boolean(),
+        InstanceType::Object => value.is_object(),
+        InstanceType::Array => value.is_array(),
+        InstanceType::Number => value.is_number(),
+        InstanceType::String => value.is_string(),
+        InstanceType::Integer => {
+            value.is_i64() || value.is_u64() || value.as_f64().is_some_and(|x| x.fract() == 0.0)
+        }
+    }
+}
+
+fn as_slice<T>(value: &SingleOrVec<T>) -> &[T] {
+    match value {
+        SingleOrVec::Single(x) => std::slice::from_ref(x.as_ref()),
+        SingleOrVec::Vec(x) => x,
+    }
+}
+
+struct Validator<'a> {
+    root: &'a RootSchema,
+}
+
+impl Validator<'_> {
+    fn is_valid(&self, schema: &Schema, value: &Value, depth: usize) -> bool {
+        let mut errors = Vec::new();
+        self.check(schema, value, "$", &mut errors, depth);
+        errors.is_empty()
+    }
+
+    fn check(
+        &self,
+        schema: &Schema,
+        value: &Value,
+        path: &str,
+        errors: &mut Vec<String>,
+        depth: usize,
+    ) {
+        match schema {
+            Schema::Bool(true) => {}
+            Schema::Bool(false) => errors.push(format!("{}: 不允许出现该值", path)),
+            Schema::Object(schema) => self.check_object(schema, value, path, errors, depth),
+        }
+    }
+
+    fn check_object(
+        &self,
+        schema: &SchemaObject,
+        value: &Value,
+        path: &str,
+        errors: &mut Vec<String>,
+        depth: usize,
+    ) {
+        if depth > MAX_DEPTH {
+            errors.push(format!("{}: schema的引用层数过多", path));
+            return;
+        }
+        if let Some(reference) = &schema.reference {
+            match (reference.as_str(), resolve(self.root, reference)) {
+                ("#", _) => self.check_object(&self.root.schema, value, path, errors, depth + 1),
+                (_, Some(target)) => self.check(target, value, path, errors, depth + 1),
+                (_, None) => errors.push(format!("{}: 无法解析的引用{}", path, reference)),
+            }
+        }
+        if let Some(instance_type) = &schema.instance_type {
+            let types = as_slice(instance_type);
+            if !types.iter().any(|x| type_matches(x, value)) {
+                let expected: Vec<String> = types
+                    .iter()
+                    .map(|x| {
+                        serde_json::to_value(x)
+                            .unwrap()
+                            .as_str()
+                            .unwrap()
+                            .to_string()
+                    })
+                    .collect();
+                errors.push(format!(
+                    "{}: 应为{}，实际为{}",
+                    path,
+                    expected.join("或"),
+                    type_name(value)
+                ));
+                return;
+            }
+        }
+        if let Some(enum_values) = &schema.enum_values {
+            if !enum_values.contains(value) {
+                errors.push(format!(
+                    "{}: {}不在可选值{}中",
+                    path,
+                    value,
+                    Value::Array(enum_values.clone())
+                ));
+            }
+        }
+        if let Some(const_value) = &schema.const_value {
+            if const_value != value {
+                errors.push(format!("{}: 应为{}", path, const_value));
+            }
+        }
+        match value {
+            Value::String(text) => self.check_string(schema, text, path, errors),
+            Value::Number(number) => self.check_number(schema, number.as_f64(), path, errors),
+            Value::Array(items) => self.check_array(schema, items, path, errors, depth),
+            Value::Object(map) => self.check_map(schema, map, path, errors, depth),
+            _ => {}
+        }
+        if let Some(subschemas) = &schema.subschemas {
+            for subschema in subschemas.all_of.iter().flatten() {
+                self.check(subschema, value, path, errors, depth + 1);
+            }
+            if let Some(any_of) = &subschemas.any_of {
+                if !any_of.iter().any(|x| self.is_valid(x, value, depth + 1)) {
+                    errors.push(format!("{}: 不符合anyOf中的任何一个schema", path));
+                }
+            }
+            if let Some(one_of) = &subschemas.one_of {
+                let matched = one_of
+                    .iter()
+                    .filter(|x| self.is_valid(x, value, depth + 1))
+                    .count();
+                if matched != 1 {
+                    errors.push(format!(
+                        "{}: 应恰好符合oneOf中的一个schema，实际符合{}个",
+                        path, matched
+                    ));
+                }
+            }
+            if let Some(not) = &subschemas.not {
+                if self.is_valid(not, value, depth + 1) {
+                    errors.push(format!("{}: 不应符合not中的schema", path));
+                }
+            }
+            if let Some(if_schema) = &subschemas.if_schema {
+                let branch = match self.is_valid(if_schema, value, depth + 1) {
+                    true => &subschemas.then_schema,
+                    false => &subschemas.else_schema,
+                };
+                if let Some(branch) = branch {
+                    self.check(branch, value, path, errors, depth + 1);
+                }
+            }
+        }
+    }
+
+    fn check_string(
+        &self,
+        schema: &SchemaObject,
+        text: &str,
+        path: &str,
+        errors: &mut Vec<String>,
+    ) {
+        if let Some(string) = &schema.string {
+            let length = text.chars().count() as u32;
+            if string.min_length.is_some_and(|x| length < x) {
+                errors.push(format!(
+                    "{}: 长度不能少于{}",
+                    path,
+                    string.min_length.unwrap()
+                ));
+            }
+            if string.max_length.is_some_and(|x| length > x) {
+                errors.push(format!(
+                    "{}: 长度不能超过{}",
+                    path,
+                    string.max_length.unwrap()
+                ));
+            }
+            if let Some(pattern) = &string.pattern {
+                match Regex::new(pattern) {
+                    Ok(regex) if !regex.is_match(text) => {
+                        errors.push(format!("{}: 不符合格式{}", path, pattern))
+                    }
+                    Ok(_) => {}
+                    Err(_) => errors.push(format!("{}: 无效的正则表达式{}", path, pattern)),
+                }
+            }
+        }
+        let valid_format = match schema.format.as_deref() {
+            Some("date") => chrono::NaiveDate::parse_from_str(text, "%Y-%m-%d").is_ok(),
+            Some("date-time") => chrono::DateTime::parse_from_rfc3339(text).is_ok(),
+            _ => true,
+        };
+        if !valid_format {
+            errors.push(format!(
+                "{}: 不是有效的{}",
+                path,
+                schema.format.as_deref().unwrap_or_default()
+            ));
+        }
+    }
+
+    fn check_number(
+        &self,
+        schema: &SchemaObject,
+        number: Option<f64>,
+        path: &str,
+        errors: &mut Vec<String>,
+    ) {
+        let (Some(validation), Some(number)) = (&schema.number, number) else {
+            return;
+        };
+        if let Some(minimum) = validation.minimum.filter(|x| number < *x) {
+            errors.push(format!("{}: 不能小于{}", path, minimum));
+        }
+        if let Some(maximum) = validation.maximum.filter(|x| number > *x) {
+            errors.push(format!("{}: 不能大于{}", path, maximum));
+        }
+        if let Some(minimum) = validation.exclusive_minimum.filter(|x| number <= *x) {
+            errors.push(format!("{}: 应大于{}", path, minimum));
+        }
+        if let Some(maximum) = validation.exclusive_maximum.filter(|x| number >= *x) {
+            errors.push(format!("{}: 应小于{}", path, maximum));
+        }
+        if let Some(multiple_of) = validation.multiple_of.filter(|x| *x > 0.0) {
+            let quotient = number / multiple_of;
+            if (quotient - quotient.round()).abs() > 1e-9 {
+                errors.push(format!("{}: 应为{}的倍数", path, multiple_of));
+            }
+        }
+    }
+
+    fn check_array(
+        &self,
+        schema: &SchemaObject,
+        items: &[Value],
+        path: &str,
+        errors: &mut Vec<String>,
+        depth: usize,
+    ) {
+        let Some(array) = &schema.array else {
+            return;
+        };
+        match &array.items {
+            Some(SingleOrVec::Single(item_schema)) => {
+                for (index, item) in items.iter().enumerate() {
+                    let item_path = format!("{}[{}]", path, index);
+                    self.check(item_schema, item, &item_path, errors, depth + 1);
+                }
+            }
+            Some(SingleOrVec::Vec(item_schemas)) => {
+                for (index, item) in items.iter().enumerate() {
+                    let item_path = format!("{}[{}]", path, index);
+                    match (item_schemas.get(index), array.additional_items.as_deref()) {
+                        (Some(item_schema), _) | (None, Some(item_schema)) => {
+                            self.check(item_schema, item, &item_path, errors, depth + 1)
+                        }
+                        (None, None) => {}
+                    }
+                }
+            }
+            None => {}
+        }
+        let length = items.len() as u32;
+        if array.min_items.is_some_and(|x| length < x) {
+            errors.push(format!(
+                "{}: 元素不能少于{}个",
+                path,
+                array.min_items.unwrap()
+            ));
+        }
+        if array.max_items.is_some_and(|x| length > x) {
+            errors.push(format!(
+                "{}: 元素不能超过{}个",
+                path,
+                array.max_items.unwrap()
+            ));
+        }
+        if array.unique_items == Some(true) {
+            let duplicated = items
+                .iter()
+                .enumerate()
+                .any(|(index, item)| items[..index].contains(item));
+            if duplicated {
+                errors.push(format!("{}: 元素不能重复", path));
+            }
+        }
+        if let Some(contains) = &array.contains {
+            if !items.iter().any(|x| self.is_valid(contains, x, depth + 1)) {
+                errors.push(format!("{}: 没有符合contains的元素", path));
+            }
+        }
+    }
+
+    fn check_map(
+        &self,
+        schema: &SchemaObject,
+        map: &serde_json::Map<String, Value>,
+        path: &str,
+        errors: &mut Vec<String>,
+        depth: usize,
+    ) {
+        let Some(object) = &schema.object else {
+            return;
+        };
+        for name in object.required.iter() {
+            if !map.contains_key(name) {
+                errors.push(format!("{}: 缺少必填字段{}", path, name));
+            }
+        }
+        let patterns: Vec<(Option<Regex>, &Schema)> = object
+            .pattern_properties
+            .iter()
+            .map(|(pattern, schema)| (Regex::new(pattern).ok(), schema))
+            .collect();
+        for (name, property) in map.iter() {
+            let property_path = format!("{}.{}", path, name);
+            let mut matched = false;
+            if let Some(property_schema) = object.properties.get(name) {
+                matched = true;
+                self.check(property_schema, property, &property_path, errors, depth + 1);
+            }
+            for (regex, property_schema) in patterns.iter() {
+                if regex.as_ref().is_some_and(|x| x.is_match(name)) {
+                    matched = true;
+                    self.check(property_schema, property, &property_path, errors, depth + 1);
+                }
+            }
+            match &object.additional_properties {
+                Some(additional) if !matched => match additional.as_ref() {
+                    Schema::Bool(false) => errors.push(format!("{}: 不允许的字段{}", path, name)),
+                    additional => {
+                        self.check(additional, property, &property_path, errors, depth + 1)
+                    }
+                },
+                _ => {}
+            }
+        }
+        let length = map.len() as u32;
+        if object.min_properties.is_some_and(|x| length < x) {
+            errors.push(format!(
+                "{}: 字段不能少于{}个",
+                path,
+                object.min_properties.unwrap()
+            ));
+        }
+        if object.max_properties.is_some_and(|x| length > x) {
+            errors.push(format!(
+                "{}: 字段不能超过{}个",
+                path,
+                object.max_properties.unwrap()
+            ));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::validate;
+    use schemars::{schema::RootSchema, schema_for, JsonSchema};
+    use serde::Deserialize;
+
+    #[derive(JsonSchema, Deserialize)]
+    #[allow(dead_code)]
+    struct Party {
+        name: String,
+        role: Option<String>,
+    }
+
+    #[derive(JsonSchema, Deserialize)]
+    #[allow(dead_code)]
+    struct Contract {
+        parties: Vec<Party>,
+        #[schemars(regex(pattern = r"^\d{4}-\d{2}-\d{2}$"))]
+        sign_date: String,
+        #[schemars(range(min = 0))]
+        amount: f64,
+    }
+
+    #[test]
+    fn test_validate_derived_schema() {
+        let schema = schema_for!(Contract);
+        let value = serde_json::json!({
+            "parties": [{"name": "甲公司", "role": "甲方"}, {"name": "乙公司", "role": null}],
+            "sign_date": "2024-03-01",
+            "amount": 1200.5
+        });
+        assert!(validate(&schema, &value).is_empty());
+
+        let value = serde_json::json!({
+            "parties": [{"role": "甲方"}],
+            "sign_date": "2024年3月1日",
+            "amount": -1
+        });
+        let errors = validate(&schema, &value);
+        assert_eq!(errors.len(), 3, "{:?}", errors);
+        assert!(errors[0].starts_with("$.amount: "));
+        assert!(errors[1].starts_with("$.parties[0]: 缺少必填字段name"));
+        assert!(errors[2].starts_with("$.sign_date: 不符合格式"));
+    }
+
+    #[test]
+    fn test_validate_user_schema() {
+        let schema: RootSchema = serde_json::from_value(serde_json::json!({
+            "type": "object",
+            "required": ["currency"],
+            "additionalProperties": false,
+            "properties": {
+                "currency": {"enum": ["CNY", "USD"]},
+                "due_date": {"type": ["string", "null"], "format": "date"},
+                "items": {"type": "array", "minItems": 1, "items": {"$ref": "#/$defs/item"}}
+            },
+            "$defs": {"item": {"type": "object", "properties": {"quantity": {"type": "integer"}}}}
+        }))
+        .unwrap();
+        let value = serde_json::json!({
+            "currency": "EUR",
+            "due_date": "2024-02-30",
+            "items": [{"quantity": 1.5}],
+            "note": "x"
+        });
+        let errors = validate(&schema, &value);
+        assert_eq!(
+            errors,
+            vec![
+                "$.currency: \"EUR\"不在可选值[\"CNY\",\"USD\"]中",
+                "$.due_date: 不是有效的date",
+                "$.items[0].quantity: 应为integer，实际为number",
+                "$: 不允许的字段note",
+            ]
+        );
+        let value = serde_json::json!({"currency": "CNY", "due_date": null, "items": []});
+        assert_eq!(validate(&schema, &value), vec!["$.items: 元素不能少于1个"]);
+    }
+}
@@ -0,0 +1,1045 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, Weak},
+};
+
+use anyhow::Result;
+use axum::{
+    extract::{Json, Multipart, Path, Query},
+    Extension,
+};
+use lazy_static::lazy_static;
+use sea_orm::*;
+use tracing::info;
+
+use crate::{
+    data::{
+        error_response, AttachKnowledgeBaseRequest, CreateKnowledgeBaseRequest, JsonDataResponse,
+        KnowledgeBaseOwnerQuery, TeamMemberQuery, UpdateKnowledgeBaseRequest,
+    },
+    entities::{
+        job, knowledge_base, knowledge_base_document,
+        prelude::{
+            Job, KnowledgeBase, KnowledgeBaseDocument, Session, SessionKnowledgeBase, TeamMember,
+        },
+        sea_orm_active_enums::OwnerType,
+        session_knowledge_base, team_member,
+    },
+    index::{
+        document_index_path, document_text_path, knowledge_base_index_path, session_text_path,
+        HybridIndex,
+    },
+    ingest::{IngestQueue, IngestTarget},
+    storage::{lock_key, Storage, StorageConfig},
+    upload::{store_file, UploadForm},
+};
+
+lazy_static! {
+    //同一知识库的重建需要串行，否则先读到旧文档列表的重建可能最后写入，覆盖较新的索引
+    //只保存弱引用，没有重建在进行的条目在下次取锁时清理，已删除的知识库不会一直占用
+    static ref REBUILD_LOCKS: Mutex<HashMap<i32, Weak<tokio::sync::Mutex<()>>>> =
+        Mutex::new(HashMap::new());
+}
+
+fn rebuild_lock(knowledge_base_id: i32) -> Arc<tokio::sync::Mutex<()>> {
+    let mut locks = REBUILD_LOCKS.lock().unwrap();
+    locks.retain(|_, x| x.strong_count() > 0);
+    match locks.get(&knowledge_base_id).and_then(Weak::upgrade) {
+        Some(lock) => lock,
+        None => {
+            let lock = Arc::new(tokio::sync::Mutex::new(()));
+            locks.insert(knowledge_base_id, Arc::downgrade(&lock));
+            lock
+        }
+    }
+}
+
+//知识库的索引由其中所有已完成处理的文档索引合并而成，尚未处理完的文档会在任务完成时再次触发合并
+pub async fn rebuild_knowledge_base_index(
+    db: &DatabaseConnection,
+    knowledge_base_id: i32,
+) -> Result<()> {
+    let lock = rebuild_lock(knowledge_base_id);
+    let _guard = lock.lock().await;
+    let documents = KnowledgeBaseDocument::find()
+        .filter(knowledge_base_document::Column::KnowledgeBaseId.eq(knowledge_base_id))
+        .all(db)
+        .await?;
+    //读取和合并索引都是CPU密集的同步操作
+    let task = tokio::task::spawn_blocking(move || {
+        let mut indexes = Vec::new();
+        for document in documents {
+            match HybridIndex::load(&document_index_path(&document.storage_key)) {
+                Ok(index) => indexes.push((document.filename, index)),
+                Err(e) => info!(
+                    "skip document {} of knowledge base {}: {:?}",
+                    document.document_id, knowledge_base_id, e
+                ),
+            }
+        }
+        let index = HybridIndex::merge(indexes);
+        index.save(&knowledge_base_index_path(knowledge_base_id))
+    });
+    task.await??;
+    Ok(())
+}
+
+async fn is_team_member(db: &DatabaseConnection, team_id: i32, user_id: i32) -> Result<bool> {
+    Ok(TeamMember::find_by_id((team_id, user_id))
+        .one(db)
+        .await?
+        .is_some())
+}
+
+//用户只能以自己的身份，或者以自己所在团队的身份管理知识库
+async fn can_act_as(
+    db: &DatabaseConnection,
+    user_id: i32,
+    owner_type: &OwnerType,
+    owner_id: i32,
+) -> Result<bool> {
+    match owner_type {
+        OwnerType::User => Ok(owner_id == user_id),
+        OwnerType::Team => is_team_member(db, owner_id, user_id).await,
+    }
+}
+
+async fn team_ids(db: &DatabaseConnection, user_id: i32) -> Result<Vec<i32>> {
+    let team_ids = TeamMember::find()
+        .filter(team_member::Column::UserId.eq(user_id))
+        .all(db)
+        .await?
+        .into_iter()
+        .map(|x| x.team_id)
+        .collect();
+    Ok(team_ids)
+}
+
+//用户的知识库只对该用户自己的会话可见，团队的知识库只对团队成员的会话可见；
+//关联后离开团队的用户也不能再读取该团队的知识库
+pub async fn attached_knowledge_bases(
+    db: &DatabaseConnection,
+    session_id: i32,
+) -> Result<Vec<knowledge_base::Model>> {
+    let user_id = match Session::find_by_id(session_id).one(db).await? {
+        Some(session) => session.user_id,
+        None => return Ok(Vec::new()),
+    };
+    let ids: Vec<i32> = SessionKnowledgeBase::find()
+        .filter(session_knowledge_base::Column::SessionId.eq(session_id))
+        .all(db)
+        .await?
+        .into_iter()
+        .map(|x| x.knowledge_base_id)
+        .collect();
+    let team_ids = team_ids(db, user_id).await?;
+    let knowledge_bases = KnowledgeBase::find()
+        .filter(knowledge_base::Column::KnowledgeBaseId.is_in(ids))
+        .filter(
+            Condition::any()
+                .add(
+                    Condition::all()
+                        .add(knowledge_base::Column::OwnerType.eq(OwnerType::User))
+                        .add(knowledge_base::Column::OwnerId.eq(user_id)),
+                )
+                .add(
+                    Condition::all()
+                        .add(knowledge_base::Column::OwnerType.eq(OwnerType::Team))
+                        .add(knowledge_base::Column::OwnerId.is_in(team_ids)),
+                ),
+        )
+        .order_by_asc(knowledge_base::Column::KnowledgeBaseId)
+        .all(db)
+        .await?;
+    Ok(knowledge_bases)
+}
+
+//读取文档函数处理的文本：不指定文档名时读取会话中上传的文档，否则在会话关联的知识库中按文件名查找
+pub async fn read_document(
+    db: &DatabaseConnection,
+    session_id: i32,
+    document: Option<&str>,
+) -> Result<String> {
+    let ids: Vec<i32> = attached_knowledge_bases(db, session_id)
+        .await?
+        .into_iter()
+        .map(|x| x.knowledge_base_id)
+        .collect();
+    let documents = KnowledgeBaseDocument::find()
+        .filter(knowledge_base_document::Column::KnowledgeBaseId.is_in(ids))
+        .order_by_asc(knowledge_base_document::Column::DocumentId)
+        .all(db)
+        .await?;
+    let filenames = documents
+        .iter()
+        .map(|x| x.filename.as_str())
+        .collect::<Vec<_>>()
+        .join(", ");
+    let name = match document {
+        Some(name) => name,
+        None => {
+            return match tokio::fs::read_to_string(session_text_path(session_id)).await {
+                Ok(text) => Ok(text),
+                Err(_) if documents.is_empty() => {
+                    Err(anyhow::anyhow!("当前会话没有上传文档，也没有关联知识库"))
+                }
+                Err(_) => Err(anyhow::anyhow!(
+                    "当前会话没有上传文档，可以用document参数指定关联知识库中的文档: {}",
+                    filenames
+                )),
+            };
+        }
+    };
+    //优先完全匹配，其次是包含该名称的文件名
+    let found = documents
+        .iter()
+        .find(|x| x.filename == name)
+        .or_else(|| documents.iter().find(|x| x.filename.contains(name)));
+    match found {
+        Some(found) => tokio::fs::read_to_string(document_text_path(&found.storage_key))
+            .await
+            .map_err(|_| anyhow::anyhow!("文档{}尚未处理完成", found.filename)),
+        None => Err(anyhow::anyhow!(
+            "关联的知识库中没有文档: {}，可用的文档: {}",
+            name,
+            filenames
+        )),
+    }
+}
+
+fn knowledge_base_to_json(knowledge_base: &knowledge_base::Model) -> serde_json::Value {
+    serde_json::json!({
+        "knowledge_base_id": knowledge_base.knowledge_base_id,
+        "name": knowledge_base.name,
+        "description": knowledge_base.description,
+        "owner_type": knowledge_base.owner_type.to_value(),
+        "owner_id": knowledge_base.owner_id,
+        "create_time": knowledge_base.create_time,
+        "last_update_time": knowledge_base.last_update_time,
+    })
+}
+
+fn document_to_json(document: &knowledge_base_document::Model) -> serde_json::Value {
+    serde_json::json!({
+        "document_id": document.document_id,
+        "knowledge_base_id": document.knowledge_base_id,
+        "filename": document.filename,
+        "create_time": document.create_time,
+    })
+}
+
+fn not_found(error: &str) -> Json<JsonDataResponse> {
+    Json(JsonDataResponse {
+        code: 404,
+        data: serde_json::json!({
+            "error": error
+        }),
+    })
+}
+
+fn forbidden(error: &str) -> Json<JsonDataResponse> {
+    Json(JsonDataResponse {
+        code: 403,
+        data: serde_json::json!({
+            "error": error
+        }),
+    })
+}
+
+//不属于调用方的知识库按不存在处理，不暴露其是否存在
+async fn find_owned(
+    db: &DatabaseConnection,
+    knowledge_base_id: i32,
+    owner: &KnowledgeBaseOwnerQuery,
+) -> std::result::Result<knowledge_base::Model, Json<JsonDataResponse>> {
+    let owner_type = OwnerType::try_from_value(&owner.owner_type)
+        .map_err(|_| error_response("Invalid owner type"))?;
+    match can_act_as(db, owner.user_id, &owner_type, owner.owner_id).await {
+        Ok(true) => {}
+        Ok(false) => return Err(not_found("Knowledge base not found")),
+        Err(e) => return Err(error_response(&e.to_string())),
+    }
+    let knowledge_base = KnowledgeBase::find_by_id(knowledge_base_id)
+        .filter(knowledge_base::Column::OwnerType.eq(owner_type))
+        .filter(knowledge_base::Column::OwnerId.eq(owner.owner_id))
+        .one(db)
+        .await;
+    match knowledge_base {
+        Ok(Some(knowledge_base)) => Ok(knowledge_base),
+        Ok(None) => Err(not_found("Knowledge base not found")),
+        Err(e) => Err(error_response(&e.to_string())),
+    }
+}
+
+async fn touch(db: &DatabaseConnection, knowledge_base_id: i32) -> Result<()> {
+    let knowledge_base = knowledge_base::ActiveModel {
+        knowledge_base_id: Set(knowledge_base_id),
+        last_update_time: Set(chrono::Utc::now()),
+        ..Default::default()
+    };
+    knowledge_base.update(db).await?;
+    Ok(())
+}
+
+#[axum_macros::debug_handler]
+pub async fn create_knowledge_base(
+    Extension(db): Extension<DatabaseConnection>,
+    Json(data): Json<CreateKnowledgeBaseRequest>,
+) -> Json<JsonDataResponse> {
+    let owner_type = match OwnerType::try_from_value(&data.owner_type) {
+        Ok(owner_type) => owner_type,
+        Err(_) => return error_response("Invalid owner type"),
+    };
+    match can_act_as(&db, data.user_id, &owner_type, data.owner_id).await {
+        Ok(true) => {}
+        Ok(false) => return forbidden("Not allowed to create knowledge bases for this owner"),
+        Err(e) => return error_response(&e.to_string()),
+    }
+    let knowledge_base = knowledge_base::ActiveModel {
+        name: Set(data.name),
+        description: Set(data.description),
+        owner_type: Set(owner_type),
+        owner_id: Set(data.owner_id),
+        create_time: Set(chrono::Utc::now()),
+        last_update_time: Set(chrono::Utc::now()),
+        ..Default::default()
+    };
+    match knowledge_base.insert(&db).await {
+        Ok(knowledge_base) => Json(JsonDataResponse {
+            code: 200,
+            data: knowledge_base_to_json(&knowledge_base),
+        }),
+        Err(e) => error_response(&e.to_string()),
+    }
+}
+
+#[axum_macros::debug_handler]
+pub async fn list_knowledge_bases(
+    Extension(db): Extension<DatabaseConnection>,
+    Query(owner): Query<KnowledgeBaseOwnerQuery>,
+) -> Json<JsonDataResponse> {
+    let owner_type = match OwnerType::try_from_value(&owner.owner_type) {
+        Ok(owner_type) => owner_type,
+        Err(_) => return error_response("Invalid owner type"),
+    };
+    match can_act_as(&db, owner.user_id, &owner_type, owner.owner_id).await {
+        Ok(true) => {}
+        Ok(false) => return forbidden("Not allowed to list knowledge bases of this owner"),
+        Err(e) => return error_response(&e.to_string()),
+    }
+    match KnowledgeBase::find()
+        .filter(knowledge_base::Column::OwnerType.eq(owner_type))
+        .filter(knowledge_base::Column::OwnerId.eq(owner.owner_id))
+        .order_by_asc(knowledge_base::Column::KnowledgeBaseId)
+        .all(&db)
+        .await
+    {
+        Ok(knowledge_bases) => Json(JsonDataResponse {
+            code: 200,
+            data: serde_json::json!({
+                "knowledge_bases": knowledge_bases.iter().map(knowledge_base_to_json).collect::<Vec<_>>()
+            }),
+        }),
+        Err(e) => error_response(&e.to_string()),
+    }
+}
+
+#[axum_macros::debug_handler]
+pub async fn get_knowledge_base(
+    Extension(db): Extension<DatabaseConnection>,
+    Path(knowledge_base_id): Path<i32>,
+    Query(owner): Query<KnowledgeBaseOwnerQuery>,
+) -> Json<JsonDataResponse> {
+    let knowledge_base = match find_owned(&db, knowledge_base_id, &owner).await {
+        Ok(knowledge_base) => knowledge_base,
+        Err(response) => return response,
+    };
+    let documents = KnowledgeBaseDocument::find()
+        .filter(knowledge_base_document::Column::KnowledgeBaseId.eq(knowledge_base_id))
+        .order_by_asc(knowledge_base_document::Column::DocumentId)
+        .all(&db)
+        .await;
+    match documents {
+        Ok(documents) => {
+            let mut data = knowledge_base_to_json(&knowledge_base);
+            data["documents"] = documents.iter().map(document_to_json).collect();
+            Json(JsonDataResponse { code: 200, data })
+        }
+        Err(e) => error_response(&e.to_string()),
+    }
+}
+
+#[axum_macros::debug_handler]
+pub async fn update_knowledge_base(
+    Extension(db): Extension<DatabaseConnection>,
+    Path(knowledge_base_id): Path<i32>,
+    Query(owner): Query<KnowledgeBaseOwnerQuery>,
+    Json(data): Json<UpdateKnowledgeBaseRequest>,
+) -> Json<JsonDataResponse> {
+    let knowledge_base = match find_owned(&db, knowledge_base_id, &owner).await {
+        Ok(knowledge_base) => knowledge_base,
+        Err(response) => return response,
+    };
+    let mut knowledge_base: knowledge_base::ActiveModel = knowledge_base.into();
+    if let Some(name) = data.name {
+        knowledge_base.name = Set(name);
+    }
+    if let Some(description) = data.description {
+        knowledge_base.description = Set(description);
+    }
+    knowledge_base.last_update_time = Set(chrono::Utc::now());
+    match knowledge_base.update(&db).await {
+        Ok(knowledge_base) => Json(JsonDataResponse {
+            code: 200,
+            data: knowledge_base_to_json(&knowledge_base),
+        }),
+        Err(e) => error_response(&e.to_string()),
+    }
+}
+
+//删除知识库时同时解除与会话的关联；存储后端中的文件按内容共享，提交后逐个释放不再被引用的对象
+#[axum_macros::debug_handler]
+pub async fn delete_knowledge_base(
+    Extension(db): Extension<DatabaseConnection>,
+    Extension(storage): Extension<Arc<dyn Storage>>,
+    Path(knowledge_base_id): Path<i32>,
+    Query(owner): Query<KnowledgeBaseOwnerQuery>,
+) -> Json<JsonDataResponse> {
+    if let Err(response) = find_owned(&db, knowledge_base_id, &owner).await {
+        return response;
+    }
+    let result = db
+        .transaction::<_, (u64, Vec<String>), DbErr>(|txn| {
+            Box::pin(async move {
+                let mut storage_keys = KnowledgeBaseDocument::find()
+                    .filter(knowledge_base_document::Column::KnowledgeBaseId.eq(knowledge_base_id))
+                    .all(txn)
+                    .await?
+                    .into_iter()
+                    .map(|document| document.storage_key)
+                    .collect::<Vec<_>>();
+                storage_keys.sort();
+                storage_keys.dedup();
+                SessionKnowledgeBase::delete_many()
+                    .filter(session_knowledge_base::Column::KnowledgeBaseId.eq(knowledge_base_id))
+                    .exec(txn)
+                    .await?;
+                KnowledgeBaseDocument::delete_many()
+                    .filter(knowledge_base_document::Column::KnowledgeBaseId.eq(knowledge_base_id))
+                    .exec(txn)
+                    .await?;
+                let result = KnowledgeBase::delete_by_id(knowledge_base_id)
+                    .exec(txn)
+                    .await?;
+                Ok((result.rows_affected, storage_keys))
+            })
+        })
+        .await;
+    match result {
+        Ok((0, _)) => not_found("Knowledge base not found"),
+        Ok((_, storage_keys)) => {
+            let _ = tokio::fs::remove_file(knowledge_base_index_path(knowledge_base_id)).await;
+            for storage_key in storage_keys {
+                if let Err(e) = release_storage_object(&db, storage.as_ref(), &storage_key).await {
+                    info!("release storage object {} failed: {:?}", storage_key, e);
+                }
+            }
+            Json(JsonDataResponse {
+                code: 200,
+                data: serde_json::json!({
+                    "knowledge_base_id": knowledge_base_id
+                }),
+            })
+        }
+        Err(e) => error_response(&e.to_string()),
+    }
+}
+
+#[axum_macros::debug_handler]
+pub async fn add_document(
+    Extension(db): Extension<DatabaseConnection>,
+    Extension(storage): Extension<Arc<dyn Storage>>,
+    Extension(storage_config): Extension<Arc<StorageConfig>>,
+    Extension(ingest_queue): Extension<IngestQueue>,
+    Path(knowledge_base_id): Path<i32>,
+    Query(owner): Query<KnowledgeBaseOwnerQuery>,
+    mut multipart: Multipart,
+) -> Json<JsonDataResponse> {
+    if let Err(response) = find_owned(&db, knowledge_base_id, &owner).await {
+        return response;
+    }
+    let mut form = match UploadForm::read(&mut multipart, &storage_config).await {
+        Ok(form) => form,
+        Err(e) => return error_response(&e.to_string()),
+    };
+    let staged = match form.file.clone() {
+        Some(staged) => staged,
+        None => return error_response("Invalid request"),
+    };
+    info!(
+        "upload knowledge_base_id: {}, file: {:?}",
+        knowledge_base_id, staged
+    );
+    let result = store_file(storage.as_ref(), &staged).await;
+    form.discard().await;
+    let (key, _key_guard) = match result {
+        Ok(result) => result,
+        Err(e) => return error_response(&e.to_string()),
+    };
+    let document = knowledge_base_document::ActiveModel {
+        knowledge_base_id: Set(knowledge_base_id),
+        filename: Set(form.filename.clone()),
+        storage_key: Set(key.clone()),
+        create_time: Set(chrono::Utc::now()),
+        ..Default::default()
+    };
+    let document = match document.insert(&db).await {
+        Ok(document) => document,
+        Err(e) => return error_response(&e.to_string()),
+    };
+    let _ = touch(&db, knowledge_base_id).await;
+    let job_id = match ingest_queue
+        .submit(
+            IngestTarget::KnowledgeBase(knowledge_base_id),
+            &form.filename,
+            &key,
+        )
+        .await
+    {
+        Ok(job_id) => job_id,
+        Err(e) => return error_response(&e.to_string()),
+    };
+    let mut data = document_to_json(&document);
+    data["content_type"] = staged.mime.into();
+    data["sha256"] = staged.sha256.into();
+    data["size"] = staged.size.into();
+    data["job_id"] = job_id.into();
+    Json(JsonDataResponse { code: 200, data })
+}
+
+//相同内容的文件只保存一份，没有其它知识库文档或会话上传引用时才删除存储对象和文档索引
+async fn release_storage_object(
+    db: &DatabaseConnection,
+    storage: &dyn Storage,
+    storage_key: &str,
+) -> Result<()> {
+    let _key_guard = lock_key(storage_key).await;
+    let documents = KnowledgeBaseDocument::find()
+        .filter(knowledge_base_document::Column::StorageKey.eq(storage_key))
+        .count(db)
+        .await?;
+    let session_jobs = Job::find()
+        .filter(job::Column::StorageKey.eq(storage_key))
+        .filter(job::Column::SessionId.is_not_null())
+        .count(db)
+        .await?;
+    if documents > 0 || session_jobs > 0 {
+        return Ok(());
+    }
+    storage.delete(storage_key).await?;
+    let _ = tokio::fs::remove_file(document_index_path(storage_key)).await;
+    let _ = tokio::fs::remove_file(document_text_path(storage_key)).await;
+    Ok(())
+}
+
+#[axum_macros::debug_handler]
+pub async fn remove_document(
+    Extension(db): Extension<DatabaseConnection>,
+    Extension(storage): Extension<Arc<dyn Storage>>,
+    Path((knowledge_base_id, document_id)): Path<(i32, i32)>,
+    Query(owner): Query<KnowledgeBaseOwnerQuery>,
+) -> Json<JsonDataResponse> {
+    if let Err(response) = find_owned(&db, knowledge_base_id, &owner).await {
+        return response;
+    }
+    let document = KnowledgeBaseDocument::find_by_id(document_id)
+        .filter(knowledge_base_document::Column::KnowledgeBaseId.eq(knowledge_base_id))
+        .one(&db)
+        .await;
+    let document = match document {
+        Ok(Some(document)) => document,
+        Ok(None) => return not_found("Document not found"),
+        Err(e) => return error_response(&e.to_string()),
+    };
+    let result = KnowledgeBaseDocument::delete_by_id(document_id)
+        .exec(&db)
+        .await;
+    match result {
+        Ok(result) if result.rows_affected == 0 => return not_found("Document not found"),
+        Ok(_) => {}
+        Err(e) => return error_response(&e.to_string()),
+    }
+    let _ = touch(&db, knowledge_base_id).await;
+    if let Err(e) = rebuild_knowledge_base_index(&db, knowledge_base_id).await {
+        return error_response(&e.to_string());
+    }
+    //对象删除失败只会留下无用的文件，不影响移除结果
+    if let Err(e) = release_storage_object(&db, storage.as_ref(), &document.storage_key).await {
+        info!(
+            "release storage object {} failed: {:?}",
+            document.storage_key, e
+        );
+    }
+    Json(JsonDataResponse {
+        code: 200,
+        data: serde_json::json!({
+            "knowledge_base_id": knowledge_base_id,
+            "document_id": document_id
+        }),
+    })
+}
+
+//重新解析并向量化知识库中的所有文档，用于更新了切分或向量化方式之后
+#[axum_macros::debug_handler]
+pub async fn reindex_knowledge_base(
+    Extension(db): Extension<DatabaseConnection>,
+    Extension(ingest_queue): Extension<IngestQueue>,
+    Path(knowledge_base_id): Path<i32>,
+    Query(owner): Query<KnowledgeBaseOwnerQuery>,
+) -> Json<JsonDataResponse> {
+    if let Err(response) = find_owned(&db, knowledge_base_id, &owner).await {
+        return response;
+    }
+    let documents = match KnowledgeBaseDocument::find()
+        .filter(knowledge_base_document::Column::KnowledgeBaseId.eq(knowledge_base_id))
+        .all(&db)
+        .await
+    {
+        Ok(documents) => documents,
+        Err(e) => return error_response(&e.to_string()),
+    };
+    let mut job_ids = Vec::with_capacity(documents.len());
+    for document in documents {
+        match ingest_queue
+            .submit(
+                IngestTarget::KnowledgeBase(knowledge_base_id),
+                &document.filename,
+                &document.storage_key,
+            )
+            .await
+        {
+            Ok(job_id) => job_ids.push(job_id),
+            Err(e) => return error_response(&e.to_string()),
+        }
+    }
+    Json(JsonDataResponse {
+        code: 200,
+        data: serde_json::json!({
+            "knowledge_base_id": knowledge_base_id,
+            "job_ids": job_ids
+        }),
+    })
+}
+
+#[axum_macros::debug_handler]
+pub async fn attach_knowledge_base(
+    Extension(db): Extension<DatabaseConnection>,
+    Path(session_id): Path<i32>,
+    Json(data): Json<AttachKnowledgeBaseRequest>,
+) -> Json<JsonDataResponse> {
+    let session = match Session::find_by_id(session_id).one(&db).await {
+        Ok(Some(session)) => session,
+        Ok(None) => return not_found("Session not found"),
+        Err(e) => return error_response(&e.to_string()),
+    };
+    //会话只能关联其用户自己的知识库，或者该用户所在的团队的知识库；不是团队成员时按知识库不存在处理
+    let owner = match data.team_id {
+        Some(team_id) => KnowledgeBaseOwnerQuery {
+            owner_type: OwnerType::Team.to_value(),
+            owner_id: team_id,
+            user_id: session.user_id,
+        },
+        None => KnowledgeBaseOwnerQuery {
+            owner_type: OwnerType::User.to_value(),
+            owner_id: session.user_id,
+            user_id: session.user_id,
+        },
+    };
+    if let Err(response) = find_owned(&db, data.knowledge_base_id, &owner).await {
+        return response;
+    }
+    let attachment = session_knowledge_base::ActiveModel {
+        session_id: Set(session_id),
+        knowledge_base_id: Set(data.knowledge_base_id),
+    };
+    //重复关联时保持原样
+    let result = SessionKnowledgeBase::insert(attachment)
+        .on_conflict(
+            sea_query::OnConflict::columns([
+                session_knowledge_base::Column::SessionId,
+                session_knowledge_base::Column::KnowledgeBaseId,
+            ])
+            .do_nothing()
+            .to_owned(),
+        )
+        .do_nothing()
+        .exec(&db)
+        .await;
+    if let Err(e) = result {
+        return error_response(&e.to_string());
+    }
+    list_session_knowledge_bases(Extension(db), Path(session_id)).await
+}
+
+#[axum_macros::debug_handler]
+pub async fn list_session_knowledge_bases(
+    Extension(db): Extension<DatabaseConnection>,
+    Path(session_id): Path<i32>,
+) -> Json<JsonDataResponse> {
+    match attached_knowledge_bases(&db, session_id).await {
+        Ok(knowledge_bases) => Json(JsonDataResponse {
+            code: 200,
+            data: serde_json::json!({
+                "session_id": session_id,
+                "knowledge_bases": knowledge_bases.iter().map(knowledge_base_to_json).collect::<Vec<_>>()
+            }),
+        }),
+        Err(e) => error_response(&e.to_string()),
+    }
+}
+
+#[axum_macros::debug_handler]
+pub async fn detach_knowledge_base(
+    Extension(db): Extension<DatabaseConnection>,
+    Path((session_id, knowledge_base_id)): Path<(i32, i32)>,
+) -> Json<JsonDataResponse> {
+    let result = SessionKnowledgeBase::delete_by_id((session_id, knowledge_base_id))
+        .exec(&db)
+        .await;
+    match result {
+        Ok(result) if result.rows_affected == 0 => not_found("Knowledge base not attached"),
+        Ok(_) => list_session_knowledge_bases(Extension(db), Path(session_id)).await,
+        Err(e) => error_response(&e.to_string()),
+    }
+}
+
+fn team_members_json(team_id: i32, members: &[team_member::Model]) -> serde_json::Value {
+    serde_json::json!({
+        "team_id": team_id,
+        "members": members
+            .iter()
+            .map(|x| {
+                serde_json::json!({
+                    "user_id": x.user_id,
+                    "create_time": x.create_time,
+                })
+            })
+            .collect::<Vec<_>>()
+    })
+}
+
+#[axum_macros::debug_handler]
+pub async fn list_team_members(
+    Extension(db): Extension<DatabaseConnection>,
+    Path(team_id): Path<i32>,
+) -> Json<JsonDataResponse> {
+    match TeamMember::find()
+        .filter(team_member::Column::TeamId.eq(team_id))
+        .order_by_asc(team_member::Column::UserId)
+        .all(&db)
+        .await
+    {
+        Ok(members) => Json(JsonDataResponse {
+            code: 200,
+            data: team_members_json(team_id, &members),
+        }),
+        Err(e) => error_response(&e.to_string()),
+    }
+}
+
+//团队成员的会话可以关联该团队的知识库；只有团队成员可以添加新成员，还没有成员的团队只能由用户自己加入
+#[axum_macros::debug_handler]
+pub async fn add_team_member(
+    Extension(db): Extension<DatabaseConnection>,
+    Path((team_id, user_id)): Path<(i32, i32)>,
+    Query(actor): Query<TeamMemberQuery>,
+) -> Json<JsonDataResponse> {
+    let members = match TeamMember::find()
+        .filter(team_member::Column::TeamId.eq(team_id))
+        .all(&db)
+        .await
+    {
+        Ok(members) => members,
+        Err(e) => return error_response(&e.to_string()),
+    };
+    let allowed = match members.is_empty() {
+        true => actor.user_id == user_id,
+        false => members.iter().any(|x| x.user_id == actor.user_id),
+    };
+    if !allowed {
+        return forbidden("Only team members can add members");
+    }
+    let member = team_member::ActiveModel {
+        team_id: Set(team_id),
+        user_id: Set(user_id),
+        create_time: Set(chrono::Utc::now()),
+    };
+    //重复添加时保持原样
+    let result = TeamMember::insert(member)
+        .on_conflict(
+            sea_query::OnConflict::columns([
+                team_member::Column::TeamId,
+                team_member::Column::UserId,
+            ])
+            .do_nothing()
+            .to_owned(),
+        )
+        .do_nothing()
+        .exec(&db)
+        .await;
+    if let Err(e) = result {
+        return error_response(&e.to_string());
+    }
+    list_team_members(Extension(db), Path(team_id)).await
+}
+
+#[axum_macros::debug_handler]
+pub async fn remove_team_member(
+    Extension(db): Extension<DatabaseConnection>,
+    Path((team_id, user_id)): Path<(i32, i32)>,
+    Query(actor): Query<TeamMemberQuery>,
+) -> Json<JsonDataResponse> {
+    match is_team_member(&db, team_id, actor.user_id).await {
+        Ok(true) => {}
+        Ok(false) => return forbidden("Only team members can remove members"),
+        Err(e) => return error_response(&e.to_string()),
+    }
+    let result = TeamMember::delete_by_id((team_id, user_id)).exec(&db).await;
+    match result {
+        Ok(result) if result.rows_affected == 0 => not_found("Team member not found"),
+        Ok(_) => list_team_members(Extension(db), Path(team_id)).await,
+        Err(e) => error_response(&e.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        add_team_member, attach_knowledge_base, create_knowledge_base, read_document, rebuild_lock,
+        REBUILD_LOCKS,
+    };
+    use crate::{
+        data::{AttachKnowledgeBaseRequest, CreateKnowledgeBaseRequest, TeamMemberQuery},
+        entities::{
+            knowledge_base, knowledge_base_document,
+            prelude::{
+                KnowledgeBase, KnowledgeBaseDocument, Session, SessionKnowledgeBase, TeamMember,
+            },
+            sea_orm_active_enums::OwnerType,
+            session, session_knowledge_base, team_member,
+        },
+        index::{document_text_path, files_dir},
+    };
+    use axum::{
+        extract::{Json, Path, Query},
+        Extension,
+    };
+    use sea_orm::*;
+    use std::sync::Arc;
+
+    async fn test_db() -> DatabaseConnection {
+        let mut options = ConnectOptions::new("sqlite::memory:");
+        options.max_connections(1);
+        let db = Database::connect(options).await.unwrap();
+        let backend = db.get_database_backend();
+        let schema = Schema::new(backend);
+        for statement in [
+            schema.create_table_from_entity(KnowledgeBase),
+            schema.create_table_from_entity(KnowledgeBaseDocument),
+            schema.create_table_from_entity(SessionKnowledgeBase),
+            schema.create_table_from_entity(Session),
+            schema.create_table_from_entity(TeamMember),
+        ] {
+            db.execute(backend.build(&statement)).await.unwrap();
+        }
+        db
+    }
+
+    #[tokio::test]
+    async fn test_read_document_from_knowledge_base() {
+        let db = test_db().await;
+        let now = chrono::Utc::now();
+        //会话-1不会有上传的文档
+        let session = session::ActiveModel {
+            session_id: Set(-1),
+            user_id: Set(1),
+            create_time: Set(now),
+            last_update_time: Set(now),
+            ..Default::default()
+        };
+        Session::insert(session)
+            .exec_without_returning(&db)
+            .await
+            .unwrap();
+        //用户1是团队1的成员；知识库2属于其它用户，知识库3属于用户1不在其中的团队，即使存在关联记录也不能被会话读取
+        team_member::ActiveModel {
+            team_id: Set(1),
+            user_id: Set(1),
+            create_time: Set(now),
+        }
+        .insert(&db)
+        .await
+        .unwrap();
+        for (knowledge_base_id, name, owner_type, owner_id) in [
+            (1, "员工手册", OwnerType::Team, 1),
+            (2, "个人笔记", OwnerType::User, 2),
+            (3, "财务制度", OwnerType::Team, 2),
+        ] {
+            knowledge_base::ActiveModel {
+                knowledge_base_id: Set(knowledge_base_id),
+                name: Set(name.to_string()),
+                description: Set(String::new()),
+                owner_type: Set(owner_type),
+                owner_id: Set(owner_id),
+                create_time: Set(now),
+                last_update_time: Set(now),
+            }
+            .insert(&db)
+            .await
+            .unwrap();
+            session_knowledge_base::ActiveModel {
+                session_id: Set(-1),
+                knowledge_base_id: Set(knowledge_base_id),
+            }
+            .insert(&db)
+            .await
+            .unwrap();
+        }
+        let storage_key = format!("objects/{}.txt", uuid::Uuid::new_v4().simple());
+        knowledge_base_document::ActiveModel {
+            knowledge_base_id: Set(1),
+            filename: Set("员工手册2024.pdf".to_string()),
+            storage_key: Set(storage_key.clone()),
+            create_time: Set(now),
+            ..Default::default()
+        }
+        .insert(&db)
+        .await
+        .unwrap();
+        for (knowledge_base_id, filename) in [(2, "笔记.txt"), (3, "财务制度.pdf")] {
+            knowledge_base_document::ActiveModel {
+                knowledge_base_id: Set(knowledge_base_id),
+                filename: Set(filename.to_string()),
+                storage_key: Set(storage_key.clone()),
+                create_time: Set(now),
+                ..Default::default()
+            }
+            .insert(&db)
+            .await
+            .unwrap();
+        }
+
+        let error = read_document(&db, -1, Some("员工手册")).await.unwrap_err();
+        assert!(error.to_string().contains("尚未处理完成"));
+        tokio::fs::create_dir_all(format!("{}/documents", files_dir()))
+            .await
+            .unwrap();
+        tokio::fs::write(document_text_path(&storage_key), "第一章 考勤制度")
+            .await
+            .unwrap();
+        let text = read_document(&db, -1, Some("员工手册")).await;
+        let _ = tokio::fs::remove_file(document_text_path(&storage_key)).await;
+        assert_eq!(text.unwrap(), "第一章 考勤制度");
+        let error = read_document(&db, -1, None).await.unwrap_err();
+        assert!(error.to_string().contains("员工手册2024.pdf"));
+        assert!(!error.to_string().contains("笔记.txt"));
+        assert!(!error.to_string().contains("财务制度.pdf"));
+        let error = read_document(&db, -1, Some("财务制度")).await.unwrap_err();
+        assert!(error.to_string().contains("没有文档"));
+        let error = read_document(&db, -1, Some("笔记")).await.unwrap_err();
+        assert!(error.to_string().contains("没有文档"));
+    }
+
+    #[tokio::test]
+    async fn test_team_access() {
+        let db = test_db().await;
+        let now = chrono::Utc::now();
+        //用户2的会话-2
+        let session = session::ActiveModel {
+            session_id: Set(-2),
+            user_id: Set(2),
+            create_time: Set(now),
+            last_update_time: Set(now),
+            ..Default::default()
+        };
+        Session::insert(session)
+            .exec_without_returning(&db)
+            .await
+            .unwrap();
+        let add_member = |user_id: i32, actor: i32| {
+            add_team_member(
+                Extension(db.clone()),
+                Path((5, user_id)),
+                Query(TeamMemberQuery { user_id: actor }),
+            )
+        };
+        let create = |user_id: i32| {
+            create_knowledge_base(
+                Extension(db.clone()),
+                Json(CreateKnowledgeBaseRequest {
+                    name: "员工手册".to_string(),
+                    description: String::new(),
+                    owner_type: "team".to_string(),
+                    owner_id: 5,
+                    user_id,
+                }),
+            )
+        };
+        let attach = |knowledge_base_id: i32| {
+            attach_knowledge_base(
+                Extension(db.clone()),
+                Path(-2),
+                Json(AttachKnowledgeBaseRequest {
+                    knowledge_base_id,
+                    team_id: Some(5),
+                }),
+            )
+        };
+
+        //空团队只能由用户自己加入，之后不是成员的用户不能把自己加进去
+        assert_eq!(add_member(1, 2).await.code, 403);
+        assert_eq!(add_member(1, 1).await.code, 200);
+        assert_eq!(add_member(2, 2).await.code, 403);
+        assert!(TeamMember::find_by_id((5, 2))
+            .one(&db)
+            .await
+            .unwrap()
+            .is_none());
+
+        //不是团队成员不能以团队的名义创建知识库
+        assert_eq!(create(2).await.code, 403);
+        let response = create(1).await;
+        assert_eq!(response.code, 200);
+        let knowledge_base_id = response.data["knowledge_base_id"].as_i64().unwrap() as i32;
+
+        //团队外的用户2不能把团队的知识库关联到自己的会话
+        assert_eq!(attach(knowledge_base_id).await.code, 404);
+        assert!(SessionKnowledgeBase::find()
+            .all(&db)
+            .await
+            .unwrap()
+            .is_empty());
+
+        //由团队成员添加后就可以关联
+        assert_eq!(add_member(2, 1).await.code, 200);
+        let response = attach(knowledge_base_id).await;
+        assert_eq!(response.code, 200);
+        assert_eq!(
+            response.data["knowledge_bases"][0]["knowledge_base_id"],
+            knowledge_base_id
+        );
+    }
+
+    #[test]
+    fn test_rebuild_lock() {
+        //同一知识库在重建期间共享一把锁，重建结束后条目会被清理
+        let lock = rebuild_lock(-100);
+        assert!(Arc::ptr_eq(&lock, &rebuild_lock(-100)));
+        assert!(!Arc::ptr_eq(&lock, &rebuild_lock(-101)));
+        drop(lock);
+        rebuild_lock(-102);
+        let locks = REBUILD_LOCKS.lock().unwrap();
+        assert!(!locks.contains_key(&-100));
+        assert!(!locks.contains_key(&-101));
+    }
+}
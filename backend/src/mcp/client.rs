@@ -0,0 +1,399 @@
+use std::{
+    collections::HashMap,
+    process::Stdio,
+    sync::atomic::{AtomicI64, Ordering},
+    time::Duration,
+};
+
+use anyhow::Result;
+use serde::Deserialize;
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    process::{Child, ChildStdin, ChildStdout, Command},
+    sync::Mutex,
+};
+use tracing::info;
+
+use super::protocol::{
+    parse_sse_data, CallToolResult, JsonRpcMessage, Tool, METHOD_NOT_FOUND, PROTOCOL_VERSION,
+};
+
+const SESSION_HEADER: &str = "Mcp-Session-Id";
+
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+#[serde(tag = "transport", rename_all = "snake_case")]
+pub enum McpTransportConfig {
+    //启动子进程，通过标准输入输出逐行收发json消息
+    Stdio {
+        command: String,
+        #[serde(default)]
+        args: Vec<String>,
+        #[serde(default)]
+        env: HashMap<String, String>,
+    },
+    //Streamable HTTP，每条消息POST到同一个地址，响应为json或SSE
+    Http {
+        url: String,
+        #[serde(default)]
+        headers: HashMap<String, String>,
+    },
+}
+
+struct StdioTransport {
+    //子进程随客户端一起结束
+    _child: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+}
+
+struct HttpTransport {
+    client: reqwest::Client,
+    url: String,
+    headers: HashMap<String, String>,
+    session_id: Mutex<Option<String>>,
+}
+
+enum Transport {
+    Stdio(Mutex<StdioTransport>),
+    Http(HttpTransport),
+}
+
+pub struct McpClient {
+    pub name: String,
+    transport: Transport,
+    next_id: AtomicI64,
+    timeout: Duration,
+}
+
+impl McpClient {
+    pub async fn connect(
+        name: &str,
+        config: &McpTransportConfig,
+        timeout: Duration,
+    ) -> Result<Self> {
+        let transport = match config {
+            McpTransportConfig::Stdio { command, args, env } => {
+                //子进程不继承后端的环境变量(其中有模型的密钥等)，只传入PATH和配置的变量
+                let mut command_builder = Command::new(command);
+                command_builder.env_clear();
+                if let Some(path) = std::env::var_os("PATH") {
+                    command_builder.env("PATH", path);
+                }
+                let mut child = command_builder
+                    .args(args)
+                    .envs(env)
+                    .stdin(Stdio::piped())
+                    .stdout(Stdio::piped())
+                    .stderr(Stdio::inherit())
+                    .kill_on_drop(true)
+                    .spawn()
+                    .map_err(|e| anyhow::anyhow!("{}: failed to start {}: {}", name, command, e))?;
+                let stdin = child.stdin.take().unwrap();
+                let stdout = BufReader::new(child.stdout.take().unwrap());
+                Transport::Stdio(Mutex::new(StdioTransport {
+                    _child: child,
+                    stdin,
+                    stdout,
+                }))
+            }
+            McpTransportConfig::Http { url, headers } => Transport::Http(HttpTransport {
+                client: reqwest::Client::new(),
+                url: url.clone(),
+                headers: headers.clone(),
+                session_id: Mutex::new(None),
+            }),
+        };
+        let client = Self {
+            name: name.to_string(),
+            transport,
+            next_id: AtomicI64::new(1),
+            timeout,
+        };
+        client.initialize().await?;
+        Ok(client)
+    }
+
+    async fn initialize(&self) -> Result<()> {
+        let result = self
+            .request(
+                "initialize",
+                serde_json::json!({
+                    "protocolVersion": PROTOCOL_VERSION,
+                    "capabilities": {},
+                    "clientInfo": {"name": "erniebot-rs-chat", "version": env!("CARGO_PKG_VERSION")},
+                }),
+            )
+            .await?;
+        info!(
+            "connected to mcp server {}: {}",
+            self.name,
+            result.get("serverInfo").unwrap_or(&serde_json::Value::Null)
+        );
+        self.notify("notifications/initialized", serde_json::json!({}))
+            .await
+    }
+
+    pub async fn list_tools(&self) -> Result<Vec<Tool>> {
+        let mut tools = Vec::new();
+        let mut cursor: Option<String> = None;
+        loop {
+            let params = match &cursor {
+                Some(cursor) => serde_json::json!({ "cursor": cursor }),
+                None => serde_json::json!({}),
+            };
+            let result = self.request("tools/list", params).await?;
+            let page: Vec<Tool> = serde_json::from_value(result["tools"].clone())?;
+            tools.extend(page);
+            cursor = result["nextCursor"].as_str().map(|x| x.to_string());
+            if cursor.is_none() {
+                return Ok(tools);
+            }
+        }
+    }
+
+    //工具返回isError时视为函数执行失败，错误信息是工具返回的文本
+    pub async fn call_tool(&self, name: &str, arguments: serde_json::Value) -> Result<String> {
+        let result = self
+            .request(
+                "tools/call",
+                serde_json::json!({ "name": name, "arguments": arguments }),
+            )
+            .await?;
+        let result: CallToolResult = serde_json::from_value(result)?;
+        if result.is_error {
+            return Err(anyhow::anyhow!(
+                "{}: tool {} failed: {}",
+                self.name,
+                name,
+                result.to_text()
+            ));
+        }
+        Ok(result.to_text())
+    }
+
+    async fn request(&self, method: &str, params: serde_json::Value) -> Result<serde_json::Value> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let message = JsonRpcMessage::request(id, method, params);
+        let exchange = async {
+            match &self.transport {
+                Transport::Stdio(stdio) => stdio.lock().await.exchange(&message, id).await,
+                Transport::Http(http) => http.exchange(&message, id).await,
+            }
+        };
+        let response = match tokio::time::timeout(self.timeout, exchange).await {
+            Ok(response) => response.map_err(|e| anyhow::anyhow!("{}: {}", self.name, e))?,
+            Err(_) => {
+                //通知服务端放弃这个请求，失败也不影响返回超时错误
+                let _ = self
+                    .notify(
+                        "notifications/cancelled",
+                        serde_json::json!({ "requestId": id, "reason": "timeout" }),
+                    )
+                    .await;
+                return Err(anyhow::anyhow!(
+                    "{}: {} timed out after {}s",
+                    self.name,
+                    method,
+                    self.timeout.as_secs()
+                ));
+            }
+        };
+        if let Some(error) = response.error {
+            return Err(anyhow::anyhow!(
+                "{}: {} failed ({}): {}",
+                self.name,
+                method,
+                error.code,
+                error.message
+            ));
+        }
+        Ok(response.result.unwrap_or(serde_json::Value::Null))
+    }
+
+    async fn notify(&self, method: &str, params: serde_json::Value) -> Result<()> {
+        let message = JsonRpcMessage::notification(method, params);
+        match &self.transport {
+            Transport::Stdio(stdio) => stdio.lock().await.send(&message).await,
+            Transport::Http(http) => http.post(&message).await.map(|_| ()),
+        }
+    }
+}
+
+impl StdioTransport {
+    async fn send(&mut self, message: &JsonRpcMessage) -> Result<()> {
+        let mut line = serde_json::to_string(message)?;
+        line.push('\n');
+        self.stdin.write_all(line.as_bytes()).await?;
+        self.stdin.flush().await?;
+        Ok(())
+    }
+
+    //逐行读取直到收到对应的响应；之前超时的请求的响应和服务端的通知都会被跳过
+    async fn exchange(&mut self, message: &JsonRpcMessage, id: i64) -> Result<JsonRpcMessage> {
+        self.send(message).await?;
+        let mut line = String::new();
+        loop {
+            line.clear();
+            if self.stdout.read_line(&mut line).await? == 0 {
+                return Err(anyhow::anyhow!("server process exited"));
+            }
+            let received: JsonRpcMessage = match serde_json::from_str(line.trim()) {
+                Ok(received) => received,
+                Err(_) => continue,
+            };
+            if received.is_response_to(id) {
+                return Ok(received);
+            }
+            //服务端发来的请求(如ping)需要回复，否则服务端可能一直等待
+            if let (Some(method), Some(request_id)) = (&received.method, received.id.clone()) {
+                let reply = if method == "ping" {
+                    JsonRpcMessage::response(request_id, serde_json::json!({}))
+                } else {
+                    JsonRpcMessage::error_response(
+                        Some(request_id),
+                        METHOD_NOT_FOUND,
+                        "Method not supported by client",
+                    )
+                };
+                self.send(&reply).await?;
+            }
+        }
+    }
+}
+
+impl HttpTransport {
+    async fn post(&self, message: &JsonRpcMessage) -> Result<reqwest::Response> {
+        let mut request = self
+            .client
+            .post(&self.url)
+            .header("Accept", "application/json, text/event-stream")
+            .json(message);
+        for (name, value) in self.headers.iter() {
+            request = request.header(name, value);
+        }
+        if let Some(session_id) = self.session_id.lock().await.as_ref() {
+            request = request.header(SESSION_HEADER, session_id);
+        }
+        let response = request.send().await?;
+        let status = response.status();
+        if !status.is_success() {
+            let text = response.text().await.unwrap_or_default();
+            let snippet: String = text.chars().take(200).collect();
+            return Err(anyhow::anyhow!("server returned {}: {}", status, snippet));
+        }
+        //初始化时服务端分配会话id，之后的请求都要带上
+        if let Some(session_id) = response.headers().get(SESSION_HEADER) {
+            *self.session_id.lock().await = Some(session_id.to_str()?.to_string());
+        }
+        Ok(response)
+    }
+
+    async fn exchange(&self, message: &JsonRpcMessage, id: i64) -> Result<JsonRpcMessage> {
+        let response = self.post(message).await?;
+        let is_sse = response
+            .headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|x| x.to_str().ok())
+            .is_some_and(|x| x.starts_with("text/event-stream"));
+        let body = response.text().await?;
+        if !is_sse {
+            return Ok(serde_json::from_str(&body)?);
+        }
+        parse_sse_data(&body)
+            .iter()
+            .filter_map(|x| serde_json::from_str::<JsonRpcMessage>(x).ok())
+            .find(|x| x.is_response_to(id))
+            .ok_or_else(|| anyhow::anyhow!("no response in event stream"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{McpClient, McpTransportConfig};
+    use crate::mcp::protocol::JsonRpcMessage;
+    use axum::{http::header, response::IntoResponse, routing::post, Json, Router};
+    use std::{collections::HashMap, time::Duration};
+
+    //最小的Streamable HTTP服务端：tools/call以SSE返回，其它请求返回json
+    async fn handle(Json(message): Json<JsonRpcMessage>) -> axum::response::Response {
+        let id = match message.id.clone() {
+            Some(id) => id,
+            None => return axum::http::StatusCode::ACCEPTED.into_response(),
+        };
+        let params = message.params.unwrap_or_default();
+        let result = match message.method.as_deref() {
+            Some("initialize") => serde_json::json!({
+                "protocolVersion": "2025-03-26",
+                "capabilities": {"tools": {}},
+                "serverInfo": {"name": "stub", "version": "0.1.0"}
+            }),
+            Some("tools/list") => serde_json::json!({"tools": [{
+                "name": "weather",
+                "description": "查询天气",
+                "inputSchema": {"type": "object", "properties": {"city": {"type": "string"}}},
+                "annotations": {"readOnlyHint": true}
+            }]}),
+            Some("tools/call") if params["arguments"]["city"] == "北京" => {
+                let response = JsonRpcMessage::response(
+                    id,
+                    serde_json::json!({"content": [{"type": "text", "text": "晴"}]}),
+                );
+                let body = format!(
+                    "event: message\ndata: {}\n\n",
+                    serde_json::to_string(&response).unwrap()
+                );
+                return ([(header::CONTENT_TYPE, "text/event-stream")], body).into_response();
+            }
+            Some("tools/call") => serde_json::json!({
+                "content": [{"type": "text", "text": "未知城市"}], "isError": true
+            }),
+            _ => {
+                return Json(JsonRpcMessage::error_response(
+                    Some(id),
+                    -32601,
+                    "no such method",
+                ))
+                .into_response()
+            }
+        };
+        (
+            [("Mcp-Session-Id", "stub-session")],
+            Json(JsonRpcMessage::response(id, result)),
+        )
+            .into_response()
+    }
+
+    #[tokio::test]
+    async fn test_http_client() {
+        let app = Router::new().route("/mcp", post(handle));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let config = McpTransportConfig::Http {
+            url: format!("http://{}/mcp", address),
+            headers: HashMap::new(),
+        };
+        let client = McpClient::connect("stub", &config, Duration::from_secs(5))
+            .await
+            .unwrap();
+        let tools = client.list_tools().await.unwrap();
+        assert_eq!(tools.len(), 1);
+        assert_eq!(tools[0].name, "weather");
+        let result = client
+            .call_tool("weather", serde_json::json!({"city": "北京"}))
+            .await
+            .unwrap();
+        assert_eq!(result, "晴");
+        let error = client
+            .call_tool("weather", serde_json::json!({"city": "火星"}))
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("未知城市"));
+        let error = client
+            .request("resources/list", serde_json::json!({}))
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("-32601"));
+    }
+}
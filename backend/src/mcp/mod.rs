@@ -0,0 +1,7 @@
+mod client;
+mod protocol;
+mod server;
+
+pub use client::{McpClient, McpTransportConfig};
+pub use protocol::Tool;
+pub use server::{McpServer, ServeOptions};
@@ -0,0 +1,213 @@
+use serde::{Deserialize, Serialize};
+
+pub const PROTOCOL_VERSION: &str = "2025-03-26";
+
+pub const PARSE_ERROR: i64 = -32700;
+pub const METHOD_NOT_FOUND: i64 = -32601;
+pub const INVALID_PARAMS: i64 = -32602;
+pub const INTERNAL_ERROR: i64 = -32603;
+pub const RESOURCE_NOT_FOUND: i64 = -32002;
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct JsonRpcError {
+    pub code: i64,
+    pub message: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data: Option<serde_json::Value>,
+}
+
+//请求、通知和响应共用一个结构：有method和id的是请求，只有method的是通知，只有id的是响应
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct JsonRpcMessage {
+    pub jsonrpc: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<serde_json::Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub method: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub params: Option<serde_json::Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub result: Option<serde_json::Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<JsonRpcError>,
+}
+
+impl JsonRpcMessage {
+    fn new() -> Self {
+        Self {
+            jsonrpc: "2.0".to_string(),
+            id: None,
+            method: None,
+            params: None,
+            result: None,
+            error: None,
+        }
+    }
+
+    pub fn request(id: i64, method: &str, params: serde_json::Value) -> Self {
+        Self {
+            id: Some(id.into()),
+            method: Some(method.to_string()),
+            params: Some(params),
+            ..Self::new()
+        }
+    }
+
+    pub fn notification(method: &str, params: serde_json::Value) -> Self {
+        Self {
+            method: Some(method.to_string()),
+            params: Some(params),
+            ..Self::new()
+        }
+    }
+
+    pub fn response(id: serde_json::Value, result: serde_json::Value) -> Self {
+        Self {
+            id: Some(id),
+            result: Some(result),
+            ..Self::new()
+        }
+    }
+
+    pub fn error_response(id: Option<serde_json::Value>, code: i64, message: &str) -> Self {
+        Self {
+            id: Some(id.unwrap_or(serde_json::Value::Null)),
+            error: Some(JsonRpcError {
+                code,
+                message: message.to_string(),
+                data: None,
+            }),
+            ..Self::new()
+        }
+    }
+
+    pub fn is_response_to(&self, id: i64) -> bool {
+        self.method.is_none() && self.id.as_ref().and_then(|x| x.as_i64()) == Some(id)
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ToolAnnotations {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub read_only_hint: Option<bool>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Tool {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    pub input_schema: serde_json::Value,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub annotations: Option<ToolAnnotations>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Content {
+    Text {
+        text: String,
+    },
+    Image {
+        #[serde(rename = "mimeType")]
+        mime_type: String,
+    },
+    Resource {
+        resource: serde_json::Value,
+    },
+    #[serde(other)]
+    Unsupported,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct CallToolResult {
+    #[serde(default)]
+    pub content: Vec<Content>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub structured_content: Option<serde_json::Value>,
+    #[serde(default)]
+    pub is_error: bool,
+}
+
+impl CallToolResult {
+    pub fn text(text: String, is_error: bool) -> Self {
+        Self {
+            content: vec![Content::Text { text }],
+            structured_content: None,
+            is_error,
+        }
+    }
+
+    //函数结果交给大模型处理，只保留文本；图片等内容用占位说明代替
+    pub fn to_text(&self) -> String {
+        let parts: Vec<String> = self
+            .content
+            .iter()
+            .filter_map(|x| match x {
+                Content::Text { text } => Some(text.clone()),
+                Content::Image { mime_type } => Some(format!("[图片: {}]", mime_type)),
+                Content::Resource { resource } => resource
+                    .get("text")
+                    .or_else(|| resource.get("uri"))
+                    .and_then(|x| x.as_str())
+                    .map(|x| x.to_string()),
+                Content::Unsupported => None,
+            })
+            .collect();
+        match (&self.structured_content, parts.is_empty()) {
+            (Some(structured), true) => structured.to_string(),
+            _ => parts.join("\n"),
+        }
+    }
+}
+
+//Streamable HTTP传输的响应可能是text/event-stream，取出每个事件的data
+pub fn parse_sse_data(body: &str) -> Vec<String> {
+    let mut events = Vec::new();
+    let mut data: Vec<&str> = Vec::new();
+    for line in body.lines() {
+        if line.is_empty() {
+            if !data.is_empty() {
+                events.push(data.join("\n"));
+                data.clear();
+            }
+        } else if let Some(value) = line.strip_prefix("data:") {
+            data.push(value.strip_prefix(' ').unwrap_or(value));
+        }
+    }
+    if !data.is_empty() {
+        events.push(data.join("\n"));
+    }
+    events
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_sse_data, CallToolResult, JsonRpcMessage};
+
+    #[test]
+    fn test_parse_sse_data() {
+        let body = "event: message\ndata: {\"a\":1}\n\n: ping\n\ndata: first\ndata: second\n";
+        assert_eq!(parse_sse_data(body), vec!["{\"a\":1}", "first\nsecond"]);
+    }
+
+    #[test]
+    fn test_message_and_result() {
+        let message: JsonRpcMessage = serde_json::from_str(
+            r#"{"jsonrpc": "2.0", "id": 3, "result": {"content": [
+                {"type": "text", "text": "晴"},
+                {"type": "image", "data": "AAAA", "mimeType": "image/png"},
+                {"type": "audio", "data": "AAAA", "mimeType": "audio/wav"}
+            ]}}"#,
+        )
+        .unwrap();
+        assert!(message.is_response_to(3));
+        assert!(!message.is_response_to(4));
+        let result: CallToolResult = serde_json::from_value(message.result.unwrap()).unwrap();
+        assert!(!result.is_error);
+        assert_eq!(result.to_text(), "晴\n[图片: image/png]");
+    }
+}
@@ -0,0 +1,457 @@
+use anyhow::Result;
+use erniebot_rs::{chat::ChatEndpoint, embedding::EmbeddingEndpoint};
+use sea_orm::{ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+use super::protocol::{
+    CallToolResult, JsonRpcError, JsonRpcMessage, Tool, ToolAnnotations, INTERNAL_ERROR,
+    INVALID_PARAMS, METHOD_NOT_FOUND, PARSE_ERROR, PROTOCOL_VERSION, RESOURCE_NOT_FOUND,
+};
+use crate::{
+    context_window::format_transcript,
+    entities::{message, prelude::*},
+    functions::{Context, FunctionRegistry},
+    index::session_text_path,
+    session_settings::SessionSettings,
+};
+
+//客户端请求的协议版本在这些之中时按客户端的版本回复
+const SUPPORTED_PROTOCOL_VERSIONS: [&str; 2] = ["2024-11-05", PROTOCOL_VERSION];
+
+fn rpc_error(code: i64, message: impl std::fmt::Display) -> JsonRpcError {
+    JsonRpcError {
+        code,
+        message: message.to_string(),
+        data: None,
+    }
+}
+
+fn initialize_result(params: &serde_json::Value) -> serde_json::Value {
+    let version = params["protocolVersion"]
+        .as_str()
+        .filter(|x| SUPPORTED_PROTOCOL_VERSIONS.contains(x))
+        .unwrap_or(PROTOCOL_VERSION);
+    serde_json::json!({
+        "protocolVersion": version,
+        "capabilities": {"tools": {}, "resources": {}},
+        "serverInfo": {"name": "erniebot-rs-chat", "version": env!("CARGO_PKG_VERSION")},
+    })
+}
+
+fn add_session_parameter(schema: &mut serde_json::Value) {
+    let Some(object) = schema.as_object_mut() else {
+        return;
+    };
+    if let Some(properties) = object
+        .entry("properties")
+        .or_insert_with(|| serde_json::json!({}))
+        .as_object_mut()
+    {
+        properties.insert(
+            "session_id".to_string(),
+            serde_json::json!({"type": "integer", "description": "会话id，文档和记忆都属于会话"}),
+        );
+    }
+    if let Some(required) = object
+        .entry("required")
+        .or_insert_with(|| serde_json::json!([]))
+        .as_array_mut()
+    {
+        required.push(serde_json::json!("session_id"));
+    }
+}
+
+//mcp-serve的命令行参数：--session-id N 固定会话，--all-sessions 允许访问所有会话(二者必须指定一个)，
+//--allow-side-effects 提供有副作用的工具
+#[derive(Debug, Clone, PartialEq)]
+pub struct ServeOptions {
+    pub session_id: Option<i32>,
+    pub allow_side_effects: bool,
+}
+
+impl ServeOptions {
+    pub fn parse(args: &[String]) -> Result<Self> {
+        let session_id = match args.iter().position(|x| x == "--session-id") {
+            Some(index) => Some(
+                args.get(index + 1)
+                    .and_then(|x| x.parse::<i32>().ok())
+                    .ok_or_else(|| anyhow::anyhow!("--session-id requires a session id"))?,
+            ),
+            None => None,
+        };
+        let all_sessions = args.iter().any(|x| x == "--all-sessions");
+        match (session_id, all_sessions) {
+            (Some(_), true) => {
+                return Err(anyhow::anyhow!(
+                    "--session-id and --all-sessions cannot be used together"
+                ))
+            }
+            (None, false) => {
+                return Err(anyhow::anyhow!(
+                    "mcp-serve requires --session-id N, or --all-sessions to serve every session"
+                ))
+            }
+            _ => {}
+        }
+        Ok(Self {
+            session_id,
+            allow_side_effects: args.iter().any(|x| x == "--allow-side-effects"),
+        })
+    }
+}
+
+//direct_reply只在agent中有意义，不对外提供；有副作用的函数没有确认渠道，只在启动时允许后提供；
+//未固定会话时每个工具都需要session_id参数
+fn is_exposed(registry: &FunctionRegistry, name: &str, allow_side_effects: bool) -> bool {
+    name != "direct_reply"
+        && registry.function_names().iter().any(|x| x == name)
+        && (allow_side_effects || !registry.requires_confirmation(name))
+}
+
+fn tool_definitions(
+    registry: &FunctionRegistry,
+    with_session: bool,
+    allow_side_effects: bool,
+) -> Vec<Tool> {
+    let mut tools: Vec<Tool> = registry
+        .get_ernie_functions()
+        .into_iter()
+        .filter(|x| is_exposed(registry, &x.name, allow_side_effects))
+        .map(|function| {
+            let mut schema = serde_json::to_value(&function.parameters).unwrap_or_default();
+            if with_session {
+                add_session_parameter(&mut schema);
+            }
+            Tool {
+                annotations: Some(ToolAnnotations {
+                    read_only_hint: Some(!registry.requires_confirmation(&function.name)),
+                }),
+                name: function.name,
+                description: Some(function.description),
+                input_schema: schema,
+            }
+        })
+        .collect();
+    tools.sort_by(|a, b| a.name.cmp(&b.name));
+    tools
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ResourceKind {
+    Messages,
+    Document,
+}
+
+//资源地址为 session://{session_id}/messages 或 session://{session_id}/document
+fn parse_resource_uri(uri: &str) -> Option<(i32, ResourceKind)> {
+    let (session_id, kind) = uri.strip_prefix("session://")?.split_once('/')?;
+    let kind = match kind {
+        "messages" => ResourceKind::Messages,
+        "document" => ResourceKind::Document,
+        _ => return None,
+    };
+    Some((session_id.parse().ok()?, kind))
+}
+
+fn resource_entry(session_id: i32, kind: ResourceKind) -> serde_json::Value {
+    let (uri, name) = match kind {
+        ResourceKind::Messages => (
+            format!("session://{}/messages", session_id),
+            format!("会话{}的对话记录", session_id),
+        ),
+        ResourceKind::Document => (
+            format!("session://{}/document", session_id),
+            format!("会话{}上传的文档", session_id),
+        ),
+    };
+    serde_json::json!({"uri": uri, "name": name, "mimeType": "text/plain"})
+}
+
+//通过标准输入输出提供MCP服务，工具就是FunctionRegistry中注册的函数
+pub struct McpServer {
+    registry: FunctionRegistry,
+    db: DatabaseConnection,
+    chat_endpoint: ChatEndpoint,
+    embedding_endpoint: EmbeddingEndpoint,
+    //启动时固定了会话的，只能访问这个会话，工具参数中也不需要session_id
+    options: ServeOptions,
+}
+
+impl McpServer {
+    pub fn new(
+        registry: FunctionRegistry,
+        db: DatabaseConnection,
+        chat_endpoint: ChatEndpoint,
+        embedding_endpoint: EmbeddingEndpoint,
+        options: ServeOptions,
+    ) -> Self {
+        Self {
+            registry,
+            db,
+            chat_endpoint,
+            embedding_endpoint,
+            options,
+        }
+    }
+
+    //逐行读取消息，按顺序处理；标准输出只能写协议消息，日志需要写到标准错误
+    pub async fn serve_stdio(&self) -> Result<()> {
+        let mut lines = BufReader::new(tokio::io::stdin()).lines();
+        let mut stdout = tokio::io::stdout();
+        while let Some(line) = lines.next_line().await? {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let reply = match serde_json::from_str::<JsonRpcMessage>(&line) {
+                Ok(message) => self.handle(message).await,
+                Err(e) => Some(JsonRpcMessage::error_response(
+                    None,
+                    PARSE_ERROR,
+                    &e.to_string(),
+                )),
+            };
+            if let Some(reply) = reply {
+                let mut line = serde_json::to_string(&reply)?;
+                line.push('\n');
+                stdout.write_all(line.as_bytes()).await?;
+                stdout.flush().await?;
+            }
+        }
+        Ok(())
+    }
+
+    //通知和客户端发来的响应不需要回复
+    async fn handle(&self, message: JsonRpcMessage) -> Option<JsonRpcMessage> {
+        let method = message.method?;
+        let id = message.id?;
+        let params = message.params.unwrap_or_else(|| serde_json::json!({}));
+        let result = match method.as_str() {
+            "initialize" => Ok(initialize_result(&params)),
+            "ping" => Ok(serde_json::json!({})),
+            "tools/list" => Ok(serde_json::json!({
+                "tools": tool_definitions(
+                    &self.registry,
+                    self.options.session_id.is_none(),
+                    self.options.allow_side_effects
+                )
+            })),
+            "tools/call" => self.call_tool(params).await,
+            "resources/list" => self.list_resources().await,
+            "resources/read" => self.read_resource(params).await,
+            _ => Err(rpc_error(
+                METHOD_NOT_FOUND,
+                format!("Unknown method {}", method),
+            )),
+        };
+        Some(match result {
+            Ok(result) => JsonRpcMessage::response(id, result),
+            Err(error) => JsonRpcMessage::error_response(Some(id), error.code, &error.message),
+        })
+    }
+
+    //函数执行失败作为工具结果的isError返回，让调用方的模型看到错误信息
+    async fn call_tool(
+        &self,
+        params: serde_json::Value,
+    ) -> Result<serde_json::Value, JsonRpcError> {
+        let name = params["name"]
+            .as_str()
+            .ok_or_else(|| rpc_error(INVALID_PARAMS, "Missing tool name"))?;
+        if !is_exposed(&self.registry, name, self.options.allow_side_effects) {
+            return Err(rpc_error(INVALID_PARAMS, format!("Unknown tool {}", name)));
+        }
+        let mut arguments = params
+            .get("arguments")
+            .cloned()
+            .unwrap_or_else(|| serde_json::json!({}));
+        let session_id = match self.options.session_id {
+            Some(session_id) => Some(session_id),
+            None => arguments
+                .as_object_mut()
+                .and_then(|x| x.remove("session_id"))
+                .and_then(|x| x.as_i64())
+                .map(|x| x as i32),
+        };
+        let result = match self.execute(name, session_id, arguments).await {
+            Ok(text) => CallToolResult::text(text, false),
+            Err(e) => CallToolResult::text(e.to_string(), true),
+        };
+        serde_json::to_value(result).map_err(|e| rpc_error(INTERNAL_ERROR, e))
+    }
+
+    async fn execute(
+        &self,
+        name: &str,
+        session_id: Option<i32>,
+        arguments: serde_json::Value,
+    ) -> Result<String> {
+        let session_id = session_id.ok_or_else(|| anyhow::anyhow!("session_id is required"))?;
+        let session = Session::find_by_id(session_id)
+            .one(&self.db)
+            .await?
+            .ok_or_else(|| anyhow::anyhow!("Session not found"))?;
+        let settings = SessionSettings::from_model(&session);
+        if !settings.is_function_enabled(name) {
+            return Err(anyhow::anyhow!(
+                "{} is disabled in session {}",
+                name,
+                session_id
+            ));
+        }
+        //有副作用的函数只有启动时指定了--allow-side-effects才会执行到这里，由调用方自己负责确认
+        let context = Context {
+            session_id,
+            chat_endpoint: settings.chat_endpoint(&self.chat_endpoint).await?,
+            embedding_endpoint: self.embedding_endpoint.clone(),
+            db: self.db.clone(),
+            confirmer: None,
+        };
+        self.registry
+            .execute_function_by_name(name, arguments, &context)
+    }
+
+    async fn list_resources(&self) -> Result<serde_json::Value, JsonRpcError> {
+        let sessions = match self.options.session_id {
+            Some(session_id) => Session::find_by_id(session_id).all(&self.db).await,
+            None => Session::find().all(&self.db).await,
+        }
+        .map_err(|e| rpc_error(INTERNAL_ERROR, e))?;
+        let mut resources = Vec::new();
+        for session in sessions {
+            resources.push(resource_entry(session.session_id, ResourceKind::Messages));
+            if tokio::fs::try_exists(session_text_path(session.session_id))
+                .await
+                .unwrap_or(false)
+            {
+                resources.push(resource_entry(session.session_id, ResourceKind::Document));
+            }
+        }
+        Ok(serde_json::json!({ "resources": resources }))
+    }
+
+    async fn read_resource(
+        &self,
+        params: serde_json::Value,
+    ) -> Result<serde_json::Value, JsonRpcError> {
+        let uri = params["uri"]
+            .as_str()
+            .ok_or_else(|| rpc_error(INVALID_PARAMS, "Missing resource uri"))?;
+        let not_found = || rpc_error(RESOURCE_NOT_FOUND, format!("Resource not found: {}", uri));
+        let (session_id, kind) = parse_resource_uri(uri).ok_or_else(not_found)?;
+        if self.options.session_id.is_some_and(|x| x != session_id) {
+            return Err(not_found());
+        }
+        let text = match kind {
+            ResourceKind::Messages => {
+                Session::find_by_id(session_id)
+                    .one(&self.db)
+                    .await
+                    .map_err(|e| rpc_error(INTERNAL_ERROR, e))?
+                    .ok_or_else(not_found)?;
+                let mut messages = Message::find()
+                    .filter(message::Column::SessionId.eq(session_id))
+                    .all(&self.db)
+                    .await
+                    .map_err(|e| rpc_error(INTERNAL_ERROR, e))?;
+                messages.sort_by_key(|x| x.create_time);
+                format_transcript(&messages)
+            }
+            ResourceKind::Document => tokio::fs::read_to_string(session_text_path(session_id))
+                .await
+                .map_err(|_| not_found())?,
+        };
+        Ok(serde_json::json!({
+            "contents": [{"uri": uri, "mimeType": "text/plain", "text": text}]
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        initialize_result, parse_resource_uri, tool_definitions, ResourceKind, ServeOptions,
+    };
+    use crate::functions::get_function_registry;
+
+    #[test]
+    fn test_initialize_result() {
+        let result = initialize_result(&serde_json::json!({"protocolVersion": "2024-11-05"}));
+        assert_eq!(result["protocolVersion"], "2024-11-05");
+        let result = initialize_result(&serde_json::json!({"protocolVersion": "1999-01-01"}));
+        assert_eq!(result["protocolVersion"], super::PROTOCOL_VERSION);
+    }
+
+    #[test]
+    fn test_tool_definitions() {
+        let registry = get_function_registry();
+        let tools = tool_definitions(&registry, true, true);
+        assert!(tools.iter().all(|x| x.name != "direct_reply"));
+        let calculator = tools.iter().find(|x| x.name == "calculator").unwrap();
+        assert_eq!(
+            calculator.input_schema["properties"]["session_id"]["type"],
+            "integer"
+        );
+        assert!(calculator.input_schema["required"]
+            .as_array()
+            .unwrap()
+            .contains(&serde_json::json!("session_id")));
+        assert_eq!(
+            calculator.annotations.as_ref().unwrap().read_only_hint,
+            Some(true)
+        );
+        let remember = tools.iter().find(|x| x.name == "remember").unwrap();
+        assert_eq!(
+            remember.annotations.as_ref().unwrap().read_only_hint,
+            Some(false)
+        );
+        let tools = tool_definitions(&registry, false, false);
+        let calculator = tools.iter().find(|x| x.name == "calculator").unwrap();
+        assert!(calculator.input_schema["properties"]
+            .get("session_id")
+            .is_none());
+        //没有允许副作用时不提供remember、forget等需要确认的函数
+        assert!(tools
+            .iter()
+            .all(|x| !registry.requires_confirmation(&x.name)));
+        assert!(tools.iter().all(|x| x.name != "remember"));
+    }
+
+    #[test]
+    fn test_serve_options() {
+        let parse = |args: &str| {
+            let args: Vec<String> = args.split_whitespace().map(|x| x.to_string()).collect();
+            ServeOptions::parse(&args)
+        };
+        assert_eq!(
+            parse("backend mcp-serve --session-id 12").unwrap(),
+            ServeOptions {
+                session_id: Some(12),
+                allow_side_effects: false
+            }
+        );
+        assert_eq!(
+            parse("backend mcp-serve --all-sessions --allow-side-effects").unwrap(),
+            ServeOptions {
+                session_id: None,
+                allow_side_effects: true
+            }
+        );
+        assert!(parse("backend mcp-serve").is_err());
+        assert!(parse("backend mcp-serve --session-id abc").is_err());
+        assert!(parse("backend mcp-serve --session-id 12 --all-sessions").is_err());
+    }
+
+    #[test]
+    fn test_parse_resource_uri() {
+        assert_eq!(
+            parse_resource_uri("session://12/messages"),
+            Some((12, ResourceKind::Messages))
+        );
+        assert_eq!(
+            parse_resource_uri("session://12/document"),
+            Some((12, ResourceKind::Document))
+        );
+        assert_eq!(parse_resource_uri("session://abc/document"), None);
+        assert_eq!(parse_resource_uri("file:///etc/passwd"), None);
+    }
+}
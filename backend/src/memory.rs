@@ -0,0 +1,217 @@
+use anyhow::Result;
+use erniebot_rs::{
+    chat::{ChatEndpoint, Message, Role},
+    embedding::EmbeddingEndpoint,
+};
+use sea_orm::*;
+use std::fs;
+use tracing::info;
+
+use crate::{
+    entities::{
+        memory,
+        prelude::{Memory, Session},
+    },
+    index::cosine_similarity,
+};
+
+//每轮对话放入提示词的记忆条数
+pub const RECALL_TOP_K: usize = 5;
+//相似度低于该值的记忆认为与当前问题无关
+const MIN_SIMILARITY: f64 = 0.5;
+
+pub async fn session_user_id(db: &DatabaseConnection, session_id: i32) -> Result<i32> {
+    let session = Session::find_by_id(session_id)
+        .one(db)
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("Session not found"))?;
+    Ok(session.user_id)
+}
+
+pub async fn list_memories(db: &DatabaseConnection, user_id: i32) -> Result<Vec<memory::Model>> {
+    let memories = Memory::find()
+        .filter(memory::Column::UserId.eq(user_id))
+        .order_by_asc(memory::Column::MemoryId)
+        .all(db)
+        .await?;
+    Ok(memories)
+}
+
+//相同内容的记忆只保存一条；向量化失败时仍然保存，只是不参与语义检索
+pub async fn remember(
+    db: &DatabaseConnection,
+    embedding_endpoint: &EmbeddingEndpoint,
+    user_id: i32,
+    source_session_id: Option<i32>,
+    content: &str,
+) -> Result<memory::Model> {
+    let content = content.trim();
+    if content.is_empty() {
+        return Err(anyhow::anyhow!("Memory content is empty"));
+    }
+    let existing = Memory::find()
+        .filter(memory::Column::UserId.eq(user_id))
+        .filter(memory::Column::Content.eq(content))
+        .one(db)
+        .await?;
+    if let Some(existing) = existing {
+        return Ok(existing);
+    }
+    let embedding = embed(embedding_endpoint, content).await.ok();
+    let memory = memory::ActiveModel {
+        user_id: Set(user_id),
+        content: Set(content.to_string()),
+        embedding: Set(embedding.map(|x| serde_json::json!(x))),
+        source_session_id: Set(source_session_id),
+        create_time: Set(chrono::Utc::now()),
+        ..Default::default()
+    };
+    Ok(memory.insert(db).await?)
+}
+
+pub async fn forget(db: &DatabaseConnection, user_id: i32, memory_ids: &[i32]) -> Result<u64> {
+    let result = Memory::delete_many()
+        .filter(memory::Column::UserId.eq(user_id))
+        .filter(memory::Column::MemoryId.is_in(memory_ids.iter().copied()))
+        .exec(db)
+        .await?;
+    Ok(result.rows_affected)
+}
+
+//按与当前消息的语义相似度取出最相关的记忆；向量化失败时取最近的记忆
+pub async fn recall(
+    db: &DatabaseConnection,
+    embedding_endpoint: &EmbeddingEndpoint,
+    user_id: i32,
+    query: &str,
+    top_k: usize,
+) -> Result<Vec<memory::Model>> {
+    let memories = list_memories(db, user_id).await?;
+    if memories.is_empty() {
+        return Ok(memories);
+    }
+    let query_embedding = match embed(embedding_endpoint, query).await {
+        Ok(query_embedding) => query_embedding,
+        Err(e) => {
+            info!("embed memory query failed: {:?}", e);
+            let skip = memories.len().saturating_sub(top_k);
+            return Ok(memories.into_iter().skip(skip).collect());
+        }
+    };
+    Ok(rank_memories(memories, &query_embedding, top_k))
+}
+
+fn rank_memories(
+    memories: Vec<memory::Model>,
+    query_embedding: &[f64],
+    top_k: usize,
+) -> Vec<memory::Model> {
+    let mut scored: Vec<(f64, memory::Model)> = memories
+        .into_iter()
+        .filter_map(|memory| {
+            let embedding: Vec<f64> = serde_json::from_value(memory.embedding.clone()?).ok()?;
+            Some((cosine_similarity(query_embedding, &embedding), memory))
+        })
+        .filter(|(score, _)| *score >= MIN_SIMILARITY)
+        .collect();
+    scored.sort_by(|a, b| b.0.total_cmp(&a.0));
+    scored.into_iter().take(top_k).map(|x| x.1).collect()
+}
+
+//放入提示词时带上编号，方便用户要求忘记某条记忆时引用
+pub fn format_memories(memories: &[memory::Model]) -> String {
+    if memories.is_empty() {
+        return "（无）".to_string();
+    }
+    memories
+        .iter()
+        .map(|x| format!("[{}] {}", x.memory_id, x.content))
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+//每轮对话结束后在后台执行，从用户的消息中提取值得长期保存的事实
+pub async fn extract_memories(
+    db: &DatabaseConnection,
+    chat_endpoint: &ChatEndpoint,
+    embedding_endpoint: &EmbeddingEndpoint,
+    session_id: i32,
+    message: &str,
+) -> Result<Vec<memory::Model>> {
+    let user_id = session_user_id(db, session_id).await?;
+    let known = recall(db, embedding_endpoint, user_id, message, RECALL_TOP_K).await?;
+    let template = fs::read_to_string("templates/memory_extract.template")?;
+    let request = template
+        .replace("{{message}}", message)
+        .replace("{{memories}}", &format_memories(&known));
+    let messages = vec![Message {
+        role: Role::User,
+        content: request,
+        ..Default::default()
+    }];
+    let response = chat_endpoint.ainvoke(&messages, &Vec::new()).await?;
+    let facts = parse_extracted(&response.get_chat_result()?);
+    let mut memories = Vec::with_capacity(facts.len());
+    for fact in facts {
+        memories.push(remember(db, embedding_endpoint, user_id, Some(session_id), &fact).await?);
+    }
+    Ok(memories)
+}
+
+//模型可能在json数组前后加上说明或代码块标记，只取第一个 '[' 到最后一个 ']' 之间的内容
+fn parse_extracted(response: &str) -> Vec<String> {
+    let (start, end) = match (response.find('['), response.rfind(']')) {
+        (Some(start), Some(end)) if start < end => (start, end),
+        _ => return Vec::new(),
+    };
+    serde_json::from_str::<Vec<String>>(&response[start..=end])
+        .unwrap_or_default()
+        .into_iter()
+        .map(|x| x.trim().to_string())
+        .filter(|x| !x.is_empty())
+        .collect()
+}
+
+async fn embed(embedding_endpoint: &EmbeddingEndpoint, text: &str) -> Result<Vec<f64>> {
+    let response = embedding_endpoint
+        .ainvoke(&vec![text.to_string()], None)
+        .await?;
+    response
+        .get_embedding_results()?
+        .pop()
+        .ok_or_else(|| anyhow::anyhow!("Empty embedding result"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_extracted, rank_memories};
+    use crate::entities::memory;
+
+    #[test]
+    fn test_parse_extracted() {
+        let response = "好的：\n```json\n[\"用户希望用英文回答\", \" \"]\n```";
+        assert_eq!(parse_extracted(response), vec!["用户希望用英文回答"]);
+        assert!(parse_extracted("没有需要记住的内容").is_empty());
+    }
+
+    #[test]
+    fn test_rank_memories() {
+        let make = |memory_id, embedding: Option<Vec<f64>>| memory::Model {
+            memory_id,
+            user_id: 1,
+            content: format!("memory {}", memory_id),
+            embedding: embedding.map(|x| serde_json::json!(x)),
+            source_session_id: None,
+            create_time: chrono::Utc::now(),
+        };
+        let memories = vec![
+            make(1, Some(vec![0.0, 1.0])),
+            make(2, Some(vec![1.0, 0.1])),
+            make(3, None),
+            make(4, Some(vec![1.0, 0.0])),
+        ];
+        let ranked = rank_memories(memories, &[1.0, 0.0], 5);
+        let ids: Vec<i32> = ranked.iter().map(|x| x.memory_id).collect();
+        assert_eq!(ids, vec![4, 2]);
+    }
+}
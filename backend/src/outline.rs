@@ -0,0 +1,152 @@
+use serde::{Deserialize, Serialize};
+
+use crate::chunker::{heading_level, heading_title};
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct OutlineNode {
+    pub title: String,
+    pub level: usize,
+    //标题所在的页码(从1开始)，pdf按页、pptx按幻灯片计算
+    pub page: usize,
+    //章节在原文中的字节范围 [start, end)，包含标题行和所有子章节
+    #[serde(skip)]
+    pub start: usize,
+    #[serde(skip)]
+    pub end: usize,
+    pub children: Vec<OutlineNode>,
+}
+
+//pdftotext用换页符分隔每一页，pptx解析结果以 "## Slide N" 开始每一页；都没有时全文视为一页
+pub fn page_ranges(text: &str) -> Vec<(usize, usize)> {
+    let mut starts = vec![0];
+    if text.contains('\x0c') {
+        //pdftotext在最后一页之后也会输出换页符，其后没有内容时不算新的一页
+        starts.extend(
+            text.match_indices('\x0c')
+                .map(|(index, _)| index + 1)
+                .filter(|index| !text[*index..].trim().is_empty()),
+        );
+    } else {
+        let mut offset = 0;
+        for line in text.split_inclusive('\n') {
+            if line.starts_with("## Slide ") && offset > 0 {
+                starts.push(offset);
+            }
+            offset += line.len();
+        }
+    }
+    let mut ranges = Vec::with_capacity(starts.len());
+    for (index, start) in starts.iter().enumerate() {
+        let end = starts.get(index + 1).copied().unwrap_or(text.len());
+        ranges.push((*start, end));
+    }
+    ranges
+}
+
+pub fn page_of(pages: &[(usize, usize)], offset: usize) -> usize {
+    pages
+        .iter()
+        .position(|(start, end)| offset >= *start && offset < *end)
+        .unwrap_or(pages.len().saturating_sub(1))
+        + 1
+}
+
+pub fn build_outline(text: &str) -> Vec<OutlineNode> {
+    let pages = page_ranges(text);
+    let mut flat: Vec<OutlineNode> = Vec::new();
+    let mut offset = 0;
+    for line in text.split_inclusive('\n') {
+        let content = line.trim_start_matches('\x0c');
+        if let Some(level) = heading_level(content) {
+            let start = offset + line.len() - content.len();
+            flat.push(OutlineNode {
+                title: heading_title(content),
+                level,
+                page: page_of(&pages, start),
+                start,
+                end: text.len(),
+                children: Vec::new(),
+            });
+        }
+        offset += line.len();
+    }
+    //章节在下一个同级或更高级标题处结束
+    for index in 0..flat.len() {
+        let level = flat[index].level;
+        if let Some(next) = flat[index + 1..].iter().find(|x| x.level <= level) {
+            flat[index].end = next.start;
+        }
+    }
+
+    let mut roots = Vec::new();
+    let mut stack: Vec<OutlineNode> = Vec::new();
+    for node in flat {
+        while stack.last().is_some_and(|x| x.level >= node.level) {
+            let finished = stack.pop().unwrap();
+            attach(&mut stack, &mut roots, finished);
+        }
+        stack.push(node);
+    }
+    while let Some(finished) = stack.pop() {
+        attach(&mut stack, &mut roots, finished);
+    }
+    roots
+}
+
+fn attach(stack: &mut [OutlineNode], roots: &mut Vec<OutlineNode>, node: OutlineNode) {
+    match stack.last_mut() {
+        Some(parent) => parent.children.push(node),
+        None => roots.push(node),
+    }
+}
+
+//优先完全匹配标题，其次匹配包含查询内容的第一个标题，忽略大小写
+pub fn find_section<'a>(nodes: &'a [OutlineNode], query: &str) -> Option<&'a OutlineNode> {
+    let query = query.trim().to_lowercase();
+    let mut flat = Vec::new();
+    flatten(nodes, &mut flat);
+    flat.iter()
+        .find(|x| x.title.to_lowercase() == query)
+        .or_else(|| {
+            flat.iter()
+                .find(|x| x.title.to_lowercase().contains(&query))
+        })
+        .copied()
+}
+
+fn flatten<'a>(nodes: &'a [OutlineNode], flat: &mut Vec<&'a OutlineNode>) {
+    for node in nodes {
+        flat.push(node);
+        flatten(&node.children, flat);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{build_outline, find_section, page_ranges};
+
+    #[test]
+    fn test_page_ranges_trailing_form_feed() {
+        let text = "第一页\n\x0c第二页\n\x0c";
+        assert_eq!(page_ranges(text), vec![(0, 11), (11, text.len())]);
+        assert_eq!(page_ranges("没有换页符").len(), 1);
+    }
+
+    #[test]
+    fn test_build_outline() {
+        let text = "# 引言\n背景\n## 1.1 动机\n内容\n\x0c# 方法\n## 2.1 数据\n数据说明\n";
+        let outline = build_outline(text);
+        assert_eq!(outline.len(), 2);
+        assert_eq!(outline[0].title, "引言");
+        assert_eq!(outline[0].children[0].title, "1.1 动机");
+        assert_eq!(outline[1].page, 2);
+        let section = find_section(&outline, "方法").unwrap();
+        assert_eq!(
+            &text[section.start..section.end],
+            "# 方法\n## 2.1 数据\n数据说明\n"
+        );
+        let section = find_section(&outline, "动机").unwrap();
+        assert_eq!(&text[section.start..section.end], "## 1.1 动机\n内容\n\x0c");
+        assert_eq!(page_ranges(text).len(), 2);
+    }
+}
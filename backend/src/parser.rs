@@ -1,6 +1,58 @@
-use std::{path::Path, process::Command};
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{Read, Seek},
+    path::Path,
+    process::Command,
+};
 
 use anyhow::Result;
+use quick_xml::{events::Event, name::ResolveResult, NsReader, Reader};
+use zip::ZipArchive;
+
+//pptx中单个xml文件解压后的字节数上限，防止压缩炸弹耗尽内存
+const MAX_ZIP_ENTRY_SIZE: u64 = 32 * 1024 * 1024;
+//所有读取的xml文件解压后合计的字节数上限，很多页或者重复引用同一页时单个文件的上限不够
+const MAX_TOTAL_DECOMPRESSED_SIZE: u64 = 64 * 1024 * 1024;
+
+//元素和属性按命名空间匹配，前缀可以任意绑定；过渡(Transitional)和严格(Strict)格式的命名空间不同
+const DRAWINGML_NAMESPACES: [&[u8]; 2] = [
+    b"http://schemas.openxmlformats.org/drawingml/2006/main",
+    b"http://purl.oclc.org/ooxml/drawingml/main",
+];
+const PRESENTATIONML_NAMESPACES: [&[u8]; 2] = [
+    b"http://schemas.openxmlformats.org/presentationml/2006/main",
+    b"http://purl.oclc.org/ooxml/presentationml/main",
+];
+const RELATIONSHIPS_NAMESPACES: [&[u8]; 2] = [
+    b"http://schemas.openxmlformats.org/officeDocument/2006/relationships",
+    b"http://purl.oclc.org/ooxml/officeDocument/relationships",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum XmlNamespace {
+    DrawingMl,
+    PresentationMl,
+    Relationships,
+    Other,
+}
+
+impl XmlNamespace {
+    fn of(result: &ResolveResult) -> Self {
+        match result {
+            ResolveResult::Bound(namespace) if DRAWINGML_NAMESPACES.contains(&namespace.0) => {
+                Self::DrawingMl
+            }
+            ResolveResult::Bound(namespace) if PRESENTATIONML_NAMESPACES.contains(&namespace.0) => {
+                Self::PresentationMl
+            }
+            ResolveResult::Bound(namespace) if RELATIONSHIPS_NAMESPACES.contains(&namespace.0) => {
+                Self::Relationships
+            }
+            _ => Self::Other,
+        }
+    }
+}
 
 pub fn parse_file(path: &str) -> Result<String> {
     let path_obj = Path::new(path);
@@ -16,6 +68,10 @@ pub fn parse_file(path: &str) -> Result<String> {
             let output = Command::new("pdftotext").arg(path).arg("-").output()?;
             Ok(String::from_utf8_lossy(&output.stdout).to_string())
         }
+        "pptx" => {
+            let file = File::open(path)?;
+            parse_pptx(file)
+        }
         "txt" | "md" => {
             let output = std::fs::read_to_string(path)?;
             Ok(output)
@@ -23,3 +79,405 @@ pub fn parse_file(path: &str) -> Result<String> {
         _ => Err(anyhow::anyhow!("Unsupported file format")),
     }
 }
+
+#[derive(Debug, Default, PartialEq)]
+struct Slide {
+    title: String,
+    body: Vec<String>,
+    notes: Vec<String>,
+}
+
+//按照演示文稿中的顺序逐页输出标题、正文和备注，每页以 "## Slide N" 开头
+fn parse_pptx<R: Read + Seek>(reader: R) -> Result<String> {
+    let mut archive = PptxArchive::new(reader)?;
+    let presentation = archive.read("ppt/presentation.xml")?;
+    let presentation_rels = archive.read("ppt/_rels/presentation.xml.rels")?;
+    let rels = parse_relationships(&presentation_rels)?;
+    let slide_ids = parse_slide_id_list(&presentation)?;
+
+    let mut output = String::new();
+    for (index, rel_id) in slide_ids.iter().enumerate() {
+        let target = match rels.get(rel_id) {
+            Some((_, target)) => resolve_target("ppt", target),
+            None => continue,
+        };
+        let slide_xml = archive.read(&target)?;
+        let mut slide = Slide::default();
+        for shape in parse_shapes(&slide_xml)? {
+            match shape.placeholder.as_deref() {
+                Some("title") | Some("ctrTitle") if slide.title.is_empty() => {
+                    slide.title = shape.paragraphs.join(" ");
+                }
+                Some("sldNum") | Some("dt") | Some("ftr") => {}
+                _ => slide.body.extend(shape.paragraphs),
+            }
+        }
+        if let Some(notes_target) = find_notes_target(&mut archive, &target)? {
+            let notes_xml = archive.read(&notes_target)?;
+            for shape in parse_shapes(&notes_xml)? {
+                if shape.placeholder.as_deref() == Some("body") {
+                    slide.notes.extend(shape.paragraphs);
+                }
+            }
+        }
+        output.push_str(&format_slide(index + 1, &slide));
+    }
+    Ok(output)
+}
+
+fn format_slide(number: usize, slide: &Slide) -> String {
+    let mut text = if slide.title.is_empty() {
+        format!("## Slide {}\n", number)
+    } else {
+        format!("## Slide {}: {}\n", number, slide.title)
+    };
+    for line in slide.body.iter() {
+        text.push_str(line);
+        text.push('\n');
+    }
+    if !slide.notes.is_empty() {
+        text.push_str("Notes:\n");
+        for line in slide.notes.iter() {
+            text.push_str(line);
+            text.push('\n');
+        }
+    }
+    text.push('\n');
+    text
+}
+
+//记录已经解压的字节数，每次读取都计入合计的上限
+struct PptxArchive<R> {
+    archive: ZipArchive<R>,
+    remaining: u64,
+}
+
+impl<R: Read + Seek> PptxArchive<R> {
+    fn new(reader: R) -> Result<Self> {
+        Ok(Self {
+            archive: ZipArchive::new(reader)?,
+            remaining: MAX_TOTAL_DECOMPRESSED_SIZE,
+        })
+    }
+
+    fn contains(&mut self, name: &str) -> bool {
+        self.archive.by_name(name).is_ok()
+    }
+
+    fn read(&mut self, name: &str) -> Result<String> {
+        let entry = self.archive.by_name(name)?;
+        let limit = MAX_ZIP_ENTRY_SIZE.min(self.remaining);
+        let mut content = String::new();
+        //多读一个字节用来判断是否超出上限，不依赖zip中声明的大小
+        entry.take(limit + 1).read_to_string(&mut content)?;
+        if content.len() as u64 > MAX_ZIP_ENTRY_SIZE {
+            return Err(anyhow::anyhow!(
+                "{} exceeds {} bytes after decompression",
+                name,
+                MAX_ZIP_ENTRY_SIZE
+            ));
+        }
+        if content.len() as u64 > self.remaining {
+            return Err(anyhow::anyhow!(
+                "presentation exceeds {} bytes after decompression",
+                MAX_TOTAL_DECOMPRESSED_SIZE
+            ));
+        }
+        self.remaining -= content.len() as u64;
+        Ok(content)
+    }
+}
+
+//rels中的Target是相对于所在目录的路径，如 "slides/slide1.xml" 或 "../notesSlides/notesSlide1.xml"
+fn resolve_target(base_dir: &str, target: &str) -> String {
+    if let Some(absolute) = target.strip_prefix('/') {
+        return absolute.to_string();
+    }
+    let mut parts: Vec<&str> = base_dir.split('/').filter(|x| !x.is_empty()).collect();
+    for part in target.split('/') {
+        match part {
+            ".." => {
+                parts.pop();
+            }
+            "." | "" => {}
+            _ => parts.push(part),
+        }
+    }
+    parts.join("/")
+}
+
+fn find_notes_target<R: Read + Seek>(
+    archive: &mut PptxArchive<R>,
+    slide_path: &str,
+) -> Result<Option<String>> {
+    let (slide_dir, slide_file) = slide_path.rsplit_once('/').unwrap_or(("", slide_path));
+    let rels_path = format!("{}/_rels/{}.rels", slide_dir, slide_file);
+    //没有rels的页没有备注，超出大小上限仍然报错
+    if !archive.contains(&rels_path) {
+        return Ok(None);
+    }
+    let rels_xml = archive.read(&rels_path)?;
+    let notes = parse_relationships(&rels_xml)?
+        .into_values()
+        .find(|(rel_type, _)| rel_type.ends_with("/notesSlide"))
+        .map(|(_, target)| resolve_target(slide_dir, &target));
+    Ok(notes)
+}
+
+//返回 Id -> (Type, Target)
+fn parse_relationships(xml: &str) -> Result<HashMap<String, (String, String)>> {
+    let mut reader = Reader::from_str(xml);
+    let mut rels = HashMap::new();
+    loop {
+        match reader.read_event()? {
+            Event::Start(e) | Event::Empty(e) if e.local_name().as_ref() == b"Relationship" => {
+                let mut id = String::new();
+                let mut rel_type = String::new();
+                let mut target = String::new();
+                for attr in e.attributes() {
+                    let attr = attr?;
+                    let value = attr.unescape_value()?.to_string();
+                    match attr.key.local_name().as_ref() {
+                        b"Id" => id = value,
+                        b"Type" => rel_type = value,
+                        b"Target" => target = value,
+                        _ => {}
+                    }
+                }
+                rels.insert(id, (rel_type, target));
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    Ok(rels)
+}
+
+fn parse_slide_id_list(xml: &str) -> Result<Vec<String>> {
+    let mut reader = NsReader::from_str(xml);
+    let mut ids = Vec::new();
+    loop {
+        let (namespace, event) = reader.read_resolved_event()?;
+        let namespace = XmlNamespace::of(&namespace);
+        match event {
+            Event::Start(e) | Event::Empty(e)
+                if namespace == XmlNamespace::PresentationMl
+                    && e.local_name().as_ref() == b"sldId" =>
+            {
+                for attr in e.attributes() {
+                    let attr = attr?;
+                    let (attr_namespace, local_name) = reader.resolve_attribute(attr.key);
+                    if XmlNamespace::of(&attr_namespace) == XmlNamespace::Relationships
+                        && local_name.as_ref() == b"id"
+                    {
+                        ids.push(attr.unescape_value()?.to_string());
+                    }
+                }
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    Ok(ids)
+}
+
+#[derive(Debug, Default)]
+struct Shape {
+    placeholder: Option<String>,
+    paragraphs: Vec<String>,
+}
+
+//提取每个形状(p:sp)的占位符类型和段落文本(a:p)，表格等图形框(p:graphicFrame)中的文本也作为正文
+fn parse_shapes(xml: &str) -> Result<Vec<Shape>> {
+    let mut reader = NsReader::from_str(xml);
+    let mut shapes = Vec::new();
+    let mut current: Option<Shape> = None;
+    let mut paragraph: Option<String> = None;
+    let mut in_text = false;
+    loop {
+        let (namespace, event) = reader.read_resolved_event()?;
+        let namespace = XmlNamespace::of(&namespace);
+        match (namespace, event) {
+            (XmlNamespace::PresentationMl, Event::Start(e)) => match e.local_name().as_ref() {
+                b"sp" | b"graphicFrame" => current = Some(Shape::default()),
+                b"ph" => set_placeholder(&mut current, &e)?,
+                _ => {}
+            },
+            (XmlNamespace::DrawingMl, Event::Start(e)) => match e.local_name().as_ref() {
+                b"p" if current.is_some() => paragraph = Some(String::new()),
+                b"t" => in_text = true,
+                _ => {}
+            },
+            (XmlNamespace::PresentationMl, Event::Empty(e)) if e.local_name().as_ref() == b"ph" => {
+                set_placeholder(&mut current, &e)?
+            }
+            (XmlNamespace::DrawingMl, Event::Empty(e)) if e.local_name().as_ref() == b"br" => {
+                if let Some(paragraph) = paragraph.as_mut() {
+                    paragraph.push(' ');
+                }
+            }
+            (_, Event::Text(t)) if in_text => {
+                if let Some(paragraph) = paragraph.as_mut() {
+                    paragraph.push_str(&t.unescape()?);
+                }
+            }
+            (XmlNamespace::DrawingMl, Event::End(e)) => match e.local_name().as_ref() {
+                b"t" => in_text = false,
+                b"p" => {
+                    if let (Some(shape), Some(text)) = (current.as_mut(), paragraph.take()) {
+                        let text = text.trim();
+                        if !text.is_empty() {
+                            shape.paragraphs.push(text.to_string());
+                        }
+                    }
+                }
+                _ => {}
+            },
+            (XmlNamespace::PresentationMl, Event::End(e))
+                if matches!(e.local_name().as_ref(), b"sp" | b"graphicFrame") =>
+            {
+                if let Some(shape) = current.take() {
+                    shapes.push(shape);
+                }
+            }
+            (_, Event::Eof) => break,
+            _ => {}
+        }
+    }
+    Ok(shapes)
+}
+
+fn set_placeholder(
+    current: &mut Option<Shape>,
+    e: &quick_xml::events::BytesStart<'_>,
+) -> Result<()> {
+    if let Some(shape) = current.as_mut() {
+        //没有type属性的占位符默认为正文(body)
+        let mut placeholder = "body".to_string();
+        for attr in e.attributes() {
+            let attr = attr?;
+            if attr.key.local_name().as_ref() == b"type" {
+                placeholder = attr.unescape_value()?.to_string();
+            }
+        }
+        shape.placeholder = Some(placeholder);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Write};
+    use zip::{write::FileOptions, ZipWriter};
+
+    fn write_pptx(files: &[(&str, String)]) -> Cursor<Vec<u8>> {
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        for (name, content) in files.iter() {
+            writer.start_file(*name, FileOptions::default()).unwrap();
+            writer.write_all(content.as_bytes()).unwrap();
+        }
+        writer.finish().unwrap()
+    }
+
+    const SLIDE_RELS: &str = r#"<Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships"><Relationship Id="rId2" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/slide" Target="slides/slide1.xml"/></Relationships>"#;
+
+    fn slide_xml(title: &str, body: &str) -> String {
+        format!(
+            r#"<p:sld xmlns:a="http://schemas.openxmlformats.org/drawingml/2006/main" xmlns:p="http://schemas.openxmlformats.org/presentationml/2006/main"><p:cSld><p:spTree>
+<p:sp><p:nvSpPr><p:nvPr><p:ph type="title"/></p:nvPr></p:nvSpPr><p:txBody><a:p><a:r><a:t>{}</a:t></a:r></a:p></p:txBody></p:sp>
+<p:sp><p:nvSpPr><p:nvPr><p:ph idx="1"/></p:nvPr></p:nvSpPr><p:txBody><a:p><a:r><a:t>{}</a:t></a:r></a:p></p:txBody></p:sp>
+</p:spTree></p:cSld></p:sld>"#,
+            title, body
+        )
+    }
+
+    #[test]
+    fn test_parse_pptx() {
+        let files = [
+            (
+                "ppt/presentation.xml",
+                r#"<p:presentation xmlns:p="http://schemas.openxmlformats.org/presentationml/2006/main" xmlns:r="http://schemas.openxmlformats.org/officeDocument/2006/relationships"><p:sldIdLst><p:sldId id="256" r:id="rId3"/><p:sldId id="257" r:id="rId2"/></p:sldIdLst></p:presentation>"#.to_string(),
+            ),
+            (
+                "ppt/_rels/presentation.xml.rels",
+                r#"<Relationships><Relationship Id="rId2" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/slide" Target="slides/slide1.xml"/><Relationship Id="rId3" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/slide" Target="slides/slide2.xml"/></Relationships>"#.to_string(),
+            ),
+            ("ppt/slides/slide1.xml", slide_xml("Second", "world")),
+            ("ppt/slides/slide2.xml", slide_xml("First", "hello &amp; welcome")),
+            (
+                "ppt/slides/_rels/slide2.xml.rels",
+                r#"<Relationships><Relationship Id="rId1" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/notesSlide" Target="../notesSlides/notesSlide1.xml"/></Relationships>"#.to_string(),
+            ),
+            (
+                "ppt/notesSlides/notesSlide1.xml",
+                r#"<p:notes xmlns:a="http://schemas.openxmlformats.org/drawingml/2006/main" xmlns:p="http://schemas.openxmlformats.org/presentationml/2006/main"><p:cSld><p:spTree><p:sp><p:nvSpPr><p:nvPr><p:ph type="body" idx="1"/></p:nvPr></p:nvSpPr><p:txBody><a:p><a:r><a:t>remember to smile</a:t></a:r></a:p></p:txBody></p:sp><p:sp><p:nvSpPr><p:nvPr><p:ph type="sldNum" idx="5"/></p:nvPr></p:nvSpPr><p:txBody><a:p><a:r><a:t>1</a:t></a:r></a:p></p:txBody></p:sp></p:spTree></p:cSld></p:notes>"#.to_string(),
+            ),
+        ];
+        let text = super::parse_pptx(write_pptx(&files)).unwrap();
+        assert_eq!(
+            text,
+            "## Slide 1: First\nhello & welcome\nNotes:\nremember to smile\n\n## Slide 2: Second\nworld\n\n"
+        );
+    }
+
+    //命名空间绑定到其它前缀或作为默认命名空间时同样能提取文本，其它命名空间中的同名元素被忽略
+    #[test]
+    fn test_parse_pptx_custom_prefixes() {
+        let files = [
+            (
+                "ppt/presentation.xml",
+                r#"<presentation xmlns="http://schemas.openxmlformats.org/presentationml/2006/main" xmlns:rel="http://schemas.openxmlformats.org/officeDocument/2006/relationships"><sldIdLst><sldId id="256" rel:id="rId2"/></sldIdLst></presentation>"#.to_string(),
+            ),
+            ("ppt/_rels/presentation.xml.rels", SLIDE_RELS.to_string()),
+            (
+                "ppt/slides/slide1.xml",
+                r#"<sld xmlns="http://schemas.openxmlformats.org/presentationml/2006/main" xmlns:dml="http://schemas.openxmlformats.org/drawingml/2006/main" xmlns:x="urn:other"><cSld><spTree>
+<sp><nvSpPr><nvPr><ph type="title"/></nvPr></nvSpPr><txBody><dml:p><dml:r><dml:t>Title</dml:t></dml:r></dml:p></txBody></sp>
+<sp><txBody><dml:p><dml:r><dml:t>line one</dml:t></dml:r><dml:br/><dml:r><dml:t>line two</dml:t></dml:r></dml:p><x:p><x:t>ignored</x:t></x:p></txBody></sp>
+</spTree></cSld></sld>"#.to_string(),
+            ),
+        ];
+        let text = super::parse_pptx(write_pptx(&files)).unwrap();
+        assert_eq!(text, "## Slide 1: Title\nline one line two\n\n");
+    }
+
+    #[test]
+    fn test_parse_pptx_rejects_oversized_entry() {
+        let presentation = format!(
+            r#"<p:presentation xmlns:p="http://schemas.openxmlformats.org/presentationml/2006/main">{}</p:presentation>"#,
+            " ".repeat(super::MAX_ZIP_ENTRY_SIZE as usize)
+        );
+        let files = [
+            ("ppt/presentation.xml", presentation),
+            ("ppt/_rels/presentation.xml.rels", SLIDE_RELS.to_string()),
+        ];
+        let error = super::parse_pptx(write_pptx(&files)).unwrap_err();
+        assert!(error.to_string().contains("exceeds"), "{}", error);
+    }
+
+    //每页都在单个文件的上限内，但presentation.xml重复引用同一页，合计超出上限
+    #[test]
+    fn test_parse_pptx_rejects_repeated_slides() {
+        let repeats = (super::MAX_TOTAL_DECOMPRESSED_SIZE / super::MAX_ZIP_ENTRY_SIZE + 1) as usize;
+        let presentation = format!(
+            r#"<p:presentation xmlns:p="http://schemas.openxmlformats.org/presentationml/2006/main" xmlns:r="http://schemas.openxmlformats.org/officeDocument/2006/relationships"><p:sldIdLst>{}</p:sldIdLst></p:presentation>"#,
+            r#"<p:sldId id="256" r:id="rId2"/>"#.repeat(repeats)
+        );
+        let slide = slide_xml("Repeated", "slide");
+        let padding = super::MAX_ZIP_ENTRY_SIZE as usize - slide.len() - 1024;
+        let files = [
+            ("ppt/presentation.xml", presentation),
+            ("ppt/_rels/presentation.xml.rels", SLIDE_RELS.to_string()),
+            (
+                "ppt/slides/slide1.xml",
+                format!("{}{}", slide, " ".repeat(padding)),
+            ),
+        ];
+        let error = super::parse_pptx(write_pptx(&files)).unwrap_err();
+        assert!(
+            error.to_string().starts_with("presentation exceeds"),
+            "{}",
+            error
+        );
+    }
+}
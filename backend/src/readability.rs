@@ -0,0 +1,292 @@
+use lazy_static::lazy_static;
+use regex::Regex;
+
+lazy_static! {
+    //内容不是正文的元素连同内容一起删除
+    static ref INVISIBLE: Regex = Regex::new(
+        r"(?is)<!--.*?-->|<(script|style|noscript|template|svg|iframe|canvas|select)\b.*?</(?:script|style|noscript|template|svg|iframe|canvas|select)\s*>"
+    )
+    .unwrap();
+    static ref TITLE: Regex = Regex::new(r"(?is)<title\b[^>]*>(.*?)</title\s*>").unwrap();
+    static ref TAG: Regex = Regex::new(r"(?s)<(/?)([a-zA-Z][a-zA-Z0-9]*)\b[^>]*?(/?)>").unwrap();
+    static ref ENTITY: Regex = Regex::new(r"&(#[0-9]+|#[xX][0-9a-fA-F]+|[a-zA-Z]+);").unwrap();
+    static ref SPACES: Regex = Regex::new(r"\s+").unwrap();
+    static ref META_CHARSET: Regex =
+        Regex::new(r#"(?i)<meta\b[^>]*charset\s*=\s*["']?([a-zA-Z0-9_-]+)"#).unwrap();
+}
+
+//导航、页眉页脚、侧栏、表单等不属于正文，其中的文本全部丢弃
+const BOILERPLATE_TAGS: [&str; 7] = ["nav", "header", "footer", "aside", "form", "menu", "dialog"];
+const BLOCK_TAGS: [&str; 24] = [
+    "p",
+    "div",
+    "section",
+    "article",
+    "main",
+    "li",
+    "ul",
+    "ol",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "blockquote",
+    "pre",
+    "table",
+    "tr",
+    "td",
+    "th",
+    "dd",
+    "dt",
+    "figcaption",
+    "br",
+];
+//正文段落中链接文字的比例超过该值时视为导航或推荐列表
+const MAX_LINK_DENSITY: f64 = 0.5;
+//没有article、main时，短于该字数且不是标题、列表项的段落视为噪音
+const MIN_PARAGRAPH_CHARS: usize = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum BlockKind {
+    Heading(usize),
+    ListItem,
+    Paragraph,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Block {
+    kind: BlockKind,
+    text: String,
+    link_chars: usize,
+    //是否位于article或main元素中
+    in_main: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Article {
+    pub title: Option<String>,
+    pub text: String,
+}
+
+fn decode_entity(entity: &str) -> Option<String> {
+    let decoded = match entity {
+        "amp" => '&',
+        "lt" => '<',
+        "gt" => '>',
+        "quot" => '"',
+        "apos" => '\'',
+        "nbsp" | "ensp" | "emsp" | "thinsp" => ' ',
+        "mdash" => '—',
+        "ndash" => '–',
+        "hellip" => '…',
+        "middot" => '·',
+        "copy" => '©',
+        "reg" => '®',
+        "ldquo" => '“',
+        "rdquo" => '”',
+        "lsquo" => '‘',
+        "rsquo" => '’',
+        entity if entity.starts_with("#x") || entity.starts_with("#X") => {
+            char::from_u32(u32::from_str_radix(&entity[2..], 16).ok()?)?
+        }
+        entity if entity.starts_with('#') => char::from_u32(entity[1..].parse().ok()?)?,
+        _ => return None,
+    };
+    Some(decoded.to_string())
+}
+
+pub fn decode_entities(text: &str) -> String {
+    ENTITY
+        .replace_all(text, |captures: &regex::Captures| {
+            decode_entity(&captures[1]).unwrap_or(captures[0].to_string())
+        })
+        .to_string()
+}
+
+//<meta charset="gbk"> 或 <meta http-equiv="Content-Type" content="text/html; charset=gbk">
+pub fn meta_charset(html: &[u8]) -> Option<String> {
+    let head = String::from_utf8_lossy(&html[..html.len().min(4096)]);
+    META_CHARSET.captures(&head).map(|x| x[1].to_lowercase())
+}
+
+struct Extractor {
+    blocks: Vec<Block>,
+    text: String,
+    link_chars: usize,
+    kind: BlockKind,
+    boilerplate_depth: usize,
+    link_depth: usize,
+    main_depth: usize,
+    pre_depth: usize,
+}
+
+impl Extractor {
+    fn flush(&mut self) {
+        let text = match self.pre_depth {
+            0 => SPACES.replace_all(self.text.trim(), " ").to_string(),
+            _ => self.text.trim_matches('\n').to_string(),
+        };
+        if !text.trim().is_empty() {
+            self.blocks.push(Block {
+                kind: self.kind,
+                text,
+                link_chars: self.link_chars,
+                in_main: self.main_depth > 0,
+            });
+        }
+        self.text.clear();
+        self.link_chars = 0;
+        self.kind = BlockKind::Paragraph;
+    }
+
+    fn push_text(&mut self, text: &str) {
+        if self.boilerplate_depth > 0 {
+            return;
+        }
+        let text = decode_entities(text);
+        if self.link_depth > 0 {
+            self.link_chars += text.trim().chars().count();
+        }
+        self.text.push_str(&text);
+    }
+
+    fn tag(&mut self, name: &str, closing: bool, self_closing: bool) {
+        let depth_change = |depth: &mut usize| match closing {
+            true => *depth = depth.saturating_sub(1),
+            false if !self_closing => *depth += 1,
+            false => {}
+        };
+        if BOILERPLATE_TAGS.contains(&name) {
+            depth_change(&mut self.boilerplate_depth);
+            return;
+        }
+        if self.boilerplate_depth > 0 {
+            return;
+        }
+        if BLOCK_TAGS.contains(&name) {
+            self.flush();
+        }
+        match name {
+            "a" => depth_change(&mut self.link_depth),
+            "article" | "main" => depth_change(&mut self.main_depth),
+            "pre" => depth_change(&mut self.pre_depth),
+            "li" | "dd" | "dt" if !closing => self.kind = BlockKind::ListItem,
+            "h1" | "h2" | "h3" | "h4" | "h5" | "h6" if !closing => {
+                self.kind = BlockKind::Heading(name[1..].parse().unwrap_or(1))
+            }
+            "td" | "th" if !closing => self.text.push('\t'),
+            _ => {}
+        }
+    }
+}
+
+fn is_content(block: &Block) -> bool {
+    let chars = block.text.chars().count().max(1);
+    block.link_chars as f64 / chars as f64 <= MAX_LINK_DENSITY
+}
+
+//提取网页的标题和正文：删除脚本、样式和导航等模板内容，有article或main元素时只取其中的内容，
+//再去掉链接密度过高的段落；标题输出为markdown标题，列表项以 "- " 开头
+pub fn extract(html: &str) -> Article {
+    let html = INVISIBLE.replace_all(html, " ");
+    let title = TITLE
+        .captures(&html)
+        .map(|x| {
+            SPACES
+                .replace_all(decode_entities(&x[1]).trim(), " ")
+                .to_string()
+        })
+        .filter(|x| !x.is_empty());
+    let body_start = html.find("<body").unwrap_or(0);
+    let html = &html[body_start..];
+    let mut extractor = Extractor {
+        blocks: Vec::new(),
+        text: String::new(),
+        link_chars: 0,
+        kind: BlockKind::Paragraph,
+        boilerplate_depth: 0,
+        link_depth: 0,
+        main_depth: 0,
+        pre_depth: 0,
+    };
+    let mut position = 0;
+    for captures in TAG.captures_iter(html) {
+        let whole = captures.get(0).unwrap();
+        extractor.push_text(&html[position..whole.start()]);
+        position = whole.end();
+        let name = captures[2].to_lowercase();
+        extractor.tag(&name, &captures[1] == "/", &captures[3] == "/");
+    }
+    extractor.push_text(&html[position..]);
+    extractor.flush();
+
+    let has_main = extractor.blocks.iter().any(|x| x.in_main);
+    let blocks: Vec<&Block> = extractor
+        .blocks
+        .iter()
+        .filter(|x| !has_main || x.in_main)
+        .filter(|x| is_content(x))
+        .filter(|x| {
+            has_main
+                || x.kind != BlockKind::Paragraph
+                || x.text.chars().count() >= MIN_PARAGRAPH_CHARS
+        })
+        .collect();
+    let lines: Vec<String> = blocks
+        .iter()
+        .map(|block| match block.kind {
+            BlockKind::Heading(level) => format!("{} {}", "#".repeat(level), block.text),
+            BlockKind::ListItem => format!("- {}", block.text),
+            BlockKind::Paragraph => block.text.clone(),
+        })
+        .collect();
+    Article {
+        title,
+        text: lines.join("\n\n"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode_entities, extract, meta_charset};
+
+    #[test]
+    fn test_extract() {
+        let html = r#"<html><head><title>新闻 &amp; 评论</title>
+            <meta charset="GBK"><style>p { color: red }</style></head>
+            <body>
+            <nav><a href="/">首页</a><a href="/news">新闻</a></nav>
+            <div class="ad"><a href="/x">广告链接文字很长很长很长</a></div>
+            <article>
+                <h1>标题一</h1>
+                <p>这是正文的第一段，包含一个<a href="/y">链接</a>。</p>
+                <script>document.write("<p>不应出现</p>")</script>
+                <ul><li>要点一</li><li>要点二</li></ul>
+                <p>第二段&nbsp;内容&#x4E2D;&#25991;</p>
+            </article>
+            <footer>版权所有</footer>
+            </body></html>"#;
+        let article = extract(html);
+        assert_eq!(article.title.as_deref(), Some("新闻 & 评论"));
+        assert_eq!(
+            article.text,
+            "# 标题一\n\n这是正文的第一段，包含一个链接。\n\n- 要点一\n\n- 要点二\n\n第二段 内容中文"
+        );
+        assert_eq!(meta_charset(html.as_bytes()).as_deref(), Some("gbk"));
+        assert_eq!(decode_entities("&lt;a&gt; &unknown;"), "<a> &unknown;");
+    }
+
+    #[test]
+    fn test_extract_without_article() {
+        let html = r#"<body><div><a href="/1">相关阅读一</a> <a href="/2">相关阅读二</a></div>
+            <div><p>没有article元素时，按链接密度和长度选出正文段落。</p><p>短句</p></div></body>"#;
+        let article = extract(html);
+        assert_eq!(article.title, None);
+        assert_eq!(
+            article.text,
+            "没有article元素时，按链接密度和长度选出正文段落。"
+        );
+    }
+}
@@ -0,0 +1,343 @@
+use std::collections::HashMap;
+
+use anyhow::Result;
+use axum::{
+    extract::{Json, Path},
+    Extension,
+};
+use erniebot_rs::chat::{ChatEndpoint, ChatOpt};
+use lazy_static::lazy_static;
+use sea_orm::*;
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+
+use crate::{
+    data::{error_response, JsonDataResponse, SetFunctionEnabledRequest},
+    entities::{prelude::Session, session},
+    functions::get_function_registry,
+    CHAT_MODEL,
+};
+
+lazy_static! {
+    //创建ChatEndpoint需要请求access token，按模型名缓存
+    static ref CHAT_ENDPOINTS: Mutex<HashMap<String, ChatEndpoint>> = Mutex::new(HashMap::new());
+    //函数在启动时注册，之后不会变化，校验设置时不需要每次重新创建注册表
+    static ref FUNCTION_NAMES: Vec<String> = get_function_registry().function_names();
+}
+
+//千帆接口对各参数的取值范围要求
+const MAX_SYSTEM_PROMPT_CHARS: usize = 1024;
+const MAX_OUTPUT_TOKENS: u32 = 2048;
+
+//会话级别的设置，未设置的字段使用千帆接口的默认值
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+#[serde(default)]
+pub struct SessionSettings {
+    //系统人设
+    pub system_prompt: Option<String>,
+    //千帆的模型接口名，如 ernie-4.0-8k、ernie-speed-128k
+    pub model: Option<String>,
+    pub temperature: Option<f32>,
+    pub top_p: Option<f32>,
+    pub penalty_score: Option<f32>,
+    pub max_output_tokens: Option<u32>,
+    //启用的函数，不设置时启用全部函数
+    pub enabled_functions: Option<Vec<String>>,
+    //每轮对话后是否在后台自动提取长期记忆，不设置时不提取
+    pub auto_memory: Option<bool>,
+}
+
+impl SessionSettings {
+    pub fn from_model(session: &session::Model) -> Self {
+        Self {
+            system_prompt: session.system_prompt.clone(),
+            model: session.model.clone(),
+            temperature: session.temperature,
+            top_p: session.top_p,
+            penalty_score: session.penalty_score,
+            max_output_tokens: session.max_output_tokens.map(|x| x as u32),
+            enabled_functions: session
+                .enabled_functions
+                .clone()
+                .and_then(|x| serde_json::from_value(x).ok()),
+            auto_memory: session.auto_memory,
+        }
+    }
+
+    pub fn apply(&self, session: &mut session::ActiveModel) {
+        session.system_prompt = Set(self.system_prompt.clone());
+        session.model = Set(self.model.clone());
+        session.temperature = Set(self.temperature);
+        session.top_p = Set(self.top_p);
+        session.penalty_score = Set(self.penalty_score);
+        session.max_output_tokens = Set(self.max_output_tokens.map(|x| x as i32));
+        session.enabled_functions = Set(self
+            .enabled_functions
+            .as_ref()
+            .map(|x| serde_json::json!(x)));
+        session.auto_memory = Set(self.auto_memory);
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        if let Some(system_prompt) = &self.system_prompt {
+            if system_prompt.chars().count() > MAX_SYSTEM_PROMPT_CHARS {
+                return Err(anyhow::anyhow!(
+                    "system_prompt must not exceed {} characters",
+                    MAX_SYSTEM_PROMPT_CHARS
+                ));
+            }
+        }
+        if let Some(model) = &self.model {
+            if model.trim().is_empty() {
+                return Err(anyhow::anyhow!("model must not be empty"));
+            }
+        }
+        if self.temperature.is_some_and(|x| x <= 0.0 || x > 1.0) {
+            return Err(anyhow::anyhow!("temperature must be in (0, 1]"));
+        }
+        if self.top_p.is_some_and(|x| !(0.0..=1.0).contains(&x)) {
+            return Err(anyhow::anyhow!("top_p must be in [0, 1]"));
+        }
+        if self
+            .penalty_score
+            .is_some_and(|x| !(1.0..=2.0).contains(&x))
+        {
+            return Err(anyhow::anyhow!("penalty_score must be in [1, 2]"));
+        }
+        if self
+            .max_output_tokens
+            .is_some_and(|x| !(2..=MAX_OUTPUT_TOKENS).contains(&x))
+        {
+            return Err(anyhow::anyhow!(
+                "max_output_tokens must be in [2, {}]",
+                MAX_OUTPUT_TOKENS
+            ));
+        }
+        if let Some(enabled_functions) = &self.enabled_functions {
+            if let Some(unknown) = enabled_functions
+                .iter()
+                .find(|x| !FUNCTION_NAMES.contains(x))
+            {
+                return Err(anyhow::anyhow!("Unknown function: {}", unknown));
+            }
+        }
+        Ok(())
+    }
+
+    //direct_reply始终启用，见FunctionRegistry::retain_enabled
+    pub fn is_function_enabled(&self, function_name: &str) -> bool {
+        function_name == "direct_reply"
+            || self
+                .enabled_functions
+                .as_ref()
+                .is_none_or(|x| x.iter().any(|name| name == function_name))
+    }
+
+    pub fn auto_memory(&self) -> bool {
+        self.auto_memory.unwrap_or(false)
+    }
+
+    pub fn model_name(&self) -> &str {
+        self.model.as_deref().unwrap_or(CHAT_MODEL)
+    }
+
+    pub fn chat_options(&self) -> Vec<ChatOpt> {
+        let mut options = Vec::new();
+        if let Some(system_prompt) = &self.system_prompt {
+            options.push(ChatOpt::System(system_prompt.clone()));
+        }
+        if let Some(temperature) = self.temperature {
+            options.push(ChatOpt::Temperature(temperature));
+        }
+        if let Some(top_p) = self.top_p {
+            options.push(ChatOpt::TopP(top_p));
+        }
+        if let Some(penalty_score) = self.penalty_score {
+            options.push(ChatOpt::PenaltyScore(penalty_score));
+        }
+        if let Some(max_output_tokens) = self.max_output_tokens {
+            options.push(ChatOpt::MaxOutputTokens(max_output_tokens));
+        }
+        options
+    }
+
+    //使用默认模型时直接返回默认的endpoint
+    pub async fn chat_endpoint(&self, default_endpoint: &ChatEndpoint) -> Result<ChatEndpoint> {
+        let model = match &self.model {
+            Some(model) if model != CHAT_MODEL => model.clone(),
+            _ => return Ok(default_endpoint.clone()),
+        };
+        if let Some(endpoint) = CHAT_ENDPOINTS.lock().await.get(&model) {
+            return Ok(endpoint.clone());
+        }
+        //获取access token时不持有锁，避免一个模型的请求阻塞其它模型；并发创建时保留先创建的
+        let model_name = model.clone();
+        let endpoint = tokio::task::spawn_blocking(move || {
+            ChatEndpoint::new_with_custom_endpoint(&model_name)
+        })
+        .await??;
+        let endpoint = CHAT_ENDPOINTS
+            .lock()
+            .await
+            .entry(model)
+            .or_insert(endpoint)
+            .clone();
+        Ok(endpoint)
+    }
+}
+
+#[axum_macros::debug_handler]
+pub async fn get_session_settings(
+    Extension(db): Extension<DatabaseConnection>,
+    Path(session_id): Path<i32>,
+) -> Json<JsonDataResponse> {
+    match Session::find_by_id(session_id).one(&db).await {
+        Ok(Some(session)) => Json(JsonDataResponse {
+            code: 200,
+            data: serde_json::json!(SessionSettings::from_model(&session)),
+        }),
+        Ok(None) => session_not_found(),
+        Err(e) => error_response(&e.to_string()),
+    }
+}
+
+//整体替换会话设置，请求中没有的字段恢复为默认值
+#[axum_macros::debug_handler]
+pub async fn update_session_settings(
+    Extension(db): Extension<DatabaseConnection>,
+    Path(session_id): Path<i32>,
+    Json(settings): Json<SessionSettings>,
+) -> Json<JsonDataResponse> {
+    if let Err(e) = settings.validate() {
+        return error_response(&e.to_string());
+    }
+    let mut session = session::ActiveModel {
+        session_id: Set(session_id),
+        last_update_time: Set(chrono::Utc::now()),
+        ..Default::default()
+    };
+    settings.apply(&mut session);
+    match session.update(&db).await {
+        Ok(session) => Json(JsonDataResponse {
+            code: 200,
+            data: serde_json::json!(SessionSettings::from_model(&session)),
+        }),
+        Err(DbErr::RecordNotUpdated) => session_not_found(),
+        Err(e) => error_response(&e.to_string()),
+    }
+}
+
+fn session_not_found() -> Json<JsonDataResponse> {
+    Json(JsonDataResponse {
+        code: 404,
+        data: serde_json::json!({
+            "error": "Session not found"
+        }),
+    })
+}
+
+fn session_functions_json(session_id: i32, settings: &SessionSettings) -> serde_json::Value {
+    let registry = get_function_registry();
+    let functions: Vec<serde_json::Value> = registry
+        .describe()
+        .into_iter()
+        .map(|function| {
+            let mut value = serde_json::json!(function);
+            value["enabled"] = settings.is_function_enabled(&function.name).into();
+            value["requires_confirmation"] = registry.requires_confirmation(&function.name).into();
+            value
+        })
+        .collect();
+    serde_json::json!({
+        "session_id": session_id,
+        "functions": functions
+    })
+}
+
+//列出所有函数的说明、风险等级，以及在该会话中是否启用
+#[axum_macros::debug_handler]
+pub async fn list_session_functions(
+    Extension(db): Extension<DatabaseConnection>,
+    Path(session_id): Path<i32>,
+) -> Json<JsonDataResponse> {
+    match Session::find_by_id(session_id).one(&db).await {
+        Ok(Some(session)) => Json(JsonDataResponse {
+            code: 200,
+            data: session_functions_json(session_id, &SessionSettings::from_model(&session)),
+        }),
+        Ok(None) => session_not_found(),
+        Err(e) => error_response(&e.to_string()),
+    }
+}
+
+#[axum_macros::debug_handler]
+pub async fn set_session_function(
+    Extension(db): Extension<DatabaseConnection>,
+    Path((session_id, function_name)): Path<(i32, String)>,
+    Json(data): Json<SetFunctionEnabledRequest>,
+) -> Json<JsonDataResponse> {
+    if !FUNCTION_NAMES.contains(&function_name) {
+        return error_response(&format!("Unknown function: {}", function_name));
+    }
+    if function_name == "direct_reply" && !data.enabled {
+        return error_response("direct_reply cannot be disabled");
+    }
+    let session = match Session::find_by_id(session_id).one(&db).await {
+        Ok(Some(session)) => session,
+        Ok(None) => return session_not_found(),
+        Err(e) => return error_response(&e.to_string()),
+    };
+    let mut settings = SessionSettings::from_model(&session);
+    //没有设置过启用列表时，以全部函数为起点
+    let mut enabled_functions = settings
+        .enabled_functions
+        .take()
+        .unwrap_or_else(|| FUNCTION_NAMES.clone());
+    enabled_functions.retain(|x| x != &function_name);
+    if data.enabled {
+        enabled_functions.push(function_name);
+        enabled_functions.sort();
+    }
+    settings.enabled_functions = Some(enabled_functions);
+    let mut session: session::ActiveModel = session.into();
+    settings.apply(&mut session);
+    session.last_update_time = Set(chrono::Utc::now());
+    match session.update(&db).await {
+        Ok(session) => Json(JsonDataResponse {
+            code: 200,
+            data: session_functions_json(session_id, &SessionSettings::from_model(&session)),
+        }),
+        Err(e) => error_response(&e.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SessionSettings;
+
+    #[test]
+    fn test_validate_session_settings() {
+        let settings = SessionSettings {
+            system_prompt: Some("你是一名严谨的法律顾问".to_string()),
+            temperature: Some(0.3),
+            top_p: Some(0.8),
+            max_output_tokens: Some(1024),
+            enabled_functions: Some(vec!["document_search".to_string()]),
+            ..Default::default()
+        };
+        assert!(settings.validate().is_ok());
+        assert_eq!(settings.chat_options().len(), 4);
+        //自动提取记忆需要显式开启
+        assert!(!settings.auto_memory());
+        let settings = SessionSettings {
+            temperature: Some(0.0),
+            ..Default::default()
+        };
+        assert!(settings.validate().is_err());
+        let settings = SessionSettings {
+            enabled_functions: Some(vec!["rm_rf".to_string()]),
+            ..Default::default()
+        };
+        assert!(settings.validate().is_err());
+    }
+}
@@ -0,0 +1,93 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use async_trait::async_trait;
+
+use super::Storage;
+
+pub struct LocalStorage {
+    root: PathBuf,
+}
+
+impl LocalStorage {
+    pub fn new(root: &str) -> Self {
+        Self {
+            root: PathBuf::from(root),
+        }
+    }
+
+    fn object_path(&self, key: &str) -> Result<PathBuf> {
+        //key由哈希生成，这里仍然拒绝任何可能逃出root的路径
+        if key
+            .split('/')
+            .any(|x| x.is_empty() || x == "." || x == "..")
+        {
+            return Err(anyhow::anyhow!("Invalid storage key: {}", key));
+        }
+        Ok(self.root.join(key))
+    }
+}
+
+#[async_trait]
+impl Storage for LocalStorage {
+    async fn exists(&self, key: &str) -> Result<bool> {
+        let path = self.object_path(key)?;
+        Ok(tokio::fs::try_exists(path).await?)
+    }
+
+    async fn put_file(&self, key: &str, source: &Path) -> Result<()> {
+        let path = self.object_path(key)?;
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        //先复制到同目录下的临时文件再rename，保证对象要么完整存在要么不存在；
+        //相同内容可能被并发上传，临时文件名需要唯一
+        let partial_path = path.with_extension(format!("{}.partial", uuid::Uuid::new_v4()));
+        tokio::fs::copy(source, &partial_path).await?;
+        tokio::fs::rename(&partial_path, &path).await?;
+        Ok(())
+    }
+
+    async fn get(&self, key: &str) -> Result<Vec<u8>> {
+        let path = self.object_path(key)?;
+        Ok(tokio::fs::read(path).await?)
+    }
+
+    async fn delete(&self, key: &str) -> Result<()> {
+        let path = self.object_path(key)?;
+        tokio::fs::remove_file(path).await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{LocalStorage, Storage};
+
+    #[tokio::test]
+    async fn test_local_storage_roundtrip() {
+        let root = std::env::temp_dir().join(format!("storage-test-{}", uuid::Uuid::new_v4()));
+        let storage = LocalStorage::new(root.to_str().unwrap());
+        let source = root.join("source.txt");
+        tokio::fs::create_dir_all(&root).await.unwrap();
+        tokio::fs::write(&source, "hello").await.unwrap();
+
+        let key = "2c/2cf24dba.txt";
+        assert!(!storage.exists(key).await.unwrap());
+        storage.put_file(key, &source).await.unwrap();
+        assert!(storage.exists(key).await.unwrap());
+        assert_eq!(storage.get(key).await.unwrap(), b"hello");
+        assert!(storage.get("../source.txt").await.is_err());
+        //并发保存相同内容时各自使用不同的临时文件
+        let (first, second) = tokio::join!(
+            storage.put_file(key, &source),
+            storage.put_file(key, &source)
+        );
+        first.unwrap();
+        second.unwrap();
+        assert_eq!(storage.get(key).await.unwrap(), b"hello");
+        storage.delete(key).await.unwrap();
+        assert!(!storage.exists(key).await.unwrap());
+        tokio::fs::remove_dir_all(&root).await.unwrap();
+    }
+}
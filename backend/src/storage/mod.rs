@@ -0,0 +1,107 @@
+mod local;
+mod s3;
+mod staging;
+
+use std::{
+    collections::HashMap,
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+use anyhow::Result;
+use async_trait::async_trait;
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tokio::sync::OwnedMutexGuard;
+
+pub use local::LocalStorage;
+pub use s3::{S3Storage, S3StorageConfig};
+pub use staging::{StagedFile, UploadStager};
+
+//上传文件的存储后端，对象以内容的SHA-256哈希作为key，相同内容只会保存一份
+#[async_trait]
+pub trait Storage: Send + Sync {
+    async fn exists(&self, key: &str) -> Result<bool>;
+    //将本地暂存文件保存到key，调用方保证暂存文件在调用期间存在
+    async fn put_file(&self, key: &str, source: &Path) -> Result<()>;
+    async fn get(&self, key: &str) -> Result<Vec<u8>>;
+    async fn delete(&self, key: &str) -> Result<()>;
+}
+
+lazy_static! {
+    static ref KEY_LOCKS: Mutex<HashMap<String, Arc<tokio::sync::Mutex<()>>>> =
+        Mutex::new(HashMap::new());
+}
+
+//同一个key的对象保存(连同写入引用它的记录)和引用计数后删除必须串行执行，
+//否则删除可能发生在新引用写入之前，导致新记录指向已删除的对象
+pub struct KeyGuard {
+    key: String,
+    _guard: OwnedMutexGuard<()>,
+}
+
+impl Drop for KeyGuard {
+    fn drop(&mut self) {
+        let mut locks = KEY_LOCKS.lock().unwrap();
+        //除了map和自己之外没有等待者时移除，避免map随key的数量无限增长
+        if locks
+            .get(&self.key)
+            .is_some_and(|lock| Arc::strong_count(lock) == 2)
+        {
+            locks.remove(&self.key);
+        }
+    }
+}
+
+pub async fn lock_key(key: &str) -> KeyGuard {
+    let lock = KEY_LOCKS
+        .lock()
+        .unwrap()
+        .entry(key.to_string())
+        .or_default()
+        .clone();
+    KeyGuard {
+        key: key.to_string(),
+        _guard: lock.lock_owned().await,
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "backend", rename_all = "snake_case")]
+pub enum StorageBackendConfig {
+    Local { root: String },
+    S3(S3StorageConfig),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct StorageConfig {
+    pub max_upload_size: u64,
+    pub staging_dir: String,
+    pub storage: StorageBackendConfig,
+}
+
+impl StorageConfig {
+    pub fn load(path: &str) -> Result<Self> {
+        let config_string = std::fs::read_to_string(path)?;
+        let config = serde_json::from_str(&config_string)?;
+        Ok(config)
+    }
+
+    pub fn build_storage(&self) -> Result<Arc<dyn Storage>> {
+        let storage: Arc<dyn Storage> = match &self.storage {
+            StorageBackendConfig::Local { root } => Arc::new(LocalStorage::new(root)),
+            StorageBackendConfig::S3(config) => Arc::new(S3Storage::new(config)?),
+        };
+        Ok(storage)
+    }
+}
+
+//key形如 "ab/abcdef....pdf"，按哈希前两位分目录，避免单个目录下文件过多
+pub fn content_key(sha256: &str, extension: &str) -> String {
+    format!("{}/{}.{}", &sha256[..2], sha256, extension)
+}
+
+pub fn sha256_hex(data: &[u8]) -> String {
+    hex::encode(Sha256::digest(data))
+}
@@ -0,0 +1,167 @@
+use std::path::Path;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use object_store::{
+    aws::{AmazonS3, AmazonS3Builder},
+    path::Path as ObjectPath,
+    ObjectStore, WriteMultipart,
+};
+use serde::{Deserialize, Serialize};
+use tokio::io::AsyncReadExt;
+
+use super::Storage;
+
+//分片上传的单片大小，S3要求除最后一片外不小于5MB
+const PART_SIZE: usize = 8 * 1024 * 1024;
+//同时上传的分片数，达到后暂停读取文件，内存中最多保留这么多分片加上正在填充的一片
+const MAX_CONCURRENT_PARTS: usize = 2;
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct S3StorageConfig {
+    //兼容S3协议的服务地址，如本地MinIO的 http://localhost:9000
+    pub endpoint: String,
+    pub bucket: String,
+    pub region: String,
+    pub access_key_id: String,
+    pub secret_access_key: String,
+    #[serde(default)]
+    pub prefix: String,
+}
+
+pub struct S3Storage {
+    store: AmazonS3,
+    prefix: String,
+}
+
+impl S3Storage {
+    pub fn new(config: &S3StorageConfig) -> Result<Self> {
+        let store = AmazonS3Builder::new()
+            .with_endpoint(&config.endpoint)
+            .with_bucket_name(&config.bucket)
+            .with_region(&config.region)
+            .with_access_key_id(&config.access_key_id)
+            .with_secret_access_key(&config.secret_access_key)
+            .with_allow_http(config.endpoint.starts_with("http://"))
+            .build()?;
+        Ok(Self {
+            store,
+            prefix: config.prefix.trim_matches('/').to_string(),
+        })
+    }
+
+    async fn write_file(writer: &mut WriteMultipart, source: &Path) -> Result<()> {
+        let mut file = tokio::fs::File::open(source).await?;
+        let mut buffer = vec![0u8; 64 * 1024];
+        loop {
+            let n = file.read(&mut buffer).await?;
+            if n == 0 {
+                break;
+            }
+            writer.wait_for_capacity(MAX_CONCURRENT_PARTS).await?;
+            writer.write(&buffer[..n]);
+        }
+        //在finish之前等待已提交的分片上传完成，失败时还能放弃整个上传
+        writer.wait_for_capacity(0).await?;
+        Ok(())
+    }
+
+    fn object_path(&self, key: &str) -> ObjectPath {
+        if self.prefix.is_empty() {
+            ObjectPath::from(key)
+        } else {
+            ObjectPath::from(format!("{}/{}", self.prefix, key))
+        }
+    }
+}
+
+#[async_trait]
+impl Storage for S3Storage {
+    async fn exists(&self, key: &str) -> Result<bool> {
+        match self.store.head(&self.object_path(key)).await {
+            Ok(_) => Ok(true),
+            Err(object_store::Error::NotFound { .. }) => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn put_file(&self, key: &str, source: &Path) -> Result<()> {
+        let upload = self.store.put_multipart(&self.object_path(key)).await?;
+        let mut writer = WriteMultipart::new_with_chunk_size(upload, PART_SIZE);
+        //读取文件或上传分片失败时放弃分片上传，不在bucket中留下未完成的上传
+        if let Err(e) = Self::write_file(&mut writer, source).await {
+            let _ = writer.abort().await;
+            return Err(e);
+        }
+        //合并分片失败时finish会自行放弃上传
+        writer.finish().await?;
+        Ok(())
+    }
+
+    async fn get(&self, key: &str) -> Result<Vec<u8>> {
+        let result = self.store.get(&self.object_path(key)).await?;
+        Ok(result.bytes().await?.to_vec())
+    }
+
+    async fn delete(&self, key: &str) -> Result<()> {
+        self.store.delete(&self.object_path(key)).await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{S3Storage, S3StorageConfig, PART_SIZE};
+    use crate::storage::Storage;
+    use object_store::{memory::InMemory, path::Path as ObjectPath, ObjectStore, WriteMultipart};
+
+    #[tokio::test]
+    async fn test_write_file() {
+        //超过两片的文件按分片写入，读取失败时返回错误
+        let store = InMemory::new();
+        let path = ObjectPath::from("uploads/large.bin");
+        let source = std::env::temp_dir().join(format!("s3-test-{}", uuid::Uuid::new_v4()));
+        let content: Vec<u8> = (0..PART_SIZE * 2 + 10).map(|x| (x % 251) as u8).collect();
+        tokio::fs::write(&source, &content).await.unwrap();
+        let upload = store.put_multipart(&path).await.unwrap();
+        let mut writer = WriteMultipart::new_with_chunk_size(upload, PART_SIZE);
+        let result = S3Storage::write_file(&mut writer, &source).await;
+        tokio::fs::remove_file(&source).await.unwrap();
+        result.unwrap();
+        writer.finish().await.unwrap();
+        let bytes = store.get(&path).await.unwrap().bytes().await.unwrap();
+        assert_eq!(bytes.as_ref(), content.as_slice());
+
+        let upload = store.put_multipart(&path).await.unwrap();
+        let mut writer = WriteMultipart::new_with_chunk_size(upload, PART_SIZE);
+        assert!(S3Storage::write_file(&mut writer, &source).await.is_err());
+        writer.abort().await.unwrap();
+    }
+
+    //需要本地运行MinIO等兼容S3的服务，例如：
+    //docker run -p 9000:9000 -e MINIO_ROOT_USER=minioadmin -e MINIO_ROOT_PASSWORD=minioadmin minio/minio server /data
+    //并预先创建名为test的bucket，然后执行 cargo test -- --ignored
+    #[tokio::test]
+    #[ignore]
+    async fn test_s3_storage_roundtrip() {
+        let config = S3StorageConfig {
+            endpoint: std::env::var("S3_ENDPOINT").unwrap_or("http://localhost:9000".to_string()),
+            bucket: "test".to_string(),
+            region: "us-east-1".to_string(),
+            access_key_id: "minioadmin".to_string(),
+            secret_access_key: "minioadmin".to_string(),
+            prefix: "uploads".to_string(),
+        };
+        let storage = S3Storage::new(&config).unwrap();
+        let source = std::env::temp_dir().join(format!("s3-test-{}", uuid::Uuid::new_v4()));
+        tokio::fs::write(&source, "hello").await.unwrap();
+
+        let key = "2c/2cf24dba.txt";
+        storage.put_file(key, &source).await.unwrap();
+        assert!(storage.exists(key).await.unwrap());
+        assert_eq!(storage.get(key).await.unwrap(), b"hello");
+        storage.delete(key).await.unwrap();
+        assert!(!storage.exists(key).await.unwrap());
+        tokio::fs::remove_file(&source).await.unwrap();
+    }
+}
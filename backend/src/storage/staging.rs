@@ -0,0 +1,153 @@
+use std::path::PathBuf;
+
+use anyhow::Result;
+use sha2::{Digest, Sha256};
+use tokio::{fs::File, io::AsyncWriteExt};
+
+use super::content_key;
+
+//用于MIME嗅探的文件头长度
+const SNIFF_LENGTH: usize = 8192;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct StagedFile {
+    pub path: PathBuf,
+    pub sha256: String,
+    pub size: u64,
+    pub mime: String,
+    pub extension: String,
+}
+
+impl StagedFile {
+    pub fn key(&self) -> String {
+        content_key(&self.sha256, &self.extension)
+    }
+}
+
+//将上传内容边接收边写入暂存文件，同时计算哈希、统计大小并保留文件头用于嗅探类型
+pub struct UploadStager {
+    path: PathBuf,
+    file: File,
+    hasher: Sha256,
+    size: u64,
+    max_size: u64,
+    head: Vec<u8>,
+}
+
+impl UploadStager {
+    pub async fn new(staging_dir: &str, max_size: u64) -> Result<Self> {
+        tokio::fs::create_dir_all(staging_dir).await?;
+        let path = PathBuf::from(staging_dir).join(uuid::Uuid::new_v4().to_string());
+        let file = File::create(&path).await?;
+        Ok(Self {
+            path,
+            file,
+            hasher: Sha256::new(),
+            size: 0,
+            max_size,
+            head: Vec::new(),
+        })
+    }
+
+    pub async fn write(&mut self, chunk: &[u8]) -> Result<()> {
+        self.size += chunk.len() as u64;
+        if self.size > self.max_size {
+            return Err(anyhow::anyhow!(
+                "File too large, the maximum size is {} bytes",
+                self.max_size
+            ));
+        }
+        if self.head.len() < SNIFF_LENGTH {
+            let take = (SNIFF_LENGTH - self.head.len()).min(chunk.len());
+            self.head.extend_from_slice(&chunk[..take]);
+        }
+        self.hasher.update(chunk);
+        self.file.write_all(chunk).await?;
+        Ok(())
+    }
+
+    //filename只用来区分txt和md这类无法从内容区分的文本格式
+    pub async fn finish(mut self, filename: &str) -> Result<StagedFile> {
+        self.file.flush().await?;
+        let (mime, extension) = match sniff_type(&self.head, filename) {
+            Some(result) => result,
+            None => {
+                self.abort().await;
+                return Err(anyhow::anyhow!("Invalid content type"));
+            }
+        };
+        //解析器根据扩展名选择解析方式
+        let path = self.path.with_extension(extension);
+        tokio::fs::rename(&self.path, &path).await?;
+        Ok(StagedFile {
+            path,
+            sha256: hex::encode(self.hasher.clone().finalize()),
+            size: self.size,
+            mime: mime.to_string(),
+            extension: extension.to_string(),
+        })
+    }
+
+    pub async fn abort(self) {
+        let _ = tokio::fs::remove_file(&self.path).await;
+    }
+}
+
+//根据文件内容判断类型，不信任客户端提供的content-type；返回 (MIME, 扩展名)
+pub fn sniff_type(head: &[u8], filename: &str) -> Option<(&'static str, &'static str)> {
+    if let Some(kind) = infer::get(head) {
+        return match kind.mime_type() {
+            "application/pdf" => Some(("application/pdf", "pdf")),
+            "application/vnd.openxmlformats-officedocument.presentationml.presentation" => Some((
+                "application/vnd.openxmlformats-officedocument.presentationml.presentation",
+                "pptx",
+            )),
+            _ => None,
+        };
+    }
+    //文本文件没有魔数，只要求内容是合法的UTF-8(文件头可能截断在多字节字符中间)
+    let valid_utf8 = match std::str::from_utf8(head) {
+        Ok(_) => true,
+        Err(e) => e.error_len().is_none(),
+    };
+    if !valid_utf8 || head.contains(&0) {
+        return None;
+    }
+    if filename.to_lowercase().ends_with(".md") {
+        Some(("text/markdown", "md"))
+    } else {
+        Some(("text/plain", "txt"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{sniff_type, UploadStager};
+
+    #[test]
+    fn test_sniff_type() {
+        assert_eq!(
+            sniff_type(b"%PDF-1.7\n", "report.txt"),
+            Some(("application/pdf", "pdf"))
+        );
+        assert_eq!(
+            sniff_type("你好".as_bytes(), "a.md"),
+            Some(("text/markdown", "md"))
+        );
+        assert_eq!(
+            sniff_type("你好".as_bytes(), "a.pdf"),
+            Some(("text/plain", "txt"))
+        );
+        assert_eq!(sniff_type(b"\x89PNG\r\n\x1a\n\0\0", "a.txt"), None);
+    }
+
+    #[tokio::test]
+    async fn test_stager_limits_size() {
+        let dir = std::env::temp_dir().join(format!("stager-test-{}", uuid::Uuid::new_v4()));
+        let mut stager = UploadStager::new(dir.to_str().unwrap(), 4).await.unwrap();
+        stager.write(b"abc").await.unwrap();
+        assert!(stager.write(b"de").await.is_err());
+        stager.abort().await;
+        tokio::fs::remove_dir_all(&dir).await.unwrap();
+    }
+}
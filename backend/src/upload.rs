@@ -0,0 +1,104 @@
+use std::collections::HashMap;
+
+use anyhow::Result;
+use axum::extract::Multipart;
+
+use crate::storage::{lock_key, KeyGuard, StagedFile, Storage, StorageConfig, UploadStager};
+
+//multipart表单中的普通字段和上传的文件，文件先写入暂存目录
+pub struct UploadForm {
+    pub fields: HashMap<String, String>,
+    pub filename: String,
+    pub file: Option<StagedFile>,
+}
+
+impl UploadForm {
+    pub async fn read(multipart: &mut Multipart, config: &StorageConfig) -> Result<Self> {
+        let mut form = UploadForm {
+            fields: HashMap::new(),
+            filename: String::new(),
+            file: None,
+        };
+        loop {
+            let field = match multipart.next_field().await {
+                Ok(Some(field)) => field,
+                Ok(None) => break,
+                Err(e) => {
+                    form.discard().await;
+                    return Err(e.into());
+                }
+            };
+            let name = field.name().unwrap_or_default().to_string();
+            if name != "file" {
+                match field.text().await {
+                    Ok(text) => {
+                        form.fields.insert(name, text);
+                    }
+                    Err(e) => {
+                        form.discard().await;
+                        return Err(e.into());
+                    }
+                }
+                continue;
+            }
+            //只用文件名区分txt和md，真实类型由文件内容判断
+            form.filename = field.file_name().unwrap_or_default().to_string();
+            match stage_field(field, &form.filename, config).await {
+                Ok(staged) => {
+                    form.discard().await;
+                    form.file = Some(staged);
+                }
+                Err(e) => {
+                    form.discard().await;
+                    return Err(e);
+                }
+            }
+        }
+        Ok(form)
+    }
+
+    pub fn field<T: std::str::FromStr>(&self, name: &str) -> Option<T> {
+        self.fields.get(name).and_then(|x| x.parse().ok())
+    }
+
+    //删除暂存的文件，请求无效或文件已经保存到存储后端后调用
+    pub async fn discard(&mut self) {
+        if let Some(staged) = self.file.take() {
+            let _ = tokio::fs::remove_file(&staged.path).await;
+        }
+    }
+}
+
+async fn stage_field(
+    mut field: axum::extract::multipart::Field<'_>,
+    filename: &str,
+    config: &StorageConfig,
+) -> Result<StagedFile> {
+    let mut stager = UploadStager::new(&config.staging_dir, config.max_upload_size).await?;
+    loop {
+        let chunk = match field.chunk().await {
+            Ok(Some(chunk)) => chunk,
+            Ok(None) => break,
+            Err(e) => {
+                stager.abort().await;
+                return Err(e.into());
+            }
+        };
+        if let Err(e) = stager.write(&chunk).await {
+            stager.abort().await;
+            return Err(e);
+        }
+    }
+    stager.finish(filename).await
+}
+
+//相同内容的文件只保存一份，返回文件在存储后端中的key
+//返回的锁需要持有到引用该对象的记录写入数据库之后，见storage::lock_key
+pub async fn store_file(storage: &dyn Storage, staged: &StagedFile) -> Result<(String, KeyGuard)> {
+    let key = staged.key();
+    let guard = lock_key(&key).await;
+    if !storage.exists(&key).await? {
+        storage.put_file(&key, &staged.path).await?;
+    }
+    Ok((key, guard))
+}
@@ -61,15 +61,16 @@ const ChatApp = function () {
 
   useEffect(() => {
     // 组件挂载后调用createSession  
+    const socket = io('http://localhost:8888/ws', { transports: ['websocket', 'polling', 'flashsocket'] });
+    setSocket(socket);
+    socket.connect();
     createSession().then(newSessionId => {
       console.log("newSessionId: " + newSessionId);
       if (newSessionId !== undefined) {
         setSessionId(newSessionId); // 更新sessionId状态 
+        socket.emit("subscribe", { session_id: newSessionId }); // 订阅该会话的文档处理进度
       }
     });
-    const socket = io('http://localhost:8888/ws', { transports: ['websocket', 'polling', 'flashsocket'] });
-    setSocket(socket);
-    socket.connect();
     function onResponse(res: any) {
       console.log("start response");
       if (res.code === 200) {
@@ -84,6 +85,38 @@ const ChatApp = function () {
       }
       setTyping(false);
     }
+    function onJobProgress(job: any) {
+      console.log("job progress", job);
+      if (job.status === 'completed') {
+        appendMsg({
+          type: 'text',
+          content: { text: job.filename + '已经处理完成，你可以提问有关这篇文档的问题了！' },
+          position: 'left',
+        });
+      } else if (job.status === 'failed') {
+        appendMsg({
+          type: 'text',
+          content: { text: job.filename + '处理失败：' + job.error },
+          position: 'left',
+        });
+      }
+    }
+    // 有副作用的函数需要用户确认后才会执行，通过ack回复服务端
+    function onConfirmFunction(req: any, ack: (reply: { approved: boolean }) => void) {
+      Modal.confirm({
+        title: '是否允许执行 ' + req.function + '？',
+        content: (
+          <div>
+            <p>{req.description}</p>
+            <pre style={{ whiteSpace: 'pre-wrap' }}>{JSON.stringify(req.parameters, null, 2)}</pre>
+          </div>
+        ),
+        okText: '执行',
+        cancelText: '取消',
+        onOk: () => ack({ approved: true }),
+        onCancel: () => ack({ approved: false }),
+      });
+    }
     function onConnect() {
       console.log("connected");
     }
@@ -93,11 +126,15 @@ const ChatApp = function () {
     }
     // 监听socket.io事件
     socket.on("response", onResponse);
+    socket.on("job_progress", onJobProgress);
+    socket.on("confirm_function", onConfirmFunction);
     socket.on("connect", onConnect);
     socket.on("disconnect", onDisconnect);
     return () => {
       socket.disconnect();
       socket.off("response", onResponse);
+      socket.off("job_progress", onJobProgress);
+      socket.off("confirm_function", onConfirmFunction);
       socket.off("connect", onConnect);
       socket.off("disconnect", onDisconnect);
     }
@@ -209,7 +246,7 @@ const ChatApp = function () {
         message.success("文件上传成功");
         appendMsg({
           type: 'text',
-          content: { text: '您已经上传了' + info.file.name + '你可以提问有关这篇文档的问题了！' },
+          content: { text: '您已经上传了' + info.file.name + '，文档正在后台处理中，请稍候' },
           position: 'left',
         });
       } else {